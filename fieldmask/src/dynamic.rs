@@ -0,0 +1,819 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::RwLock,
+};
+
+use prost_reflect::{
+    DynamicMessage, FieldDescriptor, MessageDescriptor, OneofDescriptor, ReflectMessage, Value,
+};
+use prost_types::Struct;
+
+use crate::{
+    DeserializeMaskError, KeyMask, Maskable, OptionMaskable, ProjectOptions, RepeatedStrategy,
+    SelfMaskable, UpdateOptions,
+};
+
+/// A field mask of a message type that is only known at runtime, e.g. from a
+/// `prost_reflect::DescriptorPool`.
+///
+/// The mask is parsed against a `MessageDescriptor`. It projects and updates `DynamicMessage`s of
+/// that type the same way `Mask` does for types that derive `SelfMaskable`:
+///  * singular message fields can be selected as a whole or by their nested fields, e.g.
+///    `child.field_one`. A message field selected as a whole is merged by updates.
+///  * the fields of a oneof are selected by their own names, like flattened oneof enums. Setting
+///    one of them clears the others.
+///  * repeated fields, map fields and the well-known types in `google.protobuf` are atomic, except
+///    `google.protobuf.Struct`, whose fields are selected by their keys, e.g. `metadata.some_key`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DynamicMask {
+    descriptor: MessageDescriptor,
+    fields: FieldsMask,
+}
+
+impl DynamicMask {
+    /// Returns an empty mask of the message type described by `descriptor`.
+    pub fn new(descriptor: MessageDescriptor) -> Self {
+        Self {
+            descriptor,
+            fields: FieldsMask::default(),
+        }
+    }
+
+    /// Parses a mask of the message type described by `descriptor` from field mask paths, e.g.
+    /// `["parent.child", "other"]`.
+    ///
+    /// The errors are the same as the ones of `Mask`. Since `DeserializeMaskError` holds the names
+    /// of types and fields as `&'static str`, the names from the descriptor are leaked when an
+    /// error is returned, once for each distinct name.
+    pub fn parse<'a>(
+        descriptor: MessageDescriptor,
+        paths: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Self, DeserializeMaskError<'a>> {
+        let mut mask = Self::new(descriptor);
+        for path in paths {
+            mask.include_field(&path.split('.').collect::<Vec<_>>())?;
+        }
+        Ok(mask)
+    }
+
+    /// Returns the descriptor of the message type the mask is parsed against.
+    pub fn descriptor(&self) -> &MessageDescriptor {
+        &self.descriptor
+    }
+
+    /// Returns whether the mask selects no field.
+    pub fn is_empty(&self) -> bool {
+        self.fields.0.is_empty()
+    }
+
+    /// Includes the field specified by `field_path`.
+    ///
+    /// `field_path` is a field mask path splitted by '.'.
+    pub fn include_field<'a>(
+        &mut self,
+        field_path: &[&'a str],
+    ) -> Result<(), DeserializeMaskError<'a>> {
+        self.fields.include_field(&self.descriptor, field_path)
+    }
+
+    /// Returns the field paths selected by the mask, e.g. `["parent.child", "other"]`.
+    ///
+    /// Parsing the returned paths with `DynamicMask::parse` gives back the same mask.
+    pub fn paths(&self) -> Vec<String> {
+        let mut paths = Vec::new();
+        self.fields.collect_paths(&self.descriptor, "", &mut paths);
+        paths
+    }
+
+    /// Project the fields of `source` according to the field mask.
+    ///
+    /// An empty field mask is treated as a full mask.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `source` is not of the message type the mask is parsed against.
+    pub fn project(&self, source: DynamicMessage) -> DynamicMessage {
+        self.project_with_options(source, &Default::default())
+    }
+
+    /// The same as `project`, but with additional options.
+    pub fn project_with_options(
+        &self,
+        mut source: DynamicMessage,
+        options: &ProjectOptions,
+    ) -> DynamicMessage {
+        self.check_descriptor(&source);
+        project_message(&mut source, Selection::Fields(&self.fields), options);
+        source
+    }
+
+    /// Update the fields of `target` with the fields of `source` according to the field mask.
+    ///
+    /// An empty field mask is treated as a full mask.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `target` or `source` is not of the message type the mask is parsed against.
+    pub fn update(&self, target: &mut DynamicMessage, source: DynamicMessage) {
+        self.update_with_options(target, source, &Default::default());
+    }
+
+    /// The same as `update`, but with additional options.
+    ///
+    /// Field behaviors are not declared on dynamic messages, so `options.field_behavior_policy`
+    /// and `options.check_required` have no effect. Repeated fields have no key, so
+    /// `RepeatedStrategy::MergeByKey` appends.
+    pub fn update_with_options(
+        &self,
+        target: &mut DynamicMessage,
+        source: DynamicMessage,
+        options: &UpdateOptions,
+    ) {
+        self.check_descriptor(target);
+        self.check_descriptor(&source);
+        if self.is_empty() {
+            update_message(target, source, Selection::Full, options);
+            return;
+        }
+        update_message(target, source, Selection::Fields(&self.fields), options);
+    }
+
+    fn check_descriptor(&self, message: &DynamicMessage) {
+        assert!(
+            message.descriptor() == self.descriptor,
+            "the mask of `{}` cannot be applied to a message of `{}`",
+            self.descriptor.full_name(),
+            message.descriptor().full_name(),
+        );
+    }
+}
+
+/// Merge the fields of `source` into `target`, the same way `SelfMaskable::merge` does for types
+/// that derive it.
+///
+/// # Panics
+///
+/// Panics if `target` and `source` are not of the same message type.
+pub fn merge_dynamic(target: &mut DynamicMessage, source: DynamicMessage, options: &UpdateOptions) {
+    assert!(
+        target.descriptor() == source.descriptor(),
+        "a message of `{}` cannot be merged into a message of `{}`",
+        source.descriptor().full_name(),
+        target.descriptor().full_name(),
+    );
+    merge_message(target, source, options);
+}
+
+/// The selected fields of a message, by their numbers. A field with an empty sub-mask is selected
+/// as a whole.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct FieldsMask(BTreeMap<u32, FieldMask>);
+
+/// The sub-mask of a selected field.
+#[derive(Clone, Debug, PartialEq)]
+enum FieldMask {
+    /// The selected fields of a message field.
+    Fields(FieldsMask),
+    /// The selected keys of a `google.protobuf.Struct` field.
+    Keys(KeyMask),
+}
+
+impl FieldsMask {
+    fn include_field<'a>(
+        &mut self,
+        descriptor: &MessageDescriptor,
+        field_path: &[&'a str],
+    ) -> Result<(), DeserializeMaskError<'a>> {
+        let [name, tail @ ..] = field_path else {
+            return Ok(());
+        };
+        let Some(field) = descriptor.get_field_by_name(name) else {
+            return Err(DeserializeMaskError::FieldNotFound {
+                field: name,
+                type_name: intern(descriptor.full_name()),
+            });
+        };
+
+        let mask = self.0.entry(field.number()).or_insert_with(|| {
+            if is_struct(&field) {
+                FieldMask::Keys(KeyMask::default())
+            } else {
+                FieldMask::Fields(FieldsMask::default())
+            }
+        });
+        let result = match (mask, message_descriptor(&field), tail) {
+            (FieldMask::Keys(mask), _, _) => {
+                mask.include_field(tail);
+                Ok(())
+            }
+            (FieldMask::Fields(mask), Some(descriptor), _) => mask.include_field(&descriptor, tail),
+            (_, None, []) => Ok(()),
+            // Atomic fields can only be selected as a whole.
+            (_, None, [nested, ..]) => Err(DeserializeMaskError::FieldNotFound {
+                field: nested,
+                type_name: intern(&type_name(&field)),
+            }),
+        };
+        result.map_err(|err| DeserializeMaskError::InvalidField {
+            field: intern(field.name()),
+            err: Box::new(err),
+        })
+    }
+
+    fn collect_paths(&self, descriptor: &MessageDescriptor, prefix: &str, paths: &mut Vec<String>) {
+        for (number, mask) in &self.0 {
+            let field = descriptor
+                .get_field(*number)
+                .expect("the mask is parsed against the descriptor");
+            let path = if prefix.is_empty() {
+                field.name().to_owned()
+            } else {
+                format!("{prefix}.{}", field.name())
+            };
+            match (mask, message_descriptor(&field)) {
+                (FieldMask::Fields(mask), Some(descriptor)) if !mask.0.is_empty() => {
+                    mask.collect_paths(&descriptor, &path, paths)
+                }
+                (FieldMask::Keys(mask), _) if !mask.is_empty() => {
+                    paths.extend(
+                        Struct::mask_field_paths(mask)
+                            .into_iter()
+                            .map(|keys| format!("{path}.{}", keys.join("."))),
+                    );
+                }
+                _ => paths.push(path),
+            }
+        }
+    }
+}
+
+/// The fields selected in a message. Masks of derived types can be full, which selects every
+/// field with its full mask. Since the full mask of a recursive message is infinite, it's
+/// represented by a variant instead.
+#[derive(Clone, Copy)]
+enum Selection<'a> {
+    Full,
+    Fields(&'a FieldsMask),
+    Keys(&'a KeyMask),
+}
+
+static EMPTY_KEYS: KeyMask = KeyMask {
+    fields: BTreeMap::new(),
+};
+
+impl<'a> Selection<'a> {
+    const EMPTY: Selection<'static> = Selection::Fields(&FieldsMask(BTreeMap::new()));
+
+    fn is_empty(self) -> bool {
+        match self {
+            Self::Full => false,
+            Self::Fields(mask) => mask.0.is_empty(),
+            Self::Keys(mask) => mask.is_empty(),
+        }
+    }
+
+    fn field(self, field: &FieldDescriptor) -> Option<Self> {
+        match self {
+            Self::Full => Some(Self::Full),
+            Self::Fields(mask) => mask.0.get(&field.number()).map(|mask| match mask {
+                FieldMask::Fields(mask) => Self::Fields(mask),
+                FieldMask::Keys(mask) => Self::Keys(mask),
+            }),
+            Self::Keys(_) => unreachable!("only messages have fields"),
+        }
+    }
+
+    /// Returns the selected keys of a `google.protobuf.Struct`. The full mask of a struct selects
+    /// it as a whole, the same as an empty one.
+    fn keys(self) -> &'a KeyMask {
+        match self {
+            Self::Keys(mask) => mask,
+            Self::Full | Self::Fields(_) => &EMPTY_KEYS,
+        }
+    }
+
+    /// Returns whether any field of `oneof` is selected, i.e. whether the mask of the oneof is not
+    /// empty.
+    fn selects_oneof(self, oneof: &OneofDescriptor) -> bool {
+        oneof.fields().any(|field| self.field(&field).is_some())
+    }
+}
+
+/// How a field is masked, matching the Rust type that prost generates for it.
+enum FieldKind {
+    /// An optional message, e.g. `Option<Child>`.
+    Message,
+    /// An optional `google.protobuf.Struct`, i.e. `Option<prost_types::Struct>`.
+    Struct,
+    /// An atomic value with presence, e.g. `Option<i32>` or `Option<prost_types::Timestamp>`.
+    Optional,
+    /// A repeated field, e.g. `Vec<i32>`.
+    List,
+    /// A map field, e.g. `HashMap<String, i32>`.
+    Map,
+    /// An atomic value without presence, e.g. `i32`.
+    Scalar,
+}
+
+fn field_kind(field: &FieldDescriptor) -> FieldKind {
+    if field.is_map() {
+        FieldKind::Map
+    } else if field.is_list() {
+        FieldKind::List
+    } else if message_descriptor(field).is_some() {
+        FieldKind::Message
+    } else if is_struct(field) {
+        FieldKind::Struct
+    } else if field.supports_presence() {
+        FieldKind::Optional
+    } else {
+        FieldKind::Scalar
+    }
+}
+
+/// Returns the descriptor of the message held by `field`, if it's a singular message field whose
+/// fields can be selected. The well-known types are atomic, as they are with the `prost` feature.
+fn message_descriptor(field: &FieldDescriptor) -> Option<MessageDescriptor> {
+    if field.is_list() || field.is_map() {
+        return None;
+    }
+    field
+        .kind()
+        .as_message()
+        .filter(|descriptor| descriptor.package_name() != "google.protobuf")
+        .cloned()
+}
+
+/// Returns whether `field` is a singular `google.protobuf.Struct` field, whose fields are selected
+/// by their keys.
+fn is_struct(field: &FieldDescriptor) -> bool {
+    !field.is_list()
+        && field
+            .kind()
+            .as_message()
+            .is_some_and(|descriptor| descriptor.full_name() == "google.protobuf.Struct")
+}
+
+/// Runs `f` on the `google.protobuf.Struct` held by `field` as a `prost_types::Struct`, so that
+/// it's masked the same way as with the `prost` feature. The field is cleared if `f` leaves `None`.
+fn with_struct(
+    message: &mut DynamicMessage,
+    field: &FieldDescriptor,
+    f: impl FnOnce(&mut Option<Struct>),
+) {
+    let mut value = message.take_field(field).map(into_struct);
+    f(&mut value);
+    if let Some(value) = value {
+        let mut inner = DynamicMessage::new(
+            field
+                .kind()
+                .as_message()
+                .expect("struct fields hold messages")
+                .clone(),
+        );
+        inner
+            .transcode_from(&value)
+            .expect("the descriptor of `google.protobuf.Struct` matches `prost_types::Struct`");
+        message.set_field(field, Value::Message(inner));
+    }
+}
+
+fn into_struct(value: Value) -> Struct {
+    into_message(value)
+        .transcode_to()
+        .expect("the descriptor of `google.protobuf.Struct` matches `prost_types::Struct`")
+}
+
+/// Returns the name of the type of an atomic field used in error messages, e.g. `int32` or
+/// `map<string, int32>`.
+fn type_name(field: &FieldDescriptor) -> String {
+    if let Some(entry) = field.kind().as_message().filter(|_| field.is_map()) {
+        format!(
+            "map<{:?}, {:?}>",
+            entry.map_entry_key_field().kind(),
+            entry.map_entry_value_field().kind(),
+        )
+    } else if field.is_list() {
+        format!("repeated {:?}", field.kind())
+    } else {
+        format!("{:?}", field.kind())
+    }
+}
+
+/// Returns `name` as a `&'static str`, as `DeserializeMaskError` needs for the names of types and
+/// fields. Each distinct name is leaked once. This is only used to report errors.
+fn intern(name: &str) -> &'static str {
+    static NAMES: RwLock<BTreeSet<&'static str>> = RwLock::new(BTreeSet::new());
+
+    if let Some(name) = NAMES.read().unwrap().get(name) {
+        return name;
+    }
+    let mut names = NAMES.write().unwrap();
+    if let Some(name) = names.get(name) {
+        return name;
+    }
+    let name = Box::leak(name.to_owned().into_boxed_str());
+    names.insert(name);
+    name
+}
+
+/// Returns whether `oneof` is the synthetic oneof of a proto3 optional field.
+fn is_synthetic(oneof: &OneofDescriptor) -> bool {
+    oneof
+        .fields()
+        .all(|field| field.field_descriptor_proto().proto3_optional())
+}
+
+/// Returns the oneofs of a message, excluding the synthetic ones of proto3 optional fields.
+fn oneofs(descriptor: &MessageDescriptor) -> Vec<OneofDescriptor> {
+    descriptor
+        .oneofs()
+        .filter(|oneof| !is_synthetic(oneof))
+        .collect()
+}
+
+/// Returns the fields of a message that are not in a oneof. Proto3 optional fields are included.
+fn plain_fields(descriptor: &MessageDescriptor) -> Vec<FieldDescriptor> {
+    descriptor
+        .fields()
+        .filter(|field| {
+            field
+                .containing_oneof()
+                .is_none_or(|oneof| is_synthetic(&oneof))
+        })
+        .collect()
+}
+
+/// Returns the field of `oneof` that is set in `message`, if any.
+fn oneof_field(message: &DynamicMessage, oneof: &OneofDescriptor) -> Option<FieldDescriptor> {
+    oneof.fields().find(|field| message.has_field(field))
+}
+
+fn is_default_message(message: &DynamicMessage) -> bool {
+    message
+        .descriptor()
+        .fields()
+        .all(|field| !message.has_field(&field))
+}
+
+fn is_default_value(field: &FieldDescriptor, value: &Value) -> bool {
+    match value {
+        Value::Message(message) => is_default_message(message),
+        value => value.is_default_for_field(field),
+    }
+}
+
+/// Sets `field` to `value`. Fields without presence are cleared instead when `value` is the
+/// default, so that equal messages compare equal.
+fn set_value(message: &mut DynamicMessage, field: &FieldDescriptor, value: Value) {
+    if !field.supports_presence() && is_default_value(field, &value) {
+        message.clear_field(field);
+    } else {
+        message.set_field(field, value);
+    }
+}
+
+/// Takes the value of `field` out of `message`. Fields without presence always have a value.
+fn take_value(message: &mut DynamicMessage, field: &FieldDescriptor) -> Option<Value> {
+    let value = message.take_field(field);
+    if value.is_none() && !field.supports_presence() {
+        return Some(Value::default_value_for_field(field));
+    }
+    value
+}
+
+fn message_mut<'a>(
+    message: &'a mut DynamicMessage,
+    field: &FieldDescriptor,
+) -> &'a mut DynamicMessage {
+    message
+        .get_field_mut(field)
+        .as_message_mut()
+        .expect("message fields hold messages")
+}
+
+fn into_message(value: Value) -> DynamicMessage {
+    match value {
+        Value::Message(message) => message,
+        _ => unreachable!("message fields hold messages"),
+    }
+}
+
+fn project_message(message: &mut DynamicMessage, mask: Selection, options: &ProjectOptions) {
+    let descriptor = message.descriptor();
+    if mask.is_empty() {
+        if options.normalize {
+            for field in plain_fields(&descriptor) {
+                project_field(message, &field, Selection::EMPTY, options);
+            }
+            for oneof in oneofs(&descriptor) {
+                if let Some(field) = oneof_field(message, &oneof) {
+                    project_variant(message, &field, Selection::EMPTY, options);
+                }
+            }
+        }
+        return;
+    }
+
+    for field in plain_fields(&descriptor) {
+        match mask.field(&field) {
+            Some(mask) => project_field(message, &field, mask, options),
+            None => message.clear_field(&field),
+        }
+    }
+    for oneof in oneofs(&descriptor) {
+        let Some(field) = oneof_field(message, &oneof) else {
+            continue;
+        };
+        match mask.field(&field) {
+            Some(mask) => project_variant(message, &field, mask, options),
+            None => message.clear_field(&field),
+        }
+    }
+}
+
+fn project_field(
+    message: &mut DynamicMessage,
+    field: &FieldDescriptor,
+    mask: Selection,
+    options: &ProjectOptions,
+) {
+    if !message.has_field(field) {
+        return;
+    }
+    match field_kind(field) {
+        // Optional messages are normalized to `None` when they are projected to the default value.
+        FieldKind::Message => {
+            let inner = message_mut(message, field);
+            project_message(inner, mask, options);
+            if is_default_message(inner) {
+                message.clear_field(field);
+            }
+        }
+        FieldKind::Struct => with_struct(message, field, |value| {
+            Struct::option_project(value, mask.keys(), options);
+        }),
+        FieldKind::Optional => {
+            if options.normalize && is_default_value(field, &message.get_field(field)) {
+                message.clear_field(field);
+            }
+        }
+        FieldKind::List | FieldKind::Map | FieldKind::Scalar => {}
+    }
+}
+
+/// Projects the value of the set field of a oneof. Unlike optional messages, a message in a oneof
+/// is kept when it's projected to the default value.
+fn project_variant(
+    message: &mut DynamicMessage,
+    field: &FieldDescriptor,
+    mask: Selection,
+    options: &ProjectOptions,
+) {
+    if message_descriptor(field).is_some() {
+        project_message(message_mut(message, field), mask, options);
+    } else if is_struct(field) {
+        with_struct(message, field, |value| {
+            if let Some(value) = value {
+                value.project(mask.keys(), options);
+            }
+        });
+    }
+}
+
+/// Updates `target` with `source` as a field of a parent message, so an empty `mask` merges them.
+fn update_message(
+    target: &mut DynamicMessage,
+    mut source: DynamicMessage,
+    mask: Selection,
+    options: &UpdateOptions,
+) {
+    if mask.is_empty() {
+        merge_message(target, source, options);
+        return;
+    }
+
+    let descriptor = target.descriptor();
+    for field in plain_fields(&descriptor) {
+        if let Some(mask) = mask.field(&field) {
+            let value = take_value(&mut source, &field);
+            update_field(target, &field, value, mask, options);
+        }
+    }
+    for oneof in oneofs(&descriptor) {
+        if mask.selects_oneof(&oneof) {
+            update_oneof(target, &mut source, &oneof, mask, options);
+        }
+    }
+}
+
+fn update_field(
+    target: &mut DynamicMessage,
+    field: &FieldDescriptor,
+    source: Option<Value>,
+    mask: Selection,
+    options: &UpdateOptions,
+) {
+    match field_kind(field) {
+        FieldKind::Message => match (target.has_field(field), source) {
+            (true, source) => {
+                let source = source.map(into_message).unwrap_or_else(|| {
+                    DynamicMessage::new(message_descriptor(field).expect("message field"))
+                });
+                update_message(message_mut(target, field), source, mask, options);
+            }
+            (false, Some(source)) => {
+                let mut source = into_message(source);
+                project_message(&mut source, mask, &Default::default());
+                target.set_field(field, Value::Message(source));
+            }
+            (false, None) => {}
+        },
+        FieldKind::Struct => {
+            let source = source.map(into_struct);
+            with_struct(target, field, |target| {
+                Struct::option_update_as_field(target, source, mask.keys(), options);
+            });
+        }
+        // Optional values are set to the default value if they are unset in `source`.
+        FieldKind::Optional => {
+            if let Some(source) = source {
+                target.set_field(field, source);
+            } else if target.has_field(field) {
+                target.set_field(field, Value::default_value_for_field(field));
+            }
+        }
+        FieldKind::List => {
+            let source = source.unwrap_or_else(|| Value::default_value_for_field(field));
+            merge_list(target, field, source, options);
+        }
+        FieldKind::Map | FieldKind::Scalar => {
+            let source = source.unwrap_or_else(|| Value::default_value_for_field(field));
+            set_value(target, field, source);
+        }
+    }
+}
+
+/// Updates a oneof whose mask is not empty. If the field set in `source` is selected, it's set in
+/// `target`. Otherwise, if the field set in `target` is selected, it's reset to the default value.
+fn update_oneof(
+    target: &mut DynamicMessage,
+    source: &mut DynamicMessage,
+    oneof: &OneofDescriptor,
+    mask: Selection,
+    options: &UpdateOptions,
+) {
+    if let Some(field) = oneof_field(source, oneof)
+        && let Some(mask) = mask.field(&field)
+    {
+        let value = source.take_field(&field).expect("the field is set");
+        if target.has_field(&field) {
+            update_variant(target, &field, value, mask, options);
+        } else {
+            target.set_field(&field, value);
+            project_variant(target, &field, mask, &Default::default());
+        }
+        return;
+    }
+
+    if let Some(field) = oneof_field(target, oneof)
+        && let Some(mask) = mask.field(&field)
+    {
+        let default = Value::default_value_for_field(&field);
+        update_variant(target, &field, default, mask, options);
+    }
+}
+
+fn update_variant(
+    target: &mut DynamicMessage,
+    field: &FieldDescriptor,
+    source: Value,
+    mask: Selection,
+    options: &UpdateOptions,
+) {
+    if message_descriptor(field).is_some() {
+        update_message(
+            message_mut(target, field),
+            into_message(source),
+            mask,
+            options,
+        );
+    } else if is_struct(field) {
+        with_struct(target, field, |target| {
+            target.get_or_insert_default().update_as_field(
+                into_struct(source),
+                mask.keys(),
+                options,
+            );
+        });
+    } else {
+        target.set_field(field, source);
+    }
+}
+
+fn merge_message(target: &mut DynamicMessage, mut source: DynamicMessage, options: &UpdateOptions) {
+    if options.replace_message {
+        *target = source;
+        return;
+    }
+
+    let descriptor = target.descriptor();
+    for field in plain_fields(&descriptor) {
+        if let Some(value) = source.take_field(&field) {
+            merge_field(target, &field, value, options);
+        }
+    }
+    for oneof in oneofs(&descriptor) {
+        let Some(field) = oneof_field(&source, &oneof) else {
+            continue;
+        };
+        let value = source.take_field(&field).expect("the field is set");
+        if target.has_field(&field) {
+            merge_variant(target, &field, value, options);
+        } else {
+            target.set_field(&field, value);
+        }
+    }
+}
+
+/// Merges `source`, the value of `field` set in the source message, into `target`.
+fn merge_field(
+    target: &mut DynamicMessage,
+    field: &FieldDescriptor,
+    source: Value,
+    options: &UpdateOptions,
+) {
+    match field_kind(field) {
+        FieldKind::Message | FieldKind::Optional if !target.has_field(field) => {
+            target.set_field(field, source);
+        }
+        FieldKind::Message => {
+            merge_message(message_mut(target, field), into_message(source), options);
+        }
+        FieldKind::Struct => with_struct(target, field, |target| {
+            Struct::option_merge(target, Some(into_struct(source)), options);
+        }),
+        FieldKind::List => merge_list(target, field, source, options),
+        // Atomic values are replaced by non-default values, e.g. a non-empty map.
+        FieldKind::Optional | FieldKind::Map | FieldKind::Scalar => {
+            if !is_default_value(field, &source) {
+                target.set_field(field, source);
+            }
+        }
+    }
+}
+
+fn merge_variant(
+    target: &mut DynamicMessage,
+    field: &FieldDescriptor,
+    source: Value,
+    options: &UpdateOptions,
+) {
+    if message_descriptor(field).is_some() {
+        merge_message(message_mut(target, field), into_message(source), options);
+    } else if is_struct(field) {
+        with_struct(target, field, |target| {
+            target
+                .get_or_insert_default()
+                .merge(into_struct(source), options);
+        });
+    } else if !is_default_value(field, &source) {
+        target.set_field(field, source);
+    }
+}
+
+/// Combines the repeated field in `source` with the one in `target`, the same way as `Vec<T>`.
+fn merge_list(
+    target: &mut DynamicMessage,
+    field: &FieldDescriptor,
+    source: Value,
+    options: &UpdateOptions,
+) {
+    let Value::List(source) = source else {
+        unreachable!("repeated fields hold lists");
+    };
+    let mut list = match target.take_field(field) {
+        Some(Value::List(list)) => list,
+        _ => Vec::new(),
+    };
+
+    if options.replace_repeated {
+        list = source;
+    } else {
+        match options.repeated_strategy {
+            RepeatedStrategy::Append | RepeatedStrategy::MergeByKey => list.extend(source),
+            RepeatedStrategy::DedupAppend => {
+                for item in source {
+                    if !list.contains(&item) {
+                        list.push(item);
+                    }
+                }
+            }
+            RepeatedStrategy::Prepend => {
+                list.splice(0..0, source);
+            }
+        }
+    }
+
+    set_value(target, field, Value::List(list));
+}
@@ -24,7 +24,8 @@
 //!     one_of_field: Option<OneOfField>,
 //!
 //!     // You can use an enum to represent oneof fields from a protobuf message.
-//!     // Each variant in the enum must be tuple variant with a single inner type.
+//!     // Each variant in the enum can be a unit variant, a tuple variant with a single inner type,
+//!     // or a struct-like variant.
 //!     //
 //!     // If you flatten it, the behavior will be exactly the same as the protobuf message.
 //!     #[fieldmask(flatten)]
@@ -67,63 +68,58 @@
 //!     Two = 2,
 //! }
 //!
-//! mod project {
-//!     use super::*;
-//!
-//!     #[test]
-//!     fn case_1() {
-//!         let source = Parent {
-//!             primitive: "string".into(),
-//!
-//!             child: Child {
-//!                 field_one: "child field one".into(),
-//!                 field_two: 1,
-//!             },
-//!             flatten_child: Child {
-//!                 field_one: "flatten child field one".into(),
-//!                 field_two: 2,
-//!             },
-//!
-//!             one_of_field: Some(OneOfField::VariantOne("variant one".into())),
-//!             flatten_one_of_field: Some(OneOfField::VariantTwo(3)),
-//!
-//!             unit_enum: Some(UnitEnum::Two),
-//!             unit_enum_with_default: UnitEnumWithDefault::Two,
-//!         };
-//!         let mask = vec![
-//!             "primitive",
-//!             "child.field_two",
-//!             "field_one",
-//!             "one_of_field.variant_one",
-//!             "variant_two",
-//!             "unit_enum",
-//!             "unit_enum_with_default",
-//!         ];
-//!         let expected = Parent {
-//!             primitive: "string".into(),
-//!
-//!             child: Child {
-//!                 field_one: Default::default(),
-//!                 field_two: 1,
-//!             },
-//!             flatten_child: Child {
-//!                 field_one: "flatten child field one".into(),
-//!                 field_two: Default::default(),
-//!             },
-//!
-//!             one_of_field: Some(OneOfField::VariantOne("variant one".into())),
-//!             flatten_one_of_field: Some(OneOfField::VariantTwo(3)),
-//!
-//!             unit_enum: Some(UnitEnum::Two),
-//!             unit_enum_with_default: UnitEnumWithDefault::Two,
-//!         };
-//!
-//!         let actual = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
-//!             .expect("unable to deserialize mask")
-//!             .project(source);
-//!
-//!         assert_eq!(actual, expected);
-//!     }
+//! fn main() {
+//!     let source = Parent {
+//!         primitive: "string".into(),
+//!
+//!         child: Child {
+//!             field_one: "child field one".into(),
+//!             field_two: 1,
+//!         },
+//!         flatten_child: Child {
+//!             field_one: "flatten child field one".into(),
+//!             field_two: 2,
+//!         },
+//!
+//!         one_of_field: Some(OneOfField::VariantOne("variant one".into())),
+//!         flatten_one_of_field: Some(OneOfField::VariantTwo(3)),
+//!
+//!         unit_enum: Some(UnitEnum::Two),
+//!         unit_enum_with_default: UnitEnumWithDefault::Two,
+//!     };
+//!     let mask = vec![
+//!         "primitive",
+//!         "child.field_two",
+//!         "field_one",
+//!         "one_of_field.variant_one",
+//!         "variant_two",
+//!         "unit_enum",
+//!         "unit_enum_with_default",
+//!     ];
+//!     let expected = Parent {
+//!         primitive: "string".into(),
+//!
+//!         child: Child {
+//!             field_one: Default::default(),
+//!             field_two: 1,
+//!         },
+//!         flatten_child: Child {
+//!             field_one: "flatten child field one".into(),
+//!             field_two: Default::default(),
+//!         },
+//!
+//!         one_of_field: Some(OneOfField::VariantOne("variant one".into())),
+//!         flatten_one_of_field: Some(OneOfField::VariantTwo(3)),
+//!
+//!         unit_enum: Some(UnitEnum::Two),
+//!         unit_enum_with_default: UnitEnumWithDefault::Two,
+//!     };
+//!
+//!     let actual = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+//!         .expect("unable to deserialize mask")
+//!         .project(source);
+//!
+//!     assert_eq!(actual, expected);
 //! }
 //! ```
 
@@ -131,12 +127,24 @@
 // Without this, `::fieldmask::*` generated by fieldmask_derive will not work.
 extern crate self as fieldmask;
 
+#[cfg(feature = "prost-reflect")]
+mod dynamic;
 mod mask;
 mod maskable;
+mod masked_hash;
+mod visit;
 
-pub use fieldmask_derive::{Maskable, OptionMaskable, SelfMaskable, maskable_atomic};
+pub use fieldmask_derive::{
+    Maskable, MaskedHash, OptionMaskable, OptionMaskedHash, SelfMaskable, maskable_atomic,
+};
 
+#[cfg(feature = "prost-reflect")]
+pub use dynamic::{DynamicMask, merge_dynamic};
 pub use mask::{Mask, MaskInput};
 pub use maskable::{
-    DeserializeMaskError, Maskable, OptionMaskable, ProjectOptions, SelfMaskable, UpdateOptions,
+    DeserializeMaskError, FieldBehavior, FieldBehaviorPolicy, Flattenable, KeyMask, KeyedRepeated,
+    Maskable, OptionMaskable, ProjectOptions, RepeatedStrategy, SelfMaskable, UpdateOptions,
+    ValidationError, assert_unique_field_names, assert_unique_field_names_once, merge_by_key,
 };
+pub use masked_hash::{KeyedMaskedHash, MaskedHash, OptionMaskedHash};
+pub use visit::{FieldRef, MaskVisitor};
@@ -1,8 +1,11 @@
-use std::convert::TryFrom;
+use std::{borrow::Cow, convert::TryFrom, hash::Hasher};
 
 use derive_more::{Deref, DerefMut};
 
-use crate::{DeserializeMaskError, Maskable, ProjectOptions, SelfMaskable, UpdateOptions};
+use crate::{
+    DeserializeMaskError, FieldBehavior, FieldBehaviorPolicy, MaskVisitor, Maskable, MaskedHash,
+    ProjectOptions, SelfMaskable, UpdateOptions, ValidationError,
+};
 
 /// A convenient wrapper around a mask value.
 /// Allows us to
@@ -12,6 +15,11 @@ use crate::{DeserializeMaskError, Maskable, ProjectOptions, SelfMaskable, Update
 pub struct Mask<T: Maskable>(T::Mask);
 
 impl<T: Maskable> Mask<T> {
+    /// Wraps a mask value of `T`.
+    pub fn new(mask: T::Mask) -> Self {
+        Self(mask)
+    }
+
     /// Returns an empty mask that selects no field.
     ///
     /// For atomic types, the empty mask is the same as the full mask.
@@ -38,9 +46,141 @@ impl<T: Maskable> Mask<T> {
     ) -> Result<(), DeserializeMaskError<'a>> {
         T::make_mask_include_field(&mut self.0, field_path)
     }
+
+    /// Returns the field paths selected by the mask, e.g. `["parent.child", "other"]`.
+    ///
+    /// Parsing the returned paths with `MaskInput` gives back the same mask.
+    pub fn paths(&self) -> Vec<String> {
+        T::mask_field_paths(&self.0)
+            .into_iter()
+            .map(|path| path.join("."))
+            .collect()
+    }
+
+    /// Check that the field mask can be used to update a value with `options`. Call this after
+    /// parsing an update mask to reject invalid masks early.
+    ///
+    /// With `FieldBehaviorPolicy::Error`, a mask that selects an output-only field (or a field
+    /// nested in one) is rejected. Immutable fields can only be checked against the value being
+    /// updated, see `try_update`.
+    pub fn check_update(&self, options: &UpdateOptions) -> Result<(), ValidationError> {
+        if options.field_behavior_policy != FieldBehaviorPolicy::Error {
+            return Ok(());
+        }
+        for path in T::mask_field_paths(&self.0) {
+            if matches!(
+                restricted_behavior::<T>(&path),
+                Some((FieldBehavior::OutputOnly, _))
+            ) {
+                return Err(field_behavior_error(
+                    path.join("."),
+                    FieldBehavior::OutputOnly,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the mask of the fields permitted by `allowed`, a list of tags, e.g. the roles of the
+    /// caller. Returns `None` if no field is permitted.
+    ///
+    /// A field is permitted if all of its tags, declared with `#[fieldmask(tags("..."))]`, are in
+    /// `allowed`; untagged fields are always permitted. A message field is selected as a whole if
+    /// all of its nested fields are permitted, and narrowed down to the permitted ones otherwise.
+    pub fn for_tags(allowed: &[&str]) -> Option<Self> {
+        let mut mask = Self::empty();
+        // Types without field, e.g. unit enums, are selected by their empty masks.
+        if T::make_mask_for_tags(&mut mask.0, allowed, &mut Vec::new())
+            || T::field_names().is_empty()
+        {
+            Some(mask)
+        } else {
+            None
+        }
+    }
+
+    /// Intersect the field mask, e.g. a read or update mask requested by a client, with `policy`,
+    /// e.g. a mask returned by `for_tags`. Returns the mask of the permitted fields, or `None` if
+    /// none of them is, along with the requested paths that are not fully permitted.
+    ///
+    /// A requested path whose nested fields are only partly permitted is narrowed down to the
+    /// permitted ones and reported as denied. An empty field mask is treated as a full mask, and
+    /// so is an empty `policy`.
+    pub fn restrict(&self, policy: &Self) -> (Option<Self>, Vec<String>) {
+        let requested = if self == &Self::empty() {
+            // Not `full_mask`, which doesn't terminate for recursive types.
+            T::field_names()
+                .into_iter()
+                .map(|name| vec![Cow::Borrowed(name)])
+                .collect()
+        } else {
+            T::mask_field_paths(&self.0)
+        };
+        if requested.is_empty() {
+            // Atomic types have no field to deny.
+            return (Some(Self::empty()), Vec::new());
+        }
+        let permitted = if policy == &Self::empty() {
+            requested.clone()
+        } else {
+            T::mask_field_paths(&policy.0)
+        };
+
+        let mut mask = Self::empty();
+        let mut denied = Vec::new();
+        for path in &requested {
+            let narrowed = intersect_paths(std::slice::from_ref(path), &permitted);
+            if narrowed != [path.clone()] {
+                denied.push(path.join("."));
+            }
+            for path in narrowed {
+                mask.include_field(&borrowed_path(&path))
+                    .expect("paths returned by `mask_field_paths` are valid");
+            }
+        }
+        let mask = if mask == Self::empty() {
+            None
+        } else {
+            Some(mask)
+        };
+        (mask, denied)
+    }
+
+    /// Returns a mask that selects the field specified by a path returned by
+    /// `Maskable::mask_field_paths`.
+    fn from_field_path(field_path: &[Cow<'static, str>]) -> Self {
+        let mut mask = Self::empty();
+        mask.include_field(&borrowed_path(field_path))
+            .expect("paths returned by `mask_field_paths` are valid");
+        mask
+    }
+
+    /// Returns a mask that selects the fields specified by paths returned by
+    /// `Maskable::mask_field_paths`.
+    fn from_field_paths(field_paths: &[Vec<Cow<'static, str>>]) -> Self {
+        let mut mask = Self::empty();
+        for field_path in field_paths {
+            mask.include_field(&borrowed_path(field_path))
+                .expect("paths returned by `mask_field_paths` are valid");
+        }
+        mask
+    }
 }
 
 impl<T: SelfMaskable> Mask<T> {
+    /// Returns the mask that selects every field whose value differs between `old` and `new`.
+    ///
+    /// Nested messages and oneof fields are compared field by field, while atomic values (e.g.
+    /// `String`, `Vec` and `HashMap`) are compared as a whole. The mask is empty if the values are
+    /// equal.
+    ///
+    /// Updating `old` with `new` using the mask and `UpdateOptions::replace_repeated` gives a value
+    /// equivalent to `new`, except that a oneof field cleared in `new` keeps its variant, with the
+    /// fields of the variant reset to their default values.
+    pub fn diff(old: &T, new: &T) -> Self {
+        Self(old.diff(new).unwrap_or_else(T::empty_mask))
+    }
+
     /// Project the fields of `source` according to the field mask.
     ///
     /// An empty field mask is treated as a full mask.
@@ -53,21 +193,320 @@ impl<T: SelfMaskable> Mask<T> {
         source
     }
 
-    /// Update the fields of `target` with the fields of `source` according to the field mask.
+    /// Project the fields of `source` according to the field mask without consuming it. Only the
+    /// selected fields are cloned.
+    ///
+    /// An empty field mask is treated as a full mask.
+    pub fn project_ref(&self, source: &T) -> T {
+        self.project_ref_with_options(source, &Default::default())
+    }
+
+    /// The same as `project_ref`, but with additional options.
+    pub fn project_ref_with_options(&self, source: &T, options: &ProjectOptions) -> T {
+        source.project_ref(self, options)
+    }
+
+    /// Reset the fields of `target` selected by the field mask to their default values, leaving
+    /// the other fields unchanged. Optional messages and oneofs are reset to `None`.
+    ///
+    /// An empty field mask is treated as a full mask, which resets `target` as a whole.
+    pub fn clear(&self, target: &mut T) {
+        self.clear_with_options(target, &Default::default());
+    }
+
+    /// The same as `clear`, but with additional options.
+    pub fn clear_with_options(&self, target: &mut T, options: &ProjectOptions) {
+        target.clear(self, options);
+    }
+
+    /// Redact the fields of `target` selected by the field mask. Fields declared with
+    /// `#[fieldmask(redact_with = ...)]` are replaced by their placeholders, and the other selected
+    /// fields are reset to their default values, as `clear` does.
+    ///
+    /// An empty field mask is treated as a full mask.
+    pub fn redact(&self, target: &mut T) {
+        target.redact(self);
+    }
+
+    /// The same as `project_ref`, but also returns the mask of the fields that were hidden, i.e.
+    /// the fields whose values in the projection differ from the ones in `source`.
+    ///
+    /// Fields that are not selected but already empty are not included.
+    pub fn project_reporting(&self, source: &T) -> (T, Self) {
+        self.project_reporting_with_options(source, &Default::default())
+    }
+
+    /// The same as `project_reporting`, but with additional options.
+    ///
+    /// With `ProjectOptions::redact`, fields whose values are equal to their placeholders are not
+    /// included.
+    pub fn project_reporting_with_options(
+        &self,
+        source: &T,
+        options: &ProjectOptions,
+    ) -> (T, Self) {
+        let projected = self.project_ref_with_options(source, options);
+        let hidden = Self::diff(source, &projected);
+        (projected, hidden)
+    }
+
+    /// Compare the fields of `a` and `b` selected by the field mask, without cloning or projecting
+    /// them.
+    ///
+    /// An empty field mask is treated as a full mask.
+    pub fn eq_masked(&self, a: &T, b: &T) -> bool {
+        a.eq_masked(b, self)
+    }
+
+    /// Visit the leaf fields of `value` selected by the field mask with `visitor`, along with
+    /// their paths.
+    ///
+    /// An empty field mask is treated as a full mask. Optional values that are `None` and oneof
+    /// variants that are not set are skipped.
+    pub fn visit<V: MaskVisitor>(&self, value: &T, visitor: &mut V) {
+        value.visit(self, &mut Vec::new(), visitor);
+    }
+
+    /// Check that the required fields of `value` selected by the field mask, declared with
+    /// `#[fieldmask(required)]`, are set to non-default values. Returns the paths of all the
+    /// missing fields otherwise.
+    ///
+    /// An empty field mask is treated as a full mask. Required fields nested in optional values
+    /// that are `None` or in oneof variants that are not set are not checked.
+    pub fn check_required(&self, value: &T) -> Result<(), Vec<String>> {
+        let mut missing = Vec::new();
+        value.check_required(self, &mut Vec::new(), &mut missing);
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(missing)
+        }
+    }
+
+    /// Run the validators of the fields of `value` selected by the field mask, declared with
+    /// `#[fieldmask(validate = path)]`. Returns the error of the first failing field.
     ///
     /// An empty field mask is treated as a full mask.
+    pub fn validate(&self, value: &T) -> Result<(), ValidationError> {
+        value.validate(self, &mut Vec::new())
+    }
+
+    /// Update the fields of `target` with the fields of `source` according to the field mask.
+    ///
+    /// An empty field mask is treated as a full mask. Output-only fields and immutable fields that
+    /// are already set are left unchanged, see `UpdateOptions::field_behavior_policy`.
     pub fn update(&self, target: &mut T, source: T) {
         self.update_with_options(target, source, &Default::default());
     }
 
     /// The same as `update`, but with additional options.
-    pub fn update_with_options(&self, target: &mut T, source: T, options: &UpdateOptions) {
+    pub fn update_with_options(&self, target: &mut T, mut source: T, options: &UpdateOptions) {
+        if options.field_behavior_policy != FieldBehaviorPolicy::Allow {
+            source.clear_output_only();
+        }
         if self == &Self::empty() {
             target.update_as_field(source, &Self::full(), options);
             return;
         }
         target.update_as_field(source, self, options);
     }
+
+    /// The same as `update`, but returns the mask of the fields whose values in `target` were
+    /// actually modified.
+    ///
+    /// Fields that are selected but already equal to the value in `source` are not included.
+    pub fn update_reporting(&self, target: &mut T, source: T) -> Self {
+        self.update_reporting_with_options(target, source, &Default::default())
+    }
+
+    /// The same as `update_reporting`, but with additional options.
+    ///
+    /// Appending to a repeated field counts as a change, unless nothing is appended.
+    pub fn update_reporting_with_options(
+        &self,
+        target: &mut T,
+        mut source: T,
+        options: &UpdateOptions,
+    ) -> Self {
+        if options.field_behavior_policy != FieldBehaviorPolicy::Allow {
+            source.clear_output_only();
+        }
+        let changed = if self == &Self::empty() {
+            target.update_as_field_reporting(source, &Self::full(), options)
+        } else {
+            target.update_as_field_reporting(source, self, options)
+        };
+        Self(changed.unwrap_or_else(T::empty_mask))
+    }
+
+    /// The same as `update`, but runs the validators of the selected fields on the updated value
+    /// before committing it. See `validate`.
+    ///
+    /// With `FieldBehaviorPolicy::Error`, it also fails if the mask selects an output-only field,
+    /// or if the update would change an output-only field or an immutable field that is already
+    /// set. With `UpdateOptions::check_required`, it also fails if a selected required field is not
+    /// set after the update. On error, `target` is left unchanged.
+    pub fn try_update(&self, target: &mut T, source: T) -> Result<(), ValidationError> {
+        self.try_update_with_options(target, source, &Default::default())
+    }
+
+    /// The same as `try_update`, but with additional options.
+    ///
+    /// The checks run before `target` is touched, on the selected values of `source` merged into
+    /// a copy of the top-level fields of `target` that the mask selects, made with `project_ref`.
+    /// The copy is needed since a message selected as a whole may be merged and a repeated field
+    /// appended to, so the updated values aren't known from `source` alone, and the validator of a
+    /// message field sees all of its nested fields. The other fields of `target` are never copied.
+    pub fn try_update_with_options(
+        &self,
+        target: &mut T,
+        source: T,
+        options: &UpdateOptions,
+    ) -> Result<(), ValidationError> {
+        let mut top_level = Self::empty();
+        for path in T::mask_field_paths(&self.0) {
+            top_level
+                .include_field(&borrowed_path(&path[..1]))
+                .expect("paths returned by `mask_field_paths` are valid");
+        }
+        let mut updated = top_level.project_ref(target);
+        let selected_source = self.project_ref(&source);
+        if options.field_behavior_policy == FieldBehaviorPolicy::Error {
+            self.check_update(options)?;
+            // Update every field, and fail if the update modified a restricted field. Otherwise,
+            // the result is the same as leaving the restricted fields unchanged.
+            let unrestricted_options = UpdateOptions {
+                field_behavior_policy: FieldBehaviorPolicy::Allow,
+                ..*options
+            };
+            let changed = self.update_reporting_with_options(
+                &mut updated,
+                selected_source,
+                &unrestricted_options,
+            );
+            let restricted = T::mask_field_paths(&changed.0)
+                .into_iter()
+                .find_map(|path| {
+                    let (behavior, len) = restricted_behavior::<T>(&path)?;
+                    // An immutable field can be set as long as it's unset.
+                    if behavior == FieldBehavior::Immutable && !is_set(target, &path[..len]) {
+                        return None;
+                    }
+                    Some((behavior, path))
+                });
+            if let Some((behavior, path)) = restricted {
+                return Err(field_behavior_error(path.join("."), behavior));
+            }
+        } else {
+            self.update_with_options(&mut updated, selected_source, options);
+        }
+        if options.check_required
+            && let Err(missing) = self.check_required(&updated)
+        {
+            return Err(field_behavior_error(
+                missing
+                    .into_iter()
+                    .next()
+                    .expect("missing paths are not empty"),
+                FieldBehavior::Required,
+            ));
+        }
+        self.validate(&updated)?;
+        self.update_with_options(target, source, options);
+        Ok(())
+    }
+
+    /// Apply the changes made in `theirs` relative to `base`, in the fields selected by the field
+    /// mask, onto `ours`. Returns the paths of the fields where `ours` and `theirs` both changed
+    /// relative to `base`, but differently.
+    ///
+    /// Conflicting fields keep the values in `ours`. Repeated fields changed in `theirs` are
+    /// replaced rather than appended to, since they already contain the elements in `base`.
+    ///
+    /// An empty field mask is treated as a full mask.
+    pub fn three_way_update(&self, base: &T, ours: &mut T, theirs: T) -> Vec<String> {
+        self.three_way_update_with_options(
+            base,
+            ours,
+            theirs,
+            &UpdateOptions::builder().replace_repeated(true).build(),
+        )
+    }
+
+    /// The same as `three_way_update`, but with additional options.
+    ///
+    /// The options are used to apply the changes in `theirs`.
+    pub fn three_way_update_with_options(
+        &self,
+        base: &T,
+        ours: &mut T,
+        theirs: T,
+        options: &UpdateOptions,
+    ) -> Vec<String> {
+        let mut their_paths = T::mask_field_paths(&Self::diff(base, &theirs).0);
+        if self != &Self::empty() {
+            their_paths = intersect_paths(&their_paths, &T::mask_field_paths(self));
+        }
+        let our_mask = Self::diff(base, ours);
+        let our_paths = T::mask_field_paths(&our_mask.0);
+
+        // Apply all the changes in `theirs` to a copy of the fields changed in `ours`, and find the
+        // ones it modifies. A change in `theirs` conflicts if its path intersects one of them.
+        let mut conflicting = vec![false; their_paths.len()];
+        if !our_paths.is_empty() && !their_paths.is_empty() {
+            let their_mask = Self::from_field_paths(&their_paths);
+            let mut merged = our_mask.project_ref(ours);
+            their_mask.update_with_options(&mut merged, their_mask.project_ref(&theirs), options);
+            for path in &our_paths {
+                if Self::from_field_path(path).eq_masked(&merged, ours) {
+                    continue;
+                }
+                let mut intersecting: Vec<_> = (0..their_paths.len())
+                    .filter(|&i| {
+                        their_paths[i].starts_with(path) || path.starts_with(&their_paths[i])
+                    })
+                    .collect();
+                // Otherwise, `theirs` switched a oneof away from the variant changed in `ours`.
+                // The changes in the other variant are the ones that share the longest prefix.
+                if intersecting.is_empty() {
+                    let prefix_len = |other: &[Cow<'static, str>]| {
+                        path.iter().zip(other).take_while(|(a, b)| a == b).count()
+                    };
+                    let longest = their_paths.iter().map(|other| prefix_len(other)).max();
+                    intersecting = (0..their_paths.len())
+                        .filter(|&i| Some(prefix_len(&their_paths[i])) == longest)
+                        .collect();
+                }
+                for i in intersecting {
+                    conflicting[i] = true;
+                }
+            }
+        }
+
+        let mut conflicts = Vec::new();
+        let mut applied = Vec::new();
+        for (path, conflicting) in their_paths.into_iter().zip(conflicting) {
+            if conflicting {
+                conflicts.push(path.join("."));
+            } else {
+                applied.push(path);
+            }
+        }
+        if !applied.is_empty() {
+            Self::from_field_paths(&applied).update_with_options(ours, theirs, options);
+        }
+        conflicts
+    }
+}
+
+impl<T: MaskedHash> Mask<T> {
+    /// Feed the fields of `value` selected by the field mask into `state`.
+    ///
+    /// Values that are equal according to `eq_masked` have the same hash. An empty field mask is
+    /// treated as a full mask.
+    pub fn hash_masked<H: Hasher>(&self, value: &T, state: &mut H) {
+        value.hash_masked(self, state);
+    }
 }
 
 impl<T> std::fmt::Debug for Mask<T>
@@ -86,6 +525,70 @@ impl<T: Maskable> PartialEq for Mask<T> {
     }
 }
 
+/// Returns the behavior that restricts updating the field at `field_path`, which can be inherited
+/// from a parent field, along with the length of the path of the field that declares it.
+fn restricted_behavior<T: Maskable>(
+    field_path: &[Cow<'static, str>],
+) -> Option<(FieldBehavior, usize)> {
+    let field_path = borrowed_path(field_path);
+    (1..=field_path.len()).find_map(|len| {
+        let behaviors = T::field_behaviors(&field_path[..len]);
+        [FieldBehavior::OutputOnly, FieldBehavior::Immutable]
+            .into_iter()
+            .find(|behavior| behaviors.contains(behavior))
+            .map(|behavior| (behavior, len))
+    })
+}
+
+/// Returns whether the field of `value` at `field_path` is set, i.e. has a non-default value.
+fn is_set<T: SelfMaskable>(value: &T, field_path: &[Cow<'static, str>]) -> bool {
+    let mask = Mask::<T>::from_field_path(field_path);
+    let mut cleared = mask.project_ref(value);
+    mask.clear(&mut cleared);
+    !mask.eq_masked(value, &cleared)
+}
+
+fn field_behavior_error(path: String, behavior: FieldBehavior) -> ValidationError {
+    let message = match behavior {
+        FieldBehavior::OutputOnly => "the field is output only",
+        FieldBehavior::Immutable => "the field is immutable",
+        FieldBehavior::Required => "the field is required",
+    };
+    ValidationError {
+        path,
+        message: message.into(),
+    }
+}
+
+/// Returns the paths that are selected by both `paths` and `other`. A path that selects the parent
+/// of a path in the other list is narrowed down to the latter.
+fn intersect_paths(
+    paths: &[Vec<Cow<'static, str>>],
+    other: &[Vec<Cow<'static, str>>],
+) -> Vec<Vec<Cow<'static, str>>> {
+    let mut result = Vec::new();
+    for path in paths {
+        for other in other {
+            let narrowed = if other.starts_with(path) {
+                other
+            } else if path.starts_with(other) {
+                path
+            } else {
+                continue;
+            };
+            if !result.contains(narrowed) {
+                result.push(narrowed.clone());
+            }
+        }
+    }
+    result
+}
+
+/// Borrows the components of a path returned by `Maskable::mask_field_paths`.
+fn borrowed_path<'a>(field_path: &'a [Cow<'static, str>]) -> Vec<&'a str> {
+    field_path.iter().map(AsRef::as_ref).collect()
+}
+
 pub struct MaskInput<T>(pub T);
 
 impl<'a, I, T> TryFrom<MaskInput<I>> for Mask<T>
@@ -1,10 +1,19 @@
-use std::{collections::HashMap, mem};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    hash::Hash,
+    mem,
+    rc::Rc,
+    sync::{Arc, RwLock},
+};
 
 use fieldmask_derive::maskable_atomic;
 use textwrap::indent;
 use thiserror::Error;
 use typed_builder::TypedBuilder;
 
+use crate::MaskVisitor;
+
 #[derive(Debug, Error)]
 pub enum DeserializeMaskError<'a> {
     #[error("type `{type_name}` has no field named \"{field}\"")]
@@ -19,6 +28,16 @@ pub enum DeserializeMaskError<'a> {
     },
 }
 
+/// An error returned by a field validator, e.g. when calling `Mask::try_update`.
+#[derive(Debug, Error, PartialEq)]
+#[error("invalid value for field \"{path}\": {message}")]
+pub struct ValidationError {
+    /// The path of the invalid field, joined by '.'.
+    pub path: String,
+    /// The message of the error returned by the validator.
+    pub message: String,
+}
+
 /// Options for projecting a message with a field mask.
 #[derive(TypedBuilder, Debug, Default)]
 #[non_exhaustive]
@@ -30,6 +49,14 @@ pub struct ProjectOptions {
     /// Defaults to `false`.
     #[builder(default = false)]
     pub normalize: bool,
+
+    /// If true, the fields that are not selected are redacted instead of being reset to their
+    /// default values, see `SelfMaskable::redact`. This lets readers tell hidden fields from empty
+    /// ones when the hidden fields have placeholders.
+    ///
+    /// Defaults to `false`.
+    #[builder(default = false)]
+    pub redact: bool,
 }
 
 /// Options for updating a message with a field mask.
@@ -46,6 +73,13 @@ pub struct UpdateOptions {
     #[builder(default = false)]
     pub replace_repeated: bool,
 
+    /// Controls how a repeated field in `source` is combined with the one in `self`, when
+    /// `replace_repeated` is false.
+    ///
+    /// Defaults to `RepeatedStrategy::Append`.
+    #[builder(default)]
+    pub repeated_strategy: RepeatedStrategy,
+
     /// Controls the behavior of updating the value of a message field in `self` with the same value
     /// of the same field in `source` when the message field is specified in the last position of
     /// the field mask.
@@ -59,6 +93,90 @@ pub struct UpdateOptions {
     /// [1]: https://protobuf.dev/reference/java/api-docs/com/google/protobuf/FieldMask.html
     #[builder(default = false)]
     pub replace_message: bool,
+
+    /// Controls how fields marked with `#[fieldmask(output_only)]` or `#[fieldmask(immutable)]` are
+    /// treated.
+    ///
+    /// Defaults to `FieldBehaviorPolicy::Drop`.
+    #[builder(default)]
+    pub field_behavior_policy: FieldBehaviorPolicy,
+
+    /// If true, fallible updates (e.g. `Mask::try_update`) fail when a field marked with
+    /// `#[fieldmask(required)]` and selected by the mask has its default value after the update.
+    ///
+    /// Defaults to `false`.
+    #[builder(default = false)]
+    pub check_required: bool,
+}
+
+impl UpdateOptions {
+    /// Returns a copy of the options with `replace_repeated` set to `replace_repeated`.
+    ///
+    /// This is used to update the fields declared with `#[fieldmask(replace_repeated = ...)]`.
+    pub fn with_replace_repeated(&self, replace_repeated: bool) -> Self {
+        Self {
+            replace_repeated,
+            ..*self
+        }
+    }
+
+    /// Returns a copy of the options with `replace_message` set to `replace_message`.
+    ///
+    /// This is used to update the fields declared with `#[fieldmask(replace_message = ...)]`.
+    pub fn with_replace_message(&self, replace_message: bool) -> Self {
+        Self {
+            replace_message,
+            ..*self
+        }
+    }
+}
+
+/// The behavior of a field declared with a field attribute, following [AIP-203][1].
+///
+/// [1]: https://google.aip.dev/203
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum FieldBehavior {
+    /// The field is set by the server and cannot be updated by clients. Declared with
+    /// `#[fieldmask(output_only)]`.
+    OutputOnly,
+    /// The field cannot be changed once it's set. Declared with `#[fieldmask(immutable)]`.
+    Immutable,
+    /// The field must be set to a non-default value. Declared with `#[fieldmask(required)]`.
+    Required,
+}
+
+/// Controls how an update combines a repeated field in the source with the one in the target.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum RepeatedStrategy {
+    /// Append the elements in the source to the target.
+    #[default]
+    Append,
+    /// Append the elements in the source that are not in the target yet, like a set union.
+    DedupAppend,
+    /// Insert the elements in the source before the ones in the target.
+    Prepend,
+    /// Update each element in the target with the element in the source with the same key,
+    /// according to the mask of the elements, and append the elements without a match. The key is
+    /// declared on the repeated field with `#[fieldmask(key = "...")]`; fields without a key
+    /// append.
+    MergeByKey,
+}
+
+/// Controls how an update treats output-only fields and immutable fields that are already set.
+/// These restrictions also apply to the fields nested in such fields.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum FieldBehaviorPolicy {
+    /// Silently leave the restricted fields unchanged, while updating the other fields.
+    #[default]
+    Drop,
+    /// Fail the update if it selects an output-only field, or if it would change a restricted
+    /// field. Only fallible operations (e.g. `Mask::try_update`) can report the error; the other
+    /// ones drop the restricted fields instead.
+    Error,
+    /// Update the restricted fields like any other field, e.g. when the server sets them itself.
+    Allow,
 }
 
 /// A trait for types that have an associated field mask type.
@@ -106,6 +224,56 @@ pub trait Maskable {
         // 2. It's easier to distinguish empty field mask (e.g. "") and empty tail (e.g. "parent.").
         field_path: &[&'a str],
     ) -> Result<(), DeserializeMaskError<'a>>;
+
+    /// Returns the names of the fields that can appear in the first segment of a field path.
+    ///
+    /// Fields of flattened children are included in place of the flattened field. Atomic types
+    /// have no field.
+    fn field_names() -> Vec<&'static str> {
+        Vec::new()
+    }
+
+    /// Returns the field paths selected by `mask`, each one splitted by '.'.
+    ///
+    /// This is the inverse of `make_mask_include_field`. A field selected with an empty sub-mask is
+    /// returned as a single path. Atomic types have no field, so their masks have no path. The
+    /// keys selected by a `KeyMask` are returned as owned path components.
+    fn mask_field_paths(_mask: &Self::Mask) -> Vec<Vec<Cow<'static, str>>> {
+        Vec::new()
+    }
+
+    /// Returns the behaviors declared on the field at `field_path`, a field mask path splitted by
+    /// '.'.
+    ///
+    /// The behaviors of the parent fields are not included. Atomic types have no field, so they
+    /// have no behavior.
+    fn field_behaviors(_field_path: &[&str]) -> Vec<FieldBehavior> {
+        Vec::new()
+    }
+
+    /// Returns whether every field of the type, including nested ones, is permitted by `allowed`.
+    /// A field is permitted if all of its tags are in `allowed`; untagged fields are always
+    /// permitted.
+    ///
+    /// `stack` holds the names of the types being checked, so that recursive types terminate.
+    /// Atomic types have no field, so they are always permitted.
+    fn tags_permit_all(_allowed: &[&str], _stack: &mut Vec<&'static str>) -> bool {
+        true
+    }
+
+    /// Make `mask` select the fields permitted by `allowed`, each fully permitted field as a whole.
+    /// Returns `false` if no field can be selected, in which case `mask` must be discarded.
+    ///
+    /// `stack` holds the names of the types being built. Since the mask of a recursive type is
+    /// finite only if it stops at a fully permitted field, a type already in `stack` selects no
+    /// field. Atomic types are selected by their empty masks.
+    fn make_mask_for_tags(
+        _mask: &mut Self::Mask,
+        _allowed: &[&str],
+        _stack: &mut Vec<&'static str>,
+    ) -> bool {
+        true
+    }
 }
 
 /// A trait for types that can be projected or updated according to a field mask.
@@ -118,6 +286,18 @@ pub trait SelfMaskable: Maskable {
     /// [1]: https://protobuf.dev/reference/protobuf/google.protobuf/#field-mask.
     fn project(&mut self, mask: &Self::Mask, options: &ProjectOptions);
 
+    /// The same as `project`, but returns the projection of `self` instead of modifying it.
+    ///
+    /// Only the selected fields are cloned.
+    fn project_ref(&self, mask: &Self::Mask, options: &ProjectOptions) -> Self;
+
+    /// Reset the fields of `self` selected by `mask` to their default values. This is the inverse
+    /// of `project`.
+    ///
+    /// An empty `mask` is the same as a full `mask`, which resets `self` as a whole. If
+    /// `options.normalize` is set, the fields that are not selected are normalized.
+    fn clear(&mut self, mask: &Self::Mask, options: &ProjectOptions);
+
     /// Update the fields of `self` with the fields of `source` according to `mask`.
     ///
     /// This message is treated as a field of the parent message, which means an empty `mask` is not
@@ -126,6 +306,98 @@ pub trait SelfMaskable: Maskable {
 
     /// Merge the fields of `source` into `self`.
     fn merge(&mut self, source: Self, options: &UpdateOptions);
+
+    /// The same as `update_as_field`, but returns the mask of the fields of `self` that were
+    /// actually modified, or `None` if `self` is unchanged. A returned empty mask means `self` was
+    /// modified as a whole.
+    ///
+    /// The default implementation compares a copy of `self` taken before the update with the
+    /// result. Types that can tell which fields they modify while updating them override it.
+    fn update_as_field_reporting(
+        &mut self,
+        source: Self,
+        mask: &Self::Mask,
+        options: &UpdateOptions,
+    ) -> Option<Self::Mask>
+    where
+        Self: Sized,
+    {
+        let original = self.project_ref(&Self::empty_mask(), &ProjectOptions::default());
+        self.update_as_field(source, mask, options);
+        original.diff(self)
+    }
+
+    /// The same as `merge`, but returns the mask of the fields of `self` that were actually
+    /// modified, the same way `update_as_field_reporting` does.
+    fn merge_reporting(&mut self, source: Self, options: &UpdateOptions) -> Option<Self::Mask>
+    where
+        Self: Sized,
+    {
+        let original = self.project_ref(&Self::empty_mask(), &ProjectOptions::default());
+        self.merge(source, options);
+        original.diff(self)
+    }
+
+    /// Compare `self` with `other` and return the mask that selects the fields that differ.
+    ///
+    /// Returns `None` if they are equal. A returned empty mask means the values differ as a whole
+    /// (e.g. they are atomic values).
+    fn diff(&self, other: &Self) -> Option<Self::Mask>;
+
+    /// Compare the fields of `self` and `other` selected by `mask`, without projecting them.
+    ///
+    /// An empty `mask` is the same as a full `mask`. Optional messages that are `None` are treated
+    /// the same as their default values.
+    fn eq_masked(&self, other: &Self, mask: &Self::Mask) -> bool;
+
+    /// Visit the leaf fields of `self` selected by `mask` with `visitor`.
+    ///
+    /// `path` is the path of `self`. It's extended with the path of each field while the field is
+    /// visited. An empty `mask` is the same as a full `mask`. Optional values that are `None` and
+    /// oneof variants that are not set are skipped.
+    fn visit(&self, mask: &Self::Mask, path: &mut Vec<&'static str>, visitor: &mut dyn MaskVisitor);
+
+    /// Run the validators of the fields of `self` selected by `mask`.
+    ///
+    /// `path` is the path of `self`, which is used to report the failing field. An empty `mask` is
+    /// the same as a full `mask`. Optional values that are `None` and oneof variants that are not
+    /// set are skipped. Types without validators are always valid.
+    fn validate(
+        &self,
+        _mask: &Self::Mask,
+        _path: &mut Vec<&'static str>,
+    ) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
+    /// Reset the output-only fields of `self`, including the nested ones, to their default values.
+    ///
+    /// This is applied to the source of an update, so that output-only fields are not copied into
+    /// values created by the update.
+    fn clear_output_only(&mut self) {}
+
+    /// Redact the fields of `self` selected by `mask`. Fields declared with
+    /// `#[fieldmask(redact_with = ...)]` are replaced by their placeholders, and the other fields
+    /// are reset to their default values, the same way `clear` does.
+    ///
+    /// An empty `mask` is the same as a full `mask`. Types without placeholders are cleared.
+    fn redact(&mut self, mask: &Self::Mask) {
+        self.clear(mask, &ProjectOptions::default());
+    }
+
+    /// Push the paths of the required fields of `self` selected by `mask` that have their default
+    /// values into `missing`.
+    ///
+    /// `path` is the path of `self`. An empty `mask` is the same as a full `mask`. The fields of
+    /// optional values that are `None`, of oneof variants that are not set and of missing required
+    /// fields are not checked.
+    fn check_required(
+        &self,
+        _mask: &Self::Mask,
+        _path: &mut Vec<&'static str>,
+        _missing: &mut Vec<String>,
+    ) {
+    }
 }
 
 /// A trait for types that can be projected or updated according to a field mask when wrapped in an
@@ -149,6 +421,18 @@ pub trait OptionMaskable: Maskable + Sized {
     /// Similar to `SelfMaskable::project`, but it takes `Option<Self>` instead of `Self`.
     fn option_project(this: &mut Option<Self>, mask: &Self::Mask, options: &ProjectOptions);
 
+    /// Similar to `SelfMaskable::project_ref`, but it takes `Option<&Self>` instead of `&Self`.
+    fn option_project_ref(
+        this: Option<&Self>,
+        mask: &Self::Mask,
+        options: &ProjectOptions,
+    ) -> Option<Self>;
+
+    /// Similar to `SelfMaskable::clear`, but it takes `Option<Self>` instead of `Self`.
+    ///
+    /// Messages and oneofs cleared as a whole are set to `None`.
+    fn option_clear(this: &mut Option<Self>, mask: &Self::Mask, options: &ProjectOptions);
+
     /// Similar to `SelfMaskable::update_as_field`, but it takes `Option<Self>` instead of `Self`.
     fn option_update_as_field(
         this: &mut Option<Self>,
@@ -159,6 +443,379 @@ pub trait OptionMaskable: Maskable + Sized {
 
     /// Similar to `SelfMaskable::merge`, but it takes `Option<Self>` instead of `Self`.
     fn option_merge(this: &mut Option<Self>, source: Option<Self>, options: &UpdateOptions);
+
+    /// Similar to `SelfMaskable::update_as_field_reporting`, but it takes `Option<Self>` instead of
+    /// `Self`.
+    fn option_update_as_field_reporting(
+        this: &mut Option<Self>,
+        source: Option<Self>,
+        mask: &Self::Mask,
+        options: &UpdateOptions,
+    ) -> Option<Self::Mask> {
+        let original =
+            Self::option_project_ref(this.as_ref(), &Self::empty_mask(), &Default::default());
+        Self::option_update_as_field(this, source, mask, options);
+        Self::option_diff(original.as_ref(), this.as_ref())
+    }
+
+    /// Similar to `SelfMaskable::merge_reporting`, but it takes `Option<Self>` instead of `Self`.
+    fn option_merge_reporting(
+        this: &mut Option<Self>,
+        source: Option<Self>,
+        options: &UpdateOptions,
+    ) -> Option<Self::Mask> {
+        let original =
+            Self::option_project_ref(this.as_ref(), &Self::empty_mask(), &Default::default());
+        Self::option_merge(this, source, options);
+        Self::option_diff(original.as_ref(), this.as_ref())
+    }
+
+    /// Similar to `SelfMaskable::diff`, but it takes `Option<&Self>` instead of `&Self`.
+    fn option_diff(this: Option<&Self>, other: Option<&Self>) -> Option<Self::Mask>;
+
+    /// Similar to `SelfMaskable::eq_masked`, but it takes `Option<&Self>` instead of `&Self`.
+    fn option_eq_masked(this: Option<&Self>, other: Option<&Self>, mask: &Self::Mask) -> bool;
+
+    /// Similar to `SelfMaskable::visit`, but it takes `Option<&Self>` instead of `&Self`.
+    fn option_visit(
+        this: Option<&Self>,
+        mask: &Self::Mask,
+        path: &mut Vec<&'static str>,
+        visitor: &mut dyn MaskVisitor,
+    );
+
+    /// Similar to `SelfMaskable::validate`, but it takes `Option<&Self>` instead of `&Self`.
+    fn option_validate(
+        _this: Option<&Self>,
+        _mask: &Self::Mask,
+        _path: &mut Vec<&'static str>,
+    ) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
+    /// Similar to `SelfMaskable::clear_output_only`, but it takes `Option<Self>` instead of `Self`.
+    fn option_clear_output_only(_this: &mut Option<Self>) {}
+
+    /// Similar to `SelfMaskable::redact`, but it takes `Option<Self>` instead of `Self`.
+    fn option_redact(this: &mut Option<Self>, mask: &Self::Mask) {
+        Self::option_clear(this, mask, &ProjectOptions::default());
+    }
+
+    /// Similar to `SelfMaskable::check_required`, but it takes `Option<&Self>` instead of `&Self`.
+    fn option_check_required(
+        _this: Option<&Self>,
+        _mask: &Self::Mask,
+        _path: &mut Vec<&'static str>,
+        _missing: &mut Vec<String>,
+    ) {
+    }
+}
+
+/// A type whose fields can be flattened into its parent, i.e. a struct or a oneof enum.
+///
+/// It's implemented by the derive macros, and used to reject flattening an atomic field at compile
+/// time.
+#[doc(hidden)]
+#[diagnostic::on_unimplemented(
+    message = "cannot flatten a field of type `{Self}`",
+    label = "only structs and oneof enums can be flattened",
+    note = "remove the flatten attribute"
+)]
+pub trait Flattenable {}
+
+impl<T: Flattenable> Flattenable for Option<T> {}
+
+impl<T: Flattenable> Flattenable for Box<T> {}
+
+impl<T: Flattenable> Flattenable for Arc<T> {}
+
+impl<T: Flattenable> Flattenable for Rc<T> {}
+
+impl<T: Flattenable + Clone> Flattenable for Cow<'_, T> {}
+
+/// Panics if two fields of a type share the same name in field masks. It's called by the derive
+/// macros for types with flattened fields, since the names of their fields are only known at
+/// runtime. `type_name` is the name of the type reported in the panic.
+#[doc(hidden)]
+pub fn assert_unique_field_names(type_name: &'static str, field_names: &[&'static str]) {
+    for (i, name) in field_names.iter().enumerate() {
+        if field_names[..i].contains(name) {
+            panic!(
+                "type `{type_name}` has more than one field named \"{name}\" in field masks, some \
+                 of them come from flattened fields; use `#[fieldmask(rename = \"...\")]` to give \
+                 them different names",
+            );
+        }
+    }
+}
+
+/// The same as `assert_unique_field_names`, but each type is only checked once. It's used for
+/// generic types, which can't keep a static per instantiation. Types are told apart by
+/// `type_path`, the full name of the type (including its generic arguments).
+#[doc(hidden)]
+pub fn assert_unique_field_names_once(
+    type_path: &'static str,
+    type_name: &'static str,
+    field_names: impl FnOnce() -> Vec<&'static str>,
+) {
+    static CHECKED: RwLock<BTreeSet<(&str, &str)>> = RwLock::new(BTreeSet::new());
+
+    let key = (type_path, type_name);
+    if CHECKED.read().unwrap().contains(&key) {
+        return;
+    }
+    assert_unique_field_names(type_name, &field_names());
+    CHECKED.write().unwrap().insert(key);
+}
+
+/// Update each element of `target` with the element of `source` with the same key according to
+/// `mask`, and append the elements without a match. This implements `RepeatedStrategy::MergeByKey`
+/// for the fields declared with `#[fieldmask(key = "...")]`.
+///
+/// `mask` is the mask of the elements, e.g. `name` for the mask path `items.name`. An empty `mask`
+/// merges the matched elements as a whole with `options`, so repeated fields nested in them are
+/// merged by key as well.
+pub fn merge_by_key<T, K>(
+    target: &mut Vec<T>,
+    source: Vec<T>,
+    key: impl Fn(&T) -> &K,
+    mask: &T::Mask,
+    options: &UpdateOptions,
+) where
+    T: SelfMaskable,
+    K: PartialEq + ?Sized,
+{
+    for item in source {
+        match target
+            .iter()
+            .position(|existing| key(existing) == key(&item))
+        {
+            Some(index) => target[index].update_as_field(item, mask, options),
+            None => target.push(item),
+        }
+    }
+}
+
+/// The operations of a repeated field declared with `#[fieldmask(key = "...")]`, whose mask is the
+/// mask of its elements, e.g. `items.name`.
+///
+/// An empty mask selects the whole field, the same as a field without a key. Otherwise, the field
+/// is projected, cleared, compared, redacted and checked element by element. Updates and diffs
+/// treat the field as a whole, except for `merge_by_key`.
+#[doc(hidden)]
+#[diagnostic::on_unimplemented(
+    message = "cannot merge a field of type `{Self}` by key",
+    label = "only `Vec`s of maskable elements can be merged by key",
+    note = "remove the key attribute"
+)]
+pub trait KeyedRepeated: SelfMaskable + Sized {
+    type Element: SelfMaskable;
+
+    fn project(&mut self, mask: &<Self::Element as Maskable>::Mask, options: &ProjectOptions);
+
+    fn project_ref(
+        &self,
+        mask: &<Self::Element as Maskable>::Mask,
+        options: &ProjectOptions,
+    ) -> Self;
+
+    fn clear(&mut self, mask: &<Self::Element as Maskable>::Mask, options: &ProjectOptions);
+
+    fn update_as_field(
+        &mut self,
+        source: Self,
+        _mask: &<Self::Element as Maskable>::Mask,
+        options: &UpdateOptions,
+    ) {
+        SelfMaskable::update_as_field(self, source, &Self::empty_mask(), options);
+    }
+
+    fn merge(&mut self, source: Self, options: &UpdateOptions) {
+        SelfMaskable::merge(self, source, options);
+    }
+
+    fn update_as_field_reporting(
+        &mut self,
+        source: Self,
+        _mask: &<Self::Element as Maskable>::Mask,
+        options: &UpdateOptions,
+    ) -> Option<<Self::Element as Maskable>::Mask> {
+        SelfMaskable::update_as_field_reporting(self, source, &Self::empty_mask(), options)
+            .map(|_| Self::Element::empty_mask())
+    }
+
+    fn merge_reporting(
+        &mut self,
+        source: Self,
+        options: &UpdateOptions,
+    ) -> Option<<Self::Element as Maskable>::Mask> {
+        SelfMaskable::merge_reporting(self, source, options).map(|_| Self::Element::empty_mask())
+    }
+
+    fn diff(&self, other: &Self) -> Option<<Self::Element as Maskable>::Mask> {
+        SelfMaskable::diff(self, other).map(|_| Self::Element::empty_mask())
+    }
+
+    fn eq_masked(&self, other: &Self, mask: &<Self::Element as Maskable>::Mask) -> bool;
+
+    fn visit(
+        &self,
+        mask: &<Self::Element as Maskable>::Mask,
+        path: &mut Vec<&'static str>,
+        visitor: &mut dyn MaskVisitor,
+    );
+
+    fn validate(
+        &self,
+        mask: &<Self::Element as Maskable>::Mask,
+        path: &mut Vec<&'static str>,
+    ) -> Result<(), ValidationError>;
+
+    fn clear_output_only(&mut self) {
+        SelfMaskable::clear_output_only(self);
+    }
+
+    fn redact(&mut self, mask: &<Self::Element as Maskable>::Mask);
+
+    fn check_required(
+        &self,
+        mask: &<Self::Element as Maskable>::Mask,
+        path: &mut Vec<&'static str>,
+        missing: &mut Vec<String>,
+    );
+}
+
+impl<T: SelfMaskable + Clone + PartialEq + 'static> KeyedRepeated for Vec<T> {
+    type Element = T;
+
+    fn project(&mut self, mask: &T::Mask, options: &ProjectOptions) {
+        if *mask == T::empty_mask() {
+            SelfMaskable::project(self, &Self::empty_mask(), options);
+        } else {
+            self.iter_mut().for_each(|item| item.project(mask, options));
+        }
+    }
+
+    fn project_ref(&self, mask: &T::Mask, options: &ProjectOptions) -> Self {
+        if *mask == T::empty_mask() {
+            SelfMaskable::project_ref(self, &Self::empty_mask(), options)
+        } else {
+            self.iter()
+                .map(|item| item.project_ref(mask, options))
+                .collect()
+        }
+    }
+
+    fn clear(&mut self, mask: &T::Mask, options: &ProjectOptions) {
+        if *mask == T::empty_mask() {
+            SelfMaskable::clear(self, &Self::empty_mask(), options);
+        } else {
+            self.iter_mut().for_each(|item| item.clear(mask, options));
+        }
+    }
+
+    fn eq_masked(&self, other: &Self, mask: &T::Mask) -> bool {
+        if *mask == T::empty_mask() {
+            SelfMaskable::eq_masked(self, other, &Self::empty_mask())
+        } else {
+            self.len() == other.len()
+                && self
+                    .iter()
+                    .zip(other)
+                    .all(|(item, other)| item.eq_masked(other, mask))
+        }
+    }
+
+    // The elements are not part of `path`, so a field selected by a mask of its elements is visited
+    // as its projection.
+    fn visit(&self, mask: &T::Mask, path: &mut Vec<&'static str>, visitor: &mut dyn MaskVisitor) {
+        if *mask == T::empty_mask() {
+            SelfMaskable::visit(self, &Self::empty_mask(), path, visitor);
+        } else {
+            let projection = KeyedRepeated::project_ref(self, mask, &ProjectOptions::default());
+            visitor.visit_field(path, crate::FieldRef::new(&projection));
+        }
+    }
+
+    fn validate(
+        &self,
+        mask: &T::Mask,
+        path: &mut Vec<&'static str>,
+    ) -> Result<(), ValidationError> {
+        if *mask == T::empty_mask() {
+            return SelfMaskable::validate(self, &Self::empty_mask(), path);
+        }
+        self.iter().try_for_each(|item| item.validate(mask, path))
+    }
+
+    fn redact(&mut self, mask: &T::Mask) {
+        if *mask == T::empty_mask() {
+            SelfMaskable::redact(self, &Self::empty_mask());
+        } else {
+            self.iter_mut().for_each(|item| item.redact(mask));
+        }
+    }
+
+    fn check_required(
+        &self,
+        mask: &T::Mask,
+        path: &mut Vec<&'static str>,
+        missing: &mut Vec<String>,
+    ) {
+        if *mask == T::empty_mask() {
+            SelfMaskable::check_required(self, &Self::empty_mask(), path, missing);
+        } else {
+            for item in self {
+                item.check_required(mask, path, missing);
+            }
+        }
+    }
+}
+
+/// The mask of a type with dynamic fields, e.g. a `prost_types::Struct` or a JSON object, which
+/// selects its fields by their keys, e.g. `metadata.some_key`. The fields of a nested object are
+/// selected by nested keys, e.g. `metadata.some_key.nested_key`.
+///
+/// An empty mask selects the whole value, and a key with an empty mask selects its whole value.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct KeyMask {
+    pub fields: BTreeMap<String, KeyMask>,
+}
+
+impl KeyMask {
+    /// Returns whether the mask selects no key, which selects the whole value.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Include the keys of `field_path`, a field mask path splitted by '.'. Any key can be
+    /// included.
+    pub fn include_field(&mut self, field_path: &[&str]) {
+        if let [key, tail @ ..] = field_path {
+            self.fields
+                .entry((*key).to_owned())
+                .or_default()
+                .include_field(tail);
+        }
+    }
+
+    /// Returns the key paths selected by the mask, the same way as `Maskable::mask_field_paths`.
+    #[cfg(any(feature = "prost", feature = "serde_json"))]
+    fn key_paths(&self) -> Vec<Vec<Cow<'static, str>>> {
+        let mut paths = Vec::new();
+        for (key, mask) in &self.fields {
+            let sub_paths = mask.key_paths();
+            if sub_paths.is_empty() {
+                paths.push(vec![Cow::Owned(key.clone())]);
+            } else {
+                paths.extend(sub_paths.into_iter().map(|mut path| {
+                    path.insert(0, Cow::Owned(key.clone()));
+                    path
+                }));
+            }
+        }
+        paths
+    }
 }
 
 // Do not implement this. Otherwise we will not be able to implement `OptionMaskable` for any other
@@ -182,6 +839,30 @@ impl<T: Maskable> Maskable for Option<T> {
     ) -> Result<(), DeserializeMaskError<'a>> {
         T::make_mask_include_field(mask, field_path)
     }
+
+    fn field_names() -> Vec<&'static str> {
+        T::field_names()
+    }
+
+    fn mask_field_paths(mask: &Self::Mask) -> Vec<Vec<Cow<'static, str>>> {
+        T::mask_field_paths(mask)
+    }
+
+    fn field_behaviors(field_path: &[&str]) -> Vec<FieldBehavior> {
+        T::field_behaviors(field_path)
+    }
+
+    fn tags_permit_all(allowed: &[&str], stack: &mut Vec<&'static str>) -> bool {
+        T::tags_permit_all(allowed, stack)
+    }
+
+    fn make_mask_for_tags(
+        mask: &mut Self::Mask,
+        allowed: &[&str],
+        stack: &mut Vec<&'static str>,
+    ) -> bool {
+        T::make_mask_for_tags(mask, allowed, stack)
+    }
 }
 
 impl<T: OptionMaskable> OptionMaskable for Option<T> {
@@ -194,6 +875,27 @@ impl<T: OptionMaskable> OptionMaskable for Option<T> {
         }
     }
 
+    fn option_project_ref(
+        this: Option<&Self>,
+        mask: &Self::Mask,
+        options: &ProjectOptions,
+    ) -> Option<Self> {
+        let this = this.map(|this| T::option_project_ref(this.as_ref(), mask, options));
+        if options.normalize && this.as_ref().map(|s| s.is_none()).unwrap_or(false) {
+            return None;
+        }
+        this
+    }
+
+    fn option_clear(this: &mut Option<Self>, mask: &Self::Mask, options: &ProjectOptions) {
+        if let Some(this) = this {
+            T::option_clear(this, mask, options);
+        }
+        if options.normalize && this.as_ref().map(|s| s.is_none()).unwrap_or(false) {
+            *this = None;
+        }
+    }
+
     fn option_update_as_field(
         this: &mut Option<Self>,
         source: Option<Self>,
@@ -222,38 +924,178 @@ impl<T: OptionMaskable> OptionMaskable for Option<T> {
             }
         }
     }
-}
 
-impl<T: OptionMaskable> SelfMaskable for Option<T> {
-    fn project(&mut self, mask: &Self::Mask, options: &ProjectOptions) {
-        T::option_project(self, mask, options)
+    fn option_diff(this: Option<&Self>, other: Option<&Self>) -> Option<Self::Mask> {
+        T::option_diff(
+            this.and_then(Option::as_ref),
+            other.and_then(Option::as_ref),
+        )
     }
 
-    fn update_as_field(&mut self, source: Self, mask: &Self::Mask, options: &UpdateOptions) {
-        T::option_update_as_field(self, source, mask, options)
+    fn option_eq_masked(this: Option<&Self>, other: Option<&Self>, mask: &Self::Mask) -> bool {
+        T::option_eq_masked(
+            this.and_then(Option::as_ref),
+            other.and_then(Option::as_ref),
+            mask,
+        )
     }
 
-    fn merge(&mut self, source: Self, options: &UpdateOptions) {
-        T::option_merge(self, source, options)
+    fn option_visit(
+        this: Option<&Self>,
+        mask: &Self::Mask,
+        path: &mut Vec<&'static str>,
+        visitor: &mut dyn MaskVisitor,
+    ) {
+        T::option_visit(this.and_then(Option::as_ref), mask, path, visitor)
     }
-}
 
-impl<T: Maskable> Maskable for Box<T> {
-    type Mask = Box<T::Mask>;
+    fn option_validate(
+        this: Option<&Self>,
+        mask: &Self::Mask,
+        path: &mut Vec<&'static str>,
+    ) -> Result<(), ValidationError> {
+        T::option_validate(this.and_then(Option::as_ref), mask, path)
+    }
 
-    fn empty_mask() -> Self::Mask {
-        Box::new(T::empty_mask())
+    fn option_clear_output_only(this: &mut Option<Self>) {
+        if let Some(this) = this {
+            T::option_clear_output_only(this);
+        }
     }
 
-    fn full_mask() -> Self::Mask {
-        Box::new(T::full_mask())
+    fn option_redact(this: &mut Option<Self>, mask: &Self::Mask) {
+        if let Some(this) = this {
+            T::option_redact(this, mask);
+        }
     }
 
-    fn make_mask_include_field<'a>(
-        mask: &mut Self::Mask,
-        field_path: &[&'a str],
-    ) -> Result<(), DeserializeMaskError<'a>> {
-        T::make_mask_include_field(mask, field_path)
+    fn option_check_required(
+        this: Option<&Self>,
+        mask: &Self::Mask,
+        path: &mut Vec<&'static str>,
+        missing: &mut Vec<String>,
+    ) {
+        T::option_check_required(this.and_then(Option::as_ref), mask, path, missing);
+    }
+}
+
+impl<T: OptionMaskable> SelfMaskable for Option<T> {
+    fn project(&mut self, mask: &Self::Mask, options: &ProjectOptions) {
+        T::option_project(self, mask, options)
+    }
+
+    fn project_ref(&self, mask: &Self::Mask, options: &ProjectOptions) -> Self {
+        T::option_project_ref(self.as_ref(), mask, options)
+    }
+
+    fn clear(&mut self, mask: &Self::Mask, options: &ProjectOptions) {
+        T::option_clear(self, mask, options)
+    }
+
+    fn update_as_field(&mut self, source: Self, mask: &Self::Mask, options: &UpdateOptions) {
+        T::option_update_as_field(self, source, mask, options)
+    }
+
+    fn merge(&mut self, source: Self, options: &UpdateOptions) {
+        T::option_merge(self, source, options)
+    }
+
+    fn update_as_field_reporting(
+        &mut self,
+        source: Self,
+        mask: &Self::Mask,
+        options: &UpdateOptions,
+    ) -> Option<Self::Mask> {
+        T::option_update_as_field_reporting(self, source, mask, options)
+    }
+
+    fn merge_reporting(&mut self, source: Self, options: &UpdateOptions) -> Option<Self::Mask> {
+        T::option_merge_reporting(self, source, options)
+    }
+
+    fn diff(&self, other: &Self) -> Option<Self::Mask> {
+        T::option_diff(self.as_ref(), other.as_ref())
+    }
+
+    fn eq_masked(&self, other: &Self, mask: &Self::Mask) -> bool {
+        T::option_eq_masked(self.as_ref(), other.as_ref(), mask)
+    }
+
+    fn visit(
+        &self,
+        mask: &Self::Mask,
+        path: &mut Vec<&'static str>,
+        visitor: &mut dyn MaskVisitor,
+    ) {
+        T::option_visit(self.as_ref(), mask, path, visitor)
+    }
+
+    fn validate(
+        &self,
+        mask: &Self::Mask,
+        path: &mut Vec<&'static str>,
+    ) -> Result<(), ValidationError> {
+        T::option_validate(self.as_ref(), mask, path)
+    }
+
+    fn clear_output_only(&mut self) {
+        T::option_clear_output_only(self);
+    }
+
+    fn redact(&mut self, mask: &Self::Mask) {
+        T::option_redact(self, mask);
+    }
+
+    fn check_required(
+        &self,
+        mask: &Self::Mask,
+        path: &mut Vec<&'static str>,
+        missing: &mut Vec<String>,
+    ) {
+        T::option_check_required(self.as_ref(), mask, path, missing);
+    }
+}
+
+impl<T: Maskable> Maskable for Box<T> {
+    type Mask = Box<T::Mask>;
+
+    fn empty_mask() -> Self::Mask {
+        Box::new(T::empty_mask())
+    }
+
+    fn full_mask() -> Self::Mask {
+        Box::new(T::full_mask())
+    }
+
+    fn make_mask_include_field<'a>(
+        mask: &mut Self::Mask,
+        field_path: &[&'a str],
+    ) -> Result<(), DeserializeMaskError<'a>> {
+        T::make_mask_include_field(mask, field_path)
+    }
+
+    fn field_names() -> Vec<&'static str> {
+        T::field_names()
+    }
+
+    fn mask_field_paths(mask: &Self::Mask) -> Vec<Vec<Cow<'static, str>>> {
+        T::mask_field_paths(mask)
+    }
+
+    fn field_behaviors(field_path: &[&str]) -> Vec<FieldBehavior> {
+        T::field_behaviors(field_path)
+    }
+
+    fn tags_permit_all(allowed: &[&str], stack: &mut Vec<&'static str>) -> bool {
+        T::tags_permit_all(allowed, stack)
+    }
+
+    fn make_mask_for_tags(
+        mask: &mut Self::Mask,
+        allowed: &[&str],
+        stack: &mut Vec<&'static str>,
+    ) -> bool {
+        T::make_mask_for_tags(mask, allowed, stack)
     }
 }
 
@@ -262,6 +1104,14 @@ impl<T: SelfMaskable> SelfMaskable for Box<T> {
         (**self).project(mask, options);
     }
 
+    fn project_ref(&self, mask: &Self::Mask, options: &ProjectOptions) -> Self {
+        Box::new((**self).project_ref(mask, options))
+    }
+
+    fn clear(&mut self, mask: &Self::Mask, options: &ProjectOptions) {
+        (**self).clear(mask, options);
+    }
+
     fn update_as_field(&mut self, source: Self, mask: &Self::Mask, options: &UpdateOptions) {
         self.as_mut().update_as_field(*source, mask, options);
     }
@@ -269,6 +1119,65 @@ impl<T: SelfMaskable> SelfMaskable for Box<T> {
     fn merge(&mut self, source: Self, options: &UpdateOptions) {
         self.as_mut().merge(*source, options);
     }
+
+    fn update_as_field_reporting(
+        &mut self,
+        source: Self,
+        mask: &Self::Mask,
+        options: &UpdateOptions,
+    ) -> Option<Self::Mask> {
+        self.as_mut()
+            .update_as_field_reporting(*source, mask, options)
+            .map(Box::new)
+    }
+
+    fn merge_reporting(&mut self, source: Self, options: &UpdateOptions) -> Option<Self::Mask> {
+        self.as_mut()
+            .merge_reporting(*source, options)
+            .map(Box::new)
+    }
+
+    fn diff(&self, other: &Self) -> Option<Self::Mask> {
+        (**self).diff(other).map(Box::new)
+    }
+
+    fn eq_masked(&self, other: &Self, mask: &Self::Mask) -> bool {
+        (**self).eq_masked(other, mask)
+    }
+
+    fn visit(
+        &self,
+        mask: &Self::Mask,
+        path: &mut Vec<&'static str>,
+        visitor: &mut dyn MaskVisitor,
+    ) {
+        (**self).visit(mask, path, visitor);
+    }
+
+    fn validate(
+        &self,
+        mask: &Self::Mask,
+        path: &mut Vec<&'static str>,
+    ) -> Result<(), ValidationError> {
+        (**self).validate(mask, path)
+    }
+
+    fn clear_output_only(&mut self) {
+        (**self).clear_output_only();
+    }
+
+    fn redact(&mut self, mask: &Self::Mask) {
+        (**self).redact(mask);
+    }
+
+    fn check_required(
+        &self,
+        mask: &Self::Mask,
+        path: &mut Vec<&'static str>,
+        missing: &mut Vec<String>,
+    ) {
+        (**self).check_required(mask, path, missing);
+    }
 }
 
 impl<T: OptionMaskable> OptionMaskable for Box<T> {
@@ -284,6 +1193,22 @@ impl<T: OptionMaskable> OptionMaskable for Box<T> {
         }
     }
 
+    fn option_project_ref(
+        this: Option<&Self>,
+        mask: &Self::Mask,
+        options: &ProjectOptions,
+    ) -> Option<Self> {
+        T::option_project_ref(this.map(|this| &**this), mask, options).map(Box::new)
+    }
+
+    fn option_clear(this: &mut Option<Self>, mask: &Self::Mask, options: &ProjectOptions) {
+        let mut temp = None;
+        mem::swap(this, &mut temp);
+        let mut temp = temp.map(|temp| *temp);
+        temp.clear(mask, options);
+        *this = temp.map(Box::new);
+    }
+
     fn option_update_as_field(
         this: &mut Option<Self>,
         source: Option<Self>,
@@ -304,6 +1229,514 @@ impl<T: OptionMaskable> OptionMaskable for Box<T> {
         temp.merge(source.map(|source| *source), options);
         *this = temp.map(Box::new);
     }
+
+    fn option_diff(this: Option<&Self>, other: Option<&Self>) -> Option<Self::Mask> {
+        T::option_diff(this.map(|this| &**this), other.map(|other| &**other)).map(Box::new)
+    }
+
+    fn option_eq_masked(this: Option<&Self>, other: Option<&Self>, mask: &Self::Mask) -> bool {
+        T::option_eq_masked(this.map(|this| &**this), other.map(|other| &**other), mask)
+    }
+
+    fn option_visit(
+        this: Option<&Self>,
+        mask: &Self::Mask,
+        path: &mut Vec<&'static str>,
+        visitor: &mut dyn MaskVisitor,
+    ) {
+        T::option_visit(this.map(|this| &**this), mask, path, visitor);
+    }
+
+    fn option_validate(
+        this: Option<&Self>,
+        mask: &Self::Mask,
+        path: &mut Vec<&'static str>,
+    ) -> Result<(), ValidationError> {
+        T::option_validate(this.map(|this| &**this), mask, path)
+    }
+
+    fn option_clear_output_only(this: &mut Option<Self>) {
+        let mut temp = None;
+        mem::swap(this, &mut temp);
+        let mut temp = temp.map(|temp| *temp);
+        temp.clear_output_only();
+        *this = temp.map(Box::new);
+    }
+
+    fn option_redact(this: &mut Option<Self>, mask: &Self::Mask) {
+        let mut temp = None;
+        mem::swap(this, &mut temp);
+        let mut temp = temp.map(|temp| *temp);
+        temp.redact(mask);
+        *this = temp.map(Box::new);
+    }
+
+    fn option_check_required(
+        this: Option<&Self>,
+        mask: &Self::Mask,
+        path: &mut Vec<&'static str>,
+        missing: &mut Vec<String>,
+    ) {
+        T::option_check_required(this.map(|this| &**this), mask, path, missing);
+    }
+}
+
+/// Implement the maskable traits for shared pointers. Like `Box<T>`, their masks are boxed so that
+/// recursive types are supported. The pointee is cloned with `make_mut` before it's modified if
+/// it's shared, and moved out with `unwrap_or_clone` when it's consumed.
+macro_rules! maskable_shared_pointer {
+    ($($ptr:ident),* $(,)?) => {
+        $(
+            impl<T: Maskable> Maskable for $ptr<T> {
+                type Mask = Box<T::Mask>;
+
+                fn empty_mask() -> Self::Mask {
+                    Box::new(T::empty_mask())
+                }
+
+                fn full_mask() -> Self::Mask {
+                    Box::new(T::full_mask())
+                }
+
+                fn make_mask_include_field<'a>(
+                    mask: &mut Self::Mask,
+                    field_path: &[&'a str],
+                ) -> Result<(), DeserializeMaskError<'a>> {
+                    T::make_mask_include_field(mask, field_path)
+                }
+
+                fn field_names() -> Vec<&'static str> {
+                    T::field_names()
+                }
+
+                fn mask_field_paths(mask: &Self::Mask) -> Vec<Vec<Cow<'static, str>>> {
+                    T::mask_field_paths(mask)
+                }
+
+                fn field_behaviors(field_path: &[&str]) -> Vec<FieldBehavior> {
+                    T::field_behaviors(field_path)
+                }
+
+                fn tags_permit_all(allowed: &[&str], stack: &mut Vec<&'static str>) -> bool {
+                    T::tags_permit_all(allowed, stack)
+                }
+
+                fn make_mask_for_tags(
+                    mask: &mut Self::Mask,
+                    allowed: &[&str],
+                    stack: &mut Vec<&'static str>,
+                ) -> bool {
+                    T::make_mask_for_tags(mask, allowed, stack)
+                }
+            }
+
+            impl<T: SelfMaskable + Clone> SelfMaskable for $ptr<T> {
+                fn project(&mut self, mask: &Self::Mask, options: &ProjectOptions) {
+                    $ptr::make_mut(self).project(mask, options);
+                }
+
+                fn project_ref(&self, mask: &Self::Mask, options: &ProjectOptions) -> Self {
+                    $ptr::new((**self).project_ref(mask, options))
+                }
+
+                fn clear(&mut self, mask: &Self::Mask, options: &ProjectOptions) {
+                    $ptr::make_mut(self).clear(mask, options);
+                }
+
+                fn update_as_field(
+                    &mut self,
+                    source: Self,
+                    mask: &Self::Mask,
+                    options: &UpdateOptions,
+                ) {
+                    $ptr::make_mut(self).update_as_field(
+                        $ptr::unwrap_or_clone(source),
+                        mask,
+                        options,
+                    );
+                }
+
+                fn merge(&mut self, source: Self, options: &UpdateOptions) {
+                    $ptr::make_mut(self).merge($ptr::unwrap_or_clone(source), options);
+                }
+
+                fn update_as_field_reporting(
+                    &mut self,
+                    source: Self,
+                    mask: &Self::Mask,
+                    options: &UpdateOptions,
+                ) -> Option<Self::Mask> {
+                    $ptr::make_mut(self)
+                        .update_as_field_reporting($ptr::unwrap_or_clone(source), mask, options)
+                        .map(Box::new)
+                }
+
+                fn merge_reporting(
+                    &mut self,
+                    source: Self,
+                    options: &UpdateOptions,
+                ) -> Option<Self::Mask> {
+                    $ptr::make_mut(self)
+                        .merge_reporting($ptr::unwrap_or_clone(source), options)
+                        .map(Box::new)
+                }
+
+                fn diff(&self, other: &Self) -> Option<Self::Mask> {
+                    (**self).diff(other).map(Box::new)
+                }
+
+                fn eq_masked(&self, other: &Self, mask: &Self::Mask) -> bool {
+                    (**self).eq_masked(other, mask)
+                }
+
+                fn visit(
+                    &self,
+                    mask: &Self::Mask,
+                    path: &mut Vec<&'static str>,
+                    visitor: &mut dyn MaskVisitor,
+                ) {
+                    (**self).visit(mask, path, visitor);
+                }
+
+                fn validate(
+                    &self,
+                    mask: &Self::Mask,
+                    path: &mut Vec<&'static str>,
+                ) -> Result<(), ValidationError> {
+                    (**self).validate(mask, path)
+                }
+
+                fn clear_output_only(&mut self) {
+                    $ptr::make_mut(self).clear_output_only();
+                }
+
+                fn redact(&mut self, mask: &Self::Mask) {
+                    $ptr::make_mut(self).redact(mask);
+                }
+
+                fn check_required(
+                    &self,
+                    mask: &Self::Mask,
+                    path: &mut Vec<&'static str>,
+                    missing: &mut Vec<String>,
+                ) {
+                    (**self).check_required(mask, path, missing);
+                }
+            }
+
+            impl<T: OptionMaskable + Clone> OptionMaskable for $ptr<T> {
+                fn option_project(
+                    this: &mut Option<Self>,
+                    mask: &Self::Mask,
+                    options: &ProjectOptions,
+                ) {
+                    let mut temp = this.take().map($ptr::unwrap_or_clone);
+                    temp.project(mask, options);
+                    *this = temp.map($ptr::new);
+                }
+
+                fn option_project_ref(
+                    this: Option<&Self>,
+                    mask: &Self::Mask,
+                    options: &ProjectOptions,
+                ) -> Option<Self> {
+                    T::option_project_ref(this.map(|this| &**this), mask, options).map($ptr::new)
+                }
+
+                fn option_clear(
+                    this: &mut Option<Self>,
+                    mask: &Self::Mask,
+                    options: &ProjectOptions,
+                ) {
+                    let mut temp = this.take().map($ptr::unwrap_or_clone);
+                    temp.clear(mask, options);
+                    *this = temp.map($ptr::new);
+                }
+
+                fn option_update_as_field(
+                    this: &mut Option<Self>,
+                    source: Option<Self>,
+                    mask: &Self::Mask,
+                    options: &UpdateOptions,
+                ) {
+                    let mut temp = this.take().map($ptr::unwrap_or_clone);
+                    temp.update_as_field(source.map($ptr::unwrap_or_clone), mask, options);
+                    *this = temp.map($ptr::new);
+                }
+
+                fn option_merge(
+                    this: &mut Option<Self>,
+                    source: Option<Self>,
+                    options: &UpdateOptions,
+                ) {
+                    let mut temp = this.take().map($ptr::unwrap_or_clone);
+                    temp.merge(source.map($ptr::unwrap_or_clone), options);
+                    *this = temp.map($ptr::new);
+                }
+
+                fn option_diff(this: Option<&Self>, other: Option<&Self>) -> Option<Self::Mask> {
+                    T::option_diff(this.map(|this| &**this), other.map(|other| &**other))
+                        .map(Box::new)
+                }
+
+                fn option_eq_masked(
+                    this: Option<&Self>,
+                    other: Option<&Self>,
+                    mask: &Self::Mask,
+                ) -> bool {
+                    T::option_eq_masked(this.map(|this| &**this), other.map(|other| &**other), mask)
+                }
+
+                fn option_visit(
+                    this: Option<&Self>,
+                    mask: &Self::Mask,
+                    path: &mut Vec<&'static str>,
+                    visitor: &mut dyn MaskVisitor,
+                ) {
+                    T::option_visit(this.map(|this| &**this), mask, path, visitor);
+                }
+
+                fn option_validate(
+                    this: Option<&Self>,
+                    mask: &Self::Mask,
+                    path: &mut Vec<&'static str>,
+                ) -> Result<(), ValidationError> {
+                    T::option_validate(this.map(|this| &**this), mask, path)
+                }
+
+                fn option_clear_output_only(this: &mut Option<Self>) {
+                    let mut temp = this.take().map($ptr::unwrap_or_clone);
+                    temp.clear_output_only();
+                    *this = temp.map($ptr::new);
+                }
+
+                fn option_redact(this: &mut Option<Self>, mask: &Self::Mask) {
+                    let mut temp = this.take().map($ptr::unwrap_or_clone);
+                    temp.redact(mask);
+                    *this = temp.map($ptr::new);
+                }
+
+                fn option_check_required(
+                    this: Option<&Self>,
+                    mask: &Self::Mask,
+                    path: &mut Vec<&'static str>,
+                    missing: &mut Vec<String>,
+                ) {
+                    T::option_check_required(this.map(|this| &**this), mask, path, missing);
+                }
+            }
+        )*
+    };
+}
+
+maskable_shared_pointer!(Arc, Rc);
+
+impl<T: Maskable + Clone> Maskable for Cow<'_, T> {
+    type Mask = T::Mask;
+
+    fn empty_mask() -> Self::Mask {
+        T::empty_mask()
+    }
+
+    fn full_mask() -> Self::Mask {
+        T::full_mask()
+    }
+
+    fn make_mask_include_field<'a>(
+        mask: &mut Self::Mask,
+        field_path: &[&'a str],
+    ) -> Result<(), DeserializeMaskError<'a>> {
+        T::make_mask_include_field(mask, field_path)
+    }
+
+    fn field_names() -> Vec<&'static str> {
+        T::field_names()
+    }
+
+    fn mask_field_paths(mask: &Self::Mask) -> Vec<Vec<Cow<'static, str>>> {
+        T::mask_field_paths(mask)
+    }
+
+    fn field_behaviors(field_path: &[&str]) -> Vec<FieldBehavior> {
+        T::field_behaviors(field_path)
+    }
+
+    fn tags_permit_all(allowed: &[&str], stack: &mut Vec<&'static str>) -> bool {
+        T::tags_permit_all(allowed, stack)
+    }
+
+    fn make_mask_for_tags(
+        mask: &mut Self::Mask,
+        allowed: &[&str],
+        stack: &mut Vec<&'static str>,
+    ) -> bool {
+        T::make_mask_for_tags(mask, allowed, stack)
+    }
+}
+
+// Borrowed values are converted into owned ones before they are modified.
+impl<T: SelfMaskable + Clone> SelfMaskable for Cow<'_, T> {
+    fn project(&mut self, mask: &Self::Mask, options: &ProjectOptions) {
+        self.to_mut().project(mask, options);
+    }
+
+    fn project_ref(&self, mask: &Self::Mask, options: &ProjectOptions) -> Self {
+        Cow::Owned((**self).project_ref(mask, options))
+    }
+
+    fn clear(&mut self, mask: &Self::Mask, options: &ProjectOptions) {
+        self.to_mut().clear(mask, options);
+    }
+
+    fn update_as_field(&mut self, source: Self, mask: &Self::Mask, options: &UpdateOptions) {
+        self.to_mut()
+            .update_as_field(source.into_owned(), mask, options);
+    }
+
+    fn merge(&mut self, source: Self, options: &UpdateOptions) {
+        self.to_mut().merge(source.into_owned(), options);
+    }
+
+    fn update_as_field_reporting(
+        &mut self,
+        source: Self,
+        mask: &Self::Mask,
+        options: &UpdateOptions,
+    ) -> Option<Self::Mask> {
+        self.to_mut()
+            .update_as_field_reporting(source.into_owned(), mask, options)
+    }
+
+    fn merge_reporting(&mut self, source: Self, options: &UpdateOptions) -> Option<Self::Mask> {
+        self.to_mut().merge_reporting(source.into_owned(), options)
+    }
+
+    fn diff(&self, other: &Self) -> Option<Self::Mask> {
+        (**self).diff(other)
+    }
+
+    fn eq_masked(&self, other: &Self, mask: &Self::Mask) -> bool {
+        (**self).eq_masked(other, mask)
+    }
+
+    fn visit(
+        &self,
+        mask: &Self::Mask,
+        path: &mut Vec<&'static str>,
+        visitor: &mut dyn MaskVisitor,
+    ) {
+        (**self).visit(mask, path, visitor);
+    }
+
+    fn validate(
+        &self,
+        mask: &Self::Mask,
+        path: &mut Vec<&'static str>,
+    ) -> Result<(), ValidationError> {
+        (**self).validate(mask, path)
+    }
+
+    fn clear_output_only(&mut self) {
+        self.to_mut().clear_output_only();
+    }
+
+    fn redact(&mut self, mask: &Self::Mask) {
+        self.to_mut().redact(mask);
+    }
+
+    fn check_required(
+        &self,
+        mask: &Self::Mask,
+        path: &mut Vec<&'static str>,
+        missing: &mut Vec<String>,
+    ) {
+        (**self).check_required(mask, path, missing);
+    }
+}
+
+impl<T: OptionMaskable + Clone> OptionMaskable for Cow<'_, T> {
+    fn option_project(this: &mut Option<Self>, mask: &Self::Mask, options: &ProjectOptions) {
+        let mut temp = this.take().map(Cow::into_owned);
+        temp.project(mask, options);
+        *this = temp.map(Cow::Owned);
+    }
+
+    fn option_project_ref(
+        this: Option<&Self>,
+        mask: &Self::Mask,
+        options: &ProjectOptions,
+    ) -> Option<Self> {
+        T::option_project_ref(this.map(|this| &**this), mask, options).map(Cow::Owned)
+    }
+
+    fn option_clear(this: &mut Option<Self>, mask: &Self::Mask, options: &ProjectOptions) {
+        let mut temp = this.take().map(Cow::into_owned);
+        temp.clear(mask, options);
+        *this = temp.map(Cow::Owned);
+    }
+
+    fn option_update_as_field(
+        this: &mut Option<Self>,
+        source: Option<Self>,
+        mask: &Self::Mask,
+        options: &UpdateOptions,
+    ) {
+        let mut temp = this.take().map(Cow::into_owned);
+        temp.update_as_field(source.map(Cow::into_owned), mask, options);
+        *this = temp.map(Cow::Owned);
+    }
+
+    fn option_merge(this: &mut Option<Self>, source: Option<Self>, options: &UpdateOptions) {
+        let mut temp = this.take().map(Cow::into_owned);
+        temp.merge(source.map(Cow::into_owned), options);
+        *this = temp.map(Cow::Owned);
+    }
+
+    fn option_diff(this: Option<&Self>, other: Option<&Self>) -> Option<Self::Mask> {
+        T::option_diff(this.map(|this| &**this), other.map(|other| &**other))
+    }
+
+    fn option_eq_masked(this: Option<&Self>, other: Option<&Self>, mask: &Self::Mask) -> bool {
+        T::option_eq_masked(this.map(|this| &**this), other.map(|other| &**other), mask)
+    }
+
+    fn option_visit(
+        this: Option<&Self>,
+        mask: &Self::Mask,
+        path: &mut Vec<&'static str>,
+        visitor: &mut dyn MaskVisitor,
+    ) {
+        T::option_visit(this.map(|this| &**this), mask, path, visitor);
+    }
+
+    fn option_validate(
+        this: Option<&Self>,
+        mask: &Self::Mask,
+        path: &mut Vec<&'static str>,
+    ) -> Result<(), ValidationError> {
+        T::option_validate(this.map(|this| &**this), mask, path)
+    }
+
+    fn option_clear_output_only(this: &mut Option<Self>) {
+        let mut temp = this.take().map(Cow::into_owned);
+        temp.clear_output_only();
+        *this = temp.map(Cow::Owned);
+    }
+
+    fn option_redact(this: &mut Option<Self>, mask: &Self::Mask) {
+        let mut temp = this.take().map(Cow::into_owned);
+        temp.redact(mask);
+        *this = temp.map(Cow::Owned);
+    }
+
+    fn option_check_required(
+        this: Option<&Self>,
+        mask: &Self::Mask,
+        path: &mut Vec<&'static str>,
+        missing: &mut Vec<String>,
+    ) {
+        T::option_check_required(this.map(|this| &**this), mask, path, missing);
+    }
 }
 
 maskable_atomic!(impl bool {});
@@ -326,7 +1759,148 @@ maskable_atomic!(impl isize {});
 maskable_atomic!(impl usize {});
 
 maskable_atomic!(
-    impl String {
+    impl String {
+        fn merge(&mut self, source: Self, _options: &UpdateOptions) {
+            if !source.is_empty() {
+                *self = source;
+            }
+        }
+
+        fn option_project(this: &mut Option<Self>, _mask: &Self::Mask, options: &ProjectOptions) {
+            if options.normalize && this.as_ref().map(|s| s.is_empty()).unwrap_or(false) {
+                *this = None;
+            }
+        }
+    }
+);
+
+maskable_atomic!(
+    impl<K: Clone + Eq + Hash + 'static, V: Clone + PartialEq + 'static> HashMap<K, V> {
+        fn merge(&mut self, source: Self, _options: &UpdateOptions) {
+            if !source.is_empty() {
+                *self = source;
+            }
+        }
+
+        fn option_project(this: &mut Option<Self>, _mask: &Self::Mask, options: &ProjectOptions) {
+            if options.normalize && this.as_ref().map(|s| s.is_empty()).unwrap_or(false) {
+                *this = None;
+            }
+        }
+    }
+);
+
+maskable_atomic!(
+    impl<K: Clone + Ord + 'static, V: Clone + PartialEq + 'static> BTreeMap<K, V> {
+        fn merge(&mut self, source: Self, _options: &UpdateOptions) {
+            if !source.is_empty() {
+                *self = source;
+            }
+        }
+
+        fn option_project(this: &mut Option<Self>, _mask: &Self::Mask, options: &ProjectOptions) {
+            if options.normalize && this.as_ref().map(|s| s.is_empty()).unwrap_or(false) {
+                *this = None;
+            }
+        }
+    }
+);
+
+maskable_atomic!(
+    impl<T: Clone + PartialEq + 'static> Vec<T> {
+        fn update_as_field(&mut self, source: Self, _mask: &Self::Mask, options: &UpdateOptions) {
+            self.merge(source, options);
+        }
+
+        fn merge(&mut self, source: Self, options: &UpdateOptions) {
+            if options.replace_repeated {
+                *self = source;
+                return;
+            }
+
+            match options.repeated_strategy {
+                RepeatedStrategy::Append | RepeatedStrategy::MergeByKey => self.extend(source),
+                RepeatedStrategy::DedupAppend => {
+                    for item in source {
+                        if !self.contains(&item) {
+                            self.push(item);
+                        }
+                    }
+                }
+                RepeatedStrategy::Prepend => {
+                    self.splice(0..0, source);
+                }
+            }
+        }
+
+        fn option_project(this: &mut Option<Self>, _mask: &Self::Mask, options: &ProjectOptions) {
+            if options.normalize && this.as_ref().map(|s| s.is_empty()).unwrap_or(false) {
+                *this = None;
+            }
+        }
+    }
+);
+
+maskable_atomic!(
+    impl<T: Clone + PartialEq + 'static> VecDeque<T> {
+        fn update_as_field(&mut self, source: Self, _mask: &Self::Mask, options: &UpdateOptions) {
+            self.merge(source, options);
+        }
+
+        fn merge(&mut self, source: Self, options: &UpdateOptions) {
+            if options.replace_repeated {
+                *self = source;
+                return;
+            }
+
+            match options.repeated_strategy {
+                RepeatedStrategy::Append | RepeatedStrategy::MergeByKey => self.extend(source),
+                RepeatedStrategy::DedupAppend => {
+                    for item in source {
+                        if !self.contains(&item) {
+                            self.push_back(item);
+                        }
+                    }
+                }
+                RepeatedStrategy::Prepend => {
+                    for item in source.into_iter().rev() {
+                        self.push_front(item);
+                    }
+                }
+            }
+        }
+
+        fn option_project(this: &mut Option<Self>, _mask: &Self::Mask, options: &ProjectOptions) {
+            if options.normalize && this.as_ref().map(|s| s.is_empty()).unwrap_or(false) {
+                *this = None;
+            }
+        }
+    }
+);
+
+// Boxed slices are merged the same way as `Vec`s.
+maskable_atomic!(
+    impl<T: Clone + PartialEq + 'static> Box<[T]> {
+        fn update_as_field(&mut self, source: Self, _mask: &Self::Mask, options: &UpdateOptions) {
+            self.merge(source, options);
+        }
+
+        fn merge(&mut self, source: Self, options: &UpdateOptions) {
+            let mut items = mem::take(self).into_vec();
+            items.merge(source.into_vec(), options);
+            *self = items.into_boxed_slice();
+        }
+
+        fn option_project(this: &mut Option<Self>, _mask: &Self::Mask, options: &ProjectOptions) {
+            if options.normalize && this.as_ref().map(|s| s.is_empty()).unwrap_or(false) {
+                *this = None;
+            }
+        }
+    }
+);
+
+maskable_atomic!(
+    impl Cow<'static, str> {
         fn merge(&mut self, source: Self, _options: &UpdateOptions) {
             if !source.is_empty() {
                 *self = source;
@@ -341,11 +1915,37 @@ maskable_atomic!(
     }
 );
 
+// Borrowed slices are merged the same way as `Vec`s.
 maskable_atomic!(
-    impl<K, V> HashMap<K, V> {
-        fn merge(&mut self, source: Self, _options: &UpdateOptions) {
-            if !source.is_empty() {
+    impl<T: Clone + PartialEq + 'static> Cow<'static, [T]> {
+        fn update_as_field(&mut self, source: Self, _mask: &Self::Mask, options: &UpdateOptions) {
+            self.merge(source, options);
+        }
+
+        fn merge(&mut self, source: Self, options: &UpdateOptions) {
+            self.to_mut().merge(source.into_owned(), options);
+        }
+
+        fn option_project(this: &mut Option<Self>, _mask: &Self::Mask, options: &ProjectOptions) {
+            if options.normalize && this.as_ref().map(|s| s.is_empty()).unwrap_or(false) {
+                *this = None;
+            }
+        }
+    }
+);
+
+// Sets are merged by union, unless the repeated fields are replaced.
+maskable_atomic!(
+    impl<T: Clone + Eq + Hash + 'static> HashSet<T> {
+        fn update_as_field(&mut self, source: Self, _mask: &Self::Mask, options: &UpdateOptions) {
+            self.merge(source, options);
+        }
+
+        fn merge(&mut self, source: Self, options: &UpdateOptions) {
+            if options.replace_repeated {
                 *self = source;
+            } else {
+                self.extend(source);
             }
         }
 
@@ -358,7 +1958,7 @@ maskable_atomic!(
 );
 
 maskable_atomic!(
-    impl<T> Vec<T> {
+    impl<T: Clone + Ord + 'static> BTreeSet<T> {
         fn update_as_field(&mut self, source: Self, _mask: &Self::Mask, options: &UpdateOptions) {
             self.merge(source, options);
         }
@@ -366,10 +1966,9 @@ maskable_atomic!(
         fn merge(&mut self, source: Self, options: &UpdateOptions) {
             if options.replace_repeated {
                 *self = source;
-                return;
+            } else {
+                self.extend(source);
             }
-
-            self.extend(source);
         }
 
         fn option_project(this: &mut Option<Self>, _mask: &Self::Mask, options: &ProjectOptions) {
@@ -380,9 +1979,63 @@ maskable_atomic!(
     }
 );
 
+// `Default` is only implemented for arrays of up to 32 elements, which derived messages require
+// for their fields as well.
+maskable_atomic!(
+    impl<T: Clone + PartialEq + 'static, const N: usize> [T; N] where [T; N]: Default {}
+);
+
+/// Implement the maskable traits for tuples, which are atomic.
+macro_rules! maskable_tuple {
+    ($(($($ty:ident),+)),* $(,)?) => {
+        $(
+            maskable_atomic!(
+                impl<$($ty: Clone + Default + PartialEq + 'static),+> ($($ty,)+) {}
+            );
+        )*
+    };
+}
+
+maskable_tuple!(
+    (A),
+    (A, B),
+    (A, B, C),
+    (A, B, C, D),
+    (A, B, C, D, E),
+    (A, B, C, D, E, F),
+);
+
+#[cfg(feature = "indexmap")]
+mod indexmap_integration {
+    use super::*;
+
+    maskable_atomic!(
+        impl<K: Clone + Eq + Hash + 'static, V: Clone + PartialEq + 'static> ::indexmap::IndexMap<K, V> {
+            fn merge(&mut self, source: Self, _options: &UpdateOptions) {
+                if !source.is_empty() {
+                    *self = source;
+                }
+            }
+
+            fn option_project(
+                this: &mut Option<Self>,
+                _mask: &Self::Mask,
+                options: &ProjectOptions,
+            ) {
+                if options.normalize && this.as_ref().map(|s| s.is_empty()).unwrap_or(false) {
+                    *this = None;
+                }
+            }
+        }
+    );
+}
+
 #[cfg(feature = "prost")]
 mod prost_integration {
+    use ::prost_types::{Struct, Value, value::Kind};
+
     use super::*;
+    use crate::FieldRef;
 
     maskable_atomic!(
         impl ::prost::bytes::Bytes {
@@ -399,4 +2052,629 @@ mod prost_integration {
             }
         }
     );
+
+    // `google.protobuf.Empty` is generated as `()`.
+    maskable_atomic!(
+        impl () {}
+    );
+
+    maskable_atomic!(impl ::prost_types::Timestamp {});
+    maskable_atomic!(impl ::prost_types::Duration {});
+    maskable_atomic!(impl ::prost_types::Any {});
+    maskable_atomic!(impl ::prost_types::FieldMask {});
+    maskable_atomic!(impl ::prost_types::Value {});
+    maskable_atomic!(impl ::prost_types::ListValue {});
+
+    /// Returns the nested struct of `value`, if it is one.
+    fn nested_struct(value: &Value) -> Option<&Struct> {
+        match &value.kind {
+            Some(Kind::StructValue(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the nested struct of `value` mutably, if it is one.
+    fn nested_struct_mut(value: &mut Value) -> Option<&mut Struct> {
+        match &mut value.kind {
+            Some(Kind::StructValue(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the nested struct of `value` by value, or `value` itself if it isn't a struct.
+    fn into_nested_struct(value: Value) -> Result<Struct, Value> {
+        match value.kind {
+            Some(Kind::StructValue(value)) => Ok(value),
+            kind => Err(Value { kind }),
+        }
+    }
+
+    impl Maskable for Struct {
+        type Mask = KeyMask;
+
+        fn empty_mask() -> Self::Mask {
+            KeyMask::default()
+        }
+
+        fn full_mask() -> Self::Mask {
+            KeyMask::default()
+        }
+
+        fn make_mask_include_field<'a>(
+            mask: &mut Self::Mask,
+            field_path: &[&'a str],
+        ) -> Result<(), DeserializeMaskError<'a>> {
+            mask.include_field(field_path);
+            Ok(())
+        }
+
+        fn mask_field_paths(mask: &Self::Mask) -> Vec<Vec<Cow<'static, str>>> {
+            mask.key_paths()
+        }
+    }
+
+    // Keys selected with a nested mask are projected, cleared or updated recursively if their
+    // values are structs, and as a whole otherwise. Structs have no field to normalize or redact,
+    // so the project options are only passed down to nested structs.
+    #[allow(clippy::only_used_in_recursion)]
+    impl SelfMaskable for Struct {
+        fn project(&mut self, mask: &Self::Mask, options: &ProjectOptions) {
+            if mask.is_empty() {
+                return;
+            }
+
+            self.fields.retain(|key, _| mask.fields.contains_key(key));
+            for (key, value) in &mut self.fields {
+                let mask = &mask.fields[key];
+                if let Some(value) = nested_struct_mut(value).filter(|_| !mask.is_empty()) {
+                    value.project(mask, options);
+                }
+            }
+        }
+
+        fn project_ref(&self, mask: &Self::Mask, options: &ProjectOptions) -> Self {
+            if mask.is_empty() {
+                return self.clone();
+            }
+
+            let fields = mask
+                .fields
+                .iter()
+                .filter_map(|(key, mask)| {
+                    let value = self.fields.get(key)?;
+                    let value = match nested_struct(value).filter(|_| !mask.is_empty()) {
+                        Some(value) => Value {
+                            kind: Some(Kind::StructValue(value.project_ref(mask, options))),
+                        },
+                        None => value.clone(),
+                    };
+                    Some((key.clone(), value))
+                })
+                .collect();
+            Self { fields }
+        }
+
+        fn clear(&mut self, mask: &Self::Mask, options: &ProjectOptions) {
+            if mask.is_empty() {
+                *self = Self::default();
+                return;
+            }
+
+            for (key, mask) in &mask.fields {
+                if mask.is_empty() {
+                    self.fields.remove(key);
+                } else if let Some(value) = self.fields.get_mut(key).and_then(nested_struct_mut) {
+                    value.clear(mask, options);
+                } else {
+                    self.fields.remove(key);
+                }
+            }
+        }
+
+        fn update_as_field(
+            &mut self,
+            mut source: Self,
+            mask: &Self::Mask,
+            options: &UpdateOptions,
+        ) {
+            if mask.is_empty() {
+                self.merge(source, options);
+                return;
+            }
+
+            for (key, mask) in &mask.fields {
+                let source = source.fields.remove(key);
+                let target = self.fields.get_mut(key).and_then(nested_struct_mut);
+                match (target, source.map(into_nested_struct)) {
+                    // A nested struct selected as a whole is merged, the same as a message field.
+                    (Some(target), Some(Ok(source))) if mask.is_empty() => {
+                        target.merge(source, options);
+                    }
+                    (Some(target), Some(Ok(source))) => {
+                        target.update_as_field(source, mask, options);
+                    }
+                    (None, Some(Ok(mut source))) => {
+                        source.project(mask, &ProjectOptions::default());
+                        self.fields.insert(
+                            key.clone(),
+                            Value {
+                                kind: Some(Kind::StructValue(source)),
+                            },
+                        );
+                    }
+                    (_, Some(Err(source))) => {
+                        self.fields.insert(key.clone(), source);
+                    }
+                    (_, None) => {
+                        self.fields.remove(key);
+                    }
+                }
+            }
+        }
+
+        fn merge(&mut self, source: Self, options: &UpdateOptions) {
+            if options.replace_message {
+                *self = source;
+                return;
+            }
+
+            // Nested structs are merged, the same way as nested messages.
+            for (key, source) in source.fields {
+                let target = self.fields.get_mut(&key).and_then(nested_struct_mut);
+                match (target, into_nested_struct(source)) {
+                    (Some(target), Ok(source)) => target.merge(source, options),
+                    (_, Ok(source)) => {
+                        self.fields.insert(
+                            key,
+                            Value {
+                                kind: Some(Kind::StructValue(source)),
+                            },
+                        );
+                    }
+                    (_, Err(source)) => {
+                        self.fields.insert(key, source);
+                    }
+                }
+            }
+        }
+
+        fn diff(&self, other: &Self) -> Option<Self::Mask> {
+            let keys = self.fields.keys().chain(other.fields.keys());
+            let mut mask = KeyMask::default();
+            for key in keys {
+                if mask.fields.contains_key(key) {
+                    continue;
+                }
+                let this = self.fields.get(key);
+                let other = other.fields.get(key);
+                let diff = match (this.and_then(nested_struct), other.and_then(nested_struct)) {
+                    (Some(this), Some(other)) => this.diff(other),
+                    _ => (this != other).then(KeyMask::default),
+                };
+                if let Some(diff) = diff {
+                    mask.fields.insert(key.clone(), diff);
+                }
+            }
+            (!mask.is_empty()).then_some(mask)
+        }
+
+        fn eq_masked(&self, other: &Self, mask: &Self::Mask) -> bool {
+            if mask.is_empty() {
+                return self == other;
+            }
+
+            mask.fields.iter().all(|(key, mask)| {
+                let this = self.fields.get(key);
+                let other = other.fields.get(key);
+                match (this.and_then(nested_struct), other.and_then(nested_struct)) {
+                    (Some(this), Some(other)) => this.eq_masked(other, mask),
+                    _ => this == other,
+                }
+            })
+        }
+
+        // Structs are visited as leaf fields, since their keys can't be part of `path`. A struct
+        // selected by keys is visited as its projection.
+        fn visit(
+            &self,
+            mask: &Self::Mask,
+            path: &mut Vec<&'static str>,
+            visitor: &mut dyn MaskVisitor,
+        ) {
+            if mask.is_empty() {
+                visitor.visit_field(path, FieldRef::new(self));
+            } else {
+                let projection = self.project_ref(mask, &ProjectOptions::default());
+                visitor.visit_field(path, FieldRef::new(&projection));
+            }
+        }
+    }
+
+    // An empty struct is only normalized to `None` if requested, since it's a set field.
+    impl OptionMaskable for Struct {
+        fn option_project(this: &mut Option<Self>, mask: &Self::Mask, options: &ProjectOptions) {
+            if let Some(inner) = this {
+                inner.project(mask, options);
+                if options.normalize && inner.fields.is_empty() {
+                    *this = None;
+                }
+            }
+        }
+
+        fn option_project_ref(
+            this: Option<&Self>,
+            mask: &Self::Mask,
+            options: &ProjectOptions,
+        ) -> Option<Self> {
+            this.map(|this| this.project_ref(mask, options))
+                .filter(|inner| !options.normalize || !inner.fields.is_empty())
+        }
+
+        fn option_clear(this: &mut Option<Self>, mask: &Self::Mask, options: &ProjectOptions) {
+            if let Some(inner) = this {
+                inner.clear(mask, options);
+                if inner.fields.is_empty() {
+                    *this = None;
+                }
+            }
+        }
+
+        fn option_update_as_field(
+            this: &mut Option<Self>,
+            source: Option<Self>,
+            mask: &Self::Mask,
+            options: &UpdateOptions,
+        ) {
+            match (this.as_mut(), source) {
+                (Some(this), source) => {
+                    this.update_as_field(source.unwrap_or_default(), mask, options)
+                }
+                (None, Some(mut source)) => {
+                    source.project(mask, &ProjectOptions::default());
+                    *this = Some(source);
+                }
+                (None, None) => {}
+            }
+        }
+
+        fn option_merge(this: &mut Option<Self>, source: Option<Self>, options: &UpdateOptions) {
+            match (this.as_mut(), source) {
+                (Some(this), Some(source)) => this.merge(source, options),
+                (_, None) => {}
+                (None, source) => *this = source,
+            }
+        }
+
+        fn option_diff(this: Option<&Self>, other: Option<&Self>) -> Option<Self::Mask> {
+            // `None` is treated the same as the default value.
+            let default = Self::default();
+            this.unwrap_or(&default).diff(other.unwrap_or(&default))
+        }
+
+        fn option_eq_masked(this: Option<&Self>, other: Option<&Self>, mask: &Self::Mask) -> bool {
+            let default = Self::default();
+            this.unwrap_or(&default)
+                .eq_masked(other.unwrap_or(&default), mask)
+        }
+
+        fn option_visit(
+            this: Option<&Self>,
+            mask: &Self::Mask,
+            path: &mut Vec<&'static str>,
+            visitor: &mut dyn MaskVisitor,
+        ) {
+            if let Some(this) = this {
+                this.visit(mask, path, visitor);
+            }
+        }
+    }
+}
+
+// A key mask selects the fields of the objects in a JSON value, including the ones in arrays of
+// objects. Other values are selected as a whole.
+#[cfg(feature = "serde_json")]
+mod serde_json_integration {
+    use ::serde_json::{Map, Value};
+
+    use super::*;
+    use crate::FieldRef;
+
+    fn project_value(value: &mut Value, mask: &KeyMask) {
+        if mask.is_empty() {
+            return;
+        }
+
+        match value {
+            Value::Object(object) => {
+                object.retain(|key, _| mask.fields.contains_key(key));
+                for (key, value) in object.iter_mut() {
+                    project_value(value, &mask.fields[key]);
+                }
+            }
+            Value::Array(items) => {
+                for item in items {
+                    project_value(item, mask);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Clear the value selected by `mask`. A key is removed only when its own mask is empty, and
+    /// the values that have no keys, such as strings, are kept as they are when the mask is not
+    /// empty.
+    fn clear_value(value: &mut Value, mask: &KeyMask) {
+        if mask.is_empty() {
+            *value = Value::Null;
+            return;
+        }
+
+        match value {
+            Value::Object(object) => {
+                for (key, mask) in &mask.fields {
+                    if mask.is_empty() {
+                        object.remove(key);
+                    } else if let Some(value) = object.get_mut(key) {
+                        clear_value(value, mask);
+                    }
+                }
+            }
+            Value::Array(items) => {
+                for item in items {
+                    clear_value(item, mask);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Update the fields of `target` selected by `mask`, which is not empty, with `source`. A
+    /// field missing from `source` is removed from `target`.
+    fn update_value(target: &mut Value, mut source: Value, mask: &KeyMask) {
+        if source.is_null() && target.is_object() {
+            source = Value::Object(Map::new());
+        }
+        match (target, source) {
+            (Value::Object(target), Value::Object(mut source)) => {
+                for (key, mask) in &mask.fields {
+                    let Some(mut source) = source.remove(key) else {
+                        target.remove(key);
+                        continue;
+                    };
+                    match target.get_mut(key) {
+                        Some(target) if !mask.is_empty() => update_value(target, source, mask),
+                        _ => {
+                            project_value(&mut source, mask);
+                            target.insert(key.clone(), source);
+                        }
+                    }
+                }
+            }
+            (target, mut source) => {
+                project_value(&mut source, mask);
+                *target = source;
+            }
+        }
+    }
+
+    /// Merge `source` into `target`. Nested objects are merged, the same way as nested messages,
+    /// and the other values are replaced unless they are null in `source`.
+    fn merge_value(target: &mut Value, source: Value) {
+        match (target, source) {
+            (_, Value::Null) => {}
+            (Value::Object(target), Value::Object(source)) => {
+                for (key, source) in source {
+                    match target.get_mut(&key) {
+                        Some(target) => merge_value(target, source),
+                        None if !source.is_null() => {
+                            target.insert(key, source);
+                        }
+                        None => {}
+                    }
+                }
+            }
+            (target, source) => *target = source,
+        }
+    }
+
+    fn diff_value(this: &Value, other: &Value) -> Option<KeyMask> {
+        let (Value::Object(this), Value::Object(other)) = (this, other) else {
+            return (this != other).then(KeyMask::default);
+        };
+
+        let mut mask = KeyMask::default();
+        for key in this.keys().chain(other.keys()) {
+            if mask.fields.contains_key(key) {
+                continue;
+            }
+            let diff = match (this.get(key), other.get(key)) {
+                (Some(this), Some(other)) => diff_value(this, other),
+                (this, other) => (this != other).then(KeyMask::default),
+            };
+            if let Some(diff) = diff {
+                mask.fields.insert(key.clone(), diff);
+            }
+        }
+        (!mask.is_empty()).then_some(mask)
+    }
+
+    fn eq_value(this: &Value, other: &Value, mask: &KeyMask) -> bool {
+        match (this, other) {
+            _ if mask.is_empty() => this == other,
+            (Value::Object(this), Value::Object(other)) => {
+                mask.fields
+                    .iter()
+                    .all(|(key, mask)| match (this.get(key), other.get(key)) {
+                        (Some(this), Some(other)) => eq_value(this, other, mask),
+                        (this, other) => this == other,
+                    })
+            }
+            (Value::Array(this), Value::Array(other)) => {
+                this.len() == other.len()
+                    && this
+                        .iter()
+                        .zip(other)
+                        .all(|(this, other)| eq_value(this, other, mask))
+            }
+            _ => this == other,
+        }
+    }
+
+    impl Maskable for Value {
+        type Mask = KeyMask;
+
+        fn empty_mask() -> Self::Mask {
+            KeyMask::default()
+        }
+
+        fn full_mask() -> Self::Mask {
+            KeyMask::default()
+        }
+
+        fn make_mask_include_field<'a>(
+            mask: &mut Self::Mask,
+            field_path: &[&'a str],
+        ) -> Result<(), DeserializeMaskError<'a>> {
+            mask.include_field(field_path);
+            Ok(())
+        }
+
+        fn mask_field_paths(mask: &Self::Mask) -> Vec<Vec<Cow<'static, str>>> {
+            mask.key_paths()
+        }
+    }
+
+    impl SelfMaskable for Value {
+        fn project(&mut self, mask: &Self::Mask, _options: &ProjectOptions) {
+            project_value(self, mask);
+        }
+
+        fn project_ref(&self, mask: &Self::Mask, _options: &ProjectOptions) -> Self {
+            let mut projection = self.clone();
+            project_value(&mut projection, mask);
+            projection
+        }
+
+        fn clear(&mut self, mask: &Self::Mask, _options: &ProjectOptions) {
+            clear_value(self, mask);
+        }
+
+        fn update_as_field(&mut self, source: Self, mask: &Self::Mask, options: &UpdateOptions) {
+            if mask.is_empty() {
+                self.merge(source, options);
+            } else {
+                update_value(self, source, mask);
+            }
+        }
+
+        fn merge(&mut self, source: Self, options: &UpdateOptions) {
+            if options.replace_message {
+                *self = source;
+            } else {
+                merge_value(self, source);
+            }
+        }
+
+        fn diff(&self, other: &Self) -> Option<Self::Mask> {
+            diff_value(self, other)
+        }
+
+        fn eq_masked(&self, other: &Self, mask: &Self::Mask) -> bool {
+            eq_value(self, other, mask)
+        }
+
+        // JSON values are visited as leaf fields, since their keys can't be part of `path`. A value
+        // selected by keys is visited as its projection.
+        fn visit(
+            &self,
+            mask: &Self::Mask,
+            path: &mut Vec<&'static str>,
+            visitor: &mut dyn MaskVisitor,
+        ) {
+            if mask.is_empty() {
+                visitor.visit_field(path, FieldRef::new(self));
+            } else {
+                let projection = self.project_ref(mask, &ProjectOptions::default());
+                visitor.visit_field(path, FieldRef::new(&projection));
+            }
+        }
+    }
+
+    // `None` is treated the same as `null`, which is normalized to `None` when projected.
+    impl OptionMaskable for Value {
+        fn option_project(this: &mut Option<Self>, mask: &Self::Mask, options: &ProjectOptions) {
+            if let Some(inner) = this {
+                inner.project(mask, options);
+                if options.normalize && inner.is_null() {
+                    *this = None;
+                }
+            }
+        }
+
+        fn option_project_ref(
+            this: Option<&Self>,
+            mask: &Self::Mask,
+            options: &ProjectOptions,
+        ) -> Option<Self> {
+            this.map(|this| this.project_ref(mask, options))
+                .filter(|inner| !(options.normalize && inner.is_null()))
+        }
+
+        fn option_clear(this: &mut Option<Self>, mask: &Self::Mask, options: &ProjectOptions) {
+            if mask.is_empty() {
+                *this = None;
+            } else if let Some(inner) = this {
+                inner.clear(mask, options);
+            }
+        }
+
+        fn option_update_as_field(
+            this: &mut Option<Self>,
+            source: Option<Self>,
+            mask: &Self::Mask,
+            options: &UpdateOptions,
+        ) {
+            match (this.as_mut(), source) {
+                (Some(this), source) => {
+                    this.update_as_field(source.unwrap_or_default(), mask, options)
+                }
+                (None, Some(mut source)) => {
+                    source.project(mask, &ProjectOptions::default());
+                    *this = Some(source);
+                }
+                (None, None) => {}
+            }
+        }
+
+        fn option_merge(this: &mut Option<Self>, source: Option<Self>, options: &UpdateOptions) {
+            match (this.as_mut(), source) {
+                (Some(this), Some(source)) => this.merge(source, options),
+                (_, None) => {}
+                (None, source) => *this = source,
+            }
+        }
+
+        fn option_diff(this: Option<&Self>, other: Option<&Self>) -> Option<Self::Mask> {
+            diff_value(this.unwrap_or(&Value::Null), other.unwrap_or(&Value::Null))
+        }
+
+        fn option_eq_masked(this: Option<&Self>, other: Option<&Self>, mask: &Self::Mask) -> bool {
+            eq_value(
+                this.unwrap_or(&Value::Null),
+                other.unwrap_or(&Value::Null),
+                mask,
+            )
+        }
+
+        fn option_visit(
+            this: Option<&Self>,
+            mask: &Self::Mask,
+            path: &mut Vec<&'static str>,
+            visitor: &mut dyn MaskVisitor,
+        ) {
+            if let Some(this) = this {
+                this.visit(mask, path, visitor);
+            }
+        }
+    }
 }
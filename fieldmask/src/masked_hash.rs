@@ -0,0 +1,437 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque, hash_map::DefaultHasher},
+    hash::{Hash, Hasher},
+    rc::Rc,
+    sync::Arc,
+};
+
+use crate::{KeyedRepeated, Maskable, SelfMaskable};
+
+/// A trait for types whose fields selected by a field mask can be hashed.
+///
+/// This is a separate trait from `SelfMaskable` because many maskable types (e.g. floats and
+/// `HashMap`) don't implement `Hash`. Values that are equal according to `SelfMaskable::eq_masked`
+/// with the same mask must have the same hash.
+pub trait MaskedHash: Maskable {
+    /// Feed the fields of `self` selected by `mask` into `state`.
+    ///
+    /// An empty `mask` is the same as a full `mask`.
+    fn hash_masked<H: Hasher>(&self, mask: &Self::Mask, state: &mut H);
+}
+
+/// A trait for types whose fields selected by a field mask can be hashed when wrapped in an
+/// `Option`.
+///
+/// Similar to `OptionMaskable`, optional messages that are `None` must be hashed the same as their
+/// default values.
+pub trait OptionMaskedHash: Maskable + Sized {
+    /// Similar to `MaskedHash::hash_masked`, but it takes `Option<&Self>` instead of `&Self`.
+    fn option_hash_masked<H: Hasher>(this: Option<&Self>, mask: &Self::Mask, state: &mut H);
+}
+
+impl<T: OptionMaskedHash> MaskedHash for Option<T> {
+    fn hash_masked<H: Hasher>(&self, mask: &Self::Mask, state: &mut H) {
+        T::option_hash_masked(self.as_ref(), mask, state);
+    }
+}
+
+impl<T: OptionMaskedHash> OptionMaskedHash for Option<T> {
+    fn option_hash_masked<H: Hasher>(this: Option<&Self>, mask: &Self::Mask, state: &mut H) {
+        T::option_hash_masked(this.and_then(Option::as_ref), mask, state);
+    }
+}
+
+impl<T: MaskedHash> MaskedHash for Box<T> {
+    fn hash_masked<H: Hasher>(&self, mask: &Self::Mask, state: &mut H) {
+        (**self).hash_masked(mask, state);
+    }
+}
+
+impl<T: OptionMaskedHash> OptionMaskedHash for Box<T> {
+    fn option_hash_masked<H: Hasher>(this: Option<&Self>, mask: &Self::Mask, state: &mut H) {
+        T::option_hash_masked(this.map(|this| &**this), mask, state);
+    }
+}
+
+/// Implement `MaskedHash` and `OptionMaskedHash` for wrappers that dereference to their values.
+macro_rules! masked_hash_wrapper {
+    ($($ptr:ident),* $(,)?) => {
+        $(
+            impl<T: MaskedHash + Clone> MaskedHash for $ptr<T> {
+                fn hash_masked<H: Hasher>(&self, mask: &Self::Mask, state: &mut H) {
+                    (**self).hash_masked(mask, state);
+                }
+            }
+
+            impl<T: OptionMaskedHash + Clone> OptionMaskedHash for $ptr<T> {
+                fn option_hash_masked<H: Hasher>(
+                    this: Option<&Self>,
+                    mask: &Self::Mask,
+                    state: &mut H,
+                ) {
+                    T::option_hash_masked(this.map(|this| &**this), mask, state);
+                }
+            }
+        )*
+    };
+}
+
+masked_hash_wrapper!(Arc, Rc);
+
+impl<T: MaskedHash + Clone> MaskedHash for Cow<'_, T> {
+    fn hash_masked<H: Hasher>(&self, mask: &Self::Mask, state: &mut H) {
+        (**self).hash_masked(mask, state);
+    }
+}
+
+impl<T: OptionMaskedHash + Clone> OptionMaskedHash for Cow<'_, T> {
+    fn option_hash_masked<H: Hasher>(this: Option<&Self>, mask: &Self::Mask, state: &mut H) {
+        T::option_hash_masked(this.map(|this| &**this), mask, state);
+    }
+}
+
+/// Hash an optional atomic value. `None` is hashed differently from any value.
+fn option_hash_atomic<T: MaskedHash, H: Hasher>(this: Option<&T>, mask: &T::Mask, state: &mut H) {
+    this.is_some().hash(state);
+    if let Some(this) = this {
+        this.hash_masked(mask, state);
+    }
+}
+
+/// Implement `MaskedHash` and `OptionMaskedHash` for atomic types that implement `Hash`.
+macro_rules! masked_hash_atomic {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl MaskedHash for $ty {
+                fn hash_masked<H: Hasher>(&self, _mask: &Self::Mask, state: &mut H) {
+                    self.hash(state);
+                }
+            }
+
+            impl OptionMaskedHash for $ty {
+                fn option_hash_masked<H: Hasher>(
+                    this: Option<&Self>,
+                    _mask: &Self::Mask,
+                    state: &mut H,
+                ) {
+                    this.hash(state);
+                }
+            }
+        )*
+    };
+}
+
+masked_hash_atomic!(
+    bool,
+    char,
+    i8,
+    u8,
+    i16,
+    u16,
+    i32,
+    u32,
+    i64,
+    u64,
+    i128,
+    u128,
+    isize,
+    usize,
+    String,
+    Cow<'static, str>,
+);
+
+/// Implement `MaskedHash` and `OptionMaskedHash` for floats by hashing their bits. Zeros are hashed
+/// the same regardless of their signs, since they are equal.
+macro_rules! masked_hash_float {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl MaskedHash for $ty {
+                fn hash_masked<H: Hasher>(&self, _mask: &Self::Mask, state: &mut H) {
+                    let value = if *self == 0.0 { 0.0 } else { *self };
+                    value.to_bits().hash(state);
+                }
+            }
+
+            impl OptionMaskedHash for $ty {
+                fn option_hash_masked<H: Hasher>(
+                    this: Option<&Self>,
+                    mask: &Self::Mask,
+                    state: &mut H,
+                ) {
+                    option_hash_atomic(this, mask, state);
+                }
+            }
+        )*
+    };
+}
+
+masked_hash_float!(f32, f64);
+
+impl<T: Clone + PartialEq + MaskedHash + 'static> MaskedHash for Vec<T> {
+    fn hash_masked<H: Hasher>(&self, _mask: &Self::Mask, state: &mut H) {
+        hash_ordered(self.len(), self, state);
+    }
+}
+
+impl<T: Clone + PartialEq + MaskedHash + 'static> OptionMaskedHash for Vec<T> {
+    fn option_hash_masked<H: Hasher>(this: Option<&Self>, mask: &Self::Mask, state: &mut H) {
+        option_hash_atomic(this, mask, state);
+    }
+}
+
+/// Hashes a repeated field declared with `#[fieldmask(key = "...")]` with the mask of its elements,
+/// the same way `KeyedRepeated` does for the other operations.
+#[doc(hidden)]
+pub trait KeyedMaskedHash: KeyedRepeated {
+    fn hash_masked<H: Hasher>(&self, mask: &<Self::Element as Maskable>::Mask, state: &mut H);
+}
+
+impl<T> KeyedMaskedHash for Vec<T>
+where
+    T: SelfMaskable + MaskedHash + Clone + PartialEq + 'static,
+{
+    fn hash_masked<H: Hasher>(&self, mask: &T::Mask, state: &mut H) {
+        if *mask == T::empty_mask() {
+            MaskedHash::hash_masked(self, &Self::empty_mask(), state);
+        } else {
+            self.len().hash(state);
+            for item in self {
+                item.hash_masked(mask, state);
+            }
+        }
+    }
+}
+
+/// Hash the items of an ordered collection.
+fn hash_ordered<'a, T: MaskedHash + 'a, H: Hasher>(
+    len: usize,
+    items: impl IntoIterator<Item = &'a T>,
+    state: &mut H,
+) {
+    len.hash(state);
+    for item in items {
+        item.hash_masked(&T::empty_mask(), state);
+    }
+}
+
+/// Hash the entries of an unordered collection. The entries are hashed independently and combined
+/// with a commutative operation, so that the hash doesn't depend on the iteration order.
+fn hash_unordered<H: Hasher, I: IntoIterator>(
+    len: usize,
+    entries: I,
+    hash_entry: impl Fn(I::Item, &mut DefaultHasher),
+    state: &mut H,
+) {
+    let mut sum = 0u64;
+    for entry in entries {
+        let mut hasher = DefaultHasher::new();
+        hash_entry(entry, &mut hasher);
+        sum = sum.wrapping_add(hasher.finish());
+    }
+    len.hash(state);
+    sum.hash(state);
+}
+
+impl<K: Clone + Eq + Hash + 'static, V: Clone + PartialEq + MaskedHash + 'static> MaskedHash
+    for HashMap<K, V>
+{
+    fn hash_masked<H: Hasher>(&self, _mask: &Self::Mask, state: &mut H) {
+        hash_unordered(
+            self.len(),
+            self,
+            |(key, value), hasher| {
+                key.hash(hasher);
+                value.hash_masked(&V::empty_mask(), hasher);
+            },
+            state,
+        );
+    }
+}
+
+impl<K: Clone + Eq + Hash + 'static, V: Clone + PartialEq + MaskedHash + 'static> OptionMaskedHash
+    for HashMap<K, V>
+{
+    fn option_hash_masked<H: Hasher>(this: Option<&Self>, mask: &Self::Mask, state: &mut H) {
+        option_hash_atomic(this, mask, state);
+    }
+}
+
+impl<T: Clone + PartialEq + MaskedHash + 'static, const N: usize> MaskedHash for [T; N]
+where
+    [T; N]: Default,
+{
+    fn hash_masked<H: Hasher>(&self, _mask: &Self::Mask, state: &mut H) {
+        hash_ordered(N, self, state);
+    }
+}
+
+impl<T: Clone + PartialEq + MaskedHash + 'static, const N: usize> OptionMaskedHash for [T; N]
+where
+    [T; N]: Default,
+{
+    fn option_hash_masked<H: Hasher>(this: Option<&Self>, mask: &Self::Mask, state: &mut H) {
+        option_hash_atomic(this, mask, state);
+    }
+}
+
+/// Implement `MaskedHash` and `OptionMaskedHash` for tuples by hashing their elements in order.
+macro_rules! masked_hash_tuple {
+    ($(($($ty:ident $index:tt),+)),* $(,)?) => {
+        $(
+            impl<$($ty: Clone + Default + PartialEq + MaskedHash + 'static),+> MaskedHash
+                for ($($ty,)+)
+            {
+                fn hash_masked<H: Hasher>(&self, _mask: &Self::Mask, state: &mut H) {
+                    $(self.$index.hash_masked(&$ty::empty_mask(), state);)+
+                }
+            }
+
+            impl<$($ty: Clone + Default + PartialEq + MaskedHash + 'static),+> OptionMaskedHash
+                for ($($ty,)+)
+            {
+                fn option_hash_masked<H: Hasher>(
+                    this: Option<&Self>,
+                    mask: &Self::Mask,
+                    state: &mut H,
+                ) {
+                    option_hash_atomic(this, mask, state);
+                }
+            }
+        )*
+    };
+}
+
+masked_hash_tuple!(
+    (A 0),
+    (A 0, B 1),
+    (A 0, B 1, C 2),
+    (A 0, B 1, C 2, D 3),
+    (A 0, B 1, C 2, D 3, E 4),
+    (A 0, B 1, C 2, D 3, E 4, F 5),
+);
+
+impl<T: Clone + PartialEq + MaskedHash + 'static> MaskedHash for VecDeque<T> {
+    fn hash_masked<H: Hasher>(&self, _mask: &Self::Mask, state: &mut H) {
+        hash_ordered(self.len(), self, state);
+    }
+}
+
+impl<T: Clone + PartialEq + MaskedHash + 'static> OptionMaskedHash for VecDeque<T> {
+    fn option_hash_masked<H: Hasher>(this: Option<&Self>, mask: &Self::Mask, state: &mut H) {
+        option_hash_atomic(this, mask, state);
+    }
+}
+
+impl<T: Clone + PartialEq + MaskedHash + 'static> MaskedHash for Box<[T]> {
+    fn hash_masked<H: Hasher>(&self, _mask: &Self::Mask, state: &mut H) {
+        hash_ordered(self.len(), self.iter(), state);
+    }
+}
+
+impl<T: Clone + PartialEq + MaskedHash + 'static> OptionMaskedHash for Box<[T]> {
+    fn option_hash_masked<H: Hasher>(this: Option<&Self>, mask: &Self::Mask, state: &mut H) {
+        option_hash_atomic(this, mask, state);
+    }
+}
+
+impl<T: Clone + PartialEq + MaskedHash + 'static> MaskedHash for Cow<'static, [T]> {
+    fn hash_masked<H: Hasher>(&self, _mask: &Self::Mask, state: &mut H) {
+        hash_ordered(self.len(), self.iter(), state);
+    }
+}
+
+impl<T: Clone + PartialEq + MaskedHash + 'static> OptionMaskedHash for Cow<'static, [T]> {
+    fn option_hash_masked<H: Hasher>(this: Option<&Self>, mask: &Self::Mask, state: &mut H) {
+        option_hash_atomic(this, mask, state);
+    }
+}
+
+impl<K: Clone + Ord + Hash + 'static, V: Clone + PartialEq + MaskedHash + 'static> MaskedHash
+    for BTreeMap<K, V>
+{
+    fn hash_masked<H: Hasher>(&self, _mask: &Self::Mask, state: &mut H) {
+        self.len().hash(state);
+        for (key, value) in self {
+            key.hash(state);
+            value.hash_masked(&V::empty_mask(), state);
+        }
+    }
+}
+
+impl<K: Clone + Ord + Hash + 'static, V: Clone + PartialEq + MaskedHash + 'static> OptionMaskedHash
+    for BTreeMap<K, V>
+{
+    fn option_hash_masked<H: Hasher>(this: Option<&Self>, mask: &Self::Mask, state: &mut H) {
+        option_hash_atomic(this, mask, state);
+    }
+}
+
+impl<T: Clone + Eq + Hash + 'static> MaskedHash for HashSet<T> {
+    fn hash_masked<H: Hasher>(&self, _mask: &Self::Mask, state: &mut H) {
+        hash_unordered(self.len(), self, |item, hasher| item.hash(hasher), state);
+    }
+}
+
+impl<T: Clone + Eq + Hash + 'static> OptionMaskedHash for HashSet<T> {
+    fn option_hash_masked<H: Hasher>(this: Option<&Self>, mask: &Self::Mask, state: &mut H) {
+        option_hash_atomic(this, mask, state);
+    }
+}
+
+impl<T: Clone + Ord + Hash + 'static> MaskedHash for BTreeSet<T> {
+    fn hash_masked<H: Hasher>(&self, _mask: &Self::Mask, state: &mut H) {
+        self.hash(state);
+    }
+}
+
+impl<T: Clone + Ord + Hash + 'static> OptionMaskedHash for BTreeSet<T> {
+    fn option_hash_masked<H: Hasher>(this: Option<&Self>, mask: &Self::Mask, state: &mut H) {
+        option_hash_atomic(this, mask, state);
+    }
+}
+
+// `IndexMap`s are equal regardless of the order of their entries, so they are hashed the same way
+// as `HashMap`s.
+#[cfg(feature = "indexmap")]
+mod indexmap_integration {
+    use ::indexmap::IndexMap;
+
+    use super::*;
+
+    impl<K: Clone + Eq + Hash + 'static, V: Clone + PartialEq + MaskedHash + 'static> MaskedHash
+        for IndexMap<K, V>
+    {
+        fn hash_masked<H: Hasher>(&self, _mask: &Self::Mask, state: &mut H) {
+            hash_unordered(
+                self.len(),
+                self,
+                |(key, value), hasher| {
+                    key.hash(hasher);
+                    value.hash_masked(&V::empty_mask(), hasher);
+                },
+                state,
+            );
+        }
+    }
+
+    impl<K: Clone + Eq + Hash + 'static, V: Clone + PartialEq + MaskedHash + 'static>
+        OptionMaskedHash for IndexMap<K, V>
+    {
+        fn option_hash_masked<H: Hasher>(this: Option<&Self>, mask: &Self::Mask, state: &mut H) {
+            option_hash_atomic(this, mask, state);
+        }
+    }
+}
+
+#[cfg(feature = "prost")]
+mod prost_integration {
+    use super::*;
+
+    masked_hash_atomic!(
+        ::prost::bytes::Bytes,
+        (),
+        ::prost_types::Timestamp,
+        ::prost_types::Duration,
+    );
+}
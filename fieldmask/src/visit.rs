@@ -0,0 +1,65 @@
+use std::any::{Any, type_name};
+
+/// A reference to a leaf field visited by a `MaskVisitor`.
+#[derive(Clone, Copy)]
+pub struct FieldRef<'a> {
+    value: &'a dyn Any,
+    type_name: &'static str,
+}
+
+impl<'a> FieldRef<'a> {
+    pub fn new<T: Any>(value: &'a T) -> Self {
+        Self {
+            value,
+            type_name: type_name::<T>(),
+        }
+    }
+
+    /// Returns the value if it is of type `T`.
+    pub fn downcast_ref<T: Any>(&self) -> Option<&'a T> {
+        self.value.downcast_ref()
+    }
+
+    /// Returns whether the value is of type `T`.
+    pub fn is<T: Any>(&self) -> bool {
+        self.value.is::<T>()
+    }
+
+    /// Returns the value as `&dyn Any`.
+    pub fn as_any(&self) -> &'a dyn Any {
+        self.value
+    }
+
+    /// Returns the name of the type of the value, as returned by `std::any::type_name`.
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+}
+
+impl std::fmt::Debug for FieldRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FieldRef")
+            .field("type_name", &self.type_name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A visitor of the leaf fields selected by a field mask. See `Mask::visit`.
+///
+/// Leaf fields are atomic values (e.g. `u32`, `String` or `Vec<T>`), unit enums and unit variants
+/// of oneof enums, which are visited as `()`.
+///
+/// Paths are made of `&'static str`s, so they can't hold keys or indices only known at runtime.
+/// A value selected by such keys, i.e. a repeated field declared with `#[fieldmask(key = "...")]`,
+/// a `google.protobuf.Struct` or a `serde_json::Value`, is visited as a single leaf: the projection
+/// of the value by its mask, at the path of the field.
+pub trait MaskVisitor {
+    /// Visit the leaf field at `path`, a field mask path splitted by '.'.
+    fn visit_field(&mut self, path: &[&'static str], field: FieldRef<'_>);
+}
+
+impl<F: FnMut(&[&'static str], FieldRef<'_>)> MaskVisitor for F {
+    fn visit_field(&mut self, path: &[&'static str], field: FieldRef<'_>) {
+        self(path, field)
+    }
+}
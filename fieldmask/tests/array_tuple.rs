@@ -0,0 +1,195 @@
+use std::{collections::hash_map::DefaultHasher, convert::TryFrom, hash::Hasher};
+
+use fieldmask::{
+    DeserializeMaskError, Mask, MaskInput, Maskable, MaskedHash, ProjectOptions, SelfMaskable,
+};
+
+#[derive(Debug, Default, Maskable, MaskedHash, PartialEq, SelfMaskable)]
+struct Parent {
+    digest: [u8; 32],
+    position: [f64; 3],
+    range: (i32, i32),
+    optional_range: Option<(i32, i32)>,
+    // Tuple structs select their elements by index.
+    coords: Coords,
+}
+
+#[derive(Debug, Default, Maskable, MaskedHash, PartialEq, SelfMaskable)]
+struct Coords(i32, i32);
+
+#[derive(Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct Outer {
+    parent: Parent,
+}
+
+fn hash(value: &Parent) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    Mask::<Parent>::empty().hash_masked(value, &mut hasher);
+    hasher.finish()
+}
+
+mod mask {
+    use super::*;
+
+    #[test]
+    fn atomic_element() {
+        let err = Mask::<Parent>::try_from(MaskInput(vec!["range.0"].into_iter()))
+            .expect_err("tuples are atomic");
+
+        let DeserializeMaskError::InvalidField { field, err } = err else {
+            panic!("unexpected error: {err}");
+        };
+        assert_eq!(field, "range");
+        assert!(matches!(
+            *err,
+            DeserializeMaskError::FieldNotFound { field: "0", .. }
+        ));
+    }
+}
+
+mod project {
+    use super::*;
+
+    #[test]
+    fn regular_mask() {
+        let source = Parent {
+            digest: [1; 32],
+            position: [1.0, 2.0, 3.0],
+            range: (1, 2),
+            optional_range: Some((3, 4)),
+            coords: Coords(5, 6),
+        };
+        let mask = vec!["digest", "range", "coords.1"];
+        let expected = Parent {
+            digest: [1; 32],
+            range: (1, 2),
+            coords: Coords(0, 6),
+            ..Default::default()
+        };
+
+        let actual = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .project(source);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn normalize() {
+        let source = Parent {
+            range: (1, 2),
+            optional_range: Some((0, 0)),
+            ..Default::default()
+        };
+        let options = ProjectOptions::builder().normalize(true).build();
+
+        let actual = Mask::<Parent>::empty().project_with_options(source, &options);
+
+        assert_eq!(actual.optional_range, None);
+    }
+}
+
+mod update {
+    use super::*;
+
+    #[test]
+    fn regular_mask() {
+        let mut target = Parent {
+            digest: [1; 32],
+            position: [1.0, 2.0, 3.0],
+            range: (1, 2),
+            optional_range: Some((3, 4)),
+            coords: Coords(5, 6),
+        };
+        let source = Parent {
+            digest: [2; 32],
+            position: [0.0; 3],
+            range: (0, 0),
+            optional_range: Some((30, 40)),
+            coords: Coords(50, 60),
+        };
+        let mask = vec!["position", "optional_range", "coords.0"];
+        let expected = Parent {
+            digest: [1; 32],
+            position: [0.0; 3],
+            range: (1, 2),
+            optional_range: Some((30, 40)),
+            coords: Coords(50, 6),
+        };
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update(&mut target, source);
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn whole_message() {
+        let mut target = Outer {
+            parent: Parent {
+                digest: [1; 32],
+                position: [1.0, 2.0, 3.0],
+                range: (1, 2),
+                ..Default::default()
+            },
+        };
+        let source = Outer {
+            parent: Parent {
+                digest: [2; 32],
+                range: (0, 3),
+                ..Default::default()
+            },
+        };
+        let mask = vec!["parent"];
+        // Default values don't overwrite the target when merged.
+        let expected = Parent {
+            digest: [2; 32],
+            position: [1.0, 2.0, 3.0],
+            range: (0, 3),
+            ..Default::default()
+        };
+
+        Mask::<Outer>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update(&mut target, source);
+
+        assert_eq!(target.parent, expected);
+    }
+}
+
+mod hash {
+    use super::*;
+
+    #[test]
+    fn equal_values() {
+        let a = Parent {
+            digest: [1; 32],
+            position: [1.0, 2.0, 3.0],
+            range: (1, 2),
+            ..Default::default()
+        };
+        let b = Parent {
+            digest: [1; 32],
+            position: [1.0, 2.0, 3.0],
+            range: (1, 2),
+            ..Default::default()
+        };
+
+        assert_eq!(hash(&a), hash(&b));
+    }
+
+    #[test]
+    fn swapped_tuple_elements() {
+        let a = Parent {
+            range: (1, 2),
+            ..Default::default()
+        };
+        let b = Parent {
+            range: (2, 1),
+            ..Default::default()
+        };
+
+        assert_ne!(hash(&a), hash(&b));
+    }
+}
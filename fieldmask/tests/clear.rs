@@ -0,0 +1,237 @@
+use std::convert::TryFrom;
+
+use fieldmask::{Mask, MaskInput, Maskable, OptionMaskable, ProjectOptions, SelfMaskable};
+
+#[derive(Clone, Debug, Default, Maskable, OptionMaskable, PartialEq, SelfMaskable)]
+struct Child {
+    a: u32,
+    b: String,
+}
+
+#[derive(Clone, Debug, Maskable, OptionMaskable, PartialEq)]
+enum OneOf {
+    Text(String),
+    Child(Child),
+    Point { x: u32, y: u32 },
+    Nothing,
+}
+
+#[derive(Clone, Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct Flattened {
+    c: u32,
+    d: u32,
+}
+
+#[derive(Clone, Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct Parent {
+    child: Child,
+    optional_child: Option<Child>,
+    #[fieldmask(flatten)]
+    flatten_child: Flattened,
+    one_of: Option<OneOf>,
+    items: Vec<u32>,
+}
+
+mod clear {
+    use super::*;
+
+    #[test]
+    fn empty_mask() {
+        let mut target = Parent {
+            child: Child {
+                a: 1,
+                b: "b".into(),
+            },
+            optional_child: Some(Child {
+                a: 2,
+                b: "b".into(),
+            }),
+            flatten_child: Flattened { c: 3, d: 4 },
+            one_of: Some(OneOf::Nothing),
+            items: vec![5],
+        };
+
+        Mask::<Parent>::empty().clear(&mut target);
+
+        assert_eq!(target, Parent::default());
+    }
+
+    #[test]
+    fn regular_mask() {
+        let mut target = Parent {
+            child: Child {
+                a: 1,
+                b: "b".into(),
+            },
+            optional_child: Some(Child {
+                a: 2,
+                b: "b".into(),
+            }),
+            flatten_child: Flattened { c: 3, d: 4 },
+            one_of: Some(OneOf::Point { x: 5, y: 6 }),
+            items: vec![7, 8],
+        };
+        let mask = vec!["child.b", "optional_child.a", "c", "items"];
+        let expected = Parent {
+            child: Child {
+                a: 1,
+                b: Default::default(),
+            },
+            optional_child: Some(Child {
+                a: 0,
+                b: "b".into(),
+            }),
+            flatten_child: Flattened { c: 0, d: 4 },
+            one_of: Some(OneOf::Point { x: 5, y: 6 }),
+            items: vec![],
+        };
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .clear(&mut target);
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn whole_message() {
+        let mut target = Parent {
+            child: Child {
+                a: 1,
+                b: "b".into(),
+            },
+            optional_child: Some(Child {
+                a: 2,
+                b: "b".into(),
+            }),
+            items: vec![3],
+            ..Default::default()
+        };
+        let mask = vec!["child", "optional_child"];
+        let expected = Parent {
+            items: vec![3],
+            ..Default::default()
+        };
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .clear(&mut target);
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn optional_child_becomes_default() {
+        let mut target = Parent {
+            optional_child: Some(Child {
+                a: 1,
+                b: "b".into(),
+            }),
+            ..Default::default()
+        };
+        let mask = vec!["optional_child.a", "optional_child.b"];
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .clear(&mut target);
+
+        assert_eq!(target.optional_child, None);
+    }
+
+    #[test]
+    fn one_of_field() {
+        let mut target = Parent {
+            one_of: Some(OneOf::Point { x: 1, y: 2 }),
+            ..Default::default()
+        };
+        let mask = vec!["one_of.point.y"];
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .clear(&mut target);
+
+        assert_eq!(target.one_of, Some(OneOf::Point { x: 1, y: 0 }));
+    }
+
+    #[test]
+    fn whole_one_of_variant() {
+        let mut target = Parent {
+            one_of: Some(OneOf::Point { x: 1, y: 2 }),
+            ..Default::default()
+        };
+        let mask = vec!["one_of.point"];
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .clear(&mut target);
+
+        assert_eq!(target.one_of, None);
+    }
+
+    #[test]
+    fn other_one_of_variant() {
+        let mut target = Parent {
+            one_of: Some(OneOf::Point { x: 1, y: 2 }),
+            ..Default::default()
+        };
+        let mask = vec!["one_of.text", "one_of.nothing"];
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .clear(&mut target);
+
+        assert_eq!(target.one_of, Some(OneOf::Point { x: 1, y: 2 }));
+    }
+
+    #[test]
+    fn inverse_of_project() {
+        let source = Parent {
+            child: Child {
+                a: 1,
+                b: "b".into(),
+            },
+            flatten_child: Flattened { c: 2, d: 3 },
+            one_of: Some(OneOf::Point { x: 4, y: 5 }),
+            ..Default::default()
+        };
+        let mask = vec!["child.a", "c", "one_of.point.x"];
+
+        let mask = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask");
+        let projected = mask.project_ref(&source);
+        let mut cleared = source.clone();
+        mask.clear(&mut cleared);
+        // Updating the cleared value with the projection restores the source.
+        mask.update(&mut cleared, projected);
+
+        assert_eq!(cleared, source);
+    }
+
+    #[test]
+    fn normalize() {
+        let mut target = Parent {
+            child: Child {
+                a: 1,
+                b: "b".into(),
+            },
+            optional_child: Some(Child::default()),
+            ..Default::default()
+        };
+        let mask = vec!["child.a"];
+        let options = ProjectOptions::builder().normalize(true).build();
+        let expected = Parent {
+            child: Child {
+                a: 0,
+                b: "b".into(),
+            },
+            optional_child: None,
+            ..Default::default()
+        };
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .clear_with_options(&mut target, &options);
+
+        assert_eq!(target, expected);
+    }
+}
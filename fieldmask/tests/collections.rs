@@ -0,0 +1,274 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet, VecDeque, hash_map::DefaultHasher},
+    convert::TryFrom,
+    hash::Hasher,
+};
+
+use fieldmask::{
+    Mask, MaskInput, Maskable, MaskedHash, ProjectOptions, RepeatedStrategy, SelfMaskable,
+    UpdateOptions,
+};
+
+#[derive(Debug, Default, Maskable, MaskedHash, PartialEq, SelfMaskable)]
+struct Collections {
+    map: BTreeMap<String, u32>,
+    set: HashSet<u32>,
+    sorted_set: BTreeSet<u32>,
+    deque: VecDeque<u32>,
+    slice: Box<[u32]>,
+    optional_set: Option<BTreeSet<u32>>,
+}
+
+fn hash(value: &Collections) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    Mask::<Collections>::empty().hash_masked(value, &mut hasher);
+    hasher.finish()
+}
+
+mod update {
+    use super::*;
+
+    #[test]
+    fn default_options() {
+        let mut target = Collections {
+            map: [("a".into(), 1), ("b".into(), 2)].into(),
+            set: [1, 2].into(),
+            sorted_set: [1, 2].into(),
+            deque: [1, 2].into(),
+            slice: [1, 2].into(),
+            optional_set: Some([1, 2].into()),
+        };
+        let source = Collections {
+            map: [("b".into(), 3), ("c".into(), 4)].into(),
+            set: [2, 3].into(),
+            sorted_set: [2, 3].into(),
+            deque: [2, 3].into(),
+            slice: [2, 3].into(),
+            optional_set: Some([2, 3].into()),
+        };
+        let expected = Collections {
+            map: [("b".into(), 3), ("c".into(), 4)].into(),
+            set: [1, 2, 3].into(),
+            sorted_set: [1, 2, 3].into(),
+            deque: [1, 2, 2, 3].into(),
+            slice: [1, 2, 2, 3].into(),
+            optional_set: Some([1, 2, 3].into()),
+        };
+
+        Mask::<Collections>::empty().update(&mut target, source);
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn replace_repeated() {
+        let mut target = Collections {
+            map: [("a".into(), 1), ("b".into(), 2)].into(),
+            set: [1, 2].into(),
+            sorted_set: [1, 2].into(),
+            deque: [1, 2].into(),
+            slice: [1, 2].into(),
+            optional_set: Some([1, 2].into()),
+        };
+        let source = Collections {
+            map: [("b".into(), 3), ("c".into(), 4)].into(),
+            set: [2, 3].into(),
+            sorted_set: [2, 3].into(),
+            deque: [2, 3].into(),
+            slice: [2, 3].into(),
+            optional_set: Some([2, 3].into()),
+        };
+        let expected = Collections {
+            map: [("b".into(), 3), ("c".into(), 4)].into(),
+            set: [2, 3].into(),
+            sorted_set: [2, 3].into(),
+            deque: [2, 3].into(),
+            slice: [2, 3].into(),
+            optional_set: Some([2, 3].into()),
+        };
+
+        Mask::<Collections>::empty().update_with_options(
+            &mut target,
+            source,
+            &UpdateOptions::builder().replace_repeated(true).build(),
+        );
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn repeated_strategy() {
+        let mut target = Collections {
+            set: [1, 2].into(),
+            deque: [1, 2].into(),
+            slice: [1, 2].into(),
+            ..Default::default()
+        };
+        let source = Collections {
+            set: [2, 3].into(),
+            deque: [2, 3].into(),
+            slice: [2, 3].into(),
+            ..Default::default()
+        };
+
+        Mask::<Collections>::empty().update_with_options(
+            &mut target,
+            source,
+            &UpdateOptions::builder()
+                .repeated_strategy(RepeatedStrategy::Prepend)
+                .build(),
+        );
+
+        assert_eq!(target.set, [1, 2, 3].into());
+        assert_eq!(target.deque, [2, 3, 1, 2]);
+        assert_eq!(*target.slice, [2, 3, 1, 2]);
+    }
+
+    #[test]
+    fn regular_mask() {
+        let mut target = Collections {
+            map: [("a".into(), 1), ("b".into(), 2)].into(),
+            set: [1, 2].into(),
+            deque: [1, 2].into(),
+            ..Default::default()
+        };
+        let source = Collections {
+            map: [("b".into(), 3), ("c".into(), 4)].into(),
+            set: [2, 3].into(),
+            deque: [2, 3].into(),
+            ..Default::default()
+        };
+        let mask = vec!["map", "set"];
+        let expected = Collections {
+            map: [("b".into(), 3), ("c".into(), 4)].into(),
+            set: [1, 2, 3].into(),
+            deque: [1, 2].into(),
+            ..Default::default()
+        };
+
+        Mask::<Collections>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update(&mut target, source);
+
+        assert_eq!(target, expected);
+    }
+}
+
+mod project {
+    use super::*;
+
+    #[test]
+    fn regular_mask() {
+        let source = Collections {
+            map: [("a".into(), 1)].into(),
+            set: [1, 2].into(),
+            deque: [1, 2].into(),
+            slice: [1, 2].into(),
+            ..Default::default()
+        };
+        let mask = vec!["set", "deque"];
+        let expected = Collections {
+            set: [1, 2].into(),
+            deque: [1, 2].into(),
+            ..Default::default()
+        };
+
+        let actual = Mask::<Collections>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .project(source);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn normalize() {
+        let source = Collections {
+            set: [1, 2].into(),
+            optional_set: Some(BTreeSet::new()),
+            ..Default::default()
+        };
+
+        let actual = Mask::<Collections>::empty()
+            .project_with_options(source, &ProjectOptions::builder().normalize(true).build());
+
+        assert_eq!(actual.optional_set, None);
+    }
+}
+
+mod hash {
+    use super::*;
+
+    #[test]
+    fn equal_values() {
+        let a = Collections {
+            map: [("a".into(), 1)].into(),
+            set: [1, 2].into_iter().collect(),
+            ..Default::default()
+        };
+        let b = Collections {
+            map: [("a".into(), 1)].into(),
+            set: [2, 1].into_iter().collect(),
+            ..Default::default()
+        };
+
+        assert_eq!(hash(&a), hash(&b));
+    }
+
+    #[test]
+    fn different_values() {
+        let a = Collections {
+            map: [("a".into(), 1), ("b".into(), 2)].into(),
+            ..Default::default()
+        };
+        let b = Collections {
+            map: [("a".into(), 1), ("b".into(), 2), ("c".into(), 3)].into(),
+            ..Default::default()
+        };
+
+        assert_ne!(hash(&a), hash(&b));
+    }
+}
+
+#[cfg(feature = "indexmap")]
+mod index_map {
+    use indexmap::IndexMap;
+
+    use super::*;
+
+    #[derive(Debug, Default, Maskable, MaskedHash, PartialEq, SelfMaskable)]
+    struct Labels {
+        labels: IndexMap<String, u32>,
+    }
+
+    #[test]
+    fn update() {
+        let mut target = Labels {
+            labels: [("a".into(), 1)].into(),
+        };
+        let source = Labels {
+            labels: [("b".into(), 2)].into(),
+        };
+
+        Mask::<Labels>::empty().update(&mut target, source);
+
+        assert_eq!(target.labels, IndexMap::from([("b".into(), 2)]));
+    }
+
+    #[test]
+    fn hash_ignores_order() {
+        let hash = |value: &Labels| {
+            let mut hasher = DefaultHasher::new();
+            Mask::<Labels>::empty().hash_masked(value, &mut hasher);
+            hasher.finish()
+        };
+        let a = Labels {
+            labels: [("a".into(), 1), ("b".into(), 2)].into(),
+        };
+        let b = Labels {
+            labels: [("b".into(), 2), ("a".into(), 1)].into(),
+        };
+
+        assert_eq!(a, b);
+        assert_eq!(hash(&a), hash(&b));
+    }
+}
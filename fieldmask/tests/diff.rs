@@ -0,0 +1,215 @@
+use std::convert::TryFrom;
+
+use fieldmask::{
+    Mask, MaskInput, Maskable, OptionMaskable, ProjectOptions, SelfMaskable, UpdateOptions,
+};
+
+#[derive(Clone, Debug, Default, Maskable, OptionMaskable, PartialEq, SelfMaskable)]
+struct Child {
+    a: u32,
+    b: String,
+}
+
+#[derive(Clone, Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct Flattened {
+    c: u32,
+}
+
+#[derive(Clone, Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct Parent {
+    child: Child,
+    optional_child: Option<Child>,
+    #[fieldmask(flatten)]
+    flatten_child: Flattened,
+    items: Vec<u32>,
+}
+
+#[derive(Clone, Debug, Maskable, OptionMaskable, PartialEq)]
+enum OneOf {
+    Text(String),
+    Child(Child),
+    Point { x: u32, y: u32 },
+    Nothing,
+}
+
+#[derive(Clone, Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct OneOfParent {
+    one_of: Option<OneOf>,
+}
+
+mod diff {
+    use super::*;
+
+    #[test]
+    fn equal_values() {
+        let old = Parent {
+            child: Child {
+                a: 1,
+                b: "b".into(),
+            },
+            optional_child: None,
+            flatten_child: Flattened { c: 2 },
+            items: vec![3],
+        };
+        let new = old.clone();
+
+        let actual = Mask::diff(&old, &new);
+
+        assert_eq!(actual, Mask::empty());
+        assert!(actual.paths().is_empty());
+    }
+
+    #[test]
+    fn nested_fields() {
+        let mut target = Parent {
+            child: Child {
+                a: 1,
+                b: "b".into(),
+            },
+            optional_child: None,
+            flatten_child: Flattened { c: 2 },
+            items: vec![3],
+        };
+        let new = Parent {
+            child: Child {
+                a: 1,
+                b: "updated-b".into(),
+            },
+            optional_child: None,
+            flatten_child: Flattened { c: 20 },
+            items: vec![3, 4],
+        };
+        let expected_paths = vec!["child.b", "c", "items"];
+
+        let actual = Mask::diff(&target, &new);
+        actual.update_with_options(
+            &mut target,
+            new.clone(),
+            &UpdateOptions::builder().replace_repeated(true).build(),
+        );
+
+        assert_eq!(actual.paths(), expected_paths);
+        assert_eq!(target, new);
+    }
+
+    #[test]
+    fn optional_message_cleared() {
+        let mut target = Parent {
+            optional_child: Some(Child {
+                a: 1,
+                b: "b".into(),
+            }),
+            ..Default::default()
+        };
+        let new = Parent::default();
+        let expected_paths = vec!["optional_child.a", "optional_child.b"];
+
+        let actual = Mask::diff(&target, &new);
+        actual.update(&mut target, new.clone());
+        // The update resets the fields of the message, normalizing it clears the message.
+        let target = Mask::<Parent>::empty()
+            .project_with_options(target, &ProjectOptions::builder().normalize(true).build());
+
+        assert_eq!(actual.paths(), expected_paths);
+        assert_eq!(target, new);
+    }
+
+    #[test]
+    fn same_one_of_variant() {
+        let mut target = OneOfParent {
+            one_of: Some(OneOf::Point { x: 1, y: 2 }),
+        };
+        let new = OneOfParent {
+            one_of: Some(OneOf::Point { x: 1, y: 20 }),
+        };
+        let expected_paths = vec!["one_of.point.y"];
+
+        let actual = Mask::diff(&target, &new);
+        actual.update(&mut target, new.clone());
+
+        assert_eq!(actual.paths(), expected_paths);
+        assert_eq!(target, new);
+    }
+
+    #[test]
+    fn different_one_of_variant() {
+        let mut target = OneOfParent {
+            one_of: Some(OneOf::Point { x: 1, y: 2 }),
+        };
+        let new = OneOfParent {
+            one_of: Some(OneOf::Child(Child {
+                a: 1,
+                b: Default::default(),
+            })),
+        };
+        let expected_paths = vec!["one_of.child"];
+
+        let actual = Mask::diff(&target, &new);
+        actual.update(&mut target, new.clone());
+
+        assert_eq!(actual.paths(), expected_paths);
+        assert_eq!(target, new);
+    }
+
+    #[test]
+    fn unit_one_of_variant() {
+        let mut target = OneOfParent {
+            one_of: Some(OneOf::Text("text".into())),
+        };
+        let new = OneOfParent {
+            one_of: Some(OneOf::Nothing),
+        };
+        let expected_paths = vec!["one_of.nothing"];
+
+        let actual = Mask::diff(&target, &new);
+        actual.update(&mut target, new.clone());
+
+        assert_eq!(actual.paths(), expected_paths);
+        assert_eq!(target, new);
+    }
+
+    #[test]
+    fn one_of_cleared() {
+        let mut target = OneOfParent {
+            one_of: Some(OneOf::Point { x: 1, y: 2 }),
+        };
+        let new = OneOfParent { one_of: None };
+        let expected_paths = vec!["one_of.point.x", "one_of.point.y"];
+        // A cleared oneof keeps its variant, with the fields reset to their default values.
+        let expected = OneOfParent {
+            one_of: Some(OneOf::Point { x: 0, y: 0 }),
+        };
+
+        let actual = Mask::diff(&target, &new);
+        actual.update(&mut target, new);
+
+        assert_eq!(actual.paths(), expected_paths);
+        assert_eq!(target, expected);
+    }
+}
+
+mod paths {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let paths = vec!["child", "optional_child.a", "c"];
+
+        let actual = Mask::<Parent>::try_from(MaskInput(paths.clone().into_iter()))
+            .expect("unable to deserialize mask")
+            .paths();
+
+        assert_eq!(actual, paths);
+    }
+
+    #[test]
+    fn one_of_round_trip() {
+        let paths = vec!["one_of.point.x", "one_of.nothing"];
+
+        let actual = Mask::<OneOfParent>::try_from(MaskInput(paths.clone().into_iter()))
+            .expect("unable to deserialize mask")
+            .paths();
+
+        assert_eq!(actual, paths);
+    }
+}
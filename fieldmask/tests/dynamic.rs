@@ -0,0 +1,708 @@
+#![cfg(feature = "prost-reflect")]
+
+use std::{collections::HashMap, sync::OnceLock};
+
+use prost_reflect::{
+    DescriptorPool, DynamicMessage, MapKey, MessageDescriptor, Value,
+    prost_types::{
+        self, DescriptorProto, FieldDescriptorProto, FileDescriptorProto, MessageOptions,
+        OneofDescriptorProto, Struct,
+        field_descriptor_proto::{Label, Type},
+        value::Kind,
+    },
+};
+
+use fieldmask::{DeserializeMaskError, DynamicMask, UpdateOptions, merge_dynamic};
+
+// The descriptors of:
+//
+// ```proto
+// syntax = "proto3";
+// package test;
+//
+// import "google/protobuf/struct.proto";
+//
+// message Child {
+//   string field_one = 1;
+//   uint32 field_two = 2;
+// }
+//
+// message Circle {
+//   uint32 radius = 1;
+// }
+//
+// message Parent {
+//   string primitive = 1;
+//   Child child = 2;
+//   repeated uint32 numbers = 3;
+//   map<string, uint32> labels = 4;
+//   optional uint32 count = 5;
+//   oneof shape {
+//     Circle circle = 6;
+//     string name = 7;
+//   }
+//   google.protobuf.Struct metadata = 8;
+// }
+// ```
+fn field(name: &str, number: i32, r#type: Type) -> FieldDescriptorProto {
+    FieldDescriptorProto {
+        name: Some(name.into()),
+        number: Some(number),
+        label: Some(Label::Optional.into()),
+        r#type: Some(r#type.into()),
+        json_name: Some(name.into()),
+        ..Default::default()
+    }
+}
+
+fn message_field(name: &str, number: i32, type_name: &str) -> FieldDescriptorProto {
+    FieldDescriptorProto {
+        type_name: Some(type_name.into()),
+        ..field(name, number, Type::Message)
+    }
+}
+
+fn build_pool() -> DescriptorPool {
+    let child = DescriptorProto {
+        name: Some("Child".into()),
+        field: vec![
+            field("field_one", 1, Type::String),
+            field("field_two", 2, Type::Uint32),
+        ],
+        ..Default::default()
+    };
+    let circle = DescriptorProto {
+        name: Some("Circle".into()),
+        field: vec![field("radius", 1, Type::Uint32)],
+        ..Default::default()
+    };
+    let labels_entry = DescriptorProto {
+        name: Some("LabelsEntry".into()),
+        field: vec![
+            field("key", 1, Type::String),
+            field("value", 2, Type::Uint32),
+        ],
+        options: Some(MessageOptions {
+            map_entry: Some(true),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let parent = DescriptorProto {
+        name: Some("Parent".into()),
+        field: vec![
+            field("primitive", 1, Type::String),
+            message_field("child", 2, ".test.Child"),
+            FieldDescriptorProto {
+                label: Some(Label::Repeated.into()),
+                ..field("numbers", 3, Type::Uint32)
+            },
+            FieldDescriptorProto {
+                label: Some(Label::Repeated.into()),
+                ..message_field("labels", 4, ".test.Parent.LabelsEntry")
+            },
+            FieldDescriptorProto {
+                oneof_index: Some(1),
+                proto3_optional: Some(true),
+                ..field("count", 5, Type::Uint32)
+            },
+            FieldDescriptorProto {
+                oneof_index: Some(0),
+                ..message_field("circle", 6, ".test.Circle")
+            },
+            FieldDescriptorProto {
+                oneof_index: Some(0),
+                ..field("name", 7, Type::String)
+            },
+            message_field("metadata", 8, ".google.protobuf.Struct"),
+        ],
+        nested_type: vec![labels_entry],
+        oneof_decl: vec![
+            OneofDescriptorProto {
+                name: Some("shape".into()),
+                ..Default::default()
+            },
+            OneofDescriptorProto {
+                name: Some("_count".into()),
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    };
+    let file = FileDescriptorProto {
+        name: Some("test.proto".into()),
+        package: Some("test".into()),
+        dependency: vec!["google/protobuf/struct.proto".into()],
+        message_type: vec![child, circle, parent],
+        syntax: Some("proto3".into()),
+        ..Default::default()
+    };
+
+    // The global pool contains the well-known types.
+    let mut pool = DescriptorPool::global();
+    pool.add_file_descriptor_proto(file)
+        .expect("invalid descriptor");
+    pool
+}
+
+// Descriptors are only equal if they come from the same pool.
+fn pool() -> &'static DescriptorPool {
+    static POOL: OnceLock<DescriptorPool> = OnceLock::new();
+    POOL.get_or_init(build_pool)
+}
+
+fn descriptor(name: &str) -> MessageDescriptor {
+    pool()
+        .get_message_by_name(&format!("test.{name}"))
+        .expect("message not found")
+}
+
+fn message(name: &str, fields: Vec<(&str, Value)>) -> DynamicMessage {
+    let descriptor = descriptor(name);
+    let mut message = DynamicMessage::new(descriptor.clone());
+    for (name, value) in fields {
+        let field = descriptor.get_field_by_name(name).expect("field not found");
+        // Fields without presence are left unset when they have default values, so that equal
+        // messages compare equal.
+        if field.supports_presence() || !value.is_default_for_field(&field) {
+            message.set_field(&field, value);
+        }
+    }
+    message
+}
+
+fn child(field_one: &str, field_two: u32) -> Value {
+    Value::Message(message(
+        "Child",
+        vec![
+            ("field_one", Value::String(field_one.into())),
+            ("field_two", Value::U32(field_two)),
+        ],
+    ))
+}
+
+fn circle(radius: u32) -> Value {
+    Value::Message(message("Circle", vec![("radius", Value::U32(radius))]))
+}
+
+fn numbers(numbers: &[u32]) -> Value {
+    Value::List(numbers.iter().copied().map(Value::U32).collect())
+}
+
+fn labels(labels: &[(&str, u32)]) -> Value {
+    Value::Map(
+        labels
+            .iter()
+            .map(|(key, value)| (MapKey::String((*key).into()), Value::U32(*value)))
+            .collect::<HashMap<_, _>>(),
+    )
+}
+
+fn string(value: &str) -> prost_types::Value {
+    prost_types::Value {
+        kind: Some(Kind::StringValue(value.into())),
+    }
+}
+
+fn object<const N: usize>(fields: [(&str, prost_types::Value); N]) -> Struct {
+    Struct {
+        fields: fields
+            .into_iter()
+            .map(|(key, value)| (key.into(), value))
+            .collect(),
+    }
+}
+
+fn nested<const N: usize>(fields: [(&str, prost_types::Value); N]) -> prost_types::Value {
+    prost_types::Value {
+        kind: Some(Kind::StructValue(object(fields))),
+    }
+}
+
+fn metadata(value: &Struct) -> Value {
+    let descriptor = pool()
+        .get_message_by_name("google.protobuf.Struct")
+        .expect("message not found");
+    let mut message = DynamicMessage::new(descriptor);
+    message.transcode_from(value).expect("invalid struct");
+    Value::Message(message)
+}
+
+fn mask(paths: Vec<&str>) -> DynamicMask {
+    DynamicMask::parse(descriptor("Parent"), paths).expect("unable to deserialize mask")
+}
+
+mod mask {
+    use super::*;
+
+    #[test]
+    fn paths() {
+        let mask = mask(vec![
+            "child.field_two",
+            "primitive",
+            "circle.radius",
+            "name",
+        ]);
+
+        assert_eq!(
+            mask.paths(),
+            vec!["primitive", "child.field_two", "circle.radius", "name"]
+        );
+        assert_eq!(
+            DynamicMask::parse(
+                descriptor("Parent"),
+                mask.paths().iter().map(String::as_str)
+            )
+            .unwrap(),
+            mask
+        );
+    }
+
+    #[test]
+    fn struct_keys() {
+        let mask = mask(vec!["metadata.nested.x", "metadata.a"]);
+
+        assert_eq!(mask.paths(), vec!["metadata.a", "metadata.nested.x"]);
+        assert_eq!(
+            DynamicMask::parse(
+                descriptor("Parent"),
+                mask.paths().iter().map(String::as_str)
+            )
+            .unwrap(),
+            mask
+        );
+    }
+
+    #[test]
+    fn field_not_found() {
+        let err = DynamicMask::parse(descriptor("Parent"), vec!["missing"])
+            .expect_err("the field doesn't exist");
+
+        assert!(matches!(
+            err,
+            DeserializeMaskError::FieldNotFound {
+                field: "missing",
+                type_name: "test.Parent"
+            }
+        ));
+    }
+
+    #[test]
+    fn nested_field_not_found() {
+        let err = DynamicMask::parse(descriptor("Parent"), vec!["child.missing"])
+            .expect_err("the nested field doesn't exist");
+
+        let DeserializeMaskError::InvalidField { field, err } = err else {
+            panic!("unexpected error: {err}");
+        };
+        assert_eq!(field, "child");
+        assert!(matches!(
+            *err,
+            DeserializeMaskError::FieldNotFound {
+                field: "missing",
+                type_name: "test.Child"
+            }
+        ));
+    }
+
+    #[test]
+    fn atomic_field() {
+        let err = DynamicMask::parse(descriptor("Parent"), vec!["labels.key"])
+            .expect_err("map fields are atomic");
+
+        assert_eq!(
+            err.to_string(),
+            "error in field \"labels\":\n\ttype `map<string, uint32>` has no field named \"key\""
+        );
+    }
+}
+
+mod project {
+    use super::*;
+
+    fn source() -> DynamicMessage {
+        message(
+            "Parent",
+            vec![
+                ("primitive", Value::String("string".into())),
+                ("child", child("child field one", 1)),
+                ("numbers", numbers(&[1, 2])),
+                ("labels", labels(&[("a", 1)])),
+                ("count", Value::U32(0)),
+                ("circle", circle(2)),
+            ],
+        )
+    }
+
+    #[test]
+    fn fields() {
+        let expected = message(
+            "Parent",
+            vec![
+                ("child", child("", 1)),
+                ("numbers", numbers(&[1, 2])),
+                ("count", Value::U32(0)),
+                ("circle", circle(2)),
+            ],
+        );
+
+        let actual = mask(vec!["child.field_two", "numbers", "count", "circle"]).project(source());
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn empty_mask() {
+        assert_eq!(mask(vec![]).project(source()), source());
+    }
+
+    #[test]
+    fn unselected_oneof_field() {
+        let expected = message(
+            "Parent",
+            vec![("primitive", Value::String("string".into()))],
+        );
+
+        let actual = mask(vec!["primitive", "name"]).project(source());
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn default_message_is_cleared() {
+        let source = message("Parent", vec![("child", child("child field one", 0))]);
+
+        let actual = mask(vec!["child.field_two"]).project(source);
+
+        assert!(!actual.has_field_by_name("child"));
+    }
+
+    #[test]
+    fn struct_keys() {
+        let source = message(
+            "Parent",
+            vec![(
+                "metadata",
+                metadata(&object([
+                    ("a", string("a")),
+                    ("b", string("b")),
+                    ("nested", nested([("x", string("x")), ("y", string("y"))])),
+                ])),
+            )],
+        );
+        let expected = message(
+            "Parent",
+            vec![(
+                "metadata",
+                metadata(&object([
+                    ("a", string("a")),
+                    ("nested", nested([("x", string("x"))])),
+                ])),
+            )],
+        );
+
+        let actual = mask(vec!["metadata.a", "metadata.nested.x"]).project(source);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn default_message_in_oneof_is_kept() {
+        let source = message("Parent", vec![("circle", circle(0))]);
+
+        let actual = mask(vec!["circle.radius"]).project(source);
+
+        assert!(actual.has_field_by_name("circle"));
+    }
+}
+
+mod update {
+    use super::*;
+
+    fn target() -> DynamicMessage {
+        message(
+            "Parent",
+            vec![
+                ("primitive", Value::String("string".into())),
+                ("child", child("child field one", 1)),
+                ("numbers", numbers(&[1, 2])),
+                ("labels", labels(&[("a", 1)])),
+                ("name", Value::String("name".into())),
+            ],
+        )
+    }
+
+    #[test]
+    fn fields() {
+        let mut target = target();
+        let source = message(
+            "Parent",
+            vec![
+                ("primitive", Value::String("updated".into())),
+                ("child", child("updated", 2)),
+                ("numbers", numbers(&[3])),
+                ("labels", labels(&[("b", 2)])),
+            ],
+        );
+        let expected = message(
+            "Parent",
+            vec![
+                ("primitive", Value::String("updated".into())),
+                ("child", child("child field one", 2)),
+                ("numbers", numbers(&[1, 2, 3])),
+                ("labels", labels(&[("b", 2)])),
+                ("name", Value::String("name".into())),
+            ],
+        );
+
+        mask(vec!["primitive", "child.field_two", "numbers", "labels"]).update(&mut target, source);
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn whole_message_is_merged() {
+        let mut target = target();
+        let source = message("Parent", vec![("child", child("", 2))]);
+        let expected = message(
+            "Parent",
+            vec![
+                ("primitive", Value::String("string".into())),
+                ("child", child("child field one", 2)),
+                ("numbers", numbers(&[1, 2])),
+                ("labels", labels(&[("a", 1)])),
+                ("name", Value::String("name".into())),
+            ],
+        );
+
+        mask(vec!["child"]).update(&mut target, source);
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn replace_message() {
+        let mut target = target();
+        let source = message("Parent", vec![("child", child("", 2))]);
+
+        mask(vec!["child"]).update_with_options(
+            &mut target,
+            source,
+            &UpdateOptions::builder().replace_message(true).build(),
+        );
+
+        assert_eq!(
+            target.get_field_by_name("child").unwrap().into_owned(),
+            child("", 2)
+        );
+    }
+
+    #[test]
+    fn replace_repeated() {
+        let mut target = target();
+        let source = message("Parent", vec![("numbers", numbers(&[3]))]);
+
+        mask(vec!["numbers"]).update_with_options(
+            &mut target,
+            source,
+            &UpdateOptions::builder().replace_repeated(true).build(),
+        );
+
+        assert_eq!(
+            target.get_field_by_name("numbers").unwrap().into_owned(),
+            numbers(&[3])
+        );
+    }
+
+    #[test]
+    fn unset_fields() {
+        let mut target = message(
+            "Parent",
+            vec![
+                ("primitive", Value::String("string".into())),
+                ("labels", labels(&[("a", 1)])),
+                ("count", Value::U32(1)),
+            ],
+        );
+        let source = message("Parent", vec![]);
+        let expected = message("Parent", vec![("count", Value::U32(0))]);
+
+        mask(vec!["primitive", "labels", "count"]).update(&mut target, source);
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn struct_keys() {
+        let mut target = message(
+            "Parent",
+            vec![(
+                "metadata",
+                metadata(&object([
+                    ("a", string("a")),
+                    ("b", string("b")),
+                    ("nested", nested([("x", string("x")), ("y", string("y"))])),
+                ])),
+            )],
+        );
+        let source = message(
+            "Parent",
+            vec![(
+                "metadata",
+                metadata(&object([
+                    ("a", string("A")),
+                    ("c", string("C")),
+                    ("nested", nested([("x", string("X")), ("y", string("Y"))])),
+                ])),
+            )],
+        );
+        // Keys missing from the source are removed, and unselected keys are kept.
+        let expected = message(
+            "Parent",
+            vec![(
+                "metadata",
+                metadata(&object([
+                    ("a", string("A")),
+                    ("nested", nested([("x", string("X")), ("y", string("y"))])),
+                ])),
+            )],
+        );
+
+        mask(vec!["metadata.a", "metadata.b", "metadata.nested.x"]).update(&mut target, source);
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn oneof_field_is_switched() {
+        let mut target = target();
+        let source = message("Parent", vec![("circle", circle(3))]);
+
+        mask(vec!["circle", "name"]).update(&mut target, source);
+
+        assert!(!target.has_field_by_name("name"));
+        assert_eq!(
+            target.get_field_by_name("circle").unwrap().into_owned(),
+            circle(3)
+        );
+    }
+
+    #[test]
+    fn oneof_field_is_reset() {
+        let mut target = target();
+        let source = message("Parent", vec![("circle", circle(3))]);
+
+        mask(vec!["name"]).update(&mut target, source);
+
+        // The selected field is reset to the default value, and it stays set.
+        assert!(!target.has_field_by_name("circle"));
+        assert!(target.has_field_by_name("name"));
+        assert_eq!(
+            target.get_field_by_name("name").unwrap().into_owned(),
+            Value::String("".into())
+        );
+    }
+
+    #[test]
+    fn new_message_is_projected() {
+        let mut target = message("Parent", vec![]);
+        let source = message("Parent", vec![("circle", circle(3))]);
+
+        mask(vec!["child.field_one", "circle"]).update(&mut target, source.clone());
+        assert_eq!(target, source);
+
+        let mut target = message("Parent", vec![]);
+        let source = message("Parent", vec![("child", child("child field one", 1))]);
+        let expected = message("Parent", vec![("child", child("child field one", 0))]);
+
+        mask(vec!["child.field_one"]).update(&mut target, source);
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn empty_mask() {
+        let mut target = target();
+        let source = message(
+            "Parent",
+            vec![
+                ("child", child("updated", 2)),
+                ("numbers", numbers(&[3])),
+                ("circle", circle(3)),
+            ],
+        );
+        let expected = message(
+            "Parent",
+            vec![
+                ("child", child("updated", 2)),
+                ("numbers", numbers(&[1, 2, 3])),
+                ("circle", circle(3)),
+            ],
+        );
+
+        mask(vec![]).update(&mut target, source);
+
+        assert_eq!(target, expected);
+    }
+}
+
+mod merge {
+    use super::*;
+
+    #[test]
+    fn fields() {
+        let mut target = message(
+            "Parent",
+            vec![
+                ("primitive", Value::String("string".into())),
+                ("child", child("child field one", 1)),
+                ("numbers", numbers(&[1])),
+                ("labels", labels(&[("a", 1)])),
+                ("circle", circle(1)),
+            ],
+        );
+        let source = message(
+            "Parent",
+            vec![
+                ("child", child("", 2)),
+                ("numbers", numbers(&[2])),
+                ("count", Value::U32(0)),
+                ("circle", circle(0)),
+            ],
+        );
+        let expected = message(
+            "Parent",
+            vec![
+                ("primitive", Value::String("string".into())),
+                ("child", child("child field one", 2)),
+                ("numbers", numbers(&[1, 2])),
+                ("labels", labels(&[("a", 1)])),
+                ("count", Value::U32(0)),
+                ("circle", circle(1)),
+            ],
+        );
+
+        merge_dynamic(&mut target, source, &Default::default());
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn oneof_field_is_switched() {
+        let mut target = message("Parent", vec![("circle", circle(1))]);
+        let source = message("Parent", vec![("name", Value::String("".into()))]);
+
+        merge_dynamic(&mut target, source.clone(), &Default::default());
+
+        assert_eq!(target, source);
+    }
+
+    #[test]
+    fn map_is_replaced() {
+        let mut target = message("Parent", vec![("labels", labels(&[("a", 1)]))]);
+        let source = message("Parent", vec![("labels", labels(&[("b", 2)]))]);
+
+        merge_dynamic(&mut target, source.clone(), &Default::default());
+
+        assert_eq!(target, source);
+    }
+}
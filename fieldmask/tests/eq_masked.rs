@@ -0,0 +1,306 @@
+use std::{
+    collections::{HashMap, hash_map::DefaultHasher},
+    convert::TryFrom,
+    hash::Hasher,
+};
+
+use fieldmask::{
+    Mask, MaskInput, Maskable, MaskedHash, OptionMaskable, OptionMaskedHash, SelfMaskable,
+};
+
+#[derive(
+    Debug, Default, Maskable, MaskedHash, OptionMaskable, OptionMaskedHash, PartialEq, SelfMaskable,
+)]
+struct Child {
+    a: u32,
+    b: f64,
+}
+
+#[derive(Debug, Maskable, OptionMaskable, OptionMaskedHash, PartialEq)]
+enum OneOf {
+    Text(String),
+    Child(Child),
+    Point { x: u32, y: u32 },
+    Nothing,
+}
+
+#[derive(Debug, Default, Maskable, MaskedHash, PartialEq, SelfMaskable)]
+struct Flattened {
+    c: u32,
+}
+
+#[derive(Debug, Default, Maskable, MaskedHash, PartialEq, SelfMaskable)]
+struct Parent {
+    child: Child,
+    optional_child: Option<Child>,
+    #[fieldmask(flatten)]
+    flatten_child: Flattened,
+    one_of: Option<OneOf>,
+    labels: HashMap<String, f32>,
+}
+
+fn hash(mask: &Mask<Parent>, value: &Parent) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    mask.hash_masked(value, &mut hasher);
+    hasher.finish()
+}
+
+mod eq {
+    use super::*;
+
+    #[test]
+    fn empty_mask_equal() {
+        let a = Parent {
+            child: Child { a: 1, b: 1.5 },
+            flatten_child: Flattened { c: 2 },
+            ..Default::default()
+        };
+        let b = Parent {
+            child: Child { a: 1, b: 1.5 },
+            flatten_child: Flattened { c: 2 },
+            ..Default::default()
+        };
+        let mask = Mask::<Parent>::empty();
+
+        assert!(mask.eq_masked(&a, &b));
+        assert_eq!(hash(&mask, &a), hash(&mask, &b));
+    }
+
+    #[test]
+    fn empty_mask_not_equal() {
+        let a = Parent {
+            flatten_child: Flattened { c: 2 },
+            ..Default::default()
+        };
+        let b = Parent {
+            flatten_child: Flattened { c: 20 },
+            ..Default::default()
+        };
+        let mask = Mask::<Parent>::empty();
+
+        assert!(!mask.eq_masked(&a, &b));
+        assert_ne!(hash(&mask, &a), hash(&mask, &b));
+    }
+
+    #[test]
+    fn unselected_fields_are_ignored() {
+        let a = Parent {
+            child: Child { a: 1, b: 1.5 },
+            optional_child: Some(Child { a: 2, b: 0.0 }),
+            flatten_child: Flattened { c: 3 },
+            one_of: Some(OneOf::Nothing),
+            labels: [("a".into(), 1.0)].into(),
+        };
+        let b = Parent {
+            child: Child { a: 1, b: 2.5 },
+            flatten_child: Flattened { c: 3 },
+            ..Default::default()
+        };
+        let mask = vec!["child.a", "c"];
+
+        let mask = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask");
+
+        assert!(mask.eq_masked(&a, &b));
+        assert!(mask.eq_masked(&b, &a));
+        assert_eq!(hash(&mask, &a), hash(&mask, &b));
+    }
+
+    #[test]
+    fn selected_field_differs() {
+        let a = Parent {
+            child: Child { a: 1, b: 1.5 },
+            ..Default::default()
+        };
+        let b = Parent {
+            child: Child { a: 10, b: 1.5 },
+            ..Default::default()
+        };
+        let mask = vec!["child.a", "c"];
+
+        let mask = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask");
+
+        assert!(!mask.eq_masked(&a, &b));
+        assert_ne!(hash(&mask, &a), hash(&mask, &b));
+    }
+
+    #[test]
+    fn optional_message_none_is_default() {
+        let a = Parent {
+            optional_child: Some(Child { a: 0, b: 1.0 }),
+            ..Default::default()
+        };
+        let b = Parent {
+            optional_child: None,
+            ..Default::default()
+        };
+        let mask = vec!["optional_child.a"];
+
+        let mask = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask");
+
+        assert!(mask.eq_masked(&a, &b));
+        assert!(mask.eq_masked(&b, &a));
+        assert_eq!(hash(&mask, &a), hash(&mask, &b));
+    }
+
+    #[test]
+    fn one_of_unselected_field_differs() {
+        let a = Parent {
+            one_of: Some(OneOf::Point { x: 4, y: 5 }),
+            ..Default::default()
+        };
+        let b = Parent {
+            one_of: Some(OneOf::Point { x: 4, y: 50 }),
+            ..Default::default()
+        };
+        let mask = vec!["one_of.point.x"];
+
+        let mask = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask");
+
+        assert!(mask.eq_masked(&a, &b));
+        assert_eq!(hash(&mask, &a), hash(&mask, &b));
+    }
+
+    #[test]
+    fn one_of_selected_field_differs() {
+        let a = Parent {
+            one_of: Some(OneOf::Point { x: 4, y: 5 }),
+            ..Default::default()
+        };
+        let b = Parent {
+            one_of: Some(OneOf::Point { x: 40, y: 5 }),
+            ..Default::default()
+        };
+        let mask = vec!["one_of.point.x"];
+
+        let mask = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask");
+
+        assert!(!mask.eq_masked(&a, &b));
+        assert_ne!(hash(&mask, &a), hash(&mask, &b));
+    }
+
+    #[test]
+    fn unselected_one_of_variant_is_none() {
+        let a = Parent {
+            one_of: Some(OneOf::Point { x: 4, y: 5 }),
+            ..Default::default()
+        };
+        let b = Parent {
+            one_of: None,
+            ..Default::default()
+        };
+        let mask = vec!["one_of.text", "one_of.nothing"];
+
+        let mask = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask");
+
+        assert!(mask.eq_masked(&a, &b));
+        assert!(mask.eq_masked(&b, &a));
+        assert_eq!(hash(&mask, &a), hash(&mask, &b));
+    }
+
+    #[test]
+    fn selected_one_of_variant_differs() {
+        let a = Parent {
+            one_of: Some(OneOf::Point { x: 4, y: 5 }),
+            ..Default::default()
+        };
+        let b = Parent {
+            one_of: Some(OneOf::Nothing),
+            ..Default::default()
+        };
+        let mask = vec!["one_of.text", "one_of.nothing"];
+
+        let mask = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask");
+
+        assert!(!mask.eq_masked(&a, &b));
+        assert!(!mask.eq_masked(&b, &a));
+        assert_ne!(hash(&mask, &a), hash(&mask, &b));
+    }
+
+    #[test]
+    fn whole_one_of_variant() {
+        let a = Parent {
+            one_of: Some(OneOf::Child(Child { a: 1, b: 1.0 })),
+            ..Default::default()
+        };
+        let b = Parent {
+            one_of: Some(OneOf::Child(Child { a: 1, b: 2.0 })),
+            ..Default::default()
+        };
+        let mask = vec!["one_of.child"];
+
+        let mask = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask");
+
+        assert!(mask.eq_masked(&a, &a));
+        assert!(!mask.eq_masked(&a, &b));
+        assert_ne!(hash(&mask, &a), hash(&mask, &b));
+    }
+}
+
+mod hash {
+    use super::*;
+
+    #[test]
+    fn signed_zeros() {
+        let a = Parent {
+            optional_child: Some(Child { a: 2, b: 0.0 }),
+            ..Default::default()
+        };
+        let b = Parent {
+            optional_child: Some(Child { a: 2, b: -0.0 }),
+            ..Default::default()
+        };
+        let mask = vec!["optional_child.b"];
+
+        let mask = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask");
+
+        assert!(mask.eq_masked(&a, &b));
+        assert_eq!(hash(&mask, &a), hash(&mask, &b));
+    }
+
+    #[test]
+    fn map_order_independent() {
+        let a = Parent {
+            labels: [("a".into(), 1.0), ("b".into(), 2.0), ("c".into(), 3.0)].into(),
+            ..Default::default()
+        };
+        let b = Parent {
+            labels: [("c".into(), 3.0), ("b".into(), 2.0), ("a".into(), 1.0)].into(),
+            ..Default::default()
+        };
+        let mask = vec!["labels"];
+
+        let mask = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask");
+
+        assert!(mask.eq_masked(&a, &b));
+        assert_eq!(hash(&mask, &a), hash(&mask, &b));
+    }
+
+    #[test]
+    fn map_value_differs() {
+        let a = Parent {
+            labels: [("a".into(), 1.0), ("b".into(), 2.0)].into(),
+            ..Default::default()
+        };
+        let b = Parent {
+            labels: [("a".into(), 10.0), ("b".into(), 2.0)].into(),
+            ..Default::default()
+        };
+        let mask = vec!["labels"];
+
+        let mask = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask");
+
+        assert!(!mask.eq_masked(&a, &b));
+        assert_ne!(hash(&mask, &a), hash(&mask, &b));
+    }
+}
@@ -0,0 +1,506 @@
+use std::convert::TryFrom;
+
+use fieldmask::{
+    FieldBehavior, FieldBehaviorPolicy, Mask, MaskInput, Maskable, OptionMaskable, SelfMaskable,
+    UpdateOptions, ValidationError,
+};
+
+#[derive(Clone, Debug, Default, Maskable, OptionMaskable, PartialEq, SelfMaskable)]
+struct Metadata {
+    #[fieldmask(output_only)]
+    etag: String,
+    labels: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, Maskable, OptionMaskable, PartialEq, SelfMaskable)]
+struct Child {
+    #[fieldmask(immutable)]
+    id: String,
+    a: u32,
+}
+
+#[derive(Clone, Debug, Maskable, OptionMaskable, PartialEq)]
+enum OneOf {
+    Text(String),
+    Point {
+        #[fieldmask(output_only)]
+        created: u32,
+        x: u32,
+    },
+}
+
+#[derive(Clone, Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct Parent {
+    #[fieldmask(output_only)]
+    create_time: u64,
+    #[fieldmask(immutable)]
+    name: String,
+    description: String,
+    #[fieldmask(flatten)]
+    metadata: Metadata,
+    optional_child: Option<Child>,
+    one_of: Option<OneOf>,
+}
+
+mod behaviors {
+    use super::*;
+
+    #[test]
+    fn field_behaviors() {
+        assert_eq!(
+            Parent::field_behaviors(&["create_time"]),
+            vec![FieldBehavior::OutputOnly]
+        );
+        assert_eq!(
+            Parent::field_behaviors(&["etag"]),
+            vec![FieldBehavior::OutputOnly]
+        );
+        assert_eq!(
+            Parent::field_behaviors(&["optional_child", "id"]),
+            vec![FieldBehavior::Immutable]
+        );
+        assert_eq!(
+            Parent::field_behaviors(&["one_of", "point", "created"]),
+            vec![FieldBehavior::OutputOnly]
+        );
+        assert!(Parent::field_behaviors(&["description"]).is_empty());
+        assert!(Parent::field_behaviors(&["optional_child"]).is_empty());
+        assert!(Parent::field_behaviors(&["unknown"]).is_empty());
+    }
+}
+
+mod drop {
+    use super::*;
+
+    #[test]
+    fn empty_mask() {
+        let mut target = Parent {
+            create_time: 1,
+            name: "name".into(),
+            description: "description".into(),
+            metadata: Metadata {
+                etag: "etag".into(),
+                labels: vec!["a".into()],
+            },
+            optional_child: Some(Child {
+                id: "id".into(),
+                a: 2,
+            }),
+            one_of: Some(OneOf::Point { created: 3, x: 4 }),
+        };
+        let source = Parent {
+            create_time: 10,
+            name: "new-name".into(),
+            description: "new-description".into(),
+            metadata: Metadata {
+                etag: "new-etag".into(),
+                labels: vec!["b".into()],
+            },
+            optional_child: Some(Child {
+                id: "new-id".into(),
+                a: 20,
+            }),
+            one_of: Some(OneOf::Point { created: 30, x: 40 }),
+        };
+        let expected = Parent {
+            create_time: 1,
+            name: "name".into(),
+            description: "new-description".into(),
+            metadata: Metadata {
+                etag: "etag".into(),
+                labels: vec!["a".into(), "b".into()],
+            },
+            optional_child: Some(Child {
+                id: "id".into(),
+                a: 20,
+            }),
+            one_of: Some(OneOf::Point { created: 3, x: 40 }),
+        };
+
+        Mask::<Parent>::empty().update(&mut target, source);
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn selected_fields() {
+        let mut target = Parent {
+            create_time: 1,
+            name: "name".into(),
+            metadata: Metadata {
+                etag: "etag".into(),
+                labels: vec![],
+            },
+            optional_child: Some(Child {
+                id: "id".into(),
+                a: 2,
+            }),
+            ..Default::default()
+        };
+        let source = Parent {
+            create_time: 10,
+            name: "new-name".into(),
+            metadata: Metadata {
+                etag: "new-etag".into(),
+                labels: vec![],
+            },
+            optional_child: Some(Child {
+                id: "new-id".into(),
+                a: 20,
+            }),
+            ..Default::default()
+        };
+        let mask = vec!["create_time", "name", "etag", "optional_child.id"];
+        let expected = target.clone();
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update(&mut target, source);
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn unset_immutable_field() {
+        let mut target = Parent {
+            name: "".into(),
+            optional_child: None,
+            ..Default::default()
+        };
+        let source = Parent {
+            name: "new-name".into(),
+            optional_child: Some(Child {
+                id: "new-id".into(),
+                a: 20,
+            }),
+            ..Default::default()
+        };
+        let mask = vec!["name", "optional_child.id"];
+        let expected = Parent {
+            name: "new-name".into(),
+            optional_child: Some(Child {
+                id: "new-id".into(),
+                a: 0,
+            }),
+            ..Default::default()
+        };
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update(&mut target, source);
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn new_values_have_no_output_only_fields() {
+        let mut target = Parent {
+            one_of: Some(OneOf::Text("text".into())),
+            ..Default::default()
+        };
+        let source = Parent {
+            one_of: Some(OneOf::Point { created: 30, x: 40 }),
+            ..Default::default()
+        };
+        let mask = vec!["one_of.point"];
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update(&mut target, source);
+
+        assert_eq!(target.one_of, Some(OneOf::Point { created: 0, x: 40 }));
+    }
+
+    #[test]
+    fn replace_message() {
+        let mut target = Parent {
+            optional_child: Some(Child {
+                id: "id".into(),
+                a: 2,
+            }),
+            ..Default::default()
+        };
+        let source = Parent {
+            optional_child: Some(Child {
+                id: "new-id".into(),
+                a: 20,
+            }),
+            ..Default::default()
+        };
+        let mask = vec!["optional_child"];
+        let options = UpdateOptions::builder().replace_message(true).build();
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update_with_options(&mut target, source, &options);
+
+        assert_eq!(
+            target.optional_child,
+            Some(Child {
+                id: "id".into(),
+                a: 20,
+            })
+        );
+    }
+
+    #[test]
+    fn try_update() {
+        let mut target = Parent {
+            create_time: 1,
+            description: "description".into(),
+            ..Default::default()
+        };
+        let source = Parent {
+            create_time: 10,
+            description: "new-description".into(),
+            ..Default::default()
+        };
+        let mask = vec!["create_time", "description"];
+
+        let result = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .try_update(&mut target, source);
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(target.create_time, 1);
+        assert_eq!(target.description, "new-description");
+    }
+}
+
+mod error {
+    use super::*;
+
+    #[test]
+    fn output_only_in_mask() {
+        let mask = vec!["description", "etag"];
+        let options = UpdateOptions::builder()
+            .field_behavior_policy(FieldBehaviorPolicy::Error)
+            .build();
+
+        let result = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .check_update(&options);
+
+        assert_eq!(
+            result,
+            Err(ValidationError {
+                path: "etag".into(),
+                message: "the field is output only".into(),
+            })
+        );
+    }
+
+    #[test]
+    fn immutable_in_mask() {
+        let mask = vec!["description", "name"];
+        let options = UpdateOptions::builder()
+            .field_behavior_policy(FieldBehaviorPolicy::Error)
+            .build();
+
+        let result = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .check_update(&options);
+
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn output_only_in_mask_with_drop_policy() {
+        let mask = vec!["etag"];
+
+        let result = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .check_update(&UpdateOptions::default());
+
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn output_only_leaves_target_unchanged() {
+        let mut target = Parent {
+            description: "description".into(),
+            one_of: Some(OneOf::Point { created: 3, x: 4 }),
+            ..Default::default()
+        };
+        let source = Parent {
+            description: "new-description".into(),
+            one_of: Some(OneOf::Point { created: 30, x: 40 }),
+            ..Default::default()
+        };
+        let mask = vec!["description", "one_of.point.created"];
+        let options = UpdateOptions::builder()
+            .field_behavior_policy(FieldBehaviorPolicy::Error)
+            .build();
+        let expected = target.clone();
+
+        let result = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .try_update_with_options(&mut target, source, &options);
+
+        assert_eq!(
+            result,
+            Err(ValidationError {
+                path: "one_of.point.created".into(),
+                message: "the field is output only".into(),
+            })
+        );
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn changed_immutable_field() {
+        let mut target = Parent {
+            optional_child: Some(Child {
+                id: "id".into(),
+                a: 2,
+            }),
+            ..Default::default()
+        };
+        let source = Parent {
+            optional_child: Some(Child {
+                id: "new-id".into(),
+                a: 20,
+            }),
+            ..Default::default()
+        };
+        let mask = vec!["optional_child"];
+        let options = UpdateOptions::builder()
+            .field_behavior_policy(FieldBehaviorPolicy::Error)
+            .build();
+        let expected = target.clone();
+
+        let result = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .try_update_with_options(&mut target, source, &options);
+
+        assert_eq!(
+            result,
+            Err(ValidationError {
+                path: "optional_child.id".into(),
+                message: "the field is immutable".into(),
+            })
+        );
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn unset_immutable_field() {
+        let mut target = Parent {
+            optional_child: Some(Child {
+                id: "".into(),
+                a: 2,
+            }),
+            ..Default::default()
+        };
+        let source = Parent {
+            name: "new-name".into(),
+            optional_child: Some(Child {
+                id: "new-id".into(),
+                a: 20,
+            }),
+            ..Default::default()
+        };
+        let mask = vec!["name", "optional_child"];
+        let options = UpdateOptions::builder()
+            .field_behavior_policy(FieldBehaviorPolicy::Error)
+            .build();
+        let expected = Parent {
+            name: "new-name".into(),
+            optional_child: Some(Child {
+                id: "new-id".into(),
+                a: 20,
+            }),
+            ..Default::default()
+        };
+
+        let result = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .try_update_with_options(&mut target, source, &options);
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn unchanged_restricted_fields() {
+        let mut target = Parent {
+            create_time: 1,
+            name: "name".into(),
+            description: "description".into(),
+            metadata: Metadata {
+                etag: "etag".into(),
+                labels: vec!["a".into()],
+            },
+            ..Default::default()
+        };
+        let source = Parent {
+            create_time: 1,
+            name: "name".into(),
+            description: "new-description".into(),
+            metadata: Metadata {
+                etag: "etag".into(),
+                labels: vec!["a".into()],
+            },
+            ..Default::default()
+        };
+        let options = UpdateOptions::builder()
+            .replace_repeated(true)
+            .field_behavior_policy(FieldBehaviorPolicy::Error)
+            .build();
+
+        let result = Mask::<Parent>::empty().try_update_with_options(&mut target, source, &options);
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(target.description, "new-description");
+    }
+}
+
+mod allow {
+    use super::*;
+
+    #[test]
+    fn updates_restricted_fields() {
+        let mut target = Parent {
+            create_time: 1,
+            name: "name".into(),
+            metadata: Metadata {
+                etag: "etag".into(),
+                labels: vec![],
+            },
+            optional_child: Some(Child {
+                id: "id".into(),
+                a: 2,
+            }),
+            ..Default::default()
+        };
+        let source = Parent {
+            create_time: 10,
+            name: "new-name".into(),
+            metadata: Metadata {
+                etag: "new-etag".into(),
+                labels: vec![],
+            },
+            optional_child: Some(Child {
+                id: "new-id".into(),
+                a: 20,
+            }),
+            ..Default::default()
+        };
+        let mask = vec!["create_time", "name", "etag", "optional_child.id"];
+        let options = UpdateOptions::builder()
+            .field_behavior_policy(FieldBehaviorPolicy::Allow)
+            .build();
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update_with_options(&mut target, source, &options);
+
+        assert_eq!(target.create_time, 10);
+        assert_eq!(target.name, "new-name");
+        assert_eq!(target.metadata.etag, "new-etag");
+        assert_eq!(
+            target.optional_child.map(|child| child.id),
+            Some("new-id".into())
+        );
+    }
+}
@@ -15,6 +15,32 @@ struct Parent {
     c: u32,
 }
 
+#[derive(Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct AnotherChild {
+    b: u32,
+}
+
+#[derive(Debug, Maskable, PartialEq, SelfMaskable)]
+struct ConflictingParent {
+    #[fieldmask(flatten)]
+    child: Child,
+    #[fieldmask(flatten)]
+    another_child: AnotherChild,
+}
+
+#[derive(Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct ThirdChild {
+    d: u32,
+}
+
+#[derive(Debug, Maskable, PartialEq, SelfMaskable)]
+struct GenericParent<T> {
+    #[fieldmask(flatten)]
+    child: Child,
+    #[fieldmask(flatten)]
+    other: T,
+}
+
 mod project {
     use super::*;
 
@@ -78,3 +104,73 @@ mod project {
         assert_eq!(actual, expected);
     }
 }
+
+mod field_names {
+    use super::*;
+
+    #[test]
+    fn field_names() {
+        assert_eq!(Parent::field_names(), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "type `ConflictingParent` has more than one field named \"b\"")]
+    fn conflicting_flattened_fields() {
+        let mask = vec!["a"];
+
+        let _ = Mask::<ConflictingParent>::try_from(MaskInput(mask.into_iter()));
+    }
+
+    #[test]
+    #[should_panic(expected = "type `ConflictingParent` has more than one field named \"b\"")]
+    fn conflicting_flattened_fields_full_mask() {
+        let _ = Mask::<ConflictingParent>::full();
+    }
+
+    #[test]
+    #[should_panic(expected = "type `ConflictingParent` has more than one field named \"b\"")]
+    fn conflicting_flattened_fields_checked_again() {
+        let mask = vec!["a"];
+
+        let result = std::panic::catch_unwind(Mask::<ConflictingParent>::empty);
+        assert!(result.is_err());
+
+        let _ = Mask::<ConflictingParent>::try_from(MaskInput(mask.into_iter()));
+    }
+
+    #[test]
+    #[should_panic(expected = "type `GenericParent` has more than one field named \"b\"")]
+    fn conflicting_generic_argument() {
+        // Checking another instantiation of the same type first doesn't skip the check.
+        let _ = Mask::<GenericParent<ThirdChild>>::empty();
+
+        let _ = Mask::<GenericParent<AnotherChild>>::empty();
+    }
+}
+
+mod update {
+    use super::*;
+
+    #[test]
+    fn child_not_selected() {
+        let mut target = Parent {
+            child: Child { a: 1, b: 2 },
+            c: 3,
+        };
+        let source = Parent {
+            child: Child { a: 10, b: 20 },
+            c: 30,
+        };
+        let mask = vec!["c"];
+        let expected = Parent {
+            child: Child { a: 1, b: 2 },
+            c: 30,
+        };
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update(&mut target, source);
+
+        assert_eq!(target, expected);
+    }
+}
@@ -15,7 +15,8 @@ struct Parent {
     one_of_field: Option<OneOfField>,
 
     // You can use an enum to represent oneof fields from a protobuf message.
-    // Each variant in the enum must be tuple variant with a single inner type.
+    // Each variant in the enum can be a unit variant, a tuple variant with a single inner type,
+    // or a struct-like variant.
     //
     // If you flatten it, the behavior will be exactly the same as the protobuf message.
     #[fieldmask(flatten)]
@@ -0,0 +1,235 @@
+use std::convert::TryFrom;
+
+use fieldmask::{Mask, MaskInput, Maskable, OptionMaskable, ProjectOptions, SelfMaskable};
+
+#[derive(Debug, Default, Maskable, OptionMaskable, PartialEq, SelfMaskable)]
+struct Meta {
+    id: u32,
+    tag: String,
+}
+
+#[derive(Debug, Default, Maskable, OptionMaskable, PartialEq, SelfMaskable)]
+struct Envelope<M> {
+    meta: Meta,
+    body: M,
+}
+
+#[derive(Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct Page<T> {
+    items: Vec<T>,
+    next: String,
+}
+
+#[derive(Debug, Maskable, OptionMaskable, PartialEq)]
+enum Either<L, R> {
+    Left(L),
+    Right(R),
+}
+
+#[derive(Debug, Default, Maskable, OptionMaskable, PartialEq, SelfMaskable)]
+struct Node<T> {
+    value: T,
+    next: Option<Box<Node<T>>>,
+}
+
+#[derive(Debug, Maskable, PartialEq, SelfMaskable)]
+struct Parent {
+    envelope: Option<Envelope<Meta>>,
+    page: Page<u32>,
+    either: Option<Either<Meta, String>>,
+    list: Node<u32>,
+}
+
+// The inferred bounds of mutually recursive types are cyclic. Bound the type parameter instead.
+#[derive(Debug, Default, Maskable, OptionMaskable, PartialEq, SelfMaskable)]
+#[fieldmask(bound = "T: SelfMaskable + Default + PartialEq")]
+struct Folder<T> {
+    name: T,
+    file: Option<Box<File<T>>>,
+}
+
+#[derive(Debug, Default, Maskable, OptionMaskable, PartialEq, SelfMaskable)]
+struct File<T> {
+    parent: Option<Box<Folder<T>>>,
+}
+
+mod project {
+    use super::*;
+
+    #[test]
+    fn regular_mask() {
+        let source = Parent {
+            envelope: Some(Envelope {
+                meta: Meta {
+                    id: 1,
+                    tag: "a".into(),
+                },
+                body: Meta {
+                    id: 2,
+                    tag: "b".into(),
+                },
+            }),
+            page: Page {
+                items: vec![1, 2],
+                next: "next".into(),
+            },
+            either: Some(Either::Left(Meta {
+                id: 3,
+                tag: "c".into(),
+            })),
+            list: Node {
+                value: 4,
+                next: Some(Box::new(Node {
+                    value: 5,
+                    next: None,
+                })),
+            },
+        };
+        let mask = vec![
+            "envelope.body.tag",
+            "page.items",
+            "either.left.id",
+            "list.next.value",
+        ];
+        let expected = Parent {
+            envelope: Some(Envelope {
+                meta: Meta::default(),
+                body: Meta {
+                    id: 0,
+                    tag: "b".into(),
+                },
+            }),
+            page: Page {
+                items: vec![1, 2],
+                next: Default::default(),
+            },
+            either: Some(Either::Left(Meta {
+                id: 3,
+                tag: Default::default(),
+            })),
+            list: Node {
+                value: 0,
+                next: Some(Box::new(Node {
+                    value: 5,
+                    next: None,
+                })),
+            },
+        };
+
+        let actual = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .project(source);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn overridden_bound() {
+        let source = Folder {
+            name: 1,
+            file: Some(Box::new(File {
+                parent: Some(Box::new(Folder {
+                    name: 2,
+                    file: None,
+                })),
+            })),
+        };
+        let mask = vec!["file.parent.name"];
+        let expected = Folder {
+            name: 0,
+            file: Some(Box::new(File {
+                parent: Some(Box::new(Folder {
+                    name: 2,
+                    file: None,
+                })),
+            })),
+        };
+
+        let actual = Mask::<Folder<u32>>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .project(source);
+
+        assert_eq!(actual, expected);
+    }
+}
+
+mod update {
+    use super::*;
+
+    #[test]
+    fn regular_mask() {
+        let mut target = Parent {
+            envelope: None,
+            page: Page {
+                items: vec![1],
+                next: "next".into(),
+            },
+            either: Some(Either::Right("right".into())),
+            list: Node {
+                value: 1,
+                next: None,
+            },
+        };
+        let source = Parent {
+            envelope: Some(Envelope {
+                meta: Meta {
+                    id: 1,
+                    tag: "a".into(),
+                },
+                body: Meta {
+                    id: 2,
+                    tag: "b".into(),
+                },
+            }),
+            page: Page {
+                items: vec![2],
+                next: "updated-next".into(),
+            },
+            either: Some(Either::Left(Meta {
+                id: 3,
+                tag: "c".into(),
+            })),
+            list: Node {
+                value: 2,
+                next: Some(Box::new(Node {
+                    value: 3,
+                    next: None,
+                })),
+            },
+        };
+        let mask = vec!["envelope.meta.id", "page.items", "either.left", "list.next"];
+        let options = Default::default();
+        let expected = Parent {
+            envelope: Some(Envelope {
+                meta: Meta {
+                    id: 1,
+                    tag: Default::default(),
+                },
+                body: Meta::default(),
+            }),
+            page: Page {
+                items: vec![1, 2],
+                next: "next".into(),
+            },
+            either: Some(Either::Left(Meta {
+                id: 3,
+                tag: "c".into(),
+            })),
+            list: Node {
+                value: 1,
+                next: Some(Box::new(Node {
+                    value: 3,
+                    next: None,
+                })),
+            },
+        };
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update_with_options(&mut target, source, &options);
+        let target = Mask::<Parent>::empty()
+            .project_with_options(target, &ProjectOptions::builder().normalize(true).build());
+
+        assert_eq!(target, expected);
+    }
+}
@@ -0,0 +1,351 @@
+#![cfg(feature = "serde_json")]
+
+use std::convert::TryFrom;
+
+use serde_json::{Value, json};
+
+use fieldmask::{Mask, MaskInput, Maskable, ProjectOptions, SelfMaskable};
+
+#[derive(Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct Product {
+    name: String,
+    attributes: Value,
+}
+
+#[derive(Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct Listing {
+    title: String,
+    details: Option<Value>,
+}
+
+mod project {
+    use super::*;
+
+    #[test]
+    fn nested_objects() {
+        let source = json!({
+            "id": 1,
+            "attributes": { "color": "red", "size": "large" },
+            "variants": [{ "sku": "a", "price": 1 }],
+        });
+        let mask = vec!["id", "attributes.color", "missing"];
+        let expected = json!({
+            "id": 1,
+            "attributes": { "color": "red" },
+        });
+
+        let mask = Mask::<Value>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask");
+
+        assert_eq!(mask.project_ref(&source), expected);
+        assert_eq!(mask.project(source), expected);
+    }
+
+    #[test]
+    fn arrays_of_objects() {
+        let source = json!({
+            "id": 1,
+            "variants": [
+                { "sku": "a", "price": 1 },
+                { "sku": "b", "price": 2 },
+            ],
+        });
+        let mask = vec!["variants.sku"];
+        let expected = json!({
+            "variants": [{ "sku": "a" }, { "sku": "b" }],
+        });
+
+        let actual = Mask::<Value>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .project(source);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn empty_mask() {
+        let source = json!({
+            "id": 1,
+            "attributes": { "color": "red" },
+        });
+        let expected = source.clone();
+
+        let actual = Mask::<Value>::empty().project(source);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn field() {
+        let source = Product {
+            name: "shirt".into(),
+            attributes: json!({ "color": "red", "size": "large" }),
+        };
+        let mask = vec!["attributes.color"];
+        let expected = Product {
+            name: Default::default(),
+            attributes: json!({ "color": "red" }),
+        };
+
+        let actual = Mask::<Product>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .project(source);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn null_field() {
+        let source = Listing {
+            title: "shirt".into(),
+            details: Some(Value::Null),
+        };
+        let mask = vec!["details"];
+        let expected = Listing {
+            title: Default::default(),
+            details: Some(Value::Null),
+        };
+
+        let actual = Mask::<Listing>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .project(source);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn null_field_normalized() {
+        let source = Listing {
+            title: "shirt".into(),
+            details: Some(Value::Null),
+        };
+        let mask = vec!["details"];
+        let options = ProjectOptions::builder().normalize(true).build();
+        let expected = Listing {
+            title: Default::default(),
+            details: None,
+        };
+
+        let mask = Mask::<Listing>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask");
+
+        assert_eq!(mask.project_ref_with_options(&source, &options), expected);
+        assert_eq!(mask.project_with_options(source, &options), expected);
+    }
+}
+
+mod update {
+    use super::*;
+
+    #[test]
+    fn nested_objects() {
+        let mut target = json!({
+            "id": 1,
+            "attributes": { "color": "red", "size": "large" },
+            "variants": [{ "sku": "a", "price": 1 }],
+        });
+        let source = json!({
+            "id": 2,
+            "attributes": { "color": "blue", "size": "small" },
+        });
+        let mask = vec!["attributes.color", "variants"];
+        // Selected fields missing from the source are removed.
+        let expected = json!({
+            "id": 1,
+            "attributes": { "color": "blue", "size": "large" },
+        });
+
+        Mask::<Value>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update(&mut target, source);
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn scalar_at_masked_path() {
+        let mut target = json!({
+            "id": 1,
+            "attributes": "none",
+        });
+        let source = json!({
+            "attributes": { "color": "blue", "size": "small" },
+        });
+        let mask = vec!["attributes.color"];
+        // A value without keys is replaced by the selected part of the source.
+        let expected = json!({
+            "id": 1,
+            "attributes": { "color": "blue" },
+        });
+
+        Mask::<Value>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update(&mut target, source);
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn field() {
+        let mut target = Product {
+            name: "shirt".into(),
+            attributes: json!({ "color": "red", "size": "large" }),
+        };
+        let source = Product {
+            name: "pants".into(),
+            attributes: json!({ "color": "blue", "fabric": "cotton" }),
+        };
+        let mask = vec!["attributes.color", "attributes.fabric"];
+        let expected = Product {
+            name: "shirt".into(),
+            attributes: json!({ "color": "blue", "size": "large", "fabric": "cotton" }),
+        };
+
+        Mask::<Product>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update(&mut target, source);
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn whole_field() {
+        let mut target = Product {
+            name: "shirt".into(),
+            attributes: json!({ "color": "red", "size": { "eu": 40 } }),
+        };
+        let source = Product {
+            name: "pants".into(),
+            attributes: json!({ "size": { "us": 8 }, "fabric": null }),
+        };
+        let mask = vec!["attributes"];
+        // Selecting the whole value merges it, including its nested objects.
+        let expected = Product {
+            name: "shirt".into(),
+            attributes: json!({ "color": "red", "size": { "eu": 40, "us": 8 } }),
+        };
+
+        Mask::<Product>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update(&mut target, source);
+
+        assert_eq!(target, expected);
+    }
+}
+
+mod clear {
+    use super::*;
+
+    #[test]
+    fn nested_objects() {
+        let mut target = json!({
+            "id": 1,
+            "attributes": { "color": "red", "size": "large" },
+            "variants": [
+                { "sku": "a", "price": 1 },
+                { "sku": "b", "price": 2 },
+            ],
+        });
+        let mask = vec!["attributes.size", "variants.price", "id"];
+        let expected = json!({
+            "attributes": { "color": "red" },
+            "variants": [{ "sku": "a" }, { "sku": "b" }],
+        });
+
+        Mask::<Value>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .clear(&mut target);
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn scalar_at_masked_path() {
+        let mut target = json!({
+            "id": 1,
+            "attributes": "none",
+        });
+        let mask = vec!["attributes.color"];
+        let expected = target.clone();
+
+        Mask::<Value>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .clear(&mut target);
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn scalar_array_elements() {
+        let mut target = json!([{ "color": 1, "k": 2 }, 5]);
+        let mask = vec!["color"];
+        let expected = json!([{ "k": 2 }, 5]);
+
+        Mask::<Value>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .clear(&mut target);
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn scalar_root() {
+        let mut target = json!("red");
+        let mask = vec!["color"];
+        let expected = json!("red");
+
+        Mask::<Value>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .clear(&mut target);
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn whole_field() {
+        let mut target = Listing {
+            title: "shirt".into(),
+            details: Some(json!({ "color": "red" })),
+        };
+        let mask = vec!["details"];
+        let expected = Listing {
+            title: "shirt".into(),
+            details: None,
+        };
+
+        Mask::<Listing>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .clear(&mut target);
+
+        assert_eq!(target, expected);
+    }
+}
+
+mod diff {
+    use super::*;
+
+    #[test]
+    fn nested_objects() {
+        let this = json!({
+            "id": 1,
+            "attributes": { "color": "red", "size": "large" },
+            "variants": [{ "sku": "a", "price": 1 }],
+        });
+        let other = json!({
+            "id": 1,
+            "attributes": { "color": "blue", "size": "large" },
+            "variants": [{ "sku": "a", "price": 1 }],
+        });
+        let unchanged =
+            Mask::<Value>::try_from(MaskInput(vec!["attributes.size", "variants"].into_iter()))
+                .expect("unable to deserialize mask");
+        let changed = Mask::<Value>::try_from(MaskInput(vec!["attributes.color"].into_iter()))
+            .expect("unable to deserialize mask");
+
+        let diff = Mask::<Value>::diff(&this, &other);
+
+        assert_eq!(diff, changed);
+        assert!(unchanged.eq_masked(&this, &other));
+        assert!(!changed.eq_masked(&this, &other));
+    }
+}
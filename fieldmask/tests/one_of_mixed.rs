@@ -0,0 +1,318 @@
+use std::convert::TryFrom;
+
+use fieldmask::{Mask, MaskInput, Maskable, OptionMaskable, ProjectOptions, SelfMaskable};
+
+#[derive(Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct Point {
+    x: u32,
+    y: u32,
+}
+
+#[derive(Debug, Maskable, OptionMaskable, PartialEq)]
+enum Shape {
+    Circle { center: Point, radius: u32 },
+    Segment(Point, Point),
+    Label(String),
+    Empty,
+}
+
+#[derive(Debug, Maskable, PartialEq, SelfMaskable)]
+struct Parent {
+    shape: Option<Shape>,
+    c: u32,
+}
+
+mod project {
+    use super::*;
+
+    #[test]
+    fn struct_variant_field() {
+        let source = Parent {
+            shape: Some(Shape::Circle {
+                center: Point { x: 1, y: 2 },
+                radius: 3,
+            }),
+            c: 1,
+        };
+        let mask = vec!["shape.circle.center.x", "c"];
+        let expected = Parent {
+            shape: Some(Shape::Circle {
+                center: Point { x: 1, y: 0 },
+                radius: 0,
+            }),
+            c: 1,
+        };
+
+        let actual = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .project(source);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn whole_struct_variant() {
+        let source = Parent {
+            shape: Some(Shape::Circle {
+                center: Point { x: 1, y: 2 },
+                radius: 3,
+            }),
+            c: 1,
+        };
+        let mask = vec!["shape.circle"];
+        let expected = Parent {
+            shape: Some(Shape::Circle {
+                center: Point { x: 1, y: 2 },
+                radius: 3,
+            }),
+            c: 0,
+        };
+
+        let actual = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .project(source);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn multi_field_tuple_variant() {
+        let source = Parent {
+            shape: Some(Shape::Segment(Point { x: 1, y: 2 }, Point { x: 3, y: 4 })),
+            c: 1,
+        };
+        let mask = vec!["shape.segment.1.y"];
+        let expected = Parent {
+            shape: Some(Shape::Segment(Point::default(), Point { x: 0, y: 4 })),
+            c: 0,
+        };
+
+        let actual = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .project(source);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn unit_variant() {
+        let source = Parent {
+            shape: Some(Shape::Empty),
+            c: 1,
+        };
+        let mask = vec!["shape.empty"];
+        let expected = Parent {
+            shape: Some(Shape::Empty),
+            c: 0,
+        };
+
+        let actual = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .project(source);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn source_variant_is_different() {
+        let source = Parent {
+            shape: Some(Shape::Empty),
+            c: 1,
+        };
+        let mask = vec!["shape.circle.radius", "shape.label", "c"];
+        let expected = Parent { shape: None, c: 1 };
+
+        let actual = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .project(source);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn invalid_field_in_struct_variant() {
+        let mask = vec!["shape.circle.diameter"];
+
+        let err = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect_err("should fail to parse field mask");
+
+        assert_eq!(
+            err.to_string(),
+            "error in field \"shape\":\n\terror in field \"circle\":\n\t\ttype `Shape::Circle` has no field named \"diameter\""
+        );
+    }
+
+    #[test]
+    fn invalid_field_in_unit_variant() {
+        let mask = vec!["shape.empty.a"];
+
+        let err = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect_err("should fail to parse field mask");
+
+        assert_eq!(
+            err.to_string(),
+            "error in field \"shape\":\n\terror in field \"empty\":\n\t\ttype `Shape::Empty` has no field named \"a\""
+        );
+    }
+}
+
+mod update {
+    use super::*;
+
+    #[test]
+    fn struct_variant_is_the_same() {
+        let mut target = Parent {
+            shape: Some(Shape::Circle {
+                center: Point { x: 1, y: 2 },
+                radius: 3,
+            }),
+            c: 1,
+        };
+        let source = Parent {
+            shape: Some(Shape::Circle {
+                center: Point { x: 10, y: 20 },
+                radius: 30,
+            }),
+            c: 2,
+        };
+        let mask = vec!["shape.circle.center.y"];
+        let options = Default::default();
+        let expected = Parent {
+            shape: Some(Shape::Circle {
+                center: Point { x: 1, y: 20 },
+                radius: 3,
+            }),
+            c: 1,
+        };
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update_with_options(&mut target, source, &options);
+        let target = Mask::<Parent>::empty()
+            .project_with_options(target, &ProjectOptions::builder().normalize(true).build());
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn struct_variant_is_different() {
+        let mut target = Parent {
+            shape: Some(Shape::Label("label".into())),
+            c: 1,
+        };
+        let source = Parent {
+            shape: Some(Shape::Circle {
+                center: Point { x: 10, y: 20 },
+                radius: 30,
+            }),
+            c: 2,
+        };
+        let mask = vec!["shape.circle.radius"];
+        let options = Default::default();
+        let expected = Parent {
+            shape: Some(Shape::Circle {
+                center: Point::default(),
+                radius: 30,
+            }),
+            c: 1,
+        };
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update_with_options(&mut target, source, &options);
+        let target = Mask::<Parent>::empty()
+            .project_with_options(target, &ProjectOptions::builder().normalize(true).build());
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn struct_variant_field_not_in_source() {
+        let mut target = Parent {
+            shape: Some(Shape::Circle {
+                center: Point { x: 1, y: 2 },
+                radius: 3,
+            }),
+            c: 1,
+        };
+        let source = Parent { shape: None, c: 2 };
+        let mask = vec!["shape.circle.radius"];
+        let options = Default::default();
+        let expected = Parent {
+            shape: Some(Shape::Circle {
+                center: Point { x: 1, y: 2 },
+                radius: 0,
+            }),
+            c: 1,
+        };
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update_with_options(&mut target, source, &options);
+        let target = Mask::<Parent>::empty()
+            .project_with_options(target, &ProjectOptions::builder().normalize(true).build());
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn unit_variant_replaces_other_variant() {
+        let mut target = Parent {
+            shape: Some(Shape::Label("label".into())),
+            c: 1,
+        };
+        let source = Parent {
+            shape: Some(Shape::Empty),
+            c: 2,
+        };
+        let mask = vec!["shape.empty"];
+        let options = Default::default();
+        let expected = Parent {
+            shape: Some(Shape::Empty),
+            c: 1,
+        };
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update_with_options(&mut target, source, &options);
+        let target = Mask::<Parent>::empty()
+            .project_with_options(target, &ProjectOptions::builder().normalize(true).build());
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn merge_struct_variant() {
+        let mut target = Parent {
+            shape: Some(Shape::Circle {
+                center: Point { x: 1, y: 2 },
+                radius: 3,
+            }),
+            c: 1,
+        };
+        let source = Parent {
+            shape: Some(Shape::Circle {
+                center: Point { x: 0, y: 20 },
+                radius: 0,
+            }),
+            c: 0,
+        };
+        let mask = vec!["shape"];
+        let options = Default::default();
+        let expected = Parent {
+            shape: Some(Shape::Circle {
+                center: Point { x: 1, y: 20 },
+                radius: 3,
+            }),
+            c: 1,
+        };
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update_with_options(&mut target, source, &options);
+        let target = Mask::<Parent>::empty()
+            .project_with_options(target, &ProjectOptions::builder().normalize(true).build());
+
+        assert_eq!(target, expected);
+    }
+}
@@ -0,0 +1,215 @@
+use std::{convert::TryFrom, sync::Arc};
+
+use fieldmask::{Mask, MaskInput, Maskable, OptionMaskable, ProjectOptions, SelfMaskable};
+
+#[derive(Clone, Debug, Default, Maskable, OptionMaskable, PartialEq, SelfMaskable)]
+struct Child {
+    a: u32,
+    b: String,
+}
+
+#[derive(Clone, Debug, Maskable, OptionMaskable, PartialEq)]
+enum OneOf {
+    Text(String),
+    Child(Child),
+    Point { x: u32, y: u32 },
+    Nothing,
+}
+
+#[derive(Clone, Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct Flattened {
+    c: u32,
+}
+
+#[derive(Clone, Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct Parent {
+    child: Child,
+    optional_child: Option<Child>,
+    #[fieldmask(flatten)]
+    flatten_child: Flattened,
+    one_of: Option<OneOf>,
+    items: Vec<u32>,
+}
+
+mod project {
+    use super::*;
+
+    #[test]
+    fn empty_mask() {
+        let source = Parent {
+            child: Child {
+                a: 1,
+                b: "b".into(),
+            },
+            optional_child: None,
+            flatten_child: Flattened { c: 2 },
+            one_of: Some(OneOf::Nothing),
+            items: vec![3],
+        };
+
+        let actual = Mask::<Parent>::empty().project_ref(&source);
+
+        assert_eq!(actual, source);
+    }
+
+    #[test]
+    fn regular_mask() {
+        let source = Parent {
+            child: Child {
+                a: 1,
+                b: "b".into(),
+            },
+            optional_child: Some(Child {
+                a: 2,
+                b: "b".into(),
+            }),
+            flatten_child: Flattened { c: 3 },
+            one_of: Some(OneOf::Point { x: 4, y: 5 }),
+            items: vec![6, 7],
+        };
+        let mask = vec!["child.b", "optional_child.a", "c", "one_of.point.y"];
+        let expected = Parent {
+            child: Child {
+                a: 0,
+                b: "b".into(),
+            },
+            optional_child: Some(Child {
+                a: 2,
+                b: Default::default(),
+            }),
+            flatten_child: Flattened { c: 3 },
+            one_of: Some(OneOf::Point { x: 0, y: 5 }),
+            items: vec![],
+        };
+
+        let mask = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask");
+        let actual = mask.project_ref(&source);
+
+        assert_eq!(actual, expected);
+        assert_eq!(actual, mask.project(source));
+    }
+
+    #[test]
+    fn unselected_one_of_variant() {
+        let source = Parent {
+            one_of: Some(OneOf::Point { x: 1, y: 2 }),
+            items: vec![3, 4],
+            ..Default::default()
+        };
+        let mask = vec!["one_of.text", "one_of.child.a", "items"];
+        let expected = Parent {
+            items: vec![3, 4],
+            ..Default::default()
+        };
+
+        let mask = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask");
+        let actual = mask.project_ref(&source);
+
+        assert_eq!(actual, expected);
+        assert_eq!(actual, mask.project(source));
+    }
+
+    #[test]
+    fn whole_one_of_variant() {
+        let source = Parent {
+            one_of: Some(OneOf::Child(Child {
+                a: 1,
+                b: "b".into(),
+            })),
+            items: vec![2],
+            ..Default::default()
+        };
+        let mask = vec!["one_of.child", "one_of.nothing"];
+        let expected = Parent {
+            one_of: Some(OneOf::Child(Child {
+                a: 1,
+                b: "b".into(),
+            })),
+            ..Default::default()
+        };
+
+        let mask = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask");
+        let actual = mask.project_ref(&source);
+
+        assert_eq!(actual, expected);
+        assert_eq!(actual, mask.project(source));
+    }
+
+    #[test]
+    fn optional_child_becomes_default() {
+        let source = Parent {
+            optional_child: Some(Child {
+                a: 0,
+                b: "b".into(),
+            }),
+            ..Default::default()
+        };
+        let mask = vec!["optional_child.a"];
+
+        let mask = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask");
+        let actual = mask.project_ref(&source);
+
+        assert_eq!(actual.optional_child, None);
+        assert_eq!(actual, mask.project(source));
+    }
+
+    #[test]
+    fn normalize() {
+        let source = Parent {
+            child: Child {
+                a: 1,
+                b: "b".into(),
+            },
+            optional_child: Some(Child::default()),
+            ..Default::default()
+        };
+        let options = ProjectOptions::builder().normalize(true).build();
+        let expected = Parent {
+            child: Child {
+                a: 1,
+                b: "b".into(),
+            },
+            optional_child: None,
+            ..Default::default()
+        };
+
+        let actual = Mask::<Parent>::empty().project_ref_with_options(&source, &options);
+
+        assert_eq!(actual, expected);
+        assert_eq!(
+            actual,
+            Mask::<Parent>::empty().project_with_options(source, &options)
+        );
+    }
+
+    #[test]
+    fn shared_source() {
+        let source = Arc::new(Parent {
+            child: Child {
+                a: 1,
+                b: "b".into(),
+            },
+            items: vec![2],
+            ..Default::default()
+        });
+        let mask = vec!["child.a"];
+        let expected = Parent {
+            child: Child {
+                a: 1,
+                b: Default::default(),
+            },
+            ..Default::default()
+        };
+
+        let actual = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .project_ref(&source);
+
+        assert_eq!(actual, expected);
+        assert_eq!(source.items, vec![2]);
+    }
+}
@@ -0,0 +1,488 @@
+#![cfg(feature = "prost")]
+
+use std::convert::TryFrom;
+
+use prost::Message;
+use prost_types::{Duration, Struct, Timestamp, Value, value::Kind};
+
+use fieldmask::{Mask, MaskInput, Maskable, ProjectOptions, SelfMaskable};
+
+#[derive(Clone, Maskable, Message, PartialEq, SelfMaskable)]
+struct Event {
+    #[prost(message, optional, tag = "1")]
+    create_time: Option<Timestamp>,
+    #[prost(message, optional, tag = "2")]
+    ttl: Option<Duration>,
+    // `google.protobuf.StringValue`.
+    #[prost(message, optional, tag = "3")]
+    title: Option<String>,
+    #[prost(message, optional, tag = "4")]
+    metadata: Option<Struct>,
+}
+
+fn string(value: &str) -> Value {
+    Value {
+        kind: Some(Kind::StringValue(value.into())),
+    }
+}
+
+fn object<const N: usize>(fields: [(&str, Value); N]) -> Struct {
+    Struct {
+        fields: fields
+            .into_iter()
+            .map(|(key, value)| (key.into(), value))
+            .collect(),
+    }
+}
+
+fn nested<const N: usize>(fields: [(&str, Value); N]) -> Value {
+    Value {
+        kind: Some(Kind::StructValue(object(fields))),
+    }
+}
+
+mod project {
+    use super::*;
+
+    #[test]
+    fn atomic_fields() {
+        let source = Event {
+            create_time: Some(Timestamp {
+                seconds: 1,
+                nanos: 2,
+            }),
+            ttl: Some(Duration {
+                seconds: 3,
+                nanos: 4,
+            }),
+            title: Some("title".into()),
+            metadata: Some(object([("a", string("a"))])),
+        };
+        let mask = vec!["create_time", "title"];
+        let expected = Event {
+            create_time: Some(Timestamp {
+                seconds: 1,
+                nanos: 2,
+            }),
+            title: Some("title".into()),
+            ..Default::default()
+        };
+
+        let actual = Mask::<Event>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .project(source);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn struct_keys() {
+        let source = Event {
+            title: Some("title".into()),
+            metadata: Some(object([
+                ("a", string("a")),
+                ("b", string("b")),
+                ("nested", nested([("x", string("x")), ("y", string("y"))])),
+            ])),
+            ..Default::default()
+        };
+        let mask = vec!["metadata.a", "metadata.nested.y", "metadata.missing"];
+        let expected = Event {
+            metadata: Some(object([
+                ("a", string("a")),
+                ("nested", nested([("y", string("y"))])),
+            ])),
+            ..Default::default()
+        };
+
+        let mask = Mask::<Event>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask");
+        let actual = mask.project_ref(&source);
+
+        assert_eq!(actual, expected);
+        assert_eq!(actual, mask.project(source));
+    }
+
+    #[test]
+    fn no_selected_key() {
+        let source = Event {
+            metadata: Some(object([("a", string("a"))])),
+            ..Default::default()
+        };
+        let mask = vec!["metadata.missing"];
+
+        let mask = Mask::<Event>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask");
+        let actual = mask.project_ref(&source);
+
+        assert_eq!(actual.metadata, Some(Struct::default()));
+        assert_eq!(actual, mask.project(source));
+    }
+
+    #[test]
+    fn no_selected_key_normalized() {
+        let source = Event {
+            metadata: Some(object([("a", string("a"))])),
+            ..Default::default()
+        };
+        let mask = vec!["metadata.missing"];
+        let options = ProjectOptions::builder().normalize(true).build();
+
+        let mask = Mask::<Event>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask");
+        let actual = mask.project_ref_with_options(&source, &options);
+
+        assert_eq!(actual.metadata, None);
+        assert_eq!(actual, mask.project_with_options(source, &options));
+    }
+}
+
+mod update {
+    use super::*;
+
+    #[test]
+    fn struct_keys() {
+        let mut target = Event {
+            metadata: Some(object([
+                ("a", string("a")),
+                ("b", string("b")),
+                ("nested", nested([("x", string("x")), ("y", string("y"))])),
+            ])),
+            ..Default::default()
+        };
+        let source = Event {
+            metadata: Some(object([
+                ("a", string("new a")),
+                ("c", string("new c")),
+                ("nested", nested([("x", string("new x"))])),
+            ])),
+            ..Default::default()
+        };
+        let mask = vec![
+            "metadata.a",
+            "metadata.b",
+            "metadata.c",
+            "metadata.nested.x",
+        ];
+        let expected = Event {
+            metadata: Some(object([
+                ("a", string("new a")),
+                ("c", string("new c")),
+                (
+                    "nested",
+                    nested([("x", string("new x")), ("y", string("y"))]),
+                ),
+            ])),
+            ..Default::default()
+        };
+
+        Mask::<Event>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update(&mut target, source);
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn new_nested_struct_is_projected() {
+        let mut target = Event {
+            metadata: Some(object([("b", string("b"))])),
+            ..Default::default()
+        };
+        let source = Event {
+            metadata: Some(object([(
+                "a",
+                nested([("x", string("1")), ("y", string("2"))]),
+            )])),
+            ..Default::default()
+        };
+        let mask = vec!["metadata.a.x"];
+        let expected = Event {
+            metadata: Some(object([
+                ("a", nested([("x", string("1"))])),
+                ("b", string("b")),
+            ])),
+            ..Default::default()
+        };
+
+        Mask::<Event>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update(&mut target, source);
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn whole_nested_struct_is_merged() {
+        let mut target = Event {
+            metadata: Some(object([
+                ("a", string("a")),
+                ("nested", nested([("x", string("x")), ("y", string("y"))])),
+            ])),
+            ..Default::default()
+        };
+        let source = Event {
+            metadata: Some(object([(
+                "nested",
+                nested([("y", string("new y")), ("z", string("z"))]),
+            )])),
+            ..Default::default()
+        };
+        let mask = vec!["metadata.nested"];
+        let expected = Event {
+            metadata: Some(object([
+                ("a", string("a")),
+                (
+                    "nested",
+                    nested([
+                        ("x", string("x")),
+                        ("y", string("new y")),
+                        ("z", string("z")),
+                    ]),
+                ),
+            ])),
+            ..Default::default()
+        };
+
+        Mask::<Event>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update(&mut target, source);
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn whole_struct() {
+        let mut target = Event {
+            metadata: Some(object([
+                ("a", string("a")),
+                ("b", string("b")),
+                ("nested", nested([("x", string("x")), ("y", string("y"))])),
+            ])),
+            ..Default::default()
+        };
+        let source = Event {
+            metadata: Some(object([
+                ("b", string("new b")),
+                ("nested", nested([("z", string("z"))])),
+            ])),
+            ..Default::default()
+        };
+        let mask = vec!["metadata"];
+        // Selecting the whole struct merges it, including its nested structs.
+        let expected = Event {
+            metadata: Some(object([
+                ("a", string("a")),
+                ("b", string("new b")),
+                (
+                    "nested",
+                    nested([("x", string("x")), ("y", string("y")), ("z", string("z"))]),
+                ),
+            ])),
+            ..Default::default()
+        };
+
+        Mask::<Event>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update(&mut target, source);
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn atomic_fields() {
+        let mut target = Event {
+            create_time: Some(Timestamp {
+                seconds: 1,
+                nanos: 2,
+            }),
+            ttl: Some(Duration {
+                seconds: 3,
+                nanos: 4,
+            }),
+            title: Some("title".into()),
+            ..Default::default()
+        };
+        let source = Event {
+            ttl: Some(Duration {
+                seconds: 30,
+                nanos: 0,
+            }),
+            title: None,
+            ..Default::default()
+        };
+        let mask = vec!["ttl", "title"];
+        let expected = Event {
+            create_time: Some(Timestamp {
+                seconds: 1,
+                nanos: 2,
+            }),
+            ttl: Some(Duration {
+                seconds: 30,
+                nanos: 0,
+            }),
+            // Like other optional atomic values, `None` updates the value to its default.
+            title: Some(String::new()),
+            ..Default::default()
+        };
+
+        Mask::<Event>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update(&mut target, source);
+
+        assert_eq!(target, expected);
+    }
+}
+
+mod clear {
+    use super::*;
+
+    #[test]
+    fn struct_keys() {
+        let mut target = Event {
+            title: Some("title".into()),
+            metadata: Some(object([
+                ("a", string("a")),
+                ("b", string("b")),
+                ("nested", nested([("x", string("x")), ("y", string("y"))])),
+            ])),
+            ..Default::default()
+        };
+        let mask = vec!["metadata.a", "metadata.nested.x"];
+        let expected = Event {
+            title: Some("title".into()),
+            metadata: Some(object([
+                ("b", string("b")),
+                ("nested", nested([("y", string("y"))])),
+            ])),
+            ..Default::default()
+        };
+
+        Mask::<Event>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .clear(&mut target);
+
+        assert_eq!(target, expected);
+    }
+}
+
+mod diff {
+    use super::*;
+
+    #[test]
+    fn struct_keys() {
+        let this = Event {
+            metadata: Some(object([
+                ("a", string("a")),
+                ("b", string("b")),
+                ("nested", nested([("x", string("x"))])),
+            ])),
+            ..Default::default()
+        };
+        let other = Event {
+            metadata: Some(object([
+                ("a", string("a")),
+                ("b", string("new b")),
+                ("nested", nested([("x", string("x"))])),
+            ])),
+            ..Default::default()
+        };
+
+        let diff = Mask::<Event>::diff(&this, &other);
+        let unchanged =
+            Mask::<Event>::try_from(MaskInput(vec!["metadata.a", "metadata.nested"].into_iter()))
+                .expect("unable to deserialize mask");
+        let changed = Mask::<Event>::try_from(MaskInput(vec!["metadata.b"].into_iter()))
+            .expect("unable to deserialize mask");
+
+        assert_eq!(diff.paths(), vec!["metadata.b"]);
+        assert!(unchanged.eq_masked(&this, &other));
+        assert!(!changed.eq_masked(&this, &other));
+
+        let mut updated = this.clone();
+        diff.update(&mut updated, other.clone());
+        assert_eq!(updated, other);
+    }
+}
+
+mod restrict {
+    use super::*;
+
+    #[test]
+    fn struct_keys() {
+        let source = Event {
+            title: Some("title".into()),
+            metadata: Some(object([
+                ("a", string("a")),
+                ("b", string("b")),
+                ("nested", nested([("x", string("x")), ("y", string("y"))])),
+            ])),
+            ..Default::default()
+        };
+        let policy = Mask::<Event>::try_from(MaskInput(
+            vec!["title", "metadata.a", "metadata.nested.x"].into_iter(),
+        ))
+        .expect("unable to deserialize mask");
+        let mask = Mask::<Event>::try_from(MaskInput(vec!["metadata"].into_iter()))
+            .expect("unable to deserialize mask");
+
+        let (restricted, denied) = mask.restrict(&policy);
+        let restricted = restricted.expect("some keys are permitted");
+
+        assert_eq!(restricted.paths(), vec!["metadata.a", "metadata.nested.x"]);
+        assert_eq!(denied, vec!["metadata"]);
+        assert_eq!(
+            restricted.project(source).metadata,
+            Some(object([
+                ("a", string("a")),
+                ("nested", nested([("x", string("x"))])),
+            ]))
+        );
+    }
+}
+
+mod three_way_update {
+    use super::*;
+
+    #[test]
+    fn deleted_struct_key() {
+        let base = Event {
+            metadata: Some(object([("a", string("a")), ("b", string("b"))])),
+            ..Default::default()
+        };
+        let mut ours = Event {
+            metadata: Some(object([("a", string("our a")), ("b", string("b"))])),
+            ..Default::default()
+        };
+        let theirs = Event {
+            metadata: Some(object([("a", string("a"))])),
+            ..Default::default()
+        };
+
+        let conflicts = Mask::<Event>::empty().three_way_update(&base, &mut ours, theirs);
+
+        assert!(conflicts.is_empty());
+        assert_eq!(ours.metadata, Some(object([("a", string("our a"))])));
+    }
+
+    #[test]
+    fn conflicting_struct_key() {
+        let base = Event {
+            metadata: Some(object([("a", string("a")), ("b", string("b"))])),
+            ..Default::default()
+        };
+        let mut ours = Event {
+            metadata: Some(object([("a", string("a")), ("b", string("our b"))])),
+            ..Default::default()
+        };
+        let theirs = Event {
+            metadata: Some(object([])),
+            ..Default::default()
+        };
+
+        let conflicts = Mask::<Event>::empty().three_way_update(&base, &mut ours, theirs);
+
+        assert_eq!(conflicts, vec!["metadata.b"]);
+        assert_eq!(ours.metadata, Some(object([("b", string("our b"))])));
+    }
+}
@@ -0,0 +1,380 @@
+use std::convert::TryFrom;
+
+use fieldmask::{Mask, MaskInput, Maskable, OptionMaskable, ProjectOptions, SelfMaskable};
+
+fn last_four(value: &str) -> String {
+    let tail = value.len().saturating_sub(4);
+    format!("***{}", &value[tail..])
+}
+
+#[derive(Debug, Default, Maskable, OptionMaskable, PartialEq, SelfMaskable)]
+struct Card {
+    #[fieldmask(redact_with = last_four)]
+    number: String,
+    expiry: String,
+}
+
+#[derive(Debug, Maskable, OptionMaskable, PartialEq)]
+enum Contact {
+    Phone(String),
+    Email {
+        #[fieldmask(redact_with = "***")]
+        address: String,
+        verified: bool,
+    },
+    Unknown,
+}
+
+#[derive(Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct User {
+    id: u64,
+    #[fieldmask(redact_with = "***")]
+    email: String,
+    #[fieldmask(redact_with = 0)]
+    age: u32,
+    card: Card,
+    optional_card: Option<Card>,
+    contact: Option<Contact>,
+}
+
+mod redact {
+    use super::*;
+
+    #[test]
+    fn selected_fields() {
+        let mut target = User {
+            id: 1,
+            email: "user@example.com".into(),
+            age: 42,
+            card: Card {
+                number: "4111111111111111".into(),
+                expiry: "12/30".into(),
+            },
+            optional_card: Some(Card {
+                number: "5500000000000004".into(),
+                expiry: "01/31".into(),
+            }),
+            contact: Some(Contact::Email {
+                address: "user@example.com".into(),
+                verified: true,
+            }),
+        };
+        let mask = vec!["email", "card.number"];
+        let expected = User {
+            id: 1,
+            email: "***".into(),
+            age: 42,
+            card: Card {
+                number: "***1111".into(),
+                expiry: "12/30".into(),
+            },
+            optional_card: Some(Card {
+                number: "5500000000000004".into(),
+                expiry: "01/31".into(),
+            }),
+            contact: Some(Contact::Email {
+                address: "user@example.com".into(),
+                verified: true,
+            }),
+        };
+
+        Mask::<User>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .redact(&mut target);
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn fields_without_placeholder_are_cleared() {
+        let mut target = User {
+            id: 1,
+            email: "user@example.com".into(),
+            age: 42,
+            card: Card {
+                number: "4111111111111111".into(),
+                expiry: "12/30".into(),
+            },
+            optional_card: Some(Card {
+                number: "5500000000000004".into(),
+                expiry: "01/31".into(),
+            }),
+            contact: Some(Contact::Email {
+                address: "user@example.com".into(),
+                verified: true,
+            }),
+        };
+        let mask = vec!["id", "card"];
+        let expected = User {
+            id: 0,
+            email: "user@example.com".into(),
+            age: 42,
+            card: Card {
+                number: "***1111".into(),
+                expiry: Default::default(),
+            },
+            optional_card: Some(Card {
+                number: "5500000000000004".into(),
+                expiry: "01/31".into(),
+            }),
+            contact: Some(Contact::Email {
+                address: "user@example.com".into(),
+                verified: true,
+            }),
+        };
+
+        Mask::<User>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .redact(&mut target);
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn empty_mask() {
+        let mut target = User {
+            id: 1,
+            email: "user@example.com".into(),
+            age: 42,
+            card: Card {
+                number: "4111111111111111".into(),
+                expiry: "12/30".into(),
+            },
+            optional_card: Some(Card {
+                number: "5500000000000004".into(),
+                expiry: "01/31".into(),
+            }),
+            contact: Some(Contact::Email {
+                address: "user@example.com".into(),
+                verified: true,
+            }),
+        };
+        let expected = User {
+            id: 0,
+            email: "***".into(),
+            age: 0,
+            card: Card {
+                number: "***1111".into(),
+                expiry: Default::default(),
+            },
+            optional_card: Some(Card {
+                number: "***0004".into(),
+                expiry: Default::default(),
+            }),
+            contact: Some(Contact::Email {
+                address: "***".into(),
+                verified: false,
+            }),
+        };
+
+        Mask::<User>::empty().redact(&mut target);
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn one_of_variant_fields() {
+        let mut target = User {
+            contact: Some(Contact::Email {
+                address: "user@example.com".into(),
+                verified: true,
+            }),
+            ..Default::default()
+        };
+        let mask = vec!["contact.email.address", "contact.phone"];
+        let expected = Some(Contact::Email {
+            address: "***".into(),
+            verified: true,
+        });
+
+        Mask::<User>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .redact(&mut target);
+
+        assert_eq!(target.contact, expected);
+    }
+
+    #[test]
+    fn one_of_variant_without_placeholder() {
+        let mut target = User {
+            contact: Some(Contact::Phone("555-0100".into())),
+            ..Default::default()
+        };
+        let mask = vec!["contact.phone"];
+
+        Mask::<User>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .redact(&mut target);
+
+        assert_eq!(target.contact, Some(Contact::Phone("".into())));
+    }
+
+    #[test]
+    fn unit_one_of_variant() {
+        let mut target = User {
+            contact: Some(Contact::Unknown),
+            ..Default::default()
+        };
+        let mask = vec!["contact.unknown"];
+
+        Mask::<User>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .redact(&mut target);
+
+        assert_eq!(target.contact, None);
+    }
+}
+
+mod project {
+    use super::*;
+
+    #[test]
+    fn redact_unselected_fields() {
+        let source = User {
+            id: 1,
+            email: "user@example.com".into(),
+            age: 42,
+            card: Card {
+                number: "4111111111111111".into(),
+                expiry: "12/30".into(),
+            },
+            optional_card: Some(Card {
+                number: "5500000000000004".into(),
+                expiry: "01/31".into(),
+            }),
+            contact: Some(Contact::Email {
+                address: "user@example.com".into(),
+                verified: true,
+            }),
+        };
+        let mask = vec!["id", "card.expiry"];
+        let options = ProjectOptions::builder().redact(true).build();
+        let expected = User {
+            id: 1,
+            email: "***".into(),
+            age: 0,
+            card: Card {
+                number: "***1111".into(),
+                expiry: "12/30".into(),
+            },
+            optional_card: Some(Card {
+                number: "***0004".into(),
+                expiry: Default::default(),
+            }),
+            contact: Some(Contact::Email {
+                address: "***".into(),
+                verified: false,
+            }),
+        };
+
+        let mask = Mask::<User>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask");
+
+        assert_eq!(mask.project_ref_with_options(&source, &options), expected);
+        assert_eq!(mask.project_with_options(source, &options), expected);
+    }
+
+    #[test]
+    fn redact_unselected_one_of_variant() {
+        let source = User {
+            contact: Some(Contact::Email {
+                address: "user@example.com".into(),
+                verified: true,
+            }),
+            ..Default::default()
+        };
+        let mask = vec!["contact.phone"];
+        let options = ProjectOptions::builder().redact(true).build();
+        let expected = Some(Contact::Email {
+            address: "***".into(),
+            verified: false,
+        });
+
+        let mask = Mask::<User>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask");
+
+        assert_eq!(
+            mask.project_ref_with_options(&source, &options).contact,
+            expected
+        );
+        assert_eq!(
+            mask.project_with_options(source, &options).contact,
+            expected
+        );
+    }
+
+    #[test]
+    fn report_hidden_fields() {
+        let source = User {
+            id: 1,
+            email: "user@example.com".into(),
+            age: 42,
+            card: Card {
+                number: "4111111111111111".into(),
+                expiry: "12/30".into(),
+            },
+            optional_card: None,
+            contact: Some(Contact::Email {
+                address: "user@example.com".into(),
+                verified: true,
+            }),
+        };
+        let mask = vec!["id", "card.expiry"];
+
+        let (projected, hidden) = Mask::<User>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .project_reporting(&source);
+
+        assert_eq!(projected.id, 1);
+        assert_eq!(
+            hidden.paths(),
+            vec![
+                "email",
+                "age",
+                "card.number",
+                "contact.email.address",
+                "contact.email.verified",
+            ]
+        );
+    }
+
+    #[test]
+    fn report_redacted_fields() {
+        let source = User {
+            id: 1,
+            email: "***".into(),
+            age: 42,
+            card: Card {
+                number: "4111111111111111".into(),
+                expiry: "12/30".into(),
+            },
+            optional_card: Some(Card {
+                number: "5500000000000004".into(),
+                expiry: "01/31".into(),
+            }),
+            contact: Some(Contact::Email {
+                address: "user@example.com".into(),
+                verified: true,
+            }),
+        };
+        let mask = vec!["contact"];
+        let options = ProjectOptions::builder().redact(true).build();
+
+        let (projected, hidden) = Mask::<User>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .project_reporting_with_options(&source, &options);
+
+        assert_eq!(projected.email, "***");
+        assert_eq!(
+            hidden.paths(),
+            vec![
+                "id",
+                "age",
+                "card.number",
+                "card.expiry",
+                "optional_card.number",
+                "optional_card.expiry",
+            ]
+        );
+    }
+}
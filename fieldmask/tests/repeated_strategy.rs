@@ -0,0 +1,317 @@
+use std::convert::TryFrom;
+
+use fieldmask::{
+    Mask, MaskInput, Maskable, OptionMaskable, RepeatedStrategy, SelfMaskable, UpdateOptions,
+};
+
+#[derive(Clone, Debug, Default, Maskable, OptionMaskable, PartialEq, SelfMaskable)]
+struct Item {
+    id: u32,
+    name: String,
+    #[fieldmask(key = "0")]
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug, Default, Maskable, OptionMaskable, PartialEq, SelfMaskable)]
+struct Part(String, u32);
+
+#[derive(Clone, Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct Order {
+    tags: Vec<String>,
+    #[fieldmask(key = "id")]
+    items: Vec<Item>,
+}
+
+fn item(id: u32, name: &str) -> Item {
+    Item {
+        id,
+        name: name.into(),
+        parts: vec![],
+    }
+}
+
+mod update {
+    use super::*;
+
+    #[test]
+    fn append() {
+        let mut target = Order {
+            tags: vec!["a".into(), "b".into()],
+            items: vec![item(1, "one"), item(2, "two")],
+        };
+        let source = Order {
+            tags: vec!["b".into(), "c".into(), "c".into()],
+            items: vec![item(2, "TWO"), item(3, "three")],
+        };
+        let mask = vec!["tags", "items"];
+
+        Mask::<Order>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update_with_options(
+                &mut target,
+                source,
+                &UpdateOptions::builder()
+                    .repeated_strategy(RepeatedStrategy::Append)
+                    .build(),
+            );
+
+        assert_eq!(target.tags, vec!["a", "b", "b", "c", "c"]);
+        assert_eq!(
+            target.items,
+            vec![
+                item(1, "one"),
+                item(2, "two"),
+                item(2, "TWO"),
+                item(3, "three")
+            ]
+        );
+    }
+
+    #[test]
+    fn dedup_append() {
+        let mut target = Order {
+            tags: vec!["a".into(), "b".into()],
+            items: vec![item(1, "one"), item(2, "two")],
+        };
+        let source = Order {
+            tags: vec!["b".into(), "c".into(), "c".into()],
+            items: vec![item(2, "TWO"), item(3, "three")],
+        };
+        let mask = vec!["tags", "items"];
+
+        Mask::<Order>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update_with_options(
+                &mut target,
+                source,
+                &UpdateOptions::builder()
+                    .repeated_strategy(RepeatedStrategy::DedupAppend)
+                    .build(),
+            );
+
+        assert_eq!(target.tags, vec!["a", "b", "c"]);
+        assert_eq!(
+            target.items,
+            vec![
+                item(1, "one"),
+                item(2, "two"),
+                item(2, "TWO"),
+                item(3, "three")
+            ]
+        );
+    }
+
+    #[test]
+    fn prepend() {
+        let mut target = Order {
+            tags: vec!["a".into(), "b".into()],
+            items: vec![item(1, "one"), item(2, "two")],
+        };
+        let source = Order {
+            tags: vec!["b".into(), "c".into(), "c".into()],
+            items: vec![item(2, "TWO"), item(3, "three")],
+        };
+        let mask = vec!["tags", "items"];
+
+        Mask::<Order>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update_with_options(
+                &mut target,
+                source,
+                &UpdateOptions::builder()
+                    .repeated_strategy(RepeatedStrategy::Prepend)
+                    .build(),
+            );
+
+        assert_eq!(target.tags, vec!["b", "c", "c", "a", "b"]);
+        assert_eq!(
+            target.items,
+            vec![
+                item(2, "TWO"),
+                item(3, "three"),
+                item(1, "one"),
+                item(2, "two")
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_by_key() {
+        let mut target = Order {
+            tags: vec!["a".into(), "b".into()],
+            items: vec![item(1, "one"), item(2, "two")],
+        };
+        let source = Order {
+            tags: vec!["b".into(), "c".into(), "c".into()],
+            items: vec![item(2, "TWO"), item(3, "three")],
+        };
+        let mask = vec!["tags", "items"];
+
+        Mask::<Order>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update_with_options(
+                &mut target,
+                source,
+                &UpdateOptions::builder()
+                    .repeated_strategy(RepeatedStrategy::MergeByKey)
+                    .build(),
+            );
+
+        // Fields without a key append.
+        assert_eq!(target.tags, vec!["a", "b", "b", "c", "c"]);
+        assert_eq!(
+            target.items,
+            vec![item(1, "one"), item(2, "TWO"), item(3, "three")]
+        );
+    }
+
+    #[test]
+    fn merge_by_key_nested() {
+        let mut target = Order {
+            items: vec![
+                Item {
+                    id: 1,
+                    name: "one".into(),
+                    parts: vec![Part("a".into(), 1), Part("b".into(), 2)],
+                },
+                item(2, "two"),
+            ],
+            ..Default::default()
+        };
+        let source = Order {
+            items: vec![Item {
+                id: 1,
+                parts: vec![Part("b".into(), 20), Part("c".into(), 3)],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let expected = Item {
+            id: 1,
+            name: "one".into(),
+            parts: vec![
+                Part("a".into(), 1),
+                Part("b".into(), 20),
+                Part("c".into(), 3),
+            ],
+        };
+
+        Mask::<Order>::empty().update_with_options(
+            &mut target,
+            source,
+            &UpdateOptions::builder()
+                .repeated_strategy(RepeatedStrategy::MergeByKey)
+                .build(),
+        );
+
+        assert_eq!(target.items[0], expected);
+    }
+
+    #[test]
+    fn merge_by_key_sub_mask() {
+        let mut target = Order {
+            items: vec![
+                item(1, "one"),
+                Item {
+                    id: 2,
+                    name: "two".into(),
+                    parts: vec![Part("a".into(), 1)],
+                },
+            ],
+            ..Default::default()
+        };
+        let source = Order {
+            items: vec![
+                Item {
+                    id: 2,
+                    name: "TWO".into(),
+                    parts: vec![Part("b".into(), 2)],
+                },
+                item(3, "three"),
+            ],
+            ..Default::default()
+        };
+        let mask = vec!["items.name"];
+        // Only the names of the matched elements are updated.
+        let expected = vec![
+            item(1, "one"),
+            Item {
+                id: 2,
+                name: "TWO".into(),
+                parts: vec![Part("a".into(), 1)],
+            },
+            item(3, "three"),
+        ];
+
+        Mask::<Order>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update_with_options(
+                &mut target,
+                source,
+                &UpdateOptions::builder()
+                    .repeated_strategy(RepeatedStrategy::MergeByKey)
+                    .build(),
+            );
+
+        assert_eq!(target.items, expected);
+    }
+
+    #[test]
+    fn replace_repeated_takes_precedence() {
+        let mut target = Order {
+            tags: vec!["a".into(), "b".into()],
+            items: vec![item(1, "one"), item(2, "two")],
+        };
+        let source = Order {
+            tags: vec!["b".into(), "c".into(), "c".into()],
+            items: vec![item(2, "TWO"), item(3, "three")],
+        };
+        let expected = Order {
+            tags: vec!["b".into(), "c".into(), "c".into()],
+            items: vec![item(2, "TWO"), item(3, "three")],
+        };
+
+        Mask::<Order>::empty().update_with_options(
+            &mut target,
+            source,
+            &UpdateOptions::builder()
+                .replace_repeated(true)
+                .repeated_strategy(RepeatedStrategy::MergeByKey)
+                .build(),
+        );
+
+        assert_eq!(target, expected);
+    }
+}
+
+mod project {
+    use super::*;
+
+    #[test]
+    fn sub_mask() {
+        let source = Order {
+            tags: vec!["a".into(), "b".into()],
+            items: vec![
+                Item {
+                    id: 1,
+                    name: "one".into(),
+                    parts: vec![Part("a".into(), 1)],
+                },
+                item(2, "two"),
+            ],
+        };
+        let mask = vec!["items.name"];
+        let expected = Order {
+            tags: vec![],
+            items: vec![item(0, "one"), item(0, "two")],
+        };
+
+        let mask = Mask::<Order>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask");
+        let actual = mask.project_ref(&source);
+
+        assert_eq!(actual, expected);
+        assert_eq!(actual, mask.project(source));
+        assert_eq!(mask.paths(), vec!["items.name"]);
+    }
+}
@@ -0,0 +1,226 @@
+use std::convert::TryFrom;
+
+use fieldmask::{
+    FieldBehavior, Mask, MaskInput, Maskable, OptionMaskable, SelfMaskable, UpdateOptions,
+    ValidationError,
+};
+
+#[derive(Clone, Debug, Default, Maskable, OptionMaskable, PartialEq, SelfMaskable)]
+struct Child {
+    #[fieldmask(required)]
+    id: String,
+    a: u32,
+}
+
+#[derive(Clone, Debug, Maskable, OptionMaskable, PartialEq)]
+enum OneOf {
+    Text(String),
+    Point {
+        #[fieldmask(required)]
+        x: u32,
+        y: u32,
+    },
+}
+
+#[derive(Clone, Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct Flattened {
+    #[fieldmask(required)]
+    c: u32,
+}
+
+#[derive(Clone, Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct Parent {
+    #[fieldmask(required)]
+    name: String,
+    description: String,
+    child: Child,
+    #[fieldmask(required)]
+    required_child: Option<Child>,
+    optional_child: Option<Child>,
+    #[fieldmask(flatten)]
+    flattened: Flattened,
+    one_of: Option<OneOf>,
+}
+
+mod check {
+    use super::*;
+
+    #[test]
+    fn complete_value() {
+        let value = Parent {
+            name: "name".into(),
+            description: "".into(),
+            child: Child {
+                id: "id".into(),
+                a: 0,
+            },
+            required_child: Some(Child {
+                id: "id".into(),
+                a: 0,
+            }),
+            optional_child: None,
+            flattened: Flattened { c: 1 },
+            one_of: Some(OneOf::Point { x: 1, y: 0 }),
+        };
+
+        let result = Mask::<Parent>::empty().check_required(&value);
+
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn every_missing_path() {
+        let value = Parent {
+            optional_child: Some(Child::default()),
+            ..Default::default()
+        };
+
+        let result = Mask::<Parent>::empty().check_required(&value);
+
+        assert_eq!(
+            result,
+            Err(vec![
+                "name".into(),
+                "child.id".into(),
+                "required_child".into(),
+                "optional_child.id".into(),
+                "c".into(),
+            ])
+        );
+    }
+
+    #[test]
+    fn unselected_missing_fields() {
+        let value = Parent {
+            name: "".into(),
+            child: Child::default(),
+            flattened: Flattened::default(),
+            ..Default::default()
+        };
+        let mask = vec!["description", "child.a"];
+
+        let result = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .check_required(&value);
+
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn selected_missing_fields() {
+        let value = Parent {
+            name: "".into(),
+            child: Child::default(),
+            flattened: Flattened::default(),
+            ..Default::default()
+        };
+        let mask = vec!["child", "c"];
+
+        let result = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .check_required(&value);
+
+        assert_eq!(result, Err(vec!["child.id".into(), "c".into()]));
+    }
+
+    #[test]
+    fn unselected_one_of_variant() {
+        let value = Parent {
+            one_of: Some(OneOf::Point { x: 0, y: 1 }),
+            ..Default::default()
+        };
+        let mask = vec!["one_of.text"];
+
+        let result = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .check_required(&value);
+
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn whole_one_of() {
+        let value = Parent {
+            one_of: Some(OneOf::Point { x: 0, y: 1 }),
+            ..Default::default()
+        };
+        let mask = vec!["one_of"];
+
+        let result = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .check_required(&value);
+
+        assert_eq!(result, Err(vec!["one_of.point.x".into()]));
+    }
+
+    #[test]
+    fn field_behaviors() {
+        assert_eq!(
+            Parent::field_behaviors(&["required_child"]),
+            vec![FieldBehavior::Required]
+        );
+        assert_eq!(
+            Parent::field_behaviors(&["c"]),
+            vec![FieldBehavior::Required]
+        );
+    }
+}
+
+mod update {
+    use super::*;
+
+    #[test]
+    fn missing_after_update() {
+        let mut target = Parent {
+            name: "name".into(),
+            description: "".into(),
+            child: Child {
+                id: "id".into(),
+                a: 0,
+            },
+            required_child: Some(Child {
+                id: "id".into(),
+                a: 0,
+            }),
+            optional_child: None,
+            flattened: Flattened { c: 1 },
+            one_of: Some(OneOf::Point { x: 1, y: 0 }),
+        };
+        let source = Parent {
+            description: "description".into(),
+            ..Default::default()
+        };
+        let mask = vec!["description", "name"];
+        let options = UpdateOptions::builder().check_required(true).build();
+        let expected = target.clone();
+
+        let result = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .try_update_with_options(&mut target, source, &options);
+
+        assert_eq!(
+            result,
+            Err(ValidationError {
+                path: "name".into(),
+                message: "the field is required".into(),
+            })
+        );
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn unchecked_by_default() {
+        let mut target = Parent {
+            name: "name".into(),
+            ..Default::default()
+        };
+        let mask = vec!["name"];
+
+        let result = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .try_update(&mut target, Parent::default());
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(target.name, "");
+    }
+}
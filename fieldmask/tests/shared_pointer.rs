@@ -0,0 +1,303 @@
+use std::{borrow::Cow, convert::TryFrom, rc::Rc, sync::Arc};
+
+use fieldmask::{Mask, MaskInput, Maskable, OptionMaskable, ProjectOptions, SelfMaskable};
+
+#[derive(Clone, Debug, Default, Maskable, OptionMaskable, PartialEq, SelfMaskable)]
+struct Child {
+    a: u32,
+    b: Cow<'static, str>,
+}
+
+#[derive(Clone, Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct Parent {
+    shared: Arc<Child>,
+    local: Rc<Child>,
+    borrowed: Cow<'static, Child>,
+    optional: Option<Arc<Child>>,
+}
+
+#[derive(Clone, Debug, Default, Maskable, OptionMaskable, PartialEq, SelfMaskable)]
+struct Node {
+    value: u32,
+    next: Option<Arc<Node>>,
+}
+
+static CHILD: Child = Child {
+    a: 5,
+    b: Cow::Borrowed("five"),
+};
+
+mod project {
+    use super::*;
+
+    #[test]
+    fn arc() {
+        let source = Parent {
+            shared: Arc::new(Child {
+                a: 1,
+                b: "one".into(),
+            }),
+            ..Default::default()
+        };
+        let mask = vec!["shared.a"];
+        let expected = Child { a: 1, b: "".into() };
+
+        let actual = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .project(source);
+
+        assert_eq!(*actual.shared, expected);
+    }
+
+    #[test]
+    fn rc() {
+        let source = Parent {
+            local: Rc::new(Child {
+                a: 2,
+                b: "two".into(),
+            }),
+            ..Default::default()
+        };
+        let mask = vec!["local.b"];
+        let expected = Child {
+            a: 0,
+            b: "two".into(),
+        };
+
+        let actual = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .project(source);
+
+        assert_eq!(*actual.local, expected);
+    }
+
+    #[test]
+    fn borrowed_cow() {
+        let source = Parent {
+            borrowed: Cow::Borrowed(&CHILD),
+            ..Default::default()
+        };
+        let mask = vec!["borrowed.a"];
+        let expected = Child { a: 5, b: "".into() };
+
+        let actual = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .project(source);
+
+        assert_eq!(actual.borrowed, Cow::<Child>::Owned(expected));
+        assert_eq!(CHILD.b, "five");
+    }
+
+    #[test]
+    fn optional_arc() {
+        let source = Parent {
+            optional: Some(Arc::new(Child {
+                a: 3,
+                b: "three".into(),
+            })),
+            ..Default::default()
+        };
+        let mask = vec!["optional.b"];
+        let expected = Child {
+            a: 0,
+            b: "three".into(),
+        };
+
+        let actual = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .project(source);
+
+        assert_eq!(actual.optional, Some(Arc::new(expected)));
+    }
+
+    #[test]
+    fn shared_value_is_not_modified() {
+        let shared = Arc::new(Child {
+            a: 1,
+            b: "one".into(),
+        });
+        let source = Parent {
+            shared: shared.clone(),
+            ..Default::default()
+        };
+        let mask = vec!["shared.a"];
+
+        let actual = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .project(source);
+
+        assert_eq!(actual.shared.b, "");
+        assert_eq!(shared.b, "one");
+    }
+
+    #[test]
+    fn normalize() {
+        let source = Parent {
+            optional: Some(Arc::new(Child {
+                a: 0,
+                b: "three".into(),
+            })),
+            ..Default::default()
+        };
+        let mask = vec!["optional.a"];
+        let options = ProjectOptions::builder().normalize(true).build();
+
+        let actual = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .project_with_options(source, &options);
+
+        assert_eq!(actual.optional, None);
+    }
+}
+
+mod update {
+    use super::*;
+
+    #[test]
+    fn arc() {
+        let shared = Arc::new(Child {
+            a: 1,
+            b: "one".into(),
+        });
+        let mut target = Parent {
+            shared: shared.clone(),
+            ..Default::default()
+        };
+        let source = Parent {
+            shared: Arc::new(Child {
+                a: 10,
+                b: "ten".into(),
+            }),
+            ..Default::default()
+        };
+        let mask = vec!["shared.b"];
+        let expected = Child {
+            a: 1,
+            b: "ten".into(),
+        };
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update(&mut target, source);
+
+        assert_eq!(*target.shared, expected);
+        assert_eq!(shared.b, "one");
+    }
+
+    #[test]
+    fn rc() {
+        let mut target = Parent {
+            local: Rc::new(Child {
+                a: 2,
+                b: "two".into(),
+            }),
+            ..Default::default()
+        };
+        let source = Parent {
+            local: Rc::new(Child {
+                a: 20,
+                b: "twenty".into(),
+            }),
+            ..Default::default()
+        };
+        let mask = vec!["local.a"];
+        let expected = Child {
+            a: 20,
+            b: "two".into(),
+        };
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update(&mut target, source);
+
+        assert_eq!(*target.local, expected);
+    }
+
+    #[test]
+    fn borrowed_cow() {
+        let mut target = Parent {
+            borrowed: Cow::Borrowed(&CHILD),
+            ..Default::default()
+        };
+        let source = Parent {
+            borrowed: Cow::Owned(Child {
+                a: 50,
+                b: "fifty".into(),
+            }),
+            ..Default::default()
+        };
+        let mask = vec!["borrowed.b"];
+        let expected = Child {
+            a: 5,
+            b: "fifty".into(),
+        };
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update(&mut target, source);
+
+        assert_eq!(target.borrowed, Cow::<Child>::Owned(expected));
+        assert_eq!(CHILD.b, "five");
+    }
+
+    #[test]
+    fn optional_arc() {
+        let mut target = Parent {
+            optional: Some(Arc::new(Child {
+                a: 3,
+                b: "three".into(),
+            })),
+            ..Default::default()
+        };
+        let source = Parent {
+            optional: Some(Arc::new(Child {
+                a: 30,
+                b: "thirty".into(),
+            })),
+            ..Default::default()
+        };
+        let mask = vec!["optional.a"];
+        let expected = Child {
+            a: 30,
+            b: "three".into(),
+        };
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update(&mut target, source);
+
+        assert_eq!(target.optional, Some(Arc::new(expected)));
+    }
+
+    #[test]
+    fn recursive() {
+        let mut target = Node {
+            value: 1,
+            next: Some(Arc::new(Node {
+                value: 2,
+                next: None,
+            })),
+        };
+        let source = Node {
+            value: 10,
+            next: Some(Arc::new(Node {
+                value: 20,
+                next: Some(Arc::new(Node::default())),
+            })),
+        };
+        let mask = vec!["next.value"];
+        let expected = Node {
+            value: 1,
+            next: Some(Arc::new(Node {
+                value: 20,
+                next: None,
+            })),
+        };
+
+        Mask::<Node>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update(&mut target, source);
+
+        assert_eq!(target, expected);
+    }
+}
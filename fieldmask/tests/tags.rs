@@ -0,0 +1,220 @@
+use std::convert::TryFrom;
+
+use fieldmask::{Mask, MaskInput, Maskable, OptionMaskable, SelfMaskable};
+
+#[derive(Debug, Default, Maskable, OptionMaskable, PartialEq, SelfMaskable)]
+struct Address {
+    street: String,
+    #[fieldmask(tags("pii"))]
+    zip: String,
+}
+
+#[derive(Debug, Maskable, OptionMaskable, PartialEq)]
+enum Contact {
+    Phone(String),
+    Mail {
+        #[fieldmask(tags("pii"))]
+        address: Address,
+        note: String,
+    },
+}
+
+#[derive(Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct Audit {
+    #[fieldmask(tags("admin"))]
+    created_by: String,
+    version: u32,
+}
+
+#[derive(Debug, Default, Maskable, OptionMaskable, PartialEq, SelfMaskable)]
+struct User {
+    id: u64,
+    #[fieldmask(tags("pii"))]
+    email: String,
+    #[fieldmask(tags("pii", "admin"))]
+    ssn: String,
+    address: Address,
+    #[fieldmask(flatten)]
+    audit: Audit,
+    contact: Option<Contact>,
+    #[fieldmask(tags("admin"))]
+    manager: Option<Box<User>>,
+}
+
+#[derive(Debug, Default, Maskable, OptionMaskable, PartialEq, SelfMaskable)]
+struct Node {
+    value: u32,
+    #[fieldmask(tags("admin"))]
+    secret: String,
+    next: Option<Box<Node>>,
+}
+
+fn paths<T: Maskable>(mask: Option<Mask<T>>) -> Option<Vec<String>> {
+    mask.map(|mask| mask.paths())
+}
+
+mod for_tags {
+    use super::*;
+
+    #[test]
+    fn all_tags_allowed() {
+        assert_eq!(
+            paths(Mask::<User>::for_tags(&["pii", "admin"])),
+            Some(vec![
+                "id".into(),
+                "email".into(),
+                "ssn".into(),
+                "address".into(),
+                "created_by".into(),
+                "version".into(),
+                "contact".into(),
+                "manager".into(),
+            ])
+        );
+    }
+
+    #[test]
+    fn some_tags_allowed() {
+        assert_eq!(
+            paths(Mask::<User>::for_tags(&["pii"])),
+            Some(vec![
+                "id".into(),
+                "email".into(),
+                "address".into(),
+                "version".into(),
+                "contact".into(),
+            ])
+        );
+    }
+
+    #[test]
+    fn no_tag_allowed() {
+        assert_eq!(
+            paths(Mask::<User>::for_tags(&[])),
+            Some(vec![
+                "id".into(),
+                "address.street".into(),
+                "version".into(),
+                "contact.phone".into(),
+                "contact.mail.note".into(),
+            ])
+        );
+    }
+
+    #[test]
+    fn all_tags_of_a_field_are_required() {
+        let paths = paths(Mask::<User>::for_tags(&["admin"])).expect("some fields are permitted");
+
+        assert!(!paths.contains(&"manager".to_string()));
+        assert!(!paths.contains(&"ssn".to_string()));
+        assert!(paths.contains(&"created_by".to_string()));
+    }
+
+    #[test]
+    fn recursive_field() {
+        assert_eq!(
+            paths(Mask::<Node>::for_tags(&["admin"])),
+            Some(vec!["value".into(), "secret".into(), "next".into()])
+        );
+        assert_eq!(
+            paths(Mask::<Node>::for_tags(&[])),
+            Some(vec!["value".into()])
+        );
+    }
+
+    #[test]
+    fn nothing_permitted() {
+        #[derive(Maskable)]
+        #[allow(dead_code)]
+        struct Secret {
+            #[fieldmask(tags("admin"))]
+            value: String,
+        }
+
+        assert!(Mask::<Secret>::for_tags(&[]).is_none());
+    }
+}
+
+mod restrict {
+    use super::*;
+
+    #[test]
+    fn permitted_paths() {
+        let mask = vec!["id", "address.street"];
+        let policy = Mask::<User>::for_tags(&[]).expect("some fields are permitted");
+
+        let (restricted, denied) = Mask::<User>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .restrict(&policy);
+
+        assert_eq!(
+            paths(restricted),
+            Some(vec!["id".into(), "address.street".into()])
+        );
+        assert!(denied.is_empty());
+    }
+
+    #[test]
+    fn denied_paths() {
+        let mask = vec!["id", "email", "address", "manager.id"];
+        let policy = Mask::<User>::for_tags(&[]).expect("some fields are permitted");
+
+        let (restricted, denied) = Mask::<User>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .restrict(&policy);
+
+        assert_eq!(
+            paths(restricted),
+            Some(vec!["id".into(), "address.street".into()])
+        );
+        assert_eq!(denied, vec!["email", "address", "manager.id"]);
+    }
+
+    #[test]
+    fn nothing_permitted() {
+        let mask = vec!["email", "created_by"];
+        let policy = Mask::<User>::for_tags(&[]).expect("some fields are permitted");
+
+        let (restricted, denied) = Mask::<User>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .restrict(&policy);
+
+        assert!(restricted.is_none());
+        assert_eq!(denied, vec!["email", "created_by"]);
+    }
+
+    #[test]
+    fn empty_mask() {
+        let policy = Mask::<User>::for_tags(&[]).expect("some fields are permitted");
+
+        let (restricted, denied) = Mask::<User>::empty().restrict(&policy);
+
+        assert!(restricted == Some(policy));
+        assert_eq!(
+            denied,
+            vec![
+                "email",
+                "ssn",
+                "address",
+                "created_by",
+                "contact",
+                "manager"
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_policy() {
+        let mask = vec!["email", "address.zip"];
+
+        let (restricted, denied) = Mask::<User>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .restrict(&Mask::empty());
+
+        assert_eq!(
+            paths(restricted),
+            Some(vec!["email".into(), "address.zip".into()])
+        );
+        assert!(denied.is_empty());
+    }
+}
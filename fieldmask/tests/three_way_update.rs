@@ -0,0 +1,245 @@
+use std::convert::TryFrom;
+
+use fieldmask::{Mask, MaskInput, Maskable, OptionMaskable, SelfMaskable};
+
+#[derive(Clone, Debug, Default, Maskable, OptionMaskable, PartialEq, SelfMaskable)]
+struct Child {
+    a: u32,
+    b: String,
+}
+
+#[derive(Clone, Debug, Maskable, OptionMaskable, PartialEq)]
+enum OneOf {
+    Text(String),
+    Point { x: u32, y: u32 },
+}
+
+#[derive(Clone, Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct Parent {
+    child: Child,
+    optional_child: Option<Child>,
+    one_of: Option<OneOf>,
+    items: Vec<u32>,
+    c: u32,
+}
+
+mod update {
+    use super::*;
+
+    #[test]
+    fn no_conflict() {
+        let base = Parent {
+            child: Child {
+                a: 1,
+                b: "b".into(),
+            },
+            optional_child: Some(Child {
+                a: 2,
+                b: "b".into(),
+            }),
+            one_of: Some(OneOf::Point { x: 3, y: 4 }),
+            items: vec![5],
+            c: 6,
+        };
+        let mut ours = base.clone();
+        ours.child.a = 10;
+        ours.items.push(7);
+        let mut theirs = base.clone();
+        theirs.child.b = "their-b".into();
+        theirs.c = 60;
+        let expected = Parent {
+            child: Child {
+                a: 10,
+                b: "their-b".into(),
+            },
+            items: vec![5, 7],
+            c: 60,
+            ..base.clone()
+        };
+
+        let conflicts = Mask::<Parent>::empty().three_way_update(&base, &mut ours, theirs);
+
+        assert!(conflicts.is_empty());
+        assert_eq!(ours, expected);
+    }
+
+    #[test]
+    fn same_change_is_not_a_conflict() {
+        let base = Parent {
+            child: Child {
+                a: 1,
+                b: "b".into(),
+            },
+            optional_child: Some(Child {
+                a: 2,
+                b: "b".into(),
+            }),
+            one_of: Some(OneOf::Point { x: 3, y: 4 }),
+            items: vec![5],
+            c: 6,
+        };
+        let mut ours = base.clone();
+        ours.c = 60;
+        let mut theirs = base.clone();
+        theirs.c = 60;
+
+        let conflicts = Mask::<Parent>::empty().three_way_update(&base, &mut ours, theirs);
+
+        assert!(conflicts.is_empty());
+        assert_eq!(ours.c, 60);
+    }
+
+    #[test]
+    fn conflicting_fields_keep_ours() {
+        let base = Parent {
+            child: Child {
+                a: 1,
+                b: "b".into(),
+            },
+            optional_child: Some(Child {
+                a: 2,
+                b: "b".into(),
+            }),
+            one_of: Some(OneOf::Point { x: 3, y: 4 }),
+            items: vec![5],
+            c: 6,
+        };
+        let mut ours = base.clone();
+        ours.child.a = 10;
+        ours.items.push(7);
+        let mut theirs = base.clone();
+        theirs.child.a = 100;
+        theirs.child.b = "their-b".into();
+        theirs.items.push(8);
+        let expected = Parent {
+            child: Child {
+                a: 10,
+                b: "their-b".into(),
+            },
+            items: vec![5, 7],
+            ..base.clone()
+        };
+
+        let conflicts = Mask::<Parent>::empty().three_way_update(&base, &mut ours, theirs);
+
+        assert_eq!(conflicts, vec!["child.a", "items"]);
+        assert_eq!(ours, expected);
+    }
+
+    #[test]
+    fn one_of_variant_switch() {
+        let base = Parent {
+            child: Child {
+                a: 1,
+                b: "b".into(),
+            },
+            optional_child: Some(Child {
+                a: 2,
+                b: "b".into(),
+            }),
+            one_of: Some(OneOf::Point { x: 3, y: 4 }),
+            items: vec![5],
+            c: 6,
+        };
+        let mut ours = base.clone();
+        ours.one_of = Some(OneOf::Point { x: 3, y: 40 });
+        let mut theirs = base.clone();
+        theirs.one_of = Some(OneOf::Text("text".into()));
+
+        let conflicts = Mask::<Parent>::empty().three_way_update(&base, &mut ours, theirs);
+
+        assert_eq!(conflicts, vec!["one_of.text"]);
+        assert_eq!(ours.one_of, Some(OneOf::Point { x: 3, y: 40 }));
+    }
+
+    #[test]
+    fn one_of_switched_away_from_changed_variant() {
+        let base = Parent {
+            one_of: Some(OneOf::Text("text".into())),
+            c: 6,
+            ..Default::default()
+        };
+        let mut ours = base.clone();
+        ours.one_of = Some(OneOf::Text("our text".into()));
+        let mut theirs = base.clone();
+        theirs.one_of = Some(OneOf::Point { x: 3, y: 4 });
+        theirs.c = 60;
+
+        let conflicts = Mask::<Parent>::empty().three_way_update(&base, &mut ours, theirs);
+
+        assert_eq!(conflicts, vec!["one_of.point"]);
+        assert_eq!(ours.one_of, Some(OneOf::Text("our text".into())));
+        assert_eq!(ours.c, 60);
+    }
+
+    #[test]
+    fn optional_message_cleared() {
+        let base = Parent {
+            child: Child {
+                a: 1,
+                b: "b".into(),
+            },
+            optional_child: Some(Child {
+                a: 2,
+                b: "b".into(),
+            }),
+            one_of: Some(OneOf::Point { x: 3, y: 4 }),
+            items: vec![5],
+            c: 6,
+        };
+        let mut ours = base.clone();
+        ours.optional_child.as_mut().unwrap().a = 20;
+        let mut theirs = base.clone();
+        theirs.optional_child = None;
+
+        let conflicts = Mask::<Parent>::empty().three_way_update(&base, &mut ours, theirs);
+
+        assert_eq!(conflicts, vec!["optional_child.a"]);
+        assert_eq!(
+            ours.optional_child,
+            Some(Child {
+                a: 20,
+                b: Default::default(),
+            })
+        );
+    }
+
+    #[test]
+    fn restricted_by_mask() {
+        let base = Parent {
+            child: Child {
+                a: 1,
+                b: "b".into(),
+            },
+            optional_child: Some(Child {
+                a: 2,
+                b: "b".into(),
+            }),
+            one_of: Some(OneOf::Point { x: 3, y: 4 }),
+            items: vec![5],
+            c: 6,
+        };
+        let mut ours = base.clone();
+        ours.c = 60;
+        let mut theirs = base.clone();
+        theirs.child.a = 100;
+        theirs.child.b = "their-b".into();
+        theirs.c = 600;
+        let mask = vec!["child.a"];
+        let expected = Parent {
+            child: Child {
+                a: 100,
+                b: "b".into(),
+            },
+            c: 60,
+            ..base.clone()
+        };
+
+        let conflicts = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .three_way_update(&base, &mut ours, theirs);
+
+        assert!(conflicts.is_empty());
+        assert_eq!(ours, expected);
+    }
+}
@@ -0,0 +1,364 @@
+use std::convert::TryFrom;
+
+use fieldmask::{Mask, MaskInput, Maskable, OptionMaskable, SelfMaskable, ValidationError};
+
+fn non_empty(value: &str) -> Result<(), String> {
+    if value.is_empty() {
+        return Err("must not be empty".into());
+    }
+    Ok(())
+}
+
+fn at_most_ten(value: &u32) -> Result<(), &'static str> {
+    if *value > 10 {
+        return Err("must be at most 10");
+    }
+    Ok(())
+}
+
+fn sorted(value: &Child) -> Result<(), String> {
+    if value.a > value.b.len() as u32 {
+        return Err(format!("a ({}) exceeds the length of b", value.a));
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug, Default, Maskable, OptionMaskable, PartialEq, SelfMaskable)]
+struct Child {
+    #[fieldmask(validate = at_most_ten)]
+    a: u32,
+    #[fieldmask(validate = non_empty)]
+    b: String,
+}
+
+#[derive(Clone, Debug, Maskable, OptionMaskable, PartialEq)]
+enum OneOf {
+    Child(Child),
+    Point {
+        #[fieldmask(validate = at_most_ten)]
+        x: u32,
+        y: u32,
+    },
+}
+
+#[derive(Clone, Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct Parent {
+    #[fieldmask(validate = sorted)]
+    child: Child,
+    optional_child: Option<Child>,
+    one_of: Option<OneOf>,
+    #[fieldmask(rename = "count", validate = at_most_ten)]
+    c: u32,
+}
+
+mod update {
+    use super::*;
+
+    #[test]
+    fn valid() {
+        let mut target = Parent {
+            child: Child {
+                a: 1,
+                b: "bbbb".into(),
+            },
+            c: 1,
+            ..Default::default()
+        };
+        let source = Parent {
+            child: Child {
+                a: 2,
+                b: "bb".into(),
+            },
+            c: 3,
+            ..Default::default()
+        };
+        let mask = vec!["child.a", "count"];
+        let expected = Parent {
+            child: Child {
+                a: 2,
+                b: "bbbb".into(),
+            },
+            c: 3,
+            ..Default::default()
+        };
+
+        let result = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .try_update(&mut target, source);
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn invalid_field_leaves_target_unchanged() {
+        let mut target = Parent {
+            child: Child {
+                a: 1,
+                b: "bbbb".into(),
+            },
+            c: 1,
+            ..Default::default()
+        };
+        let source = Parent {
+            child: Child { a: 2, b: "".into() },
+            c: 30,
+            ..Default::default()
+        };
+        let mask = vec!["child.a", "count"];
+        let expected = Parent {
+            child: Child {
+                a: 1,
+                b: "bbbb".into(),
+            },
+            c: 1,
+            ..Default::default()
+        };
+
+        let result = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .try_update(&mut target, source);
+
+        assert_eq!(
+            result,
+            Err(ValidationError {
+                path: "count".into(),
+                message: "must be at most 10".into(),
+            })
+        );
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn validator_of_partially_selected_message() {
+        let mut target = Parent {
+            child: Child {
+                a: 1,
+                b: "bbbb".into(),
+            },
+            ..Default::default()
+        };
+        let source = Parent {
+            child: Child { a: 5, b: "".into() },
+            ..Default::default()
+        };
+        let mask = vec!["child.a"];
+
+        let result = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .try_update(&mut target, source);
+
+        assert_eq!(
+            result,
+            Err(ValidationError {
+                path: "child".into(),
+                message: "a (5) exceeds the length of b".into(),
+            })
+        );
+        assert_eq!(target.child.a, 1);
+    }
+
+    #[test]
+    fn unselected_fields_are_not_validated() {
+        let mut target = Parent {
+            c: 20,
+            ..Default::default()
+        };
+        let source = Parent {
+            optional_child: Some(Child {
+                a: 1,
+                b: "b".into(),
+            }),
+            ..Default::default()
+        };
+        let mask = vec!["optional_child"];
+
+        let result = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .try_update(&mut target, source);
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(target.optional_child.map(|child| child.a), Some(1));
+    }
+
+    #[test]
+    fn empty_mask() {
+        let mut target = Parent {
+            child: Child {
+                a: 1,
+                b: "bbbb".into(),
+            },
+            ..Default::default()
+        };
+        let source = Parent {
+            child: Child {
+                a: 1,
+                b: "bbbb".into(),
+            },
+            optional_child: Some(Child {
+                a: 11,
+                b: "b".into(),
+            }),
+            ..Default::default()
+        };
+
+        let result = Mask::<Parent>::empty().try_update(&mut target, source);
+
+        assert_eq!(
+            result,
+            Err(ValidationError {
+                path: "optional_child.a".into(),
+                message: "must be at most 10".into(),
+            })
+        );
+        assert_eq!(target.optional_child, None);
+    }
+
+    #[test]
+    fn unselected_one_of_field() {
+        let mut target = Parent::default();
+        let source = Parent {
+            one_of: Some(OneOf::Point { x: 11, y: 0 }),
+            ..Default::default()
+        };
+        let mask = vec!["one_of.point.y"];
+
+        let result = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .try_update(&mut target, source);
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(target.one_of, Some(OneOf::Point { x: 0, y: 0 }));
+    }
+
+    #[test]
+    fn whole_one_of_variant() {
+        let mut target = Parent::default();
+        let source = Parent {
+            one_of: Some(OneOf::Point { x: 11, y: 0 }),
+            ..Default::default()
+        };
+        let mask = vec!["one_of.point"];
+
+        let result = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .try_update(&mut target, source);
+
+        assert_eq!(
+            result,
+            Err(ValidationError {
+                path: "one_of.point.x".into(),
+                message: "must be at most 10".into(),
+            })
+        );
+        assert_eq!(target.one_of, None);
+    }
+
+    #[test]
+    fn merged_message() {
+        let mut target = Parent {
+            child: Child {
+                a: 1,
+                b: "bbbb".into(),
+            },
+            ..Default::default()
+        };
+        let source = Parent {
+            child: Child { a: 2, b: "".into() },
+            ..Default::default()
+        };
+        let mask = vec!["child"];
+        // The empty `b` in the source is not merged, so the updated value is valid.
+        let expected = Parent {
+            child: Child {
+                a: 2,
+                b: "bbbb".into(),
+            },
+            ..Default::default()
+        };
+
+        let result = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .try_update(&mut target, source);
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn not_clone() {
+        #[derive(Debug, Default, Maskable, PartialEq, SelfMaskable)]
+        struct Counter {
+            #[fieldmask(validate = at_most_ten)]
+            count: u32,
+            name: String,
+        }
+
+        let mut target = Counter {
+            count: 1,
+            name: "name".into(),
+        };
+        let source = Counter {
+            count: 11,
+            name: "new name".into(),
+        };
+        let mask = vec!["count", "name"];
+        let expected = Counter {
+            count: 1,
+            name: "name".into(),
+        };
+
+        let result = Mask::<Counter>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .try_update(&mut target, source);
+
+        assert_eq!(
+            result,
+            Err(ValidationError {
+                path: "count".into(),
+                message: "must be at most 10".into(),
+            })
+        );
+        assert_eq!(target, expected);
+    }
+}
+
+mod validate {
+    use super::*;
+
+    #[test]
+    fn unselected_one_of_child_field() {
+        let value = Parent {
+            one_of: Some(OneOf::Child(Child { a: 1, b: "".into() })),
+            ..Default::default()
+        };
+        let mask = vec!["one_of.child.a"];
+
+        let result = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .validate(&value);
+
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn whole_one_of_child() {
+        let value = Parent {
+            one_of: Some(OneOf::Child(Child { a: 1, b: "".into() })),
+            ..Default::default()
+        };
+        let mask = vec!["one_of.child"];
+
+        let result = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .validate(&value);
+
+        assert_eq!(
+            result,
+            Err(ValidationError {
+                path: "one_of.child.b".into(),
+                message: "must not be empty".into(),
+            })
+        );
+    }
+}
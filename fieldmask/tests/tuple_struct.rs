@@ -0,0 +1,146 @@
+use std::convert::TryFrom;
+
+use fieldmask::{Mask, MaskInput, Maskable, OptionMaskable, ProjectOptions, SelfMaskable};
+
+#[derive(Debug, Default, Maskable, OptionMaskable, PartialEq, SelfMaskable)]
+struct Child {
+    a: u32,
+    b: u32,
+}
+
+// A transparent newtype shares the mask of its inner type.
+#[derive(Debug, Default, Maskable, PartialEq, SelfMaskable)]
+#[fieldmask(transparent)]
+struct UserId(String);
+
+#[derive(Debug, Default, Maskable, OptionMaskable, PartialEq, SelfMaskable)]
+#[fieldmask(transparent)]
+struct Wrapper(Child);
+
+// Fields of a tuple struct are referred to by their index unless they are renamed.
+#[derive(Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct Pair(u32, #[fieldmask(rename = "second")] Child);
+
+#[derive(Debug, Maskable, PartialEq, SelfMaskable)]
+struct Parent {
+    id: UserId,
+    wrapper: Wrapper,
+    optional_wrapper: Option<Wrapper>,
+    pair: Pair,
+}
+
+mod project {
+    use super::*;
+
+    #[test]
+    fn regular_mask() {
+        let source = Parent {
+            id: UserId("id".into()),
+            wrapper: Wrapper(Child { a: 1, b: 2 }),
+            optional_wrapper: Some(Wrapper(Child { a: 3, b: 4 })),
+            pair: Pair(5, Child { a: 6, b: 7 }),
+        };
+        let mask = vec!["id", "wrapper.a", "optional_wrapper.b", "pair.second.b"];
+        let expected = Parent {
+            id: UserId("id".into()),
+            wrapper: Wrapper(Child { a: 1, b: 0 }),
+            optional_wrapper: Some(Wrapper(Child { a: 0, b: 4 })),
+            pair: Pair(0, Child { a: 0, b: 7 }),
+        };
+
+        let actual = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .project(source);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn index_segment() {
+        let source = Parent {
+            id: UserId("id".into()),
+            wrapper: Wrapper(Child { a: 1, b: 2 }),
+            optional_wrapper: None,
+            pair: Pair(5, Child { a: 6, b: 7 }),
+        };
+        let mask = vec!["pair.0"];
+        let expected = Parent {
+            id: Default::default(),
+            wrapper: Default::default(),
+            optional_wrapper: None,
+            pair: Pair(5, Default::default()),
+        };
+
+        let actual = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .project(source);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn nested_mask_in_transparent_atomic() {
+        let mask = vec!["id.value"];
+
+        assert_eq!(
+            Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+                .expect_err("should fail to parse fieldmask")
+                .to_string(),
+            "\
+            error in field \"id\":\n\
+            \ttype `String` has no field named \"value\"\
+            ",
+        );
+    }
+
+    #[test]
+    fn renamed_index_segment() {
+        let mask = vec!["pair.1"];
+
+        assert_eq!(
+            Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+                .expect_err("should fail to parse fieldmask")
+                .to_string(),
+            "\
+            error in field \"pair\":\n\
+            \ttype `Pair` has no field named \"1\"\
+            ",
+        );
+    }
+}
+
+mod update {
+    use super::*;
+
+    #[test]
+    fn regular_mask() {
+        let mut target = Parent {
+            id: UserId("id".into()),
+            wrapper: Wrapper(Child { a: 1, b: 2 }),
+            optional_wrapper: None,
+            pair: Pair(5, Child { a: 6, b: 7 }),
+        };
+        let source = Parent {
+            id: UserId("new-id".into()),
+            wrapper: Wrapper(Child { a: 10, b: 20 }),
+            optional_wrapper: Some(Wrapper(Child { a: 30, b: 40 })),
+            pair: Pair(50, Child { a: 60, b: 70 }),
+        };
+        let mask = vec!["id", "wrapper.b", "optional_wrapper.a", "pair.0"];
+        let options = Default::default();
+        let expected = Parent {
+            id: UserId("new-id".into()),
+            wrapper: Wrapper(Child { a: 1, b: 20 }),
+            optional_wrapper: Some(Wrapper(Child { a: 30, b: 0 })),
+            pair: Pair(50, Child { a: 6, b: 7 }),
+        };
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update_with_options(&mut target, source, &options);
+        let target = Mask::<Parent>::empty()
+            .project_with_options(target, &ProjectOptions::builder().normalize(true).build());
+
+        assert_eq!(target, expected);
+    }
+}
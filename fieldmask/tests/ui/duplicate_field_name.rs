@@ -0,0 +1,10 @@
+use fieldmask::Maskable;
+
+#[derive(Maskable)]
+struct Parent {
+    a: u32,
+    #[fieldmask(rename = "a")]
+    b: u32,
+}
+
+fn main() {}
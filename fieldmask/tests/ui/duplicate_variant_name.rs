@@ -0,0 +1,10 @@
+use fieldmask::{Maskable, OptionMaskable};
+
+#[allow(non_camel_case_types)]
+#[derive(Maskable, OptionMaskable)]
+enum OneOf {
+    FooBar(String),
+    Foo_Bar(u32),
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+use std::collections::BTreeMap;
+
+use fieldmask::Maskable;
+
+#[derive(Maskable)]
+struct Parent {
+    #[fieldmask(flatten)]
+    a: Option<u32>,
+    #[fieldmask(flatten)]
+    b: BTreeMap<String, u32>,
+}
+
+fn main() {}
@@ -0,0 +1,14 @@
+use fieldmask::Maskable;
+
+#[derive(Default, Maskable)]
+struct Child {
+    a: u32,
+}
+
+#[derive(Maskable)]
+struct Parent {
+    #[fieldmask(flatten, output_only)]
+    child: Child,
+}
+
+fn main() {}
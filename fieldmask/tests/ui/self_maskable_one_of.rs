@@ -0,0 +1,9 @@
+use fieldmask::{Maskable, SelfMaskable};
+
+#[derive(Maskable, SelfMaskable)]
+enum OneOf {
+    A(String),
+    B(u32),
+}
+
+fn main() {}
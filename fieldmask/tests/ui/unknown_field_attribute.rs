@@ -0,0 +1,9 @@
+use fieldmask::Maskable;
+
+#[derive(Maskable)]
+struct Parent {
+    #[fieldmask(flaten)]
+    a: u32,
+}
+
+fn main() {}
@@ -0,0 +1,195 @@
+use std::convert::TryFrom;
+
+use fieldmask::{Mask, MaskInput, Maskable, OptionMaskable, SelfMaskable, UpdateOptions};
+
+#[derive(Clone, Debug, Default, Maskable, OptionMaskable, PartialEq, SelfMaskable)]
+struct Settings {
+    theme: String,
+    language: String,
+}
+
+#[derive(Clone, Debug, Default, Maskable, OptionMaskable, PartialEq, SelfMaskable)]
+struct Profile {
+    name: String,
+    links: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct User {
+    tags: Vec<String>,
+    #[fieldmask(replace_repeated)]
+    addresses: Vec<String>,
+    #[fieldmask(replace_message)]
+    settings: Settings,
+    #[fieldmask(replace_message = false, replace_repeated = false)]
+    profile: Option<Profile>,
+}
+
+mod update {
+    use super::*;
+
+    #[test]
+    fn default_options() {
+        let mut target = User {
+            tags: vec!["a".into()],
+            addresses: vec!["old street".into()],
+            settings: Settings {
+                theme: "dark".into(),
+                language: "en".into(),
+            },
+            profile: Some(Profile {
+                name: "name".into(),
+                links: vec!["old link".into()],
+            }),
+        };
+        let source = User {
+            tags: vec!["b".into()],
+            addresses: vec!["new street".into()],
+            settings: Settings {
+                theme: "light".into(),
+                language: Default::default(),
+            },
+            profile: Some(Profile {
+                name: Default::default(),
+                links: vec!["new link".into()],
+            }),
+        };
+        let mask = vec!["tags", "addresses", "settings", "profile"];
+        let expected = User {
+            tags: vec!["a".into(), "b".into()],
+            addresses: vec!["new street".into()],
+            settings: Settings {
+                theme: "light".into(),
+                language: Default::default(),
+            },
+            profile: Some(Profile {
+                name: "name".into(),
+                links: vec!["old link".into(), "new link".into()],
+            }),
+        };
+
+        Mask::<User>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update(&mut target, source);
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn overrides_global_options() {
+        let mut target = User {
+            tags: vec!["a".into()],
+            addresses: vec!["old street".into()],
+            settings: Settings {
+                theme: "dark".into(),
+                language: "en".into(),
+            },
+            profile: Some(Profile {
+                name: "name".into(),
+                links: vec!["old link".into()],
+            }),
+        };
+        let source = User {
+            tags: vec!["b".into()],
+            addresses: vec!["new street".into()],
+            settings: Settings {
+                theme: "light".into(),
+                language: Default::default(),
+            },
+            profile: Some(Profile {
+                name: Default::default(),
+                links: vec!["new link".into()],
+            }),
+        };
+        let mask = vec!["tags", "addresses", "settings", "profile"];
+        let options = UpdateOptions::builder()
+            .replace_repeated(true)
+            .replace_message(true)
+            .build();
+        let expected = User {
+            tags: vec!["b".into()],
+            addresses: vec!["new street".into()],
+            settings: Settings {
+                theme: "light".into(),
+                language: Default::default(),
+            },
+            profile: Some(Profile {
+                name: "name".into(),
+                links: vec!["old link".into(), "new link".into()],
+            }),
+        };
+
+        Mask::<User>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update_with_options(&mut target, source, &options);
+
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn nested_path() {
+        let mut target = User {
+            profile: Some(Profile {
+                name: "name".into(),
+                links: vec!["old link".into()],
+            }),
+            ..Default::default()
+        };
+        let source = User {
+            profile: Some(Profile {
+                name: Default::default(),
+                links: vec!["new link".into()],
+            }),
+            ..Default::default()
+        };
+        let mask = vec!["profile.links"];
+        let expected = Some(Profile {
+            name: "name".into(),
+            links: vec!["old link".into(), "new link".into()],
+        });
+
+        Mask::<User>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update_with_options(
+                &mut target,
+                source,
+                &UpdateOptions::builder().replace_repeated(true).build(),
+            );
+
+        assert_eq!(target.profile, expected);
+    }
+
+    #[test]
+    fn empty_mask() {
+        let mut target = User {
+            tags: vec!["a".into()],
+            addresses: vec!["old street".into()],
+            settings: Settings {
+                theme: "dark".into(),
+                language: "en".into(),
+            },
+            profile: Some(Profile {
+                name: "name".into(),
+                links: vec!["old link".into()],
+            }),
+        };
+        let source = User {
+            tags: vec!["b".into()],
+            addresses: vec!["new street".into()],
+            settings: Settings {
+                theme: "light".into(),
+                language: Default::default(),
+            },
+            profile: Some(Profile {
+                name: Default::default(),
+                links: vec!["new link".into()],
+            }),
+        };
+
+        Mask::<User>::empty().update(&mut target, source);
+
+        assert_eq!(target.tags, vec!["a", "b"]);
+        assert_eq!(target.addresses, vec!["new street"]);
+        assert_eq!(target.settings.language, "");
+    }
+}
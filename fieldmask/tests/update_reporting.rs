@@ -0,0 +1,144 @@
+use std::convert::TryFrom;
+
+use fieldmask::{Mask, MaskInput, Maskable, OptionMaskable, SelfMaskable, UpdateOptions};
+
+#[derive(Clone, Debug, Default, Maskable, OptionMaskable, PartialEq, SelfMaskable)]
+struct Child {
+    a: u32,
+    b: String,
+}
+
+#[derive(Clone, Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct Parent {
+    child: Child,
+    c: u32,
+    items: Vec<u32>,
+}
+
+// Doesn't implement `Clone`, reporting doesn't need a copy of the target.
+#[derive(Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct NotClone {
+    optional_child: Option<Child>,
+    d: u32,
+}
+
+mod update {
+    use super::*;
+
+    #[test]
+    fn selected_but_equal() {
+        let mut target = Parent {
+            child: Child {
+                a: 1,
+                b: "b".into(),
+            },
+            c: 2,
+            items: vec![],
+        };
+        let source = Parent {
+            child: Child {
+                a: 1,
+                b: "updated-b".into(),
+            },
+            c: 2,
+            items: vec![],
+        };
+        let mask = vec!["child", "c"];
+        let expected = Parent {
+            child: Child {
+                a: 1,
+                b: "updated-b".into(),
+            },
+            c: 2,
+            items: vec![],
+        };
+
+        let changed = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update_reporting(&mut target, source);
+
+        assert_eq!(target, expected);
+        assert_eq!(changed.paths(), vec!["child.b"]);
+    }
+
+    #[test]
+    fn append_repeated() {
+        let mut target = Parent {
+            items: vec![1],
+            ..Default::default()
+        };
+        let source = Parent {
+            items: vec![1],
+            ..Default::default()
+        };
+        let mask = vec!["items"];
+        let mask = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask");
+
+        let changed = mask.update_reporting(&mut target, source.clone());
+        assert_eq!(target.items, vec![1, 1]);
+        assert_eq!(changed.paths(), vec!["items"]);
+
+        let options = UpdateOptions::builder().replace_repeated(true).build();
+        let changed = mask.update_reporting_with_options(&mut target, source, &options);
+        assert_eq!(target.items, vec![1]);
+        assert_eq!(changed.paths(), vec!["items"]);
+    }
+
+    #[test]
+    fn replace_message() {
+        let mut target = Parent {
+            child: Child {
+                a: 1,
+                b: "b".into(),
+            },
+            ..Default::default()
+        };
+        let source = Parent {
+            child: Child {
+                a: 0,
+                b: "b".into(),
+            },
+            ..Default::default()
+        };
+        let mask = vec!["child"];
+        let mask = Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask");
+
+        // `a` isn't merged because it has the default value in `source`.
+        let changed = mask.update_reporting(&mut target, source.clone());
+        assert_eq!(changed, Mask::empty());
+
+        let options = UpdateOptions::builder().replace_message(true).build();
+        let changed = mask.update_reporting_with_options(&mut target, source, &options);
+        assert_eq!(target.child.a, 0);
+        assert_eq!(changed.paths(), vec!["child.a"]);
+    }
+
+    #[test]
+    fn optional_message_set() {
+        let mut target = NotClone::default();
+        let source = NotClone {
+            optional_child: Some(Child {
+                a: 1,
+                b: Default::default(),
+            }),
+            d: 0,
+        };
+        let mask = vec!["optional_child", "d"];
+        let expected = NotClone {
+            optional_child: Some(Child {
+                a: 1,
+                b: Default::default(),
+            }),
+            d: 0,
+        };
+
+        let changed = Mask::<NotClone>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .update_reporting(&mut target, source);
+
+        assert_eq!(target, expected);
+        assert_eq!(changed.paths(), vec!["optional_child.a"]);
+    }
+}
@@ -0,0 +1,185 @@
+use std::convert::TryFrom;
+
+use fieldmask::{FieldRef, Mask, MaskInput, MaskVisitor, Maskable, OptionMaskable, SelfMaskable};
+
+#[derive(Debug, Default, Maskable, OptionMaskable, PartialEq, SelfMaskable)]
+struct Child {
+    a: u32,
+    b: String,
+}
+
+#[derive(Debug, Maskable, OptionMaskable, PartialEq)]
+enum OneOf {
+    Text(String),
+    Child(Box<Child>),
+    Point { x: u32, y: u32 },
+    Nothing,
+}
+
+#[derive(Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct Flattened {
+    c: u32,
+}
+
+#[derive(Debug, Default, Maskable, PartialEq, SelfMaskable)]
+struct Parent {
+    child: Child,
+    optional_child: Option<Child>,
+    #[fieldmask(flatten)]
+    flatten_child: Flattened,
+    one_of: Option<OneOf>,
+    #[fieldmask(rename = "count")]
+    optional_count: Option<u64>,
+}
+
+/// Collects the visited paths and the values of the `u32` fields.
+#[derive(Default)]
+struct Collector {
+    fields: Vec<(String, Option<u32>)>,
+}
+
+impl MaskVisitor for Collector {
+    fn visit_field(&mut self, path: &[&'static str], field: FieldRef<'_>) {
+        self.fields
+            .push((path.join("."), field.downcast_ref::<u32>().copied()));
+    }
+}
+
+mod visit {
+    use super::*;
+
+    #[test]
+    fn empty_mask() {
+        let value = Parent {
+            child: Child {
+                a: 1,
+                b: "b".into(),
+            },
+            optional_child: None,
+            flatten_child: Flattened { c: 2 },
+            one_of: Some(OneOf::Point { x: 3, y: 4 }),
+            optional_count: Some(5),
+        };
+        let mut collector = Collector::default();
+
+        Mask::<Parent>::empty().visit(&value, &mut collector);
+
+        assert_eq!(
+            collector.fields,
+            vec![
+                ("child.a".into(), Some(1)),
+                ("child.b".into(), None),
+                ("c".into(), Some(2)),
+                ("one_of.point.x".into(), Some(3)),
+                ("one_of.point.y".into(), Some(4)),
+                ("count".into(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn regular_mask() {
+        let value = Parent {
+            child: Child {
+                a: 1,
+                b: "b".into(),
+            },
+            optional_child: None,
+            flatten_child: Flattened { c: 2 },
+            one_of: Some(OneOf::Point { x: 3, y: 4 }),
+            optional_count: Some(5),
+        };
+        let mask = vec!["child.b", "optional_child.a", "c", "one_of.point.y"];
+        let mut collector = Collector::default();
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .visit(&value, &mut collector);
+
+        assert_eq!(
+            collector.fields,
+            vec![
+                ("child.b".into(), None),
+                ("c".into(), Some(2)),
+                ("one_of.point.y".into(), Some(4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn unselected_one_of_variant() {
+        let value = Parent {
+            one_of: Some(OneOf::Point { x: 3, y: 4 }),
+            ..Default::default()
+        };
+        let mask = vec!["one_of.text", "one_of.child.a", "one_of.nothing"];
+        let mut collector = Collector::default();
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .visit(&value, &mut collector);
+
+        assert!(collector.fields.is_empty());
+    }
+
+    #[test]
+    fn boxed_one_of_variant() {
+        let value = Parent {
+            one_of: Some(OneOf::Child(Box::new(Child {
+                a: 6,
+                b: "b".into(),
+            }))),
+            ..Default::default()
+        };
+        let mask = vec!["one_of.text", "one_of.child.a", "one_of.nothing"];
+        let mut collector = Collector::default();
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .visit(&value, &mut collector);
+
+        assert_eq!(collector.fields, vec![("one_of.child.a".into(), Some(6))]);
+    }
+
+    #[test]
+    fn unit_one_of_variant() {
+        let value = Parent {
+            one_of: Some(OneOf::Nothing),
+            ..Default::default()
+        };
+        let mask = vec!["one_of.text", "one_of.child.a", "one_of.nothing"];
+        let mut collector = Collector::default();
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .visit(&value, &mut collector);
+
+        assert_eq!(collector.fields, vec![("one_of.nothing".into(), None)]);
+    }
+
+    #[test]
+    fn closure_visitor() {
+        let value = Parent {
+            child: Child {
+                a: 1,
+                b: "b".into(),
+            },
+            optional_count: Some(5),
+            ..Default::default()
+        };
+        let mask = vec!["child.b", "count"];
+        let mut values = Vec::new();
+
+        Mask::<Parent>::try_from(MaskInput(mask.into_iter()))
+            .expect("unable to deserialize mask")
+            .visit(&value, &mut |path: &[&'static str], field: FieldRef<'_>| {
+                if let Some(value) = field.downcast_ref::<String>() {
+                    values.push(format!("{}={}", path.join("."), value));
+                } else if let Some(value) = field.downcast_ref::<u64>() {
+                    values.push(format!("{}={}", path.join("."), value));
+                }
+            });
+
+        assert_eq!(values, vec!["child.b=b", "count=5"]);
+    }
+}
@@ -1,9 +1,13 @@
 #![allow(dead_code)]
 
+use std::collections::HashMap;
+
+use inflector::cases::snakecase::to_snake_case;
 use proc_macro2::TokenStream;
 use quote::ToTokens;
 use syn::{
-    Attribute, Expr, Generics, Ident, Meta, Path, Token, Type, Visibility, braced, parenthesized,
+    Attribute, Expr, ExprLit, ExprPath, Generics, Ident, Index, Lit, LitBool, LitStr, Member, Meta,
+    MetaList, MetaNameValue, Path, Token, Type, Visibility, WherePredicate, braced, parenthesized,
     parse::{Parse, ParseStream},
     punctuated::Punctuated,
     token::{Brace, Paren},
@@ -20,15 +24,15 @@ impl<T: Parse> Parse for Wrap<Punctuated<T, Token![,]>> {
 /// Represents the input.
 pub enum Input {
     UnitEnum(ItemUnitEnum),
-    TupleEnum(ItemTupleEnum),
+    OneOfEnum(ItemOneOfEnum),
     Struct(ItemStruct),
 }
 
 impl Input {
-    pub fn get_message_info(&self) -> MessageInfo {
+    pub fn get_message_info(&self) -> MessageInfo<'_> {
         match &self {
             Input::UnitEnum(input) => input.get_info(),
-            Input::TupleEnum(input) => input.get_info(),
+            Input::OneOfEnum(input) => input.get_info(),
             Input::Struct(input) => input.get_info(),
         }
     }
@@ -42,27 +46,92 @@ impl Parse for Input {
 
         let lookahead = input.lookahead1();
         if lookahead.peek(Token![struct]) {
-            assert_no_attr(&attrs)?;
+            let attr_iter = attrs
+                .iter()
+                .filter(|attr| attr.path().is_ident("fieldmask"))
+                .map(|attr| attr.parse_args())
+                .collect::<syn::Result<Vec<_>>>()?
+                .into_iter()
+                .flat_map(|attrs: Wrap<Punctuated<StructAttribute, Token![,]>>| attrs.0);
+
+            let mut is_transparent = false;
+            let mut bound = None;
+            for attr in attr_iter {
+                match attr {
+                    StructAttribute::Transparent { .. } => {
+                        if is_transparent {
+                            return Err(syn::Error::new_spanned(
+                                attr,
+                                "duplicated transparent attribute",
+                            ));
+                        }
+                        is_transparent = true;
+                    }
+                    StructAttribute::Bound { ref predicates, .. } => {
+                        if bound.is_some() {
+                            return Err(syn::Error::new_spanned(
+                                attr,
+                                "duplicated bound attribute",
+                            ));
+                        }
+                        bound = Some(predicates.clone());
+                    }
+                }
+            }
 
             let struct_token = input.parse()?;
-            let ident = input.parse()?;
-            let generics = {
-                let mut generics: Generics = input.parse()?;
+            let ident: Ident = input.parse()?;
+            let mut generics: Generics = input.parse()?;
+
+            let fields = if input.peek(Paren) {
+                let content;
+                parenthesized!(content in input);
+                let fields = Field::parse_unnamed_fields(&content)?;
                 generics.where_clause = input.parse()?;
-                generics
+                let _: Token![;] = input.parse()?;
+                fields
+            } else {
+                generics.where_clause = input.parse()?;
+                let content;
+                braced!(content in input);
+                content.parse_terminated(Field::parse_named, Token![,])?
             };
+            assert_unique_field_names(&fields)?;
 
-            let content;
-            let brace_token = braced!(content in input);
-            let fields = content.parse_terminated(NamedField::parse, Token![,])?;
+            if is_transparent {
+                if fields.len() != 1 {
+                    return Err(syn::Error::new_spanned(
+                        &ident,
+                        "a transparent struct must have exactly one field",
+                    ));
+                }
+                if fields[0].is_flatten
+                    || fields[0].rename.is_some()
+                    || fields[0].validate.is_some()
+                    || fields[0].is_output_only
+                    || fields[0].is_immutable
+                    || fields[0].is_required
+                    || !fields[0].tags.is_empty()
+                    || fields[0].redact_with.is_some()
+                    || fields[0].replace_repeated.is_some()
+                    || fields[0].replace_message.is_some()
+                    || fields[0].key.is_some()
+                {
+                    return Err(syn::Error::new_spanned(
+                        &fields[0].ty,
+                        "the field of a transparent struct cannot have any attribute",
+                    ));
+                }
+            }
 
             return Ok(Self::Struct(ItemStruct {
                 attrs,
+                is_transparent,
+                bound,
                 vis,
                 struct_token,
                 ident,
                 generics,
-                brace_token,
                 fields,
             }));
         }
@@ -77,65 +146,91 @@ impl Parse for Input {
 
             let content;
             let brace_token = braced!(content in input);
-            let first_variant: EnumVariant = content.parse()?;
-            let _: Option<Token![,]> = content.parse()?;
-            match first_variant {
-                EnumVariant::Unit(first_variant) => {
-                    let attr_iter = attrs
-                        .iter()
-                        .filter(|attr| attr.path().is_ident("fieldmask"))
-                        .map(|attr| attr.parse_args())
-                        .collect::<syn::Result<Vec<_>>>()?
-                        .into_iter()
-                        .flat_map(|attrs: Wrap<Punctuated<UnitEnumAttribute, Token![,]>>| attrs.0);
-
-                    let mut variants =
-                        content.parse_terminated(UnitEnumVariant::parse, Token![,])?;
-                    variants.insert(0, first_variant);
-
-                    let mut normalize_some_default = false;
-                    for attr in attr_iter {
-                        match attr {
-                            UnitEnumAttribute::NormalizeSomeDefault { .. } => {
-                                if normalize_some_default {
-                                    return Err(syn::Error::new_spanned(
-                                        attr,
-                                        "duplicated normalize_some_default attribute",
-                                    ));
-                                }
-                                normalize_some_default = true;
+            let variants = content.parse_terminated(EnumVariant::parse, Token![,])?;
+            assert_unique_variant_names(&variants)?;
+
+            // An enum with only unit variants represents an enum from a protobuf message.
+            // Otherwise, it represents a oneof field.
+            if !variants
+                .iter()
+                .all(|variant| matches!(variant, EnumVariant::Unit(_)))
+            {
+                let attr_iter = attrs
+                    .iter()
+                    .filter(|attr| attr.path().is_ident("fieldmask"))
+                    .map(|attr| attr.parse_args())
+                    .collect::<syn::Result<Vec<_>>>()?
+                    .into_iter()
+                    .flat_map(|attrs: Wrap<Punctuated<OneOfEnumAttribute, Token![,]>>| attrs.0);
+
+                let mut bound = None;
+                for attr in attr_iter {
+                    match attr {
+                        OneOfEnumAttribute::Bound { ref predicates, .. } => {
+                            if bound.is_some() {
+                                return Err(syn::Error::new_spanned(
+                                    attr,
+                                    "duplicated bound attribute",
+                                ));
                             }
+                            bound = Some(predicates.clone());
                         }
                     }
-
-                    return Ok(Self::UnitEnum(ItemUnitEnum {
-                        attrs,
-                        normalize_some_default,
-                        vis,
-                        enum_token,
-                        ident,
-                        generics,
-                        brace_token,
-                        variants,
-                    }));
                 }
-                EnumVariant::Tuple(first_variant) => {
-                    assert_no_attr(&attrs)?;
-
-                    let mut variants =
-                        content.parse_terminated(TupleEnumVariant::parse, Token![,])?;
-                    variants.insert(0, first_variant);
-                    return Ok(Self::TupleEnum(ItemTupleEnum {
-                        attrs,
-                        vis,
-                        enum_token,
-                        ident,
-                        generics,
-                        brace_token,
-                        variants,
-                    }));
+
+                return Ok(Self::OneOfEnum(ItemOneOfEnum {
+                    attrs,
+                    bound,
+                    vis,
+                    enum_token,
+                    ident,
+                    generics,
+                    brace_token,
+                    variants,
+                }));
+            }
+
+            let attr_iter = attrs
+                .iter()
+                .filter(|attr| attr.path().is_ident("fieldmask"))
+                .map(|attr| attr.parse_args())
+                .collect::<syn::Result<Vec<_>>>()?
+                .into_iter()
+                .flat_map(|attrs: Wrap<Punctuated<UnitEnumAttribute, Token![,]>>| attrs.0);
+
+            let variants = variants
+                .into_iter()
+                .map(|variant| match variant {
+                    EnumVariant::Unit(variant) => variant,
+                    _ => unreachable!("all variants are unit variants"),
+                })
+                .collect();
+
+            let mut normalize_some_default = false;
+            for attr in attr_iter {
+                match attr {
+                    UnitEnumAttribute::NormalizeSomeDefault { .. } => {
+                        if normalize_some_default {
+                            return Err(syn::Error::new_spanned(
+                                attr,
+                                "duplicated normalize_some_default attribute",
+                            ));
+                        }
+                        normalize_some_default = true;
+                    }
                 }
             }
+
+            return Ok(Self::UnitEnum(ItemUnitEnum {
+                attrs,
+                normalize_some_default,
+                vis,
+                enum_token,
+                ident,
+                generics,
+                brace_token,
+                variants,
+            }));
         }
 
         Err(lookahead.error())
@@ -155,14 +250,16 @@ pub struct ItemUnitEnum {
 }
 
 impl ItemUnitEnum {
-    pub fn get_info(&self) -> MessageInfo {
+    pub fn get_info(&self) -> MessageInfo<'_> {
         let ident = &self.ident;
         let generics = &self.generics;
 
         MessageInfo {
             ident,
             generics,
+            bound: None,
             fields: vec![],
+            variants: vec![],
         }
     }
 }
@@ -180,7 +277,10 @@ impl Parse for UnitEnumAttribute {
             Meta::Path(p) if p.is_ident("normalize_some_default") => {
                 Ok(Self::NormalizeSomeDefault { repr: p })
             }
-            _ => Err(syn::Error::new_spanned(meta, "invalid meta")),
+            _ => Err(syn::Error::new_spanned(
+                meta,
+                "unknown fieldmask attribute for a unit enum, expected `normalize_some_default`",
+            )),
         }
     }
 }
@@ -193,74 +293,232 @@ impl ToTokens for UnitEnumAttribute {
     }
 }
 
-/// Represents the declaration of a tuple enum.
-pub struct ItemTupleEnum {
+/// Represents the declaration of an enum that represents a oneof field.
+///
+/// Each variant can be a unit variant, a tuple variant or a struct variant.
+pub struct ItemOneOfEnum {
     pub attrs: Vec<Attribute>,
+    /// The where predicates that replace the inferred ones.
+    pub bound: Option<Punctuated<WherePredicate, Token![,]>>,
     pub vis: Visibility,
     pub enum_token: Token![enum],
     pub ident: Ident,
     pub generics: Generics,
     pub brace_token: Brace,
-    pub variants: Punctuated<TupleEnumVariant, Token![,]>,
+    pub variants: Punctuated<EnumVariant, Token![,]>,
 }
 
-impl ItemTupleEnum {
-    pub fn get_info(&self) -> MessageInfo {
+impl ItemOneOfEnum {
+    pub fn get_info(&self) -> MessageInfo<'_> {
         let ident = &self.ident;
         let generics = &self.generics;
 
         MessageInfo {
             ident,
             generics,
-            fields: self
+            bound: self.bound.as_ref(),
+            fields: vec![],
+            variants: self
                 .variants
                 .iter()
-                .map(|v| MessageField {
-                    ident: &v.ident,
-                    ty: &v.ty,
-                    is_flatten: false,
-                })
+                .map(|variant| variant.get_info())
                 .collect::<Vec<_>>(),
         }
     }
 }
 
 /// Represents the declaration of a struct.
+///
+/// Both structs with named fields and tuple structs are represented by this type. Fields of a tuple
+/// struct are referred to by their index unless they are renamed.
 pub struct ItemStruct {
     pub attrs: Vec<Attribute>,
+    /// Whether the struct is a transparent wrapper of its only field.
+    pub is_transparent: bool,
+    /// The where predicates that replace the inferred ones.
+    pub bound: Option<Punctuated<WherePredicate, Token![,]>>,
     pub vis: Visibility,
     pub struct_token: Token![struct],
     pub ident: Ident,
     pub generics: Generics,
-    pub brace_token: Brace,
-    pub fields: Punctuated<NamedField, Token![,]>,
+    pub fields: Punctuated<Field, Token![,]>,
 }
 
 impl ItemStruct {
-    pub fn get_info(&self) -> MessageInfo {
+    pub fn get_info(&self) -> MessageInfo<'_> {
         let ident = &self.ident;
         let generics = &self.generics;
         let fields = self
             .fields
             .iter()
             .map(|f| MessageField {
-                ident: &f.ident,
+                member: f.member.clone(),
+                name: f.name(),
                 ty: &f.ty,
                 is_flatten: f.is_flatten,
+                validate: f.validate.as_ref(),
+                is_output_only: f.is_output_only,
+                is_immutable: f.is_immutable,
+                is_required: f.is_required,
+                tags: &f.tags,
+                redact_with: f.redact_with.as_ref(),
+                replace_repeated: f.replace_repeated,
+                replace_message: f.replace_message,
+                key: f.key.as_ref(),
             })
             .collect::<Vec<_>>();
         MessageInfo {
             ident,
             generics,
+            bound: self.bound.as_ref(),
             fields,
+            variants: vec![],
+        }
+    }
+}
+
+/// Represents an attribute for a struct.
+// Attributes only live during parsing, their size doesn't matter.
+#[allow(clippy::large_enum_variant)]
+#[derive(PartialEq)]
+enum StructAttribute {
+    Transparent {
+        repr: Path,
+    },
+    Bound {
+        repr: MetaNameValue,
+        predicates: Punctuated<WherePredicate, Token![,]>,
+    },
+}
+
+impl Parse for StructAttribute {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let meta: Meta = input.parse()?;
+        match meta {
+            Meta::Path(p) if p.is_ident("transparent") => Ok(Self::Transparent { repr: p }),
+            Meta::NameValue(m) if m.path.is_ident("bound") => {
+                let predicates = parse_bound(&m)?;
+                Ok(Self::Bound {
+                    repr: m,
+                    predicates,
+                })
+            }
+            _ => Err(syn::Error::new_spanned(
+                meta,
+                "unknown fieldmask attribute for a struct, expected `transparent` or \
+                 `bound = \"...\"`",
+            )),
+        }
+    }
+}
+
+impl ToTokens for StructAttribute {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        match self {
+            Self::Transparent { repr } => repr.to_tokens(tokens),
+            Self::Bound { repr, .. } => repr.to_tokens(tokens),
+        }
+    }
+}
+
+/// Represents an attribute for a oneof enum.
+#[derive(PartialEq)]
+enum OneOfEnumAttribute {
+    Bound {
+        repr: MetaNameValue,
+        predicates: Punctuated<WherePredicate, Token![,]>,
+    },
+}
+
+impl Parse for OneOfEnumAttribute {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let meta: Meta = input.parse()?;
+        match meta {
+            Meta::NameValue(m) if m.path.is_ident("bound") => {
+                let predicates = parse_bound(&m)?;
+                Ok(Self::Bound {
+                    repr: m,
+                    predicates,
+                })
+            }
+            _ => Err(syn::Error::new_spanned(
+                meta,
+                "unknown fieldmask attribute for a oneof enum, expected `bound = \"...\"`",
+            )),
         }
     }
 }
 
+impl ToTokens for OneOfEnumAttribute {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        match self {
+            Self::Bound { repr, .. } => repr.to_tokens(tokens),
+        }
+    }
+}
+
+/// Parses the where predicates in `bound = "..."`.
+/// Parses the value of a boolean attribute, e.g. `replace_repeated = false`.
+fn parse_bool(value: &Expr) -> syn::Result<bool> {
+    let lit: LitBool = syn::parse2(value.to_token_stream())?;
+    Ok(lit.value)
+}
+
+fn parse_bound(meta: &MetaNameValue) -> syn::Result<Punctuated<WherePredicate, Token![,]>> {
+    let bound: LitStr = syn::parse2(meta.value.to_token_stream())?;
+    bound.parse_with(Punctuated::parse_terminated)
+}
+
 /// Represents the declaration of a variant in an enum.
 pub enum EnumVariant {
     Unit(UnitEnumVariant),
     Tuple(TupleEnumVariant),
+    Fields(FieldsEnumVariant),
+}
+
+impl EnumVariant {
+    pub fn ident(&self) -> &Ident {
+        match self {
+            Self::Unit(variant) => &variant.ident,
+            Self::Tuple(variant) => &variant.ident,
+            Self::Fields(variant) => &variant.ident,
+        }
+    }
+
+    pub fn get_info(&self) -> MessageVariant<'_> {
+        let ident = self.ident();
+        MessageVariant {
+            ident,
+            // Convert to snake case to match the field name in the mask. Variants are typically in
+            // PascalCase.
+            name: to_snake_case(&ident.to_string()),
+            kind: match self {
+                Self::Unit(_) => MessageVariantKind::Unit,
+                Self::Tuple(variant) => MessageVariantKind::Tuple(&variant.ty),
+                Self::Fields(variant) => MessageVariantKind::Fields(
+                    variant
+                        .fields
+                        .iter()
+                        .map(|f| MessageField {
+                            member: f.member.clone(),
+                            name: f.name(),
+                            ty: &f.ty,
+                            is_flatten: f.is_flatten,
+                            validate: f.validate.as_ref(),
+                            is_output_only: f.is_output_only,
+                            is_immutable: f.is_immutable,
+                            is_required: f.is_required,
+                            tags: &f.tags,
+                            redact_with: f.redact_with.as_ref(),
+                            replace_repeated: f.replace_repeated,
+                            replace_message: f.replace_message,
+                            key: f.key.as_ref(),
+                        })
+                        .collect::<Vec<_>>(),
+                ),
+            },
+        }
+    }
 }
 
 impl Parse for EnumVariant {
@@ -269,7 +527,22 @@ impl Parse for EnumVariant {
         let ident: Ident = input.parse()?;
 
         if input.peek(Paren) {
-            Ok(Self::Tuple(TupleEnumVariant::parse_content(
+            // A tuple variant with a single item is a wrapper of the item. Other tuple variants are
+            // treated as struct variants with fields named by their index.
+            let fork = input.fork();
+            let content;
+            parenthesized!(content in fork);
+            if Field::parse_unnamed_fields(&content)?.len() == 1 {
+                Ok(Self::Tuple(TupleEnumVariant::parse_content(
+                    attrs, ident, input,
+                )?))
+            } else {
+                Ok(Self::Fields(FieldsEnumVariant::parse_content(
+                    attrs, ident, input,
+                )?))
+            }
+        } else if input.peek(Brace) {
+            Ok(Self::Fields(FieldsEnumVariant::parse_content(
                 attrs, ident, input,
             )?))
         } else {
@@ -358,20 +631,100 @@ impl Parse for TupleEnumVariant {
     }
 }
 
-/// Represents the declaration of a named field in a struct.
-pub struct NamedField {
+/// Represents the declaration of a variant with named fields, or with multiple unnamed fields.
+pub struct FieldsEnumVariant {
     pub attrs: Vec<Attribute>,
-    pub vis: Visibility,
     pub ident: Ident,
-    pub colon_token: Token![:],
+    pub fields: Punctuated<Field, Token![,]>,
+}
+
+impl FieldsEnumVariant {
+    fn parse_content(attrs: Vec<Attribute>, ident: Ident, input: ParseStream) -> syn::Result<Self> {
+        assert_no_attr(&attrs)?;
+
+        let content;
+        let fields = if input.peek(Paren) {
+            parenthesized!(content in input);
+            Field::parse_unnamed_fields(&content)?
+        } else {
+            braced!(content in input);
+            content.parse_terminated(Field::parse_named, Token![,])?
+        };
+        assert_unique_field_names(&fields)?;
+
+        Ok(FieldsEnumVariant {
+            attrs,
+            ident,
+            fields,
+        })
+    }
+}
+
+/// Represents the declaration of a field in a struct.
+pub struct Field {
+    pub attrs: Vec<Attribute>,
+    pub vis: Visibility,
+    /// The identifier of a named field, or the index of an unnamed field.
+    pub member: Member,
     pub ty: Type,
     pub is_flatten: bool,
+    /// The name used in field masks, if it's different from the default one.
+    pub rename: Option<LitStr>,
+    /// The function used to validate the value of the field.
+    pub validate: Option<Path>,
+    pub is_output_only: bool,
+    pub is_immutable: bool,
+    pub is_required: bool,
+    /// The access tags of the field, e.g. `#[fieldmask(tags("pii"))]`.
+    pub tags: Vec<LitStr>,
+    /// The placeholder that replaces the value of the field when it is redacted.
+    pub redact_with: Option<RedactWith>,
+    /// Overrides `UpdateOptions::replace_repeated` when updating the field.
+    pub replace_repeated: Option<bool>,
+    /// Overrides `UpdateOptions::replace_message` when updating the field.
+    pub replace_message: Option<bool>,
+    /// The field of the elements that identifies them when a repeated field is merged by key.
+    pub key: Option<Member>,
 }
 
-impl Parse for NamedField {
-    fn parse(input: ParseStream) -> syn::Result<Self> {
+impl Field {
+    /// Parses a named field, e.g. `pub name: Type`.
+    fn parse_named(input: ParseStream) -> syn::Result<Self> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        let vis = input.parse()?;
+        let ident = input.parse()?;
+        let _colon_token: Token![:] = input.parse()?;
+        let ty = input.parse()?;
+        Self::from_parts(attrs, vis, Member::Named(ident), ty)
+    }
+
+    /// Parses the unnamed fields in a tuple struct or a tuple variant.
+    fn parse_unnamed_fields(input: ParseStream) -> syn::Result<Punctuated<Self, Token![,]>> {
+        let mut fields = Punctuated::new();
+        while !input.is_empty() {
+            fields.push_value(Self::parse_unnamed(input, fields.len())?);
+            if input.is_empty() {
+                break;
+            }
+            fields.push_punct(input.parse()?);
+        }
+        Ok(fields)
+    }
+
+    /// Parses an unnamed field at position `index`, e.g. `pub Type`.
+    fn parse_unnamed(input: ParseStream, index: usize) -> syn::Result<Self> {
         let attrs = input.call(Attribute::parse_outer)?;
+        let vis = input.parse()?;
+        let ty = input.parse()?;
+        Self::from_parts(attrs, vis, Member::Unnamed(Index::from(index)), ty)
+    }
 
+    fn from_parts(
+        attrs: Vec<Attribute>,
+        vis: Visibility,
+        member: Member,
+        ty: Type,
+    ) -> syn::Result<Self> {
         #[allow(unused_assignments)]
         let mut is_flatten = false;
 
@@ -393,11 +746,21 @@ impl Parse for NamedField {
             .map(|attr| attr.parse_args())
             .collect::<syn::Result<Vec<_>>>()?
             .into_iter()
-            .flat_map(|attrs: Wrap<Punctuated<NamedFieldAttribute, Token![,]>>| attrs.0);
+            .flat_map(|attrs: Wrap<Punctuated<FieldAttribute, Token![,]>>| attrs.0);
 
+        let mut rename = None;
+        let mut validate = None;
+        let mut is_output_only = false;
+        let mut is_immutable = false;
+        let mut is_required = false;
+        let mut tags = None;
+        let mut redact_with = None;
+        let mut replace_repeated = None;
+        let mut replace_message = None;
+        let mut key = None;
         for attr in attr_iter {
             match attr {
-                NamedFieldAttribute::Flatten { .. } => {
+                FieldAttribute::Flatten { .. } => {
                     if is_flatten {
                         return Err(syn::Error::new_spanned(
                             attr,
@@ -406,40 +769,308 @@ impl Parse for NamedField {
                     }
                     is_flatten = true;
                 }
+                FieldAttribute::Rename { ref name, .. } => {
+                    if rename.is_some() {
+                        return Err(syn::Error::new_spanned(attr, "duplicated rename attribute"));
+                    }
+                    rename = Some(name.clone());
+                }
+                FieldAttribute::Validate { ref path, .. } => {
+                    if validate.is_some() {
+                        return Err(syn::Error::new_spanned(
+                            attr,
+                            "duplicated validate attribute",
+                        ));
+                    }
+                    validate = Some(path.clone());
+                }
+                FieldAttribute::OutputOnly { ref repr } => {
+                    if is_output_only {
+                        return Err(syn::Error::new_spanned(
+                            attr,
+                            "duplicated output_only attribute",
+                        ));
+                    }
+                    if is_immutable {
+                        return Err(syn::Error::new_spanned(
+                            repr,
+                            "a field cannot be both output only and immutable",
+                        ));
+                    }
+                    is_output_only = true;
+                }
+                FieldAttribute::Immutable { ref repr } => {
+                    if is_immutable {
+                        return Err(syn::Error::new_spanned(
+                            attr,
+                            "duplicated immutable attribute",
+                        ));
+                    }
+                    if is_output_only {
+                        return Err(syn::Error::new_spanned(
+                            repr,
+                            "a field cannot be both output only and immutable",
+                        ));
+                    }
+                    is_immutable = true;
+                }
+                FieldAttribute::Required { .. } => {
+                    if is_required {
+                        return Err(syn::Error::new_spanned(
+                            attr,
+                            "duplicated required attribute",
+                        ));
+                    }
+                    is_required = true;
+                }
+                FieldAttribute::Tags {
+                    tags: ref values, ..
+                } => {
+                    if tags.is_some() {
+                        return Err(syn::Error::new_spanned(attr, "duplicated tags attribute"));
+                    }
+                    tags = Some(values.clone());
+                }
+                FieldAttribute::RedactWith {
+                    redact_with: ref value,
+                    ..
+                } => {
+                    if redact_with.is_some() {
+                        return Err(syn::Error::new_spanned(
+                            attr,
+                            "duplicated redact_with attribute",
+                        ));
+                    }
+                    redact_with = Some(value.clone());
+                }
+                FieldAttribute::ReplaceRepeated { value, .. } => {
+                    if replace_repeated.is_some() {
+                        return Err(syn::Error::new_spanned(
+                            attr,
+                            "duplicated replace_repeated attribute",
+                        ));
+                    }
+                    replace_repeated = Some(value);
+                }
+                FieldAttribute::ReplaceMessage { value, .. } => {
+                    if replace_message.is_some() {
+                        return Err(syn::Error::new_spanned(
+                            attr,
+                            "duplicated replace_message attribute",
+                        ));
+                    }
+                    replace_message = Some(value);
+                }
+                FieldAttribute::Key {
+                    member: ref value, ..
+                } => {
+                    if key.is_some() {
+                        return Err(syn::Error::new_spanned(attr, "duplicated key attribute"));
+                    }
+                    key = Some(value.clone());
+                }
             }
         }
+        if is_flatten && let Some(validate) = &validate {
+            return Err(syn::Error::new_spanned(
+                validate,
+                "cannot validate a flattened field; validate the fields of the flattened type \
+                 instead",
+            ));
+        }
+        if is_flatten && (is_output_only || is_immutable || is_required) {
+            return Err(syn::Error::new_spanned(
+                &ty,
+                "a flattened field cannot be output only, immutable or required; mark the fields \
+                 of the flattened type instead",
+            ));
+        }
+        if is_flatten && redact_with.is_some() {
+            return Err(syn::Error::new_spanned(
+                &ty,
+                "cannot redact a flattened field; redact the fields of the flattened type instead",
+            ));
+        }
+        if is_flatten && key.is_some() {
+            return Err(syn::Error::new_spanned(
+                &ty,
+                "a flattened field cannot have a key; only repeated fields can be merged by key",
+            ));
+        }
+        if is_flatten && tags.is_some() {
+            return Err(syn::Error::new_spanned(
+                &ty,
+                "a flattened field cannot be tagged; tag the fields of the flattened type instead",
+            ));
+        }
 
-        Ok(NamedField {
+        Ok(Field {
             attrs,
-            vis: input.parse()?,
-            ident: input.parse()?,
-            colon_token: input.parse()?,
-            ty: input.parse()?,
+            vis,
+            member,
+            ty,
             is_flatten,
+            rename,
+            validate,
+            is_output_only,
+            is_immutable,
+            is_required,
+            tags: tags.unwrap_or_default(),
+            redact_with,
+            replace_repeated,
+            replace_message,
+            key,
         })
     }
+
+    /// Returns the tokens to point at when reporting an error about the name of the field.
+    fn name_tokens(&self) -> TokenStream {
+        match (&self.rename, &self.member) {
+            (Some(rename), _) => rename.to_token_stream(),
+            (None, member) => member.to_token_stream(),
+        }
+    }
+
+    /// Returns the name of the field in field masks.
+    pub fn name(&self) -> String {
+        match (&self.rename, &self.member) {
+            (Some(rename), _) => rename.value(),
+            // Convert to snake case to match the field name in the mask.
+            (None, Member::Named(ident)) => to_snake_case(&ident.to_string()),
+            (None, Member::Unnamed(index)) => index.index.to_string(),
+        }
+    }
 }
 
-/// Represents an attribute for a named field in a struct.
+/// Represents an attribute for a field in a struct.
 #[derive(PartialEq)]
-enum NamedFieldAttribute {
-    Flatten { repr: Path },
+enum FieldAttribute {
+    Flatten {
+        repr: Path,
+    },
+    Rename {
+        repr: MetaNameValue,
+        name: LitStr,
+    },
+    Validate {
+        repr: MetaNameValue,
+        path: Path,
+    },
+    OutputOnly {
+        repr: Path,
+    },
+    Immutable {
+        repr: Path,
+    },
+    Required {
+        repr: Path,
+    },
+    Tags {
+        repr: MetaList,
+        tags: Vec<LitStr>,
+    },
+    RedactWith {
+        repr: MetaNameValue,
+        redact_with: RedactWith,
+    },
+    ReplaceRepeated {
+        repr: Meta,
+        value: bool,
+    },
+    ReplaceMessage {
+        repr: Meta,
+        value: bool,
+    },
+    Key {
+        repr: MetaNameValue,
+        member: Member,
+    },
 }
 
-impl Parse for NamedFieldAttribute {
+impl Parse for FieldAttribute {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         let meta: Meta = input.parse()?;
         match meta {
             Meta::Path(p) if p.is_ident("flatten") => Ok(Self::Flatten { repr: p }),
-            _ => Err(syn::Error::new_spanned(meta, "invalid meta")),
+            Meta::Path(p) if p.is_ident("output_only") => Ok(Self::OutputOnly { repr: p }),
+            Meta::Path(p) if p.is_ident("immutable") => Ok(Self::Immutable { repr: p }),
+            Meta::Path(p) if p.is_ident("required") => Ok(Self::Required { repr: p }),
+            Meta::NameValue(m) if m.path.is_ident("rename") => {
+                let name = syn::parse2(m.value.to_token_stream())?;
+                Ok(Self::Rename { repr: m, name })
+            }
+            Meta::NameValue(m) if m.path.is_ident("validate") => {
+                let path = syn::parse2(m.value.to_token_stream())?;
+                Ok(Self::Validate { repr: m, path })
+            }
+            Meta::Path(ref p) if p.is_ident("replace_repeated") => Ok(Self::ReplaceRepeated {
+                repr: meta,
+                value: true,
+            }),
+            Meta::NameValue(ref m) if m.path.is_ident("replace_repeated") => {
+                let value = parse_bool(&m.value)?;
+                Ok(Self::ReplaceRepeated { repr: meta, value })
+            }
+            Meta::Path(ref p) if p.is_ident("replace_message") => Ok(Self::ReplaceMessage {
+                repr: meta,
+                value: true,
+            }),
+            Meta::NameValue(ref m) if m.path.is_ident("replace_message") => {
+                let value = parse_bool(&m.value)?;
+                Ok(Self::ReplaceMessage { repr: meta, value })
+            }
+            Meta::NameValue(m) if m.path.is_ident("key") => {
+                let lit: LitStr = syn::parse2(m.value.to_token_stream())?;
+                let member = lit.parse()?;
+                Ok(Self::Key { repr: m, member })
+            }
+            Meta::NameValue(m) if m.path.is_ident("redact_with") => {
+                let redact_with = match &m.value {
+                    Expr::Lit(ExprLit { lit, .. }) => RedactWith::Value(lit.clone()),
+                    Expr::Path(ExprPath { path, .. }) => RedactWith::Function(path.clone()),
+                    value => {
+                        return Err(syn::Error::new_spanned(
+                            value,
+                            "expected a literal placeholder or the path of a function",
+                        ));
+                    }
+                };
+                Ok(Self::RedactWith {
+                    repr: m,
+                    redact_with,
+                })
+            }
+            Meta::List(m) if m.path.is_ident("tags") => {
+                let tags = m
+                    .parse_args_with(Punctuated::<LitStr, Token![,]>::parse_terminated)?
+                    .into_iter()
+                    .collect();
+                Ok(Self::Tags { repr: m, tags })
+            }
+            _ => Err(syn::Error::new_spanned(
+                meta,
+                "unknown fieldmask attribute for a field, expected `flatten`, `rename = \"...\"`, \
+                 `validate = path`, `output_only`, `immutable`, `required`, `tags(\"...\")`, \
+                 `redact_with = ...`, `replace_repeated`, `replace_message` or `key = \"...\"`",
+            )),
         }
     }
 }
 
-impl ToTokens for NamedFieldAttribute {
+impl ToTokens for FieldAttribute {
     fn to_tokens(&self, tokens: &mut TokenStream) {
         match self {
             Self::Flatten { repr } => repr.to_tokens(tokens),
+            Self::Rename { repr, .. } => repr.to_tokens(tokens),
+            Self::Validate { repr, .. } => repr.to_tokens(tokens),
+            Self::OutputOnly { repr } => repr.to_tokens(tokens),
+            Self::Immutable { repr } => repr.to_tokens(tokens),
+            Self::Required { repr } => repr.to_tokens(tokens),
+            Self::Tags { repr, .. } => repr.to_tokens(tokens),
+            Self::RedactWith { repr, .. } => repr.to_tokens(tokens),
+            Self::ReplaceRepeated { repr, .. } => repr.to_tokens(tokens),
+            Self::ReplaceMessage { repr, .. } => repr.to_tokens(tokens),
+            Self::Key { repr, .. } => repr.to_tokens(tokens),
         }
     }
 }
@@ -466,18 +1097,108 @@ impl Parse for ProstFieldAttribute {
 
 /// The metadata of a field in a message.
 pub struct MessageField<'a> {
-    pub ident: &'a Ident,
+    /// The member used to access the field.
+    pub member: Member,
+    /// The name of the field in field masks.
+    pub name: String,
     pub ty: &'a Type,
     pub is_flatten: bool,
+    /// The function used to validate the value of the field.
+    pub validate: Option<&'a Path>,
+    pub is_output_only: bool,
+    pub is_immutable: bool,
+    pub is_required: bool,
+    /// The access tags of the field.
+    pub tags: &'a [LitStr],
+    /// The placeholder that replaces the value of the field when it is redacted.
+    pub redact_with: Option<&'a RedactWith>,
+    /// Overrides `UpdateOptions::replace_repeated` when updating the field.
+    pub replace_repeated: Option<bool>,
+    /// Overrides `UpdateOptions::replace_message` when updating the field.
+    pub replace_message: Option<bool>,
+    /// The field of the elements that identifies them when a repeated field is merged by key.
+    pub key: Option<&'a Member>,
+}
+
+/// The placeholder of a redacted field, e.g. `#[fieldmask(redact_with = "***")]`.
+#[derive(Clone, PartialEq)]
+pub enum RedactWith {
+    /// A literal. String literals are converted into the type of the field with `Into`.
+    Value(Lit),
+    /// A function that takes a reference to the value of the field and returns the placeholder.
+    Function(Path),
+}
+
+/// The metadata of a variant in a oneof enum.
+pub struct MessageVariant<'a> {
+    pub ident: &'a Ident,
+    /// The name of the variant in field masks.
+    pub name: String,
+    pub kind: MessageVariantKind<'a>,
+}
+
+/// The kind of a variant in a oneof enum.
+pub enum MessageVariantKind<'a> {
+    /// A unit variant can only be selected as a whole.
+    Unit,
+    /// A tuple variant with a single item uses the mask of the item.
+    Tuple(&'a Type),
+    /// A variant with fields uses the mask of its fields, the same way a struct does.
+    Fields(Vec<MessageField<'a>>),
 }
 
 /// The metadata of a message.
 pub struct MessageInfo<'a> {
     pub ident: &'a Ident,
     pub generics: &'a Generics,
+    /// The where predicates that replace the inferred ones, if specified with
+    /// `#[fieldmask(bound = "...")]`.
+    pub bound: Option<&'a Punctuated<WherePredicate, Token![,]>>,
     /// The fields of the message.
     /// Note that unit enum is considered a single value so it does not have any field.
+    /// Oneof enums have variants instead of fields.
     pub fields: Vec<MessageField<'a>>,
+    /// The variants of a oneof enum.
+    pub variants: Vec<MessageVariant<'a>>,
+}
+
+/// Returns an error if two non-flattened fields share the same name in field masks.
+fn assert_unique_field_names(fields: &Punctuated<Field, Token![,]>) -> syn::Result<()> {
+    let mut names = HashMap::new();
+    for field in fields.iter().filter(|field| !field.is_flatten) {
+        let name = field.name();
+        if let Some(previous) = names.insert(name.clone(), field) {
+            return Err(syn::Error::new_spanned(
+                field.name_tokens(),
+                format!(
+                    "field `{}` has the same name \"{name}\" in field masks as field `{}`; use \
+                     `#[fieldmask(rename = \"...\")]` to give one of them a different name",
+                    field.member.to_token_stream(),
+                    previous.member.to_token_stream(),
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Returns an error if two variants of a oneof enum share the same name in field masks.
+fn assert_unique_variant_names(variants: &Punctuated<EnumVariant, Token![,]>) -> syn::Result<()> {
+    let mut names = HashMap::new();
+    for variant in variants {
+        let ident = variant.ident();
+        let name = to_snake_case(&ident.to_string());
+        if let Some(previous) = names.insert(name.clone(), ident) {
+            return Err(syn::Error::new_spanned(
+                ident,
+                format!(
+                    "variant `{ident}` has the same name \"{name}\" in field masks as variant \
+                     `{previous}`; rename one of the variants",
+                ),
+            ));
+        }
+    }
+    Ok(())
 }
 
 fn assert_no_attr(attrs: &[Attribute]) -> syn::Result<()> {
@@ -498,7 +1219,10 @@ enum NoAttribute {}
 impl Parse for NoAttribute {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         let meta: Meta = input.parse()?;
-        Err(syn::Error::new_spanned(meta, "invalid meta"))
+        Err(syn::Error::new_spanned(
+            meta,
+            "fieldmask attributes are not supported here",
+        ))
     }
 }
 
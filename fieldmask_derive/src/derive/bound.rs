@@ -0,0 +1,83 @@
+use proc_macro2::{TokenStream, TokenTree};
+use quote::ToTokens;
+use syn::{Generics, Ident, Token, Type, WherePredicate, parse_quote, punctuated::Punctuated};
+
+/// Returns `generics` with the where predicates required by a derived impl.
+///
+/// If the predicates are specified with `#[fieldmask(bound = "...")]`, they replace the inferred
+/// ones. Otherwise, every type in `types` that mentions a type parameter must implement
+/// `trait_bound`, the same way serde infers its bounds. Types that mention the deriving type itself
+/// are skipped so that recursive types don't lead to cyclic bounds.
+pub fn with_bound<'a>(
+    generics: &Generics,
+    ident: &Ident,
+    bound: Option<&Punctuated<WherePredicate, Token![,]>>,
+    types: impl IntoIterator<Item = &'a Type>,
+    trait_bound: TokenStream,
+) -> Generics {
+    let predicates = match bound {
+        Some(bound) => bound.iter().cloned().collect(),
+        None => {
+            let type_params = generics
+                .type_params()
+                .map(|param| &param.ident)
+                .collect::<Vec<_>>();
+            types
+                .into_iter()
+                .filter(|ty| {
+                    let tokens = ty.to_token_stream();
+                    mentions_any(&tokens, &type_params) && !mentions_any(&tokens, &[ident])
+                })
+                .map(|ty| parse_quote! { #ty: #trait_bound })
+                .collect()
+        }
+    };
+
+    with_predicates(generics, predicates)
+}
+
+/// Returns `generics` with the deriving type bounded by `trait_bound` when it has any type
+/// parameter, unless the predicates are specified with `#[fieldmask(bound = "...")]`.
+pub fn with_self_bound(
+    generics: &Generics,
+    ident: &Ident,
+    bound: Option<&Punctuated<WherePredicate, Token![,]>>,
+    trait_bound: TokenStream,
+) -> Generics {
+    let predicates = match bound {
+        Some(bound) => bound.iter().cloned().collect(),
+        None if generics.type_params().next().is_some() => {
+            let (_, ty_generics, _) = generics.split_for_impl();
+            vec![parse_quote! { #ident #ty_generics: #trait_bound }]
+        }
+        None => vec![],
+    };
+
+    with_predicates(generics, predicates)
+}
+
+fn with_predicates(generics: &Generics, predicates: Vec<WherePredicate>) -> Generics {
+    let mut generics = generics.clone();
+    if !predicates.is_empty() {
+        generics.make_where_clause().predicates.extend(predicates);
+    }
+    generics
+}
+
+/// Returns whether `ty` mentions any type parameter of `generics`.
+pub fn mentions_type_param(generics: &Generics, ty: &Type) -> bool {
+    let type_params = generics
+        .type_params()
+        .map(|param| &param.ident)
+        .collect::<Vec<_>>();
+    mentions_any(&ty.to_token_stream(), &type_params)
+}
+
+/// Returns whether any of `idents` appears in `tokens`.
+fn mentions_any(tokens: &TokenStream, idents: &[&Ident]) -> bool {
+    tokens.clone().into_iter().any(|tree| match tree {
+        TokenTree::Ident(ident) => idents.contains(&&ident),
+        TokenTree::Group(group) => mentions_any(&group.stream(), idents),
+        _ => false,
+    })
+}
@@ -1,9 +1,15 @@
-use inflector::cases::snakecase::to_snake_case;
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::{Index, parse_macro_input};
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote, quote_spanned};
+use syn::{
+    Generics, Ident, Index, Lit, Member, Type, ext::IdentExt, parse_macro_input, parse_quote,
+    spanned::Spanned,
+};
 
-use super::ast::{Input, MessageInfo};
+use super::{
+    ast::{Input, MessageField, MessageInfo, MessageVariant, MessageVariantKind, RedactWith},
+    bound::{mentions_type_param, with_bound, with_self_bound},
+};
 
 /// The implementation for `derive_maskable`.
 pub fn derive_maskable_impl(input: TokenStream) -> TokenStream {
@@ -11,124 +17,465 @@ pub fn derive_maskable_impl(input: TokenStream) -> TokenStream {
     let MessageInfo {
         ident,
         generics,
+        bound,
         fields,
-        ..
+        variants,
     } = input.get_message_info();
 
+    let assert_flattenable = assert_flattenable(generics, &fields, &variants);
+    let generics = with_bound(
+        generics,
+        ident,
+        bound,
+        fields
+            .iter()
+            .map(|field| field.ty)
+            .chain(variants.iter().flat_map(variant_types)),
+        quote! { ::fieldmask::Maskable },
+    );
     let (impl_generics, ty_generics, where_clauses) = generics.split_for_impl();
 
-    let mask_type_arms = fields.iter().map(|field| {
-        let field_ty = field.ty;
-        if field.is_flatten {
-            quote! {
-                ::fieldmask::Mask<#field_ty>,
-            }
-        } else {
-            quote! {
-                ::core::option::Option<::fieldmask::Mask<#field_ty>>,
-            }
-        }
-    });
+    // A transparent struct shares the mask of its only field.
+    if let Input::Struct(item) = &input
+        && item.is_transparent
+    {
+        let field_ty = fields[0].ty;
+        let mut flattenable_generics = generics.clone();
+        // The lifetime makes the bound hold only when the field is flattenable, instead of being
+        // rejected as a trivially false bound.
+        flattenable_generics
+            .make_where_clause()
+            .predicates
+            .push(parse_quote! { for<'__fieldmask> #field_ty: ::fieldmask::Flattenable });
+        let (_, _, flattenable_where_clauses) = flattenable_generics.split_for_impl();
+        return quote! {
+            #assert_flattenable
 
-    let empty_mask_arms = fields.iter().map(|field| {
-        if field.is_flatten {
-            quote! {
-                ::fieldmask::Mask::empty(),
-            }
-        } else {
-            quote! {
-                ::core::option::Option::None,
+            impl #impl_generics ::fieldmask::Flattenable for #ident #ty_generics
+            #flattenable_where_clauses
+            {
             }
-        }
-    });
 
-    let full_mask_arms = fields.iter().map(|field| {
-        if field.is_flatten {
-            quote! {
-                ::fieldmask::Mask::full(),
-            }
-        } else {
-            quote! {
-                ::core::option::Option::Some(::fieldmask::Mask::full()),
+            impl #impl_generics ::fieldmask::Maskable for #ident #ty_generics
+            #where_clauses
+            {
+                type Mask = <#field_ty as ::fieldmask::Maskable>::Mask;
+
+                fn empty_mask() -> Self::Mask {
+                    <#field_ty as ::fieldmask::Maskable>::empty_mask()
+                }
+
+                fn full_mask() -> Self::Mask {
+                    <#field_ty as ::fieldmask::Maskable>::full_mask()
+                }
+
+                fn make_mask_include_field<'a>(
+                    mask: &mut Self::Mask,
+                    field_path: &[&'a ::core::primitive::str],
+                ) -> ::core::result::Result<(), ::fieldmask::DeserializeMaskError<'a>> {
+                    <#field_ty as ::fieldmask::Maskable>::make_mask_include_field(mask, field_path)
+                }
+
+                fn field_names() -> ::std::vec::Vec<&'static ::core::primitive::str> {
+                    <#field_ty as ::fieldmask::Maskable>::field_names()
+                }
+
+                fn mask_field_paths(
+                    mask: &Self::Mask,
+                ) -> ::std::vec::Vec<::std::vec::Vec<::std::borrow::Cow<'static, ::core::primitive::str>>> {
+                    <#field_ty as ::fieldmask::Maskable>::mask_field_paths(mask)
+                }
+
+                fn field_behaviors(
+                    field_path: &[&::core::primitive::str],
+                ) -> ::std::vec::Vec<::fieldmask::FieldBehavior> {
+                    <#field_ty as ::fieldmask::Maskable>::field_behaviors(field_path)
+                }
+
+                fn tags_permit_all(
+                    allowed: &[&::core::primitive::str],
+                    stack: &mut ::std::vec::Vec<&'static ::core::primitive::str>,
+                ) -> ::core::primitive::bool {
+                    <#field_ty as ::fieldmask::Maskable>::tags_permit_all(allowed, stack)
+                }
+
+                fn make_mask_for_tags(
+                    mask: &mut Self::Mask,
+                    allowed: &[&::core::primitive::str],
+                    stack: &mut ::std::vec::Vec<&'static ::core::primitive::str>,
+                ) -> ::core::primitive::bool {
+                    <#field_ty as ::fieldmask::Maskable>::make_mask_for_tags(mask, allowed, stack)
+                }
             }
         }
-    });
+        .into();
+    }
 
-    // For each field in the struct, generate a match arm that processes a matching field path.
-    let make_mask_include_field_match_arms = fields.iter().enumerate().map(|(i, field)| {
-        let field_index = Index::from(i);
-        // For flatten field, try to make the field parse the mask. If the field is not found, go to
-        // the next match arm.
-        if field.is_flatten {
-            quote! {
-                _ if mask
-                    .#field_index
-                    .include_field(field_path)
-                    .map(|_| true)
-                    .or_else(|e| {
-                        if let ::fieldmask::DeserializeMaskError::FieldNotFound { .. } = e {
-                            ::core::result::Result::Ok(false)
-                        } else {
-                            ::core::result::Result::Err(e)
+    let (
+        mask_type,
+        empty_mask,
+        full_mask,
+        make_mask_include_field,
+        field_names,
+        mask_field_paths,
+        field_behaviors,
+        tags_permit_all,
+        make_mask_for_tags,
+    ) = match input {
+        Input::OneOfEnum(_) => {
+            let mask_type_arms = variants.iter().map(|variant| match &variant.kind {
+                MessageVariantKind::Unit => quote! {
+                    ::core::option::Option<()>,
+                },
+                MessageVariantKind::Tuple(ty) => quote! {
+                    ::core::option::Option<::fieldmask::Mask<#ty>>,
+                },
+                MessageVariantKind::Fields(fields) => {
+                    let mask_type = fields_mask_type(fields);
+                    quote! {
+                        ::core::option::Option<#mask_type>,
+                    }
+                }
+            });
+
+            let empty_mask_arms = variants.iter().map(|_| {
+                quote! {
+                    ::core::option::Option::None,
+                }
+            });
+
+            let full_mask_arms = variants.iter().map(|variant| match &variant.kind {
+                MessageVariantKind::Unit => quote! {
+                    ::core::option::Option::Some(()),
+                },
+                MessageVariantKind::Tuple(_) => quote! {
+                    ::core::option::Option::Some(::fieldmask::Mask::full()),
+                },
+                MessageVariantKind::Fields(fields) => {
+                    let full_mask = fields_full_mask(fields);
+                    quote! {
+                        ::core::option::Option::Some(#full_mask),
+                    }
+                }
+            });
+
+            // For each variant in the enum, generate a match arm that processes a matching field
+            // path.
+            let make_mask_include_field_match_arms =
+                variants.iter().enumerate().map(|(i, variant)| {
+                    let index = Index::from(i);
+                    let variant_name = &variant.name;
+                    match &variant.kind {
+                        // A unit variant can only be selected as a whole.
+                        MessageVariantKind::Unit => {
+                            let type_name = variant_type_name(ident, variant);
+                            quote! {
+                                [#variant_name] => {
+                                    mask.#index = ::core::option::Option::Some(());
+                                    ::core::result::Result::Ok(())
+                                }
+                                [#variant_name, field, ..] => ::core::result::Result::Err(
+                                    ::fieldmask::DeserializeMaskError::InvalidField {
+                                        field: #variant_name,
+                                        err: ::std::boxed::Box::new(
+                                            ::fieldmask::DeserializeMaskError::FieldNotFound {
+                                                type_name: #type_name,
+                                                field,
+                                            },
+                                        ),
+                                    },
+                                ),
+                            }
                         }
-                    })? =>
-                {
-                    ::core::result::Result::Ok(())
+                        MessageVariantKind::Tuple(ty) => quote! {
+                            [#variant_name, tail @ ..] => {
+                                mask.#index
+                                    .get_or_insert_with(|| ::fieldmask::Mask::<#ty>::empty())
+                                    .include_field(tail)
+                                    .map_err(|err| {
+                                        ::fieldmask::DeserializeMaskError::InvalidField {
+                                            field: #variant_name,
+                                            err: ::std::boxed::Box::new(err),
+                                        }
+                                    })?;
+                                ::core::result::Result::Ok(())
+                            }
+                        },
+                        MessageVariantKind::Fields(fields) => {
+                            let empty_mask = fields_empty_mask(fields);
+                            let make_mask_include_field =
+                                fields_include_field(fields, &variant_type_name(ident, variant));
+                            quote! {
+                                [#variant_name, tail @ ..] => {
+                                    let mask = mask.#index.get_or_insert_with(|| #empty_mask);
+                                    let field_path = tail;
+                                    let result = (|| -> ::core::result::Result<
+                                        (),
+                                        ::fieldmask::DeserializeMaskError<'a>,
+                                    > {
+                                        #make_mask_include_field
+                                    })();
+                                    result.map_err(|err| {
+                                        ::fieldmask::DeserializeMaskError::InvalidField {
+                                            field: #variant_name,
+                                            err: ::std::boxed::Box::new(err),
+                                        }
+                                    })
+                                }
+                            }
+                        }
+                    }
+                });
+
+            let variant_names = variants.iter().map(|variant| &variant.name);
+
+            // For each variant in the enum, generate a statement that collects the paths selected
+            // in the variant.
+            let mask_field_paths_arms = variants.iter().enumerate().map(|(i, variant)| {
+                let index = Index::from(i);
+                let variant_name = &variant.name;
+                let sub_paths = match &variant.kind {
+                    MessageVariantKind::Unit => quote! {
+                        let _ = mask;
+                        ::std::vec::Vec::new()
+                    },
+                    MessageVariantKind::Tuple(ty) => quote! {
+                        <#ty as ::fieldmask::Maskable>::mask_field_paths(mask)
+                    },
+                    MessageVariantKind::Fields(fields) => fields_mask_paths(fields),
+                };
+                prefixed_mask_paths(variant_name, &quote! { &mask.#index }, &sub_paths)
+            });
+
+            let field_behaviors_arms = variants.iter().filter_map(|variant| {
+                let variant_name = &variant.name;
+                match &variant.kind {
+                    MessageVariantKind::Unit => None,
+                    MessageVariantKind::Tuple(ty) => Some(quote! {
+                        [#variant_name, tail @ ..] => {
+                            <#ty as ::fieldmask::Maskable>::field_behaviors(tail)
+                        }
+                    }),
+                    MessageVariantKind::Fields(fields) => {
+                        let field_behaviors = fields_behaviors(fields);
+                        Some(quote! {
+                            [#variant_name, tail @ ..] => {
+                                let field_path = tail;
+                                #field_behaviors
+                            }
+                        })
+                    }
                 }
-            }
-        } else {
-            // Convert to snake case to match the field name in the mask.
-            // This is useful for oneof fields where each oneof field is represented by a variant
-            // in an enum, which is typically in PascalCase.
-            let field_name = to_snake_case(&field.ident.to_string());
-            let field_ty = field.ty;
-            quote! {
-                [#field_name, tail @ ..] => {
-                    mask.#field_index
-                        .get_or_insert_with(|| ::fieldmask::Mask::<#field_ty>::empty())
-                        .include_field(tail)
-                        .map_err(|err| {
-                            ::fieldmask::DeserializeMaskError::InvalidField {
-                                field: #field_name,
-                                err: ::std::boxed::Box::new(err),
+            });
+
+            let tags_permit_all_arms = variants.iter().map(|variant| match &variant.kind {
+                MessageVariantKind::Unit => quote! { true },
+                MessageVariantKind::Tuple(ty) => quote! {
+                    <#ty as ::fieldmask::Maskable>::tags_permit_all(allowed, stack)
+                },
+                MessageVariantKind::Fields(fields) => fields_tags_permit_all(fields),
+            });
+
+            // For each variant in the enum, generate a statement that selects the variant, or the
+            // permitted fields in it.
+            let make_mask_for_tags_arms = variants.iter().enumerate().map(|(i, variant)| {
+                let index = Index::from(i);
+                match &variant.kind {
+                    MessageVariantKind::Unit => quote! {
+                        mask.#index = ::core::option::Option::Some(());
+                        permitted = true;
+                    },
+                    MessageVariantKind::Tuple(ty) => permitted_sub_mask(
+                        &quote! { mask.#index },
+                        &quote! { #ty },
+                        &quote! { true },
+                    ),
+                    MessageVariantKind::Fields(fields) => {
+                        let empty_mask = fields_empty_mask(fields);
+                        let fields_permit_all = fields_tags_permit_all(fields);
+                        let make_mask_for_tags = fields_make_mask_for_tags(fields);
+                        quote! {
+                            if #fields_permit_all {
+                                mask.#index = ::core::option::Option::Some(#empty_mask);
+                                permitted = true;
+                            } else {
+                                let mut variant_mask = #empty_mask;
+                                let variant_permitted = {
+                                    let mask = &mut variant_mask;
+                                    let mut permitted = false;
+                                    #make_mask_for_tags
+                                    permitted
+                                };
+                                if variant_permitted {
+                                    mask.#index = ::core::option::Option::Some(variant_mask);
+                                    permitted = true;
+                                }
                             }
-                        })?;
-                    ::core::result::Result::Ok(())
+                        }
+                    }
                 }
-            }
+            });
+
+            (
+                quote! { (#(#mask_type_arms)*) },
+                quote! { (#(#empty_mask_arms)*) },
+                quote! { (#(#full_mask_arms)*) },
+                quote! {
+                    match field_path {
+                        [] => ::core::result::Result::Ok(()),
+                        #(#make_mask_include_field_match_arms)*
+                        [field, ..] => ::core::result::Result::Err(
+                            ::fieldmask::DeserializeMaskError::FieldNotFound {
+                                type_name: ::core::stringify!(#ident),
+                                field,
+                            }
+                        ),
+                    }
+                },
+                quote! { ::std::vec![#(#variant_names),*] },
+                quote! {
+                    let mut paths = ::std::vec::Vec::new();
+                    #(#mask_field_paths_arms)*
+                    paths
+                },
+                quote! {
+                    match field_path {
+                        #(#field_behaviors_arms)*
+                        _ => ::std::vec::Vec::new(),
+                    }
+                },
+                quote! { true #(&& #tags_permit_all_arms)* },
+                quote! {
+                    #[allow(unused_mut)]
+                    let mut permitted = false;
+                    #(#make_mask_for_tags_arms)*
+                    permitted
+                },
+            )
         }
-    });
+        Input::UnitEnum(_) | Input::Struct(_) => (
+            fields_mask_type(&fields),
+            fields_empty_mask(&fields),
+            fields_full_mask(&fields),
+            fields_include_field(&fields, &quote! { ::core::stringify!(#ident) }),
+            fields_names(&fields),
+            fields_mask_paths(&fields),
+            fields_behaviors(&fields),
+            fields_tags_permit_all(&fields),
+            {
+                let make_mask_for_tags = fields_make_mask_for_tags(&fields);
+                quote! {
+                    #[allow(unused_mut)]
+                    let mut permitted = false;
+                    #make_mask_for_tags
+                    permitted
+                }
+            },
+        ),
+    };
+
+    let is_generic = generics.type_params().next().is_some();
+    let assert_unique_field_names = match input {
+        Input::OneOfEnum(_) => variants
+            .iter()
+            .filter_map(|variant| match &variant.kind {
+                MessageVariantKind::Fields(fields) => Some(fields_assert_unique_names(
+                    fields,
+                    &variant_type_name(ident, variant),
+                    is_generic,
+                )),
+                MessageVariantKind::Unit | MessageVariantKind::Tuple(_) => None,
+            })
+            .collect(),
+        Input::UnitEnum(_) | Input::Struct(_) => {
+            fields_assert_unique_names(&fields, &quote! { ::core::stringify!(#ident) }, is_generic)
+        }
+    };
+
+    // Unit enums are atomic, so they can't be flattened.
+    let flattenable = match input {
+        Input::UnitEnum(_) => quote! {},
+        Input::OneOfEnum(_) | Input::Struct(_) => quote! {
+            impl #impl_generics ::fieldmask::Flattenable for #ident #ty_generics #where_clauses {}
+        },
+    };
 
     quote! {
+        #assert_flattenable
+
+        #flattenable
+
         impl #impl_generics ::fieldmask::Maskable for #ident #ty_generics
         #where_clauses
         {
-            type Mask = (#(#mask_type_arms)*);
+            type Mask = #mask_type;
 
             #[allow(clippy::unused_unit)]
             fn empty_mask() -> Self::Mask {
-                (#(#empty_mask_arms)*)
+                #assert_unique_field_names
+                #empty_mask
             }
 
             #[allow(clippy::unused_unit)]
             fn full_mask() -> Self::Mask {
-                (#(#full_mask_arms)*)
+                #assert_unique_field_names
+                #full_mask
             }
 
             fn make_mask_include_field<'a>(
                 mask: &mut Self::Mask,
                 field_path: &[&'a ::core::primitive::str],
             ) -> ::core::result::Result<(), ::fieldmask::DeserializeMaskError<'a>> {
-                match field_path {
-                    [] => ::core::result::Result::Ok(()),
-                    #(#make_mask_include_field_match_arms)*
-                    [field, ..] => ::core::result::Result::Err(
-                        ::fieldmask::DeserializeMaskError::FieldNotFound {
-                            type_name: ::core::stringify!(#ident),
-                            field,
-                        }
-                    ),
+                #make_mask_include_field
+            }
+
+            fn field_names() -> ::std::vec::Vec<&'static ::core::primitive::str> {
+                #field_names
+            }
+
+            fn mask_field_paths(
+                mask: &Self::Mask,
+            ) -> ::std::vec::Vec<::std::vec::Vec<::std::borrow::Cow<'static, ::core::primitive::str>>> {
+                #mask_field_paths
+            }
+
+            fn field_behaviors(
+                field_path: &[&::core::primitive::str],
+            ) -> ::std::vec::Vec<::fieldmask::FieldBehavior> {
+                #field_behaviors
+            }
+
+            fn tags_permit_all(
+                allowed: &[&::core::primitive::str],
+                stack: &mut ::std::vec::Vec<&'static ::core::primitive::str>,
+            ) -> ::core::primitive::bool {
+                let _ = allowed;
+                let type_name = ::core::any::type_name::<Self>();
+                // A recursive field is permitted as long as the rest of the type is.
+                if stack.contains(&type_name) {
+                    return true;
+                }
+                stack.push(type_name);
+                let permitted = #tags_permit_all;
+                stack.pop();
+                permitted
+            }
+
+            fn make_mask_for_tags(
+                mask: &mut Self::Mask,
+                allowed: &[&::core::primitive::str],
+                stack: &mut ::std::vec::Vec<&'static ::core::primitive::str>,
+            ) -> ::core::primitive::bool {
+                let _ = (&mask, allowed);
+                let type_name = ::core::any::type_name::<Self>();
+                // The mask of a recursive type that is not fully permitted would be infinite.
+                if stack.contains(&type_name) {
+                    return false;
                 }
+                stack.push(type_name);
+                let permitted = { #make_mask_for_tags };
+                stack.pop();
+                permitted
             }
         }
     }
@@ -141,9 +488,36 @@ pub fn derive_option_maskable_impl(input: TokenStream) -> TokenStream {
     let MessageInfo {
         ident,
         generics,
+        bound,
         fields,
-        ..
+        variants,
     } = input.get_message_info();
+
+    let generics = match input {
+        Input::UnitEnum(_) => generics.clone(),
+        Input::OneOfEnum(_) => with_bound(
+            generics,
+            ident,
+            bound,
+            variants.iter().flat_map(variant_types),
+            quote! { ::fieldmask::SelfMaskable + ::core::default::Default },
+        ),
+        Input::Struct(ref item) if item.is_transparent => with_bound(
+            generics,
+            ident,
+            bound,
+            [fields[0].ty],
+            quote! { ::fieldmask::OptionMaskable },
+        ),
+        Input::Struct(_) => with_self_bound(
+            generics,
+            ident,
+            bound,
+            quote! {
+                ::fieldmask::SelfMaskable + ::core::default::Default + ::core::cmp::PartialEq
+            },
+        ),
+    };
     let (impl_generics, ty_generics, where_clauses) = generics.split_for_impl();
 
     match input {
@@ -170,11 +544,35 @@ pub fn derive_option_maskable_impl(input: TokenStream) -> TokenStream {
                 }
             };
 
+            let variant_idents = item.variants.iter().map(|variant| &variant.ident);
+
             // Unit enums have no fields, the field mask is always empty.
             quote! {
-                impl ::fieldmask::OptionMaskable for #ident {
+                impl #impl_generics ::fieldmask::OptionMaskable for #ident #ty_generics
+                #where_clauses
+                {
                     #option_project
 
+                    fn option_project_ref(
+                        this: ::core::option::Option<&Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        options: &::fieldmask::ProjectOptions,
+                    ) -> ::core::option::Option<Self> {
+                        let mut this = this.map(|this| match this {
+                            #(Self::#variant_idents => Self::#variant_idents,)*
+                        });
+                        <Self as ::fieldmask::OptionMaskable>::option_project(&mut this, mask, options);
+                        this
+                    }
+
+                    fn option_clear(
+                        this: &mut ::core::option::Option<Self>,
+                        _mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        _options: &::fieldmask::ProjectOptions,
+                    ) {
+                        *this = ::core::option::Option::None;
+                    }
+
                     fn option_update_as_field(
                         this: &mut ::core::option::Option<Self>,
                         source: ::core::option::Option<Self>,
@@ -193,383 +591,3857 @@ pub fn derive_option_maskable_impl(input: TokenStream) -> TokenStream {
                             *this = source;
                         }
                     }
+
+                    fn option_diff(
+                        this: ::core::option::Option<&Self>,
+                        other: ::core::option::Option<&Self>,
+                    ) -> ::core::option::Option<<Self as ::fieldmask::Maskable>::Mask> {
+                        if this.map(::core::mem::discriminant) != other.map(::core::mem::discriminant) {
+                            ::core::option::Option::Some(())
+                        } else {
+                            ::core::option::Option::None
+                        }
+                    }
+
+                    fn option_eq_masked(
+                        this: ::core::option::Option<&Self>,
+                        other: ::core::option::Option<&Self>,
+                        _mask: &<Self as ::fieldmask::Maskable>::Mask,
+                    ) -> bool {
+                        this.map(::core::mem::discriminant) == other.map(::core::mem::discriminant)
+                    }
+
+                    fn option_visit(
+                        this: ::core::option::Option<&Self>,
+                        _mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        path: &mut ::std::vec::Vec<&'static ::core::primitive::str>,
+                        visitor: &mut dyn ::fieldmask::MaskVisitor,
+                    ) {
+                        if let ::core::option::Option::Some(this) = this {
+                            visitor.visit_field(path, ::fieldmask::FieldRef::new(this));
+                        }
+                    }
                 }
             }
         }
-        Input::TupleEnum(_) => {
-            let normalize_match_arms = fields.iter().map(|field| {
-                let ident = field.ident;
-                let ty = field.ty;
-                quote! {
-                    Self::#ident(this) => {
-                        ::fieldmask::SelfMaskable::project(
-                            this, 
-                            &<# ty as ::fieldmask::Maskable>::empty_mask(),
-                            options,
-                        );
+        Input::OneOfEnum(_) => {
+            let normalize_match_arms = variants.iter().map(|variant| {
+                let variant_ident = variant.ident;
+                match &variant.kind {
+                    MessageVariantKind::Unit => quote! {
+                        Self::#variant_ident => {}
+                    },
+                    MessageVariantKind::Tuple(ty) => quote! {
+                        Self::#variant_ident(this) => {
+                            ::fieldmask::SelfMaskable::project(
+                                this,
+                                &<#ty as ::fieldmask::Maskable>::empty_mask(),
+                                options,
+                            );
+                        }
+                    },
+                    MessageVariantKind::Fields(fields) => {
+                        let this_pat = variant_fields_pattern(variant_ident, fields, "this");
+                        let normalize = fields_normalize(fields, &variant_fields_places(fields));
+                        quote! {
+                            #this_pat => {
+                                #normalize
+                            }
+                        }
                     }
                 }
             });
 
-            let project_match_arms = fields.iter().enumerate().map(|(i, field)| {
+            // If the variant is not selected by the mask, return None.
+            let project_match_arms = variants.iter().enumerate().map(|(i, variant)| {
                 let index = Index::from(i);
-                let ident = field.ident;
-                // If the variant is not selected by the mask, return None.
-                quote! {
-                    Self::#ident(this) => {
-                        if let ::core::option::Option::Some(mask) = &mask.#index {
-                            ::fieldmask::SelfMaskable::project(this, mask, options);
-                            return;
+                let variant_ident = variant.ident;
+                match &variant.kind {
+                    MessageVariantKind::Unit => quote! {
+                        Self::#variant_ident => {
+                            if mask.#index.is_some() {
+                                return;
+                            }
+                        }
+                    },
+                    MessageVariantKind::Tuple(_) => quote! {
+                        Self::#variant_ident(this) => {
+                            if let ::core::option::Option::Some(mask) = &mask.#index {
+                                ::fieldmask::SelfMaskable::project(this, mask, options);
+                                return;
+                            }
+                        }
+                    },
+                    MessageVariantKind::Fields(fields) => {
+                        let this_pat = variant_fields_pattern(variant_ident, fields, "this");
+                        let empty_mask = fields_empty_mask(fields);
+                        let places = variant_fields_places(fields);
+                        let normalize = fields_normalize(fields, &places);
+                        let project = fields_project(fields, &places);
+                        quote! {
+                            #this_pat => {
+                                if let ::core::option::Option::Some(mask) = &mask.#index {
+                                    if mask == &#empty_mask {
+                                        if options.normalize {
+                                            #normalize
+                                        }
+                                    } else {
+                                        #project
+                                    }
+                                    return;
+                                }
+                            }
                         }
                     }
                 }
             });
 
-            let update_source_arms = fields.iter().enumerate().map(|(i, field)| {
-                let index = Index::from(i);
-                let ident = field.ident;
-                quote! {
-                    Self::#ident(mut source) => {
-                        if let ::core::option::Option::Some(mask) = &mask.#index {
-                            if let ::core::option::Option::Some(Self::#ident(this)) = this {
-                                ::fieldmask::SelfMaskable::update_as_field(this, source, mask, options);
-                            } else {
-                                ::fieldmask::SelfMaskable::project(&mut source, mask, &::core::default::Default::default());
-                                *this = ::core::option::Option::Some(Self::#ident(source));
-                            }
-                            return;
+            let normalize_ref_match_arms = variants.iter().map(|variant| {
+                let variant_ident = variant.ident;
+                match &variant.kind {
+                    MessageVariantKind::Unit => quote! {
+                        Self::#variant_ident => Self::#variant_ident,
+                    },
+                    MessageVariantKind::Tuple(ty) => quote! {
+                        Self::#variant_ident(this) => Self::#variant_ident(
+                            ::fieldmask::SelfMaskable::project_ref(
+                                this,
+                                &<#ty as ::fieldmask::Maskable>::empty_mask(),
+                                options,
+                            ),
+                        ),
+                    },
+                    MessageVariantKind::Fields(fields) => {
+                        let this_pat = variant_fields_pattern(variant_ident, fields, "this");
+                        let normalize = fields_normalize_ref(fields, &variant_fields_refs(fields, "this"));
+                        quote! {
+                            #this_pat => Self::#variant_ident { #normalize },
                         }
                     }
                 }
-            });
+            }).collect::<Vec<_>>();
 
-            let update_this_arms = fields.iter().enumerate().map(|(i, field)| {
+            // If the variant is not selected by the mask, return None.
+            let project_ref_match_arms = variants.iter().enumerate().map(|(i, variant)| {
                 let index = Index::from(i);
-                let ident = field.ident;
-                quote! {
-                    Self::#ident(this) => {
-                        if let ::core::option::Option::Some(mask) = &mask.#index {
-                            ::fieldmask::SelfMaskable::update_as_field(
-                                this,
-                                ::core::default::Default::default(),
-                                mask,
-                                options,
-                            );
+                let variant_ident = variant.ident;
+                match &variant.kind {
+                    MessageVariantKind::Unit => quote! {
+                        Self::#variant_ident => mask.#index.map(|_| Self::#variant_ident),
+                    },
+                    MessageVariantKind::Tuple(_) => quote! {
+                        Self::#variant_ident(this) => mask.#index.as_ref().map(|mask| {
+                            Self::#variant_ident(::fieldmask::SelfMaskable::project_ref(this, mask, options))
+                        }),
+                    },
+                    MessageVariantKind::Fields(fields) => {
+                        let this_pat = variant_fields_pattern(variant_ident, fields, "this");
+                        let empty_mask = fields_empty_mask(fields);
+                        let refs = variant_fields_refs(fields, "this");
+                        let normalize = fields_normalize_ref(fields, &refs);
+                        let project = fields_project_ref(fields, &refs);
+                        quote! {
+                            #this_pat => mask.#index.as_ref().map(|mask| {
+                                if mask == &#empty_mask {
+                                    Self::#variant_ident { #normalize }
+                                } else {
+                                    Self::#variant_ident { #project }
+                                }
+                            }),
                         }
                     }
                 }
             });
 
-            let merge_arms = fields.iter().map(|field| {
-                let ident = field.ident;
-
-                quote! {
-                    Self::#ident(source) => {
-                        if let ::core::option::Option::Some(Self::#ident(this)) = this {
-                            ::fieldmask::SelfMaskable::merge(this, source, options);
-                        } else {
-                            *this = ::core::option::Option::Some(Self::#ident(source));
+            let eq_all_same_variant_arms = variants.iter().map(|variant| {
+                let variant_ident = variant.ident;
+                match &variant.kind {
+                    MessageVariantKind::Unit => quote! {
+                        (
+                            ::core::option::Option::Some(Self::#variant_ident),
+                            ::core::option::Option::Some(Self::#variant_ident),
+                        ) => true,
+                    },
+                    MessageVariantKind::Tuple(ty) => quote! {
+                        (
+                            ::core::option::Option::Some(Self::#variant_ident(this)),
+                            ::core::option::Option::Some(Self::#variant_ident(other)),
+                        ) => ::fieldmask::SelfMaskable::eq_masked(
+                            this,
+                            other,
+                            &<#ty as ::fieldmask::Maskable>::empty_mask(),
+                        ),
+                    },
+                    MessageVariantKind::Fields(fields) => {
+                        let this_pat = variant_fields_pattern(variant_ident, fields, "this");
+                        let other_pat = variant_fields_pattern(variant_ident, fields, "other");
+                        let eq_all = fields_eq_masked_all(
+                            fields,
+                            &variant_fields_refs(fields, "this"),
+                            &variant_fields_refs(fields, "other"),
+                        );
+                        quote! {
+                            (
+                                ::core::option::Option::Some(#this_pat),
+                                ::core::option::Option::Some(#other_pat),
+                            ) => #eq_all,
                         }
                     }
                 }
             });
 
-            quote! {
-                impl #impl_generics ::fieldmask::OptionMaskable for #ident #ty_generics
-                #where_clauses
-                {
-                    fn option_project(
-                        this: &mut ::core::option::Option<Self>,
-                        mask: &<Self as ::fieldmask::Maskable>::Mask,
-                        options: &::fieldmask::ProjectOptions,
-                    ) {
-                        if mask == &<Self as ::fieldmask::Maskable>::empty_mask() {
-                            if options.normalize {
-                                if let ::core::option::Option::Some(this) = this {
-                                    match this {
-                                        #(#normalize_match_arms)*
-                                    }
+            // If both values hold the same variant, only the fields of the variant are compared.
+            let eq_same_variant_arms = variants.iter().enumerate().map(|(i, variant)| {
+                let index = Index::from(i);
+                let variant_ident = variant.ident;
+                match &variant.kind {
+                    MessageVariantKind::Unit => quote! {
+                        (
+                            ::core::option::Option::Some(Self::#variant_ident),
+                            ::core::option::Option::Some(Self::#variant_ident),
+                        ) => return true,
+                    },
+                    MessageVariantKind::Tuple(_) => quote! {
+                        (
+                            ::core::option::Option::Some(Self::#variant_ident(this)),
+                            ::core::option::Option::Some(Self::#variant_ident(other)),
+                        ) => {
+                            return match &mask.#index {
+                                ::core::option::Option::Some(mask) => {
+                                    ::fieldmask::SelfMaskable::eq_masked(this, other, mask)
                                 }
+                                ::core::option::Option::None => true,
+                            };
+                        }
+                    },
+                    MessageVariantKind::Fields(fields) => {
+                        let this_pat = variant_fields_pattern(variant_ident, fields, "this");
+                        let other_pat = variant_fields_pattern(variant_ident, fields, "other");
+                        let empty_mask = fields_empty_mask(fields);
+                        let this_refs = variant_fields_refs(fields, "this");
+                        let other_refs = variant_fields_refs(fields, "other");
+                        let eq_all = fields_eq_masked_all(fields, &this_refs, &other_refs);
+                        let eq = fields_eq_masked(fields, &this_refs, &other_refs);
+                        quote! {
+                            (
+                                ::core::option::Option::Some(#this_pat),
+                                ::core::option::Option::Some(#other_pat),
+                            ) => {
+                                return match &mask.#index {
+                                    ::core::option::Option::Some(mask) if mask == &#empty_mask => #eq_all,
+                                    ::core::option::Option::Some(mask) => #eq,
+                                    ::core::option::Option::None => true,
+                                };
                             }
-                            return;
                         }
+                    }
+                }
+            });
+            let is_selected_arms = variants.iter().enumerate().map(|(i, variant)| {
+                let index = Index::from(i);
+                let variant_ident = variant.ident;
+                match &variant.kind {
+                    MessageVariantKind::Unit => quote! {
+                        Self::#variant_ident => mask.#index.is_some(),
+                    },
+                    MessageVariantKind::Tuple(_) => quote! {
+                        Self::#variant_ident(..) => mask.#index.is_some(),
+                    },
+                    MessageVariantKind::Fields(_) => quote! {
+                        Self::#variant_ident { .. } => mask.#index.is_some(),
+                    },
+                }
+            });
 
-                        if let ::core::option::Option::Some(this) = this {
-                            match this {
-                                #(#project_match_arms)*
+            let visit_all_arms = variants.iter().map(|variant| {
+                let variant_ident = variant.ident;
+                let name = &variant.name;
+                match &variant.kind {
+                    MessageVariantKind::Unit => quote! {
+                        Self::#variant_ident => {
+                            path.push(#name);
+                            visitor.visit_field(path, ::fieldmask::FieldRef::new(&()));
+                            path.pop();
+                        }
+                    },
+                    MessageVariantKind::Tuple(ty) => quote! {
+                        Self::#variant_ident(this) => {
+                            path.push(#name);
+                            ::fieldmask::SelfMaskable::visit(
+                                this,
+                                &<#ty as ::fieldmask::Maskable>::empty_mask(),
+                                path,
+                                visitor,
+                            );
+                            path.pop();
+                        }
+                    },
+                    MessageVariantKind::Fields(fields) => {
+                        let this_pat = variant_fields_pattern(variant_ident, fields, "this");
+                        let visit_all = fields_visit_all(fields, &variant_fields_refs(fields, "this"));
+                        quote! {
+                            #this_pat => {
+                                path.push(#name);
+                                #visit_all
+                                path.pop();
                             }
                         }
-                        *this = ::core::option::Option::None;
                     }
+                }
+            });
 
-                    fn option_update_as_field(
-                        this: &mut ::core::option::Option<Self>,
-                        source: ::core::option::Option<Self>,
-                        mask: &<Self as ::fieldmask::Maskable>::Mask,
-                        options: &::fieldmask::UpdateOptions,
-                    ) {
-                        if mask == &<Self as ::fieldmask::Maskable>::empty_mask() {
-                            <Self as ::fieldmask::OptionMaskable>::option_merge(this, source, options);
-                            return;
+            // If the variant is not selected by the mask, nothing is visited.
+            let visit_arms = variants.iter().enumerate().map(|(i, variant)| {
+                let index = Index::from(i);
+                let variant_ident = variant.ident;
+                let name = &variant.name;
+                match &variant.kind {
+                    MessageVariantKind::Unit => quote! {
+                        Self::#variant_ident => {
+                            if mask.#index.is_some() {
+                                path.push(#name);
+                                visitor.visit_field(path, ::fieldmask::FieldRef::new(&()));
+                                path.pop();
+                            }
                         }
-
-                        if let ::core::option::Option::Some(source) = source {
-                            match source {
-                                #(#update_source_arms)*
+                    },
+                    MessageVariantKind::Tuple(_) => quote! {
+                        Self::#variant_ident(this) => {
+                            if let ::core::option::Option::Some(mask) = &mask.#index {
+                                path.push(#name);
+                                ::fieldmask::SelfMaskable::visit(this, mask, path, visitor);
+                                path.pop();
+                            }
+                        }
+                    },
+                    MessageVariantKind::Fields(fields) => {
+                        let this_pat = variant_fields_pattern(variant_ident, fields, "this");
+                        let empty_mask = fields_empty_mask(fields);
+                        let refs = variant_fields_refs(fields, "this");
+                        let visit_all = fields_visit_all(fields, &refs);
+                        let visit = fields_visit(fields, &refs);
+                        quote! {
+                            #this_pat => {
+                                if let ::core::option::Option::Some(mask) = &mask.#index {
+                                    path.push(#name);
+                                    if mask == &#empty_mask {
+                                        #visit_all
+                                    } else {
+                                        #visit
+                                    }
+                                    path.pop();
+                                }
                             }
                         }
+                    }
+                }
+            });
 
-                        if let ::core::option::Option::Some(this) = this {
-                            match this {
-                                #(#update_this_arms)*
+            let validate_all_arms = variants.iter().map(|variant| {
+                let variant_ident = variant.ident;
+                let name = &variant.name;
+                match &variant.kind {
+                    MessageVariantKind::Unit => quote! {
+                        Self::#variant_ident => {}
+                    },
+                    MessageVariantKind::Tuple(ty) => quote! {
+                        Self::#variant_ident(this) => {
+                            path.push(#name);
+                            ::fieldmask::SelfMaskable::validate(
+                                this,
+                                &<#ty as ::fieldmask::Maskable>::empty_mask(),
+                                path,
+                            )?;
+                            path.pop();
+                        }
+                    },
+                    MessageVariantKind::Fields(fields) => {
+                        let this_pat = variant_fields_pattern(variant_ident, fields, "this");
+                        let validate_all =
+                            fields_validate_all(fields, &variant_fields_refs(fields, "this"));
+                        quote! {
+                            #this_pat => {
+                                path.push(#name);
+                                #validate_all
+                                path.pop();
                             }
                         }
                     }
+                }
+            });
 
-                    fn option_merge(
-                        this: &mut ::core::option::Option<Self>,
-                        source: ::core::option::Option<Self>,
-                        options: &::fieldmask::UpdateOptions,
-                    ) {
-                        if options.replace_message {
-                            *this = source;
-                            return;
+            // If the variant is not selected by the mask, nothing is validated.
+            let validate_arms = variants.iter().enumerate().map(|(i, variant)| {
+                let index = Index::from(i);
+                let variant_ident = variant.ident;
+                let name = &variant.name;
+                match &variant.kind {
+                    MessageVariantKind::Unit => quote! {
+                        Self::#variant_ident => {}
+                    },
+                    MessageVariantKind::Tuple(_) => quote! {
+                        Self::#variant_ident(this) => {
+                            if let ::core::option::Option::Some(mask) = &mask.#index {
+                                path.push(#name);
+                                ::fieldmask::SelfMaskable::validate(this, mask, path)?;
+                                path.pop();
+                            }
+                        }
+                    },
+                    MessageVariantKind::Fields(fields) => {
+                        let this_pat = variant_fields_pattern(variant_ident, fields, "this");
+                        let empty_mask = fields_empty_mask(fields);
+                        let refs = variant_fields_refs(fields, "this");
+                        let validate_all = fields_validate_all(fields, &refs);
+                        let validate = fields_validate(fields, &refs);
+                        quote! {
+                            #this_pat => {
+                                if let ::core::option::Option::Some(mask) = &mask.#index {
+                                    path.push(#name);
+                                    if mask == &#empty_mask {
+                                        #validate_all
+                                    } else {
+                                        #validate
+                                    }
+                                    path.pop();
+                                }
+                            }
                         }
+                    }
+                }
+            });
 
-                        if let ::core::option::Option::Some(source) = source {
-                            match source {
-                                #(#merge_arms)*
+            let check_required_all_arms = variants.iter().map(|variant| {
+                let variant_ident = variant.ident;
+                let name = &variant.name;
+                match &variant.kind {
+                    MessageVariantKind::Unit => quote! {
+                        Self::#variant_ident => {}
+                    },
+                    MessageVariantKind::Tuple(ty) => quote! {
+                        Self::#variant_ident(this) => {
+                            path.push(#name);
+                            ::fieldmask::SelfMaskable::check_required(
+                                this,
+                                &<#ty as ::fieldmask::Maskable>::empty_mask(),
+                                path,
+                                missing,
+                            );
+                            path.pop();
+                        }
+                    },
+                    MessageVariantKind::Fields(fields) => {
+                        let this_pat = variant_fields_pattern(variant_ident, fields, "this");
+                        let check_all =
+                            fields_check_required_all(fields, &variant_fields_refs(fields, "this"));
+                        quote! {
+                            #this_pat => {
+                                path.push(#name);
+                                #check_all
+                                path.pop();
                             }
                         }
                     }
                 }
-            }
-        }
-        Input::Struct(_) => {
-            quote!{
-                impl #impl_generics ::fieldmask::OptionMaskable for #ident #ty_generics
-                #where_clauses
-                {
-                    fn option_project(
-                        this: &mut ::core::option::Option<Self>,
-                        mask: &<Self as ::fieldmask::Maskable>::Mask,
-                        options: &::fieldmask::ProjectOptions,
-                    ) {
-                        if let ::core::option::Option::Some(inner) = this {
-                            ::fieldmask::SelfMaskable::project(inner, mask, options);
-                            if inner == &::core::default::Default::default() {
-                                *this = ::core::option::Option::None;
+            });
+
+            // If the variant is not selected by the mask, nothing is checked.
+            let check_required_arms = variants.iter().enumerate().map(|(i, variant)| {
+                let index = Index::from(i);
+                let variant_ident = variant.ident;
+                let name = &variant.name;
+                match &variant.kind {
+                    MessageVariantKind::Unit => quote! {
+                        Self::#variant_ident => {}
+                    },
+                    MessageVariantKind::Tuple(_) => quote! {
+                        Self::#variant_ident(this) => {
+                            if let ::core::option::Option::Some(mask) = &mask.#index {
+                                path.push(#name);
+                                ::fieldmask::SelfMaskable::check_required(this, mask, path, missing);
+                                path.pop();
+                            }
+                        }
+                    },
+                    MessageVariantKind::Fields(fields) => {
+                        let this_pat = variant_fields_pattern(variant_ident, fields, "this");
+                        let empty_mask = fields_empty_mask(fields);
+                        let refs = variant_fields_refs(fields, "this");
+                        let check_all = fields_check_required_all(fields, &refs);
+                        let check = fields_check_required(fields, &refs);
+                        quote! {
+                            #this_pat => {
+                                if let ::core::option::Option::Some(mask) = &mask.#index {
+                                    path.push(#name);
+                                    if mask == &#empty_mask {
+                                        #check_all
+                                    } else {
+                                        #check
+                                    }
+                                    path.pop();
+                                }
                             }
                         }
                     }
+                }
+            });
 
-                    fn option_update_as_field(
-                        this: &mut ::core::option::Option<Self>,
-                        source: ::core::option::Option<Self>,
-                        mask: &<Self as ::fieldmask::Maskable>::Mask,
-                        options: &::fieldmask::UpdateOptions,
-                    ) {
-                        match (this.as_mut(), source) {
-                            (::core::option::Option::Some(this), ::core::option::Option::Some(source)) => {
-                                ::fieldmask::SelfMaskable::update_as_field(this, source, mask, options);
+            let clear_output_only_arms = variants.iter().map(|variant| {
+                let variant_ident = variant.ident;
+                match &variant.kind {
+                    MessageVariantKind::Unit => quote! {
+                        Self::#variant_ident => {}
+                    },
+                    MessageVariantKind::Tuple(_) => quote! {
+                        Self::#variant_ident(this) => {
+                            ::fieldmask::SelfMaskable::clear_output_only(this);
+                        }
+                    },
+                    MessageVariantKind::Fields(fields) => {
+                        let this_pat = variant_fields_pattern(variant_ident, fields, "this");
+                        let clear_output_only =
+                            fields_clear_output_only(fields, &variant_fields_places(fields));
+                        quote! {
+                            #this_pat => {
+                                #clear_output_only
                             }
-                            (::core::option::Option::Some(this), ::core::option::Option::None) => {
-                                ::fieldmask::SelfMaskable::update_as_field(
+                        }
+                    }
+                }
+            });
+
+            // Evaluate to whether the variant is cleared as a whole, which only happens to unit
+            // variants.
+            let redact_match_arms = variants.iter().enumerate().map(|(i, variant)| {
+                let index = Index::from(i);
+                let variant_ident = variant.ident;
+                match &variant.kind {
+                    MessageVariantKind::Unit => quote! {
+                        Self::#variant_ident => is_full || mask.#index.is_some(),
+                    },
+                    MessageVariantKind::Tuple(ty) => quote! {
+                        Self::#variant_ident(this) => {
+                            if is_full {
+                                ::fieldmask::SelfMaskable::redact(
                                     this,
-                                    ::core::default::Default::default(),
-                                    mask,
-                                    options,
+                                    &<#ty as ::fieldmask::Maskable>::empty_mask(),
                                 );
+                            } else if let ::core::option::Option::Some(mask) = &mask.#index {
+                                ::fieldmask::SelfMaskable::redact(this, mask);
                             }
-                            (::core::option::Option::None, ::core::option::Option::Some(mut source)) => {
-                                ::fieldmask::SelfMaskable::project(&mut source, mask, &::core::default::Default::default());
-                                *this = Some(source);
+                            false
+                        }
+                    },
+                    MessageVariantKind::Fields(fields) => {
+                        let this_pat = variant_fields_pattern(variant_ident, fields, "this");
+                        let empty_mask = fields_empty_mask(fields);
+                        let places = variant_fields_places(fields);
+                        let redact_all = fields_redact_all(fields, &places);
+                        let redact = fields_redact(fields, &places);
+                        quote! {
+                            #this_pat => {
+                                if is_full {
+                                    #redact_all
+                                } else if let ::core::option::Option::Some(mask) = &mask.#index {
+                                    if mask == &#empty_mask {
+                                        #redact_all
+                                    } else {
+                                        #redact
+                                    }
+                                }
+                                false
                             }
-                            (::core::option::Option::None, ::core::option::Option::None) => {}
                         }
                     }
+                }
+            });
 
-                    fn option_merge(
-                        this: &mut ::core::option::Option<Self>,
-                        source: ::core::option::Option<Self>,
-                        options: &::fieldmask::UpdateOptions,
-                    ) {
-                        match (this.as_mut(), source) {
-                            (::core::option::Option::Some(this), ::core::option::Option::Some(source)) => {
-                                ::fieldmask::SelfMaskable::merge(this, source, options);
+            // Evaluate to whether the variant is cleared as a whole.
+            let clear_match_arms = variants.iter().enumerate().map(|(i, variant)| {
+                let index = Index::from(i);
+                let variant_ident = variant.ident;
+                match &variant.kind {
+                    MessageVariantKind::Unit => quote! {
+                        Self::#variant_ident => mask.#index.is_some(),
+                    },
+                    MessageVariantKind::Tuple(ty) => quote! {
+                        Self::#variant_ident(this) => match &mask.#index {
+                            ::core::option::Option::Some(mask) if mask == &::fieldmask::Mask::<#ty>::empty() => true,
+                            ::core::option::Option::Some(mask) => {
+                                ::fieldmask::SelfMaskable::clear(this, mask, options);
+                                false
                             }
-                            (_, ::core::option::Option::None) => {}
-                            (::core::option::Option::None, source) => {
-                                *this = source;
+                            ::core::option::Option::None => {
+                                if options.normalize {
+                                    ::fieldmask::SelfMaskable::project(
+                                        this,
+                                        &<#ty as ::fieldmask::Maskable>::empty_mask(),
+                                        options,
+                                    );
+                                }
+                                false
                             }
+                        },
+                    },
+                    MessageVariantKind::Fields(fields) => {
+                        let this_pat = variant_fields_pattern(variant_ident, fields, "this");
+                        let empty_mask = fields_empty_mask(fields);
+                        let places = variant_fields_places(fields);
+                        let normalize = fields_normalize(fields, &places);
+                        let clear = fields_clear(fields, &places);
+                        quote! {
+                            #this_pat => match &mask.#index {
+                                ::core::option::Option::Some(mask) if mask == &#empty_mask => true,
+                                ::core::option::Option::Some(mask) => {
+                                    #clear
+                                    false
+                                }
+                                ::core::option::Option::None => {
+                                    if options.normalize {
+                                        #normalize
+                                    }
+                                    false
+                                }
+                            },
                         }
                     }
                 }
-            }
-        }
-    }.into()
-}
-
-/// The implementation for `derive_self_maskable`.
-pub fn derive_self_maskable_impl(input: TokenStream) -> TokenStream {
-    let input: Input = parse_macro_input!(input);
-    let MessageInfo {
-        ident,
-        generics,
-        fields,
-    } = input.get_message_info();
-
-    let (impl_generics, ty_generics, where_clauses) = generics.split_for_impl();
-
-    match input {
-        Input::UnitEnum(_) => {
-            // Unit enums have no fields, the field mask is always empty.
-            quote! {
-                impl ::fieldmask::SelfMaskable for #ident {
-                    fn project(
-                        &mut self,
-                        _mask: &<Self as ::fieldmask::Maskable>::Mask,
-                        _options: &::fieldmask::ProjectOptions,
-                    ) {}
+            });
 
-                    fn update_as_field(
-                        &mut self,
-                        source: Self,
-                        _mask: &<Self as ::fieldmask::Maskable>::Mask,
-                        _options: &::fieldmask::UpdateOptions,
-                    ) {
-                        *self = source;
+            let update_source_arms = variants.iter().enumerate().map(|(i, variant)| {
+                let index = Index::from(i);
+                let variant_ident = variant.ident;
+                match &variant.kind {
+                    MessageVariantKind::Unit => quote! {
+                        Self::#variant_ident => {
+                            if mask.#index.is_some() {
+                                *this = ::core::option::Option::Some(Self::#variant_ident);
+                                return;
+                            }
+                        }
+                    },
+                    MessageVariantKind::Tuple(_) => quote! {
+                        Self::#variant_ident(mut source) => {
+                            if let ::core::option::Option::Some(mask) = &mask.#index {
+                                if let ::core::option::Option::Some(Self::#variant_ident(this)) = this {
+                                    ::fieldmask::SelfMaskable::update_as_field(this, source, mask, options);
+                                } else {
+                                    ::fieldmask::SelfMaskable::project(&mut source, mask, &::core::default::Default::default());
+                                    *this = ::core::option::Option::Some(Self::#variant_ident(source));
+                                }
+                                return;
+                            }
+                        }
+                    },
+                    MessageVariantKind::Fields(fields) => {
+                        let this_pat = variant_fields_pattern(variant_ident, fields, "this");
+                        let source_pat = variant_fields_pattern(variant_ident, fields, "source");
+                        let source_members = fields.iter().map(|field| &field.member);
+                        let source_bindings = variant_fields_bindings(fields, "source");
+                        let empty_mask = fields_empty_mask(fields);
+                        let update = variant_fields_update(
+                            fields,
+                            &variant_fields_places(fields),
+                            &source_bindings.iter().map(|binding| quote! { #binding }).collect::<Vec<_>>(),
+                        );
+                        let project_source = fields_project(
+                            fields,
+                            &source_bindings.iter().map(|binding| quote! { #binding }).collect::<Vec<_>>(),
+                        );
+                        quote! {
+                            #[allow(unused_mut)]
+                            Self::#variant_ident { #(#source_members: mut #source_bindings),* } => {
+                                if let ::core::option::Option::Some(mask) = &mask.#index {
+                                    if let ::core::option::Option::Some(#this_pat) = this {
+                                        #update
+                                    } else {
+                                        if mask != &#empty_mask {
+                                            let options = &<::fieldmask::ProjectOptions as ::core::default::Default>::default();
+                                            #project_source
+                                        }
+                                        *this = ::core::option::Option::Some(#source_pat);
+                                    }
+                                    return;
+                                }
+                            }
+                        }
                     }
+                }
+            });
 
-                    fn merge(&mut self, source: Self, _options: &::fieldmask::UpdateOptions) {
-                        if source != ::core::default::Default::default() {
-                            *self = source;
+            let update_this_arms = variants.iter().enumerate().map(|(i, variant)| {
+                let index = Index::from(i);
+                let variant_ident = variant.ident;
+                match &variant.kind {
+                    MessageVariantKind::Unit => quote! {
+                        Self::#variant_ident => {}
+                    },
+                    MessageVariantKind::Tuple(_) => quote! {
+                        Self::#variant_ident(this) => {
+                            if let ::core::option::Option::Some(mask) = &mask.#index {
+                                ::fieldmask::SelfMaskable::update_as_field(
+                                    this,
+                                    ::core::default::Default::default(),
+                                    mask,
+                                    options,
+                                );
+                            }
+                        }
+                    },
+                    MessageVariantKind::Fields(fields) => {
+                        let this_pat = variant_fields_pattern(variant_ident, fields, "this");
+                        let update = variant_fields_update(
+                            fields,
+                            &variant_fields_places(fields),
+                            &fields
+                                .iter()
+                                .map(|_| quote! { ::core::default::Default::default() })
+                                .collect::<Vec<_>>(),
+                        );
+                        quote! {
+                            #this_pat => {
+                                if let ::core::option::Option::Some(mask) = &mask.#index {
+                                    #update
+                                }
+                            }
                         }
                     }
                 }
-            }
-        }
-        Input::TupleEnum(_) => {
-            panic!(
-                "Cannot derive `SelfMaskable` for a tuple enum. You can derive `OptionMaskable` instead."
-            );
-        }
-        Input::Struct(_) => {
-            // For each field in the struct, generate a field arm that performs normalize on the field.
-            let normalize_arms = fields.iter().map(|field| {
-                let ident = field.ident;
-                let ty = field.ty;
-                quote! {
-                    ::fieldmask::SelfMaskable::project(
-                        &mut self.#ident,
-                        &<# ty as ::fieldmask::Maskable>::empty_mask(),
-                        options,
-                    );
-                }
             });
 
-            // For each field in the struct, generate a field arm that performs projection on the field.
-            let project_arms = fields.iter().enumerate().map(|(i, field)| {
+            // If both values hold the same variant, only the fields of the variant are compared.
+            let diff_same_variant_arms = variants.iter().enumerate().map(|(i, variant)| {
                 let index = Index::from(i);
-                let ident = field.ident;
-                let ty = field.ty;
-
-                if field.is_flatten {
-                    quote! {
-                        if mask.#index == ::fieldmask::Mask::<#ty>::empty() {
-                            self.#ident = ::core::default::Default::default();
-                        } else {
-                            ::fieldmask::SelfMaskable::project(&mut self.#ident, &mask.#index, options);
+                let variant_ident = variant.ident;
+                match &variant.kind {
+                    MessageVariantKind::Unit => quote! {
+                        (
+                            ::core::option::Option::Some(Self::#variant_ident),
+                            ::core::option::Option::Some(Self::#variant_ident),
+                        ) => {
+                            return ::core::option::Option::None;
                         }
-                    }
-                } else {
-                    quote! {
-                        match mask.#index.as_deref() {
-                            ::core::option::Option::Some(mask) => {
-                                ::fieldmask::SelfMaskable::project(&mut self.#ident, mask, options);
-                            }
-                            ::core::option::Option::None => {
-                                self.#ident = ::core::default::Default::default();
+                    },
+                    MessageVariantKind::Tuple(_) => quote! {
+                        (
+                            ::core::option::Option::Some(Self::#variant_ident(this)),
+                            ::core::option::Option::Some(Self::#variant_ident(other)),
+                        ) => {
+                            return ::fieldmask::SelfMaskable::diff(this, other).map(|sub_mask| {
+                                let mut mask = <Self as ::fieldmask::Maskable>::empty_mask();
+                                mask.#index = ::core::option::Option::Some(::fieldmask::Mask::new(sub_mask));
+                                mask
+                            });
+                        }
+                    },
+                    MessageVariantKind::Fields(fields) => {
+                        let this_pat = variant_fields_pattern(variant_ident, fields, "this");
+                        let other_pat = variant_fields_pattern(variant_ident, fields, "other");
+                        let empty_mask = fields_empty_mask(fields);
+                        let diff = fields_diff(
+                            fields,
+                            &variant_fields_bindings(fields, "this")
+                                .iter()
+                                .map(|binding| quote! { #binding })
+                                .collect::<Vec<_>>(),
+                            &variant_fields_bindings(fields, "other")
+                                .iter()
+                                .map(|binding| quote! { #binding })
+                                .collect::<Vec<_>>(),
+                        );
+                        quote! {
+                            (
+                                ::core::option::Option::Some(#this_pat),
+                                ::core::option::Option::Some(#other_pat),
+                            ) => {
+                                let sub_mask = #diff;
+                                if sub_mask == #empty_mask {
+                                    return ::core::option::Option::None;
+                                }
+                                let mut mask = <Self as ::fieldmask::Maskable>::empty_mask();
+                                mask.#index = ::core::option::Option::Some(sub_mask);
+                                return ::core::option::Option::Some(mask);
                             }
                         }
                     }
                 }
             });
 
-            // For each field in the struct, generate a field arm that performs update on the field.
-            let update_arms = fields.iter().enumerate().map(|(i, field)| {
+            // Select the variant of `other` as a whole. If `other` is `None`, select all the fields
+            // of the variant of `this` so that they are reset when updating.
+            let diff_select_other_arms = variants.iter().enumerate().map(|(i, variant)| {
                 let index = Index::from(i);
-                let ident = field.ident;
-
-                if field.is_flatten {
-                    quote! {
-                        ::fieldmask::SelfMaskable::update_as_field(
-                            &mut self.#ident,
-                            source.#ident,
-                            &mask.#index,
-                            options,
-                        );
+                let variant_ident = variant.ident;
+                match &variant.kind {
+                    MessageVariantKind::Unit => quote! {
+                        Self::#variant_ident => {
+                            mask.#index = ::core::option::Option::Some(());
+                        }
+                    },
+                    MessageVariantKind::Tuple(_) => quote! {
+                        Self::#variant_ident(..) => {
+                            mask.#index = ::core::option::Option::Some(::fieldmask::Mask::empty());
+                        }
+                    },
+                    MessageVariantKind::Fields(fields) => {
+                        let empty_mask = fields_empty_mask(fields);
+                        quote! {
+                            Self::#variant_ident { .. } => {
+                                mask.#index = ::core::option::Option::Some(#empty_mask);
+                            }
+                        }
                     }
-                } else {
-                    quote! {
-                        if let Some(mask) = &mask.#index {
-                            ::fieldmask::SelfMaskable::update_as_field(&mut self.#ident, source.#ident, mask, options);
+                }
+            });
+            let diff_select_this_arms = variants.iter().enumerate().map(|(i, variant)| {
+                let index = Index::from(i);
+                let variant_ident = variant.ident;
+                match &variant.kind {
+                    MessageVariantKind::Unit => quote! {
+                        Self::#variant_ident => {
+                            mask.#index = ::core::option::Option::Some(());
+                        }
+                    },
+                    MessageVariantKind::Tuple(_) => quote! {
+                        Self::#variant_ident(..) => {
+                            mask.#index = ::core::option::Option::Some(::fieldmask::Mask::full());
+                        }
+                    },
+                    MessageVariantKind::Fields(fields) => {
+                        let full_mask = fields_full_mask(fields);
+                        quote! {
+                            Self::#variant_ident { .. } => {
+                                mask.#index = ::core::option::Option::Some(#full_mask);
+                            }
                         }
                     }
                 }
             });
 
-            let merge_arms = fields.iter().map(|field| {
-                let ident = field.ident;
-
-                quote! {
-                    ::fieldmask::SelfMaskable::merge(&mut self.#ident, source.#ident, options);
+            let merge_arms = variants.iter().map(|variant| {
+                let variant_ident = variant.ident;
+                match &variant.kind {
+                    MessageVariantKind::Unit => quote! {
+                        Self::#variant_ident => {
+                            *this = ::core::option::Option::Some(Self::#variant_ident);
+                        }
+                    },
+                    MessageVariantKind::Tuple(_) => quote! {
+                        Self::#variant_ident(source) => {
+                            if let ::core::option::Option::Some(Self::#variant_ident(this)) = this {
+                                ::fieldmask::SelfMaskable::merge(this, source, options);
+                            } else {
+                                *this = ::core::option::Option::Some(Self::#variant_ident(source));
+                            }
+                        }
+                    },
+                    MessageVariantKind::Fields(fields) => {
+                        let this_pat = variant_fields_pattern(variant_ident, fields, "this");
+                        let source_pat = variant_fields_pattern(variant_ident, fields, "source");
+                        let merge = fields_merge(
+                            fields,
+                            &variant_fields_places(fields),
+                            &variant_fields_bindings(fields, "source")
+                                .iter()
+                                .map(|binding| quote! { #binding })
+                                .collect::<Vec<_>>(),
+                            false,
+                        );
+                        quote! {
+                            #source_pat => {
+                                if let ::core::option::Option::Some(#this_pat) = this {
+                                    #merge
+                                } else {
+                                    *this = ::core::option::Option::Some(#source_pat);
+                                }
+                            }
+                        }
+                    }
                 }
             });
 
             quote! {
-                impl #impl_generics ::fieldmask::SelfMaskable for #ident #ty_generics
+                impl #impl_generics ::fieldmask::OptionMaskable for #ident #ty_generics
                 #where_clauses
                 {
-                    fn project(
-                        &mut self,
+                    fn option_project(
+                        this: &mut ::core::option::Option<Self>,
                         mask: &<Self as ::fieldmask::Maskable>::Mask,
                         options: &::fieldmask::ProjectOptions,
                     ) {
                         if mask == &<Self as ::fieldmask::Maskable>::empty_mask() {
                             if options.normalize {
-                                #(#normalize_arms)*
+                                if let ::core::option::Option::Some(this) = this {
+                                    match this {
+                                        #(#normalize_match_arms)*
+                                    }
+                                }
                             }
                             return;
                         }
 
-                        #(#project_arms) *
+                        if let ::core::option::Option::Some(this) = this {
+                            match this {
+                                #(#project_match_arms)*
+                            }
+                        }
+                        if options.redact {
+                            <Self as ::fieldmask::OptionMaskable>::option_redact(
+                                this,
+                                &<Self as ::fieldmask::Maskable>::empty_mask(),
+                            );
+                        } else {
+                            *this = ::core::option::Option::None;
+                        }
                     }
 
-                    fn update_as_field(
-                        &mut self,
-                        source: Self,
+                    fn option_project_ref(
+                        this: ::core::option::Option<&Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        options: &::fieldmask::ProjectOptions,
+                    ) -> ::core::option::Option<Self> {
+                        let this = this?;
+                        if mask == &<Self as ::fieldmask::Maskable>::empty_mask() {
+                            return ::core::option::Option::Some(match this {
+                                #(#normalize_ref_match_arms)*
+                            });
+                        }
+
+                        let projected = match this {
+                            #(#project_ref_match_arms)*
+                        };
+                        if projected.is_none() && options.redact {
+                            let mut redacted = ::core::option::Option::Some(match this {
+                                #(#normalize_ref_match_arms)*
+                            });
+                            <Self as ::fieldmask::OptionMaskable>::option_redact(
+                                &mut redacted,
+                                &<Self as ::fieldmask::Maskable>::empty_mask(),
+                            );
+                            return redacted;
+                        }
+                        projected
+                    }
+
+                    fn option_clear(
+                        this: &mut ::core::option::Option<Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        options: &::fieldmask::ProjectOptions,
+                    ) {
+                        if mask == &<Self as ::fieldmask::Maskable>::empty_mask() {
+                            *this = ::core::option::Option::None;
+                            return;
+                        }
+
+                        let is_cleared = match this {
+                            ::core::option::Option::Some(this) => match this {
+                                #(#clear_match_arms)*
+                            },
+                            ::core::option::Option::None => false,
+                        };
+                        if is_cleared {
+                            *this = ::core::option::Option::None;
+                        }
+                    }
+
+                    fn option_update_as_field(
+                        this: &mut ::core::option::Option<Self>,
+                        source: ::core::option::Option<Self>,
                         mask: &<Self as ::fieldmask::Maskable>::Mask,
                         options: &::fieldmask::UpdateOptions,
                     ) {
                         if mask == &<Self as ::fieldmask::Maskable>::empty_mask() {
-                            ::fieldmask::SelfMaskable::merge(self, source, options);
+                            <Self as ::fieldmask::OptionMaskable>::option_merge(this, source, options);
                             return;
                         }
 
-                        #(#update_arms)*
+                        if let ::core::option::Option::Some(source) = source {
+                            match source {
+                                #(#update_source_arms)*
+                            }
+                        }
+
+                        if let ::core::option::Option::Some(this) = this {
+                            match this {
+                                #(#update_this_arms)*
+                            }
+                        }
                     }
 
-                    fn merge(&mut self, source: Self, options: &::fieldmask::UpdateOptions) {
+                    fn option_merge(
+                        this: &mut ::core::option::Option<Self>,
+                        source: ::core::option::Option<Self>,
+                        options: &::fieldmask::UpdateOptions,
+                    ) {
                         if options.replace_message {
-                            *self = source;
+                            *this = source;
+                            return;
+                        }
+
+                        if let ::core::option::Option::Some(source) = source {
+                            match source {
+                                #(#merge_arms)*
+                            }
+                        }
+                    }
+
+                    fn option_diff(
+                        this: ::core::option::Option<&Self>,
+                        other: ::core::option::Option<&Self>,
+                    ) -> ::core::option::Option<<Self as ::fieldmask::Maskable>::Mask> {
+                        match (this, other) {
+                            (::core::option::Option::None, ::core::option::Option::None) => {
+                                return ::core::option::Option::None;
+                            }
+                            #(#diff_same_variant_arms)*
+                            _ => {}
+                        }
+
+                        let mut mask = <Self as ::fieldmask::Maskable>::empty_mask();
+                        match (this, other) {
+                            (_, ::core::option::Option::Some(other)) => match other {
+                                #(#diff_select_other_arms)*
+                            },
+                            (::core::option::Option::Some(this), ::core::option::Option::None) => {
+                                match this {
+                                    #(#diff_select_this_arms)*
+                                }
+                            }
+                            (::core::option::Option::None, ::core::option::Option::None) => {}
+                        }
+                        ::core::option::Option::Some(mask)
+                    }
+
+                    fn option_eq_masked(
+                        this: ::core::option::Option<&Self>,
+                        other: ::core::option::Option<&Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                    ) -> bool {
+                        if mask == &<Self as ::fieldmask::Maskable>::empty_mask() {
+                            return match (this, other) {
+                                (::core::option::Option::None, ::core::option::Option::None) => true,
+                                #(#eq_all_same_variant_arms)*
+                                _ => false,
+                            };
+                        }
+
+                        match (this, other) {
+                            #(#eq_same_variant_arms)*
+                            _ => {}
+                        }
+
+                        // A value holding a variant that is not selected is the same as `None`.
+                        let is_selected = |this: ::core::option::Option<&Self>| match this {
+                            ::core::option::Option::Some(this) => match this {
+                                #(#is_selected_arms)*
+                            },
+                            ::core::option::Option::None => false,
+                        };
+                        !is_selected(this) && !is_selected(other)
+                    }
+
+                    fn option_visit(
+                        this: ::core::option::Option<&Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        path: &mut ::std::vec::Vec<&'static ::core::primitive::str>,
+                        visitor: &mut dyn ::fieldmask::MaskVisitor,
+                    ) {
+                        let ::core::option::Option::Some(this) = this else {
+                            return;
+                        };
+
+                        if mask == &<Self as ::fieldmask::Maskable>::empty_mask() {
+                            match this {
+                                #(#visit_all_arms)*
+                            }
                             return;
                         }
 
-                        #(#merge_arms)*
+                        match this {
+                            #(#visit_arms)*
+                        }
+                    }
+
+                    fn option_validate(
+                        this: ::core::option::Option<&Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        path: &mut ::std::vec::Vec<&'static ::core::primitive::str>,
+                    ) -> ::core::result::Result<(), ::fieldmask::ValidationError> {
+                        let ::core::option::Option::Some(this) = this else {
+                            return ::core::result::Result::Ok(());
+                        };
+
+                        if mask == &<Self as ::fieldmask::Maskable>::empty_mask() {
+                            match this {
+                                #(#validate_all_arms)*
+                            }
+                        } else {
+                            match this {
+                                #(#validate_arms)*
+                            }
+                        }
+                        ::core::result::Result::Ok(())
+                    }
+
+                    fn option_clear_output_only(this: &mut ::core::option::Option<Self>) {
+                        if let ::core::option::Option::Some(this) = this {
+                            match this {
+                                #(#clear_output_only_arms)*
+                            }
+                        }
+                    }
+
+                    fn option_redact(
+                        this: &mut ::core::option::Option<Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                    ) {
+                        let is_full = mask == &<Self as ::fieldmask::Maskable>::empty_mask();
+                        let is_cleared = match this {
+                            ::core::option::Option::Some(this) => match this {
+                                #(#redact_match_arms)*
+                            },
+                            ::core::option::Option::None => false,
+                        };
+                        if is_cleared {
+                            *this = ::core::option::Option::None;
+                        }
+                    }
+
+                    fn option_check_required(
+                        this: ::core::option::Option<&Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        path: &mut ::std::vec::Vec<&'static ::core::primitive::str>,
+                        missing: &mut ::std::vec::Vec<::std::string::String>,
+                    ) {
+                        let ::core::option::Option::Some(this) = this else {
+                            return;
+                        };
+
+                        if mask == &<Self as ::fieldmask::Maskable>::empty_mask() {
+                            match this {
+                                #(#check_required_all_arms)*
+                            }
+                        } else {
+                            match this {
+                                #(#check_required_arms)*
+                            }
+                        }
                     }
                 }
             }
         }
-    }.into()
+        Input::Struct(ref item) if item.is_transparent => {
+            let member = &fields[0].member;
+            // Delegate to `Option<Field>`, the same way `Box<T>` does.
+            quote! {
+                impl #impl_generics ::fieldmask::OptionMaskable for #ident #ty_generics
+                #where_clauses
+                {
+                    fn option_project(
+                        this: &mut ::core::option::Option<Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        options: &::fieldmask::ProjectOptions,
+                    ) {
+                        let mut inner = this.take().map(|this| this.#member);
+                        ::fieldmask::SelfMaskable::project(&mut inner, mask, options);
+                        *this = inner.map(|inner| Self { #member: inner });
+                    }
+
+                    fn option_project_ref(
+                        this: ::core::option::Option<&Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        options: &::fieldmask::ProjectOptions,
+                    ) -> ::core::option::Option<Self> {
+                        ::fieldmask::OptionMaskable::option_project_ref(
+                            this.map(|this| &this.#member),
+                            mask,
+                            options,
+                        )
+                        .map(|inner| Self { #member: inner })
+                    }
+
+                    fn option_clear(
+                        this: &mut ::core::option::Option<Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        options: &::fieldmask::ProjectOptions,
+                    ) {
+                        let mut inner = this.take().map(|this| this.#member);
+                        ::fieldmask::SelfMaskable::clear(&mut inner, mask, options);
+                        *this = inner.map(|inner| Self { #member: inner });
+                    }
+
+                    fn option_update_as_field(
+                        this: &mut ::core::option::Option<Self>,
+                        source: ::core::option::Option<Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        options: &::fieldmask::UpdateOptions,
+                    ) {
+                        let mut inner = this.take().map(|this| this.#member);
+                        ::fieldmask::SelfMaskable::update_as_field(
+                            &mut inner,
+                            source.map(|source| source.#member),
+                            mask,
+                            options,
+                        );
+                        *this = inner.map(|inner| Self { #member: inner });
+                    }
+
+                    fn option_merge(
+                        this: &mut ::core::option::Option<Self>,
+                        source: ::core::option::Option<Self>,
+                        options: &::fieldmask::UpdateOptions,
+                    ) {
+                        let mut inner = this.take().map(|this| this.#member);
+                        ::fieldmask::SelfMaskable::merge(
+                            &mut inner,
+                            source.map(|source| source.#member),
+                            options,
+                        );
+                        *this = inner.map(|inner| Self { #member: inner });
+                    }
+
+                    fn option_update_as_field_reporting(
+                        this: &mut ::core::option::Option<Self>,
+                        source: ::core::option::Option<Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        options: &::fieldmask::UpdateOptions,
+                    ) -> ::core::option::Option<<Self as ::fieldmask::Maskable>::Mask> {
+                        let mut inner = this.take().map(|this| this.#member);
+                        let changed = ::fieldmask::SelfMaskable::update_as_field_reporting(
+                            &mut inner,
+                            source.map(|source| source.#member),
+                            mask,
+                            options,
+                        );
+                        *this = inner.map(|inner| Self { #member: inner });
+                        changed
+                    }
+
+                    fn option_merge_reporting(
+                        this: &mut ::core::option::Option<Self>,
+                        source: ::core::option::Option<Self>,
+                        options: &::fieldmask::UpdateOptions,
+                    ) -> ::core::option::Option<<Self as ::fieldmask::Maskable>::Mask> {
+                        let mut inner = this.take().map(|this| this.#member);
+                        let changed = ::fieldmask::SelfMaskable::merge_reporting(
+                            &mut inner,
+                            source.map(|source| source.#member),
+                            options,
+                        );
+                        *this = inner.map(|inner| Self { #member: inner });
+                        changed
+                    }
+
+                    fn option_diff(
+                        this: ::core::option::Option<&Self>,
+                        other: ::core::option::Option<&Self>,
+                    ) -> ::core::option::Option<<Self as ::fieldmask::Maskable>::Mask> {
+                        ::fieldmask::OptionMaskable::option_diff(
+                            this.map(|this| &this.#member),
+                            other.map(|other| &other.#member),
+                        )
+                    }
+
+                    fn option_eq_masked(
+                        this: ::core::option::Option<&Self>,
+                        other: ::core::option::Option<&Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                    ) -> bool {
+                        ::fieldmask::OptionMaskable::option_eq_masked(
+                            this.map(|this| &this.#member),
+                            other.map(|other| &other.#member),
+                            mask,
+                        )
+                    }
+
+                    fn option_visit(
+                        this: ::core::option::Option<&Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        path: &mut ::std::vec::Vec<&'static ::core::primitive::str>,
+                        visitor: &mut dyn ::fieldmask::MaskVisitor,
+                    ) {
+                        ::fieldmask::OptionMaskable::option_visit(
+                            this.map(|this| &this.#member),
+                            mask,
+                            path,
+                            visitor,
+                        );
+                    }
+
+                    fn option_validate(
+                        this: ::core::option::Option<&Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        path: &mut ::std::vec::Vec<&'static ::core::primitive::str>,
+                    ) -> ::core::result::Result<(), ::fieldmask::ValidationError> {
+                        ::fieldmask::OptionMaskable::option_validate(
+                            this.map(|this| &this.#member),
+                            mask,
+                            path,
+                        )
+                    }
+
+                    fn option_clear_output_only(this: &mut ::core::option::Option<Self>) {
+                        let mut inner = this.take().map(|this| this.#member);
+                        ::fieldmask::SelfMaskable::clear_output_only(&mut inner);
+                        *this = inner.map(|inner| Self { #member: inner });
+                    }
+
+                    fn option_redact(
+                        this: &mut ::core::option::Option<Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                    ) {
+                        let mut inner = this.take().map(|this| this.#member);
+                        ::fieldmask::SelfMaskable::redact(&mut inner, mask);
+                        *this = inner.map(|inner| Self { #member: inner });
+                    }
+
+                    fn option_check_required(
+                        this: ::core::option::Option<&Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        path: &mut ::std::vec::Vec<&'static ::core::primitive::str>,
+                        missing: &mut ::std::vec::Vec<::std::string::String>,
+                    ) {
+                        ::fieldmask::OptionMaskable::option_check_required(
+                            this.map(|this| &this.#member),
+                            mask,
+                            path,
+                            missing,
+                        );
+                    }
+                }
+            }
+        }
+        Input::Struct(_) => {
+            quote!{
+                impl #impl_generics ::fieldmask::OptionMaskable for #ident #ty_generics
+                #where_clauses
+                {
+                    fn option_project(
+                        this: &mut ::core::option::Option<Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        options: &::fieldmask::ProjectOptions,
+                    ) {
+                        if let ::core::option::Option::Some(inner) = this {
+                            ::fieldmask::SelfMaskable::project(inner, mask, options);
+                            if inner == &::core::default::Default::default() {
+                                *this = ::core::option::Option::None;
+                            }
+                        }
+                    }
+
+                    fn option_project_ref(
+                        this: ::core::option::Option<&Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        options: &::fieldmask::ProjectOptions,
+                    ) -> ::core::option::Option<Self> {
+                        this.map(|this| ::fieldmask::SelfMaskable::project_ref(this, mask, options))
+                            .filter(|inner| inner != &::core::default::Default::default())
+                    }
+
+                    fn option_clear(
+                        this: &mut ::core::option::Option<Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        options: &::fieldmask::ProjectOptions,
+                    ) {
+                        if let ::core::option::Option::Some(inner) = this {
+                            ::fieldmask::SelfMaskable::clear(inner, mask, options);
+                            if inner == &::core::default::Default::default() {
+                                *this = ::core::option::Option::None;
+                            }
+                        }
+                    }
+
+                    fn option_update_as_field(
+                        this: &mut ::core::option::Option<Self>,
+                        source: ::core::option::Option<Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        options: &::fieldmask::UpdateOptions,
+                    ) {
+                        match (this.as_mut(), source) {
+                            (::core::option::Option::Some(this), ::core::option::Option::Some(source)) => {
+                                ::fieldmask::SelfMaskable::update_as_field(this, source, mask, options);
+                            }
+                            (::core::option::Option::Some(this), ::core::option::Option::None) => {
+                                ::fieldmask::SelfMaskable::update_as_field(
+                                    this,
+                                    ::core::default::Default::default(),
+                                    mask,
+                                    options,
+                                );
+                            }
+                            (::core::option::Option::None, ::core::option::Option::Some(mut source)) => {
+                                ::fieldmask::SelfMaskable::project(&mut source, mask, &::core::default::Default::default());
+                                *this = Some(source);
+                            }
+                            (::core::option::Option::None, ::core::option::Option::None) => {}
+                        }
+                    }
+
+                    fn option_merge(
+                        this: &mut ::core::option::Option<Self>,
+                        source: ::core::option::Option<Self>,
+                        options: &::fieldmask::UpdateOptions,
+                    ) {
+                        match (this.as_mut(), source) {
+                            (::core::option::Option::Some(this), ::core::option::Option::Some(source)) => {
+                                ::fieldmask::SelfMaskable::merge(this, source, options);
+                            }
+                            (_, ::core::option::Option::None) => {}
+                            (::core::option::Option::None, source) => {
+                                *this = source;
+                            }
+                        }
+                    }
+
+                    // A message that is set is compared with the default value, the same way
+                    // `option_diff` does.
+                    fn option_update_as_field_reporting(
+                        this: &mut ::core::option::Option<Self>,
+                        source: ::core::option::Option<Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        options: &::fieldmask::UpdateOptions,
+                    ) -> ::core::option::Option<<Self as ::fieldmask::Maskable>::Mask> {
+                        match (this.as_mut(), source) {
+                            (::core::option::Option::Some(this), ::core::option::Option::Some(source)) => {
+                                ::fieldmask::SelfMaskable::update_as_field_reporting(this, source, mask, options)
+                            }
+                            (::core::option::Option::Some(this), ::core::option::Option::None) => {
+                                ::fieldmask::SelfMaskable::update_as_field_reporting(
+                                    this,
+                                    ::core::default::Default::default(),
+                                    mask,
+                                    options,
+                                )
+                            }
+                            (::core::option::Option::None, ::core::option::Option::Some(mut source)) => {
+                                ::fieldmask::SelfMaskable::project(&mut source, mask, &::core::default::Default::default());
+                                let changed = ::fieldmask::SelfMaskable::diff(
+                                    &<Self as ::core::default::Default>::default(),
+                                    &source,
+                                );
+                                *this = Some(source);
+                                changed
+                            }
+                            (::core::option::Option::None, ::core::option::Option::None) => {
+                                ::core::option::Option::None
+                            }
+                        }
+                    }
+
+                    fn option_merge_reporting(
+                        this: &mut ::core::option::Option<Self>,
+                        source: ::core::option::Option<Self>,
+                        options: &::fieldmask::UpdateOptions,
+                    ) -> ::core::option::Option<<Self as ::fieldmask::Maskable>::Mask> {
+                        match (this.as_mut(), source) {
+                            (::core::option::Option::Some(this), ::core::option::Option::Some(source)) => {
+                                ::fieldmask::SelfMaskable::merge_reporting(this, source, options)
+                            }
+                            (_, ::core::option::Option::None) => ::core::option::Option::None,
+                            (::core::option::Option::None, ::core::option::Option::Some(source)) => {
+                                let changed = ::fieldmask::SelfMaskable::diff(
+                                    &<Self as ::core::default::Default>::default(),
+                                    &source,
+                                );
+                                *this = Some(source);
+                                changed
+                            }
+                        }
+                    }
+
+                    fn option_diff(
+                        this: ::core::option::Option<&Self>,
+                        other: ::core::option::Option<&Self>,
+                    ) -> ::core::option::Option<<Self as ::fieldmask::Maskable>::Mask> {
+                        // `None` is treated the same as the default value.
+                        match (this, other) {
+                            (::core::option::Option::Some(this), ::core::option::Option::Some(other)) => {
+                                ::fieldmask::SelfMaskable::diff(this, other)
+                            }
+                            (::core::option::Option::Some(this), ::core::option::Option::None) => {
+                                ::fieldmask::SelfMaskable::diff(this, &::core::default::Default::default())
+                            }
+                            (::core::option::Option::None, ::core::option::Option::Some(other)) => {
+                                ::fieldmask::SelfMaskable::diff(
+                                    &<Self as ::core::default::Default>::default(),
+                                    other,
+                                )
+                            }
+                            (::core::option::Option::None, ::core::option::Option::None) => {
+                                ::core::option::Option::None
+                            }
+                        }
+                    }
+
+                    fn option_eq_masked(
+                        this: ::core::option::Option<&Self>,
+                        other: ::core::option::Option<&Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                    ) -> bool {
+                        // `None` is treated the same as the default value.
+                        match (this, other) {
+                            (::core::option::Option::Some(this), ::core::option::Option::Some(other)) => {
+                                ::fieldmask::SelfMaskable::eq_masked(this, other, mask)
+                            }
+                            (::core::option::Option::Some(this), ::core::option::Option::None) => {
+                                ::fieldmask::SelfMaskable::eq_masked(
+                                    this,
+                                    &::core::default::Default::default(),
+                                    mask,
+                                )
+                            }
+                            (::core::option::Option::None, ::core::option::Option::Some(other)) => {
+                                ::fieldmask::SelfMaskable::eq_masked(
+                                    &<Self as ::core::default::Default>::default(),
+                                    other,
+                                    mask,
+                                )
+                            }
+                            (::core::option::Option::None, ::core::option::Option::None) => true,
+                        }
+                    }
+
+                    fn option_visit(
+                        this: ::core::option::Option<&Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        path: &mut ::std::vec::Vec<&'static ::core::primitive::str>,
+                        visitor: &mut dyn ::fieldmask::MaskVisitor,
+                    ) {
+                        if let ::core::option::Option::Some(this) = this {
+                            ::fieldmask::SelfMaskable::visit(this, mask, path, visitor);
+                        }
+                    }
+
+                    fn option_validate(
+                        this: ::core::option::Option<&Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        path: &mut ::std::vec::Vec<&'static ::core::primitive::str>,
+                    ) -> ::core::result::Result<(), ::fieldmask::ValidationError> {
+                        match this {
+                            ::core::option::Option::Some(this) => {
+                                ::fieldmask::SelfMaskable::validate(this, mask, path)
+                            }
+                            ::core::option::Option::None => ::core::result::Result::Ok(()),
+                        }
+                    }
+
+                    fn option_clear_output_only(this: &mut ::core::option::Option<Self>) {
+                        if let ::core::option::Option::Some(this) = this {
+                            ::fieldmask::SelfMaskable::clear_output_only(this);
+                        }
+                    }
+
+                    fn option_redact(
+                        this: &mut ::core::option::Option<Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                    ) {
+                        if let ::core::option::Option::Some(inner) = this {
+                            ::fieldmask::SelfMaskable::redact(inner, mask);
+                            if inner == &::core::default::Default::default() {
+                                *this = ::core::option::Option::None;
+                            }
+                        }
+                    }
+
+                    fn option_check_required(
+                        this: ::core::option::Option<&Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        path: &mut ::std::vec::Vec<&'static ::core::primitive::str>,
+                        missing: &mut ::std::vec::Vec<::std::string::String>,
+                    ) {
+                        if let ::core::option::Option::Some(this) = this {
+                            ::fieldmask::SelfMaskable::check_required(this, mask, path, missing);
+                        }
+                    }
+                }
+            }
+        }
+    }.into()
+}
+
+/// The implementation for `derive_self_maskable`.
+pub fn derive_self_maskable_impl(input: TokenStream) -> TokenStream {
+    let input: Input = parse_macro_input!(input);
+    let MessageInfo {
+        ident,
+        generics,
+        bound,
+        fields,
+        ..
+    } = input.get_message_info();
+
+    let generics = match input {
+        Input::UnitEnum(_) | Input::OneOfEnum(_) => generics.clone(),
+        Input::Struct(ref item) if item.is_transparent => with_bound(
+            generics,
+            ident,
+            bound,
+            [fields[0].ty],
+            quote! { ::fieldmask::SelfMaskable },
+        ),
+        Input::Struct(_) => with_bound(
+            generics,
+            ident,
+            bound,
+            fields.iter().map(|field| field.ty),
+            quote! {
+                ::fieldmask::SelfMaskable + ::core::default::Default + ::core::cmp::PartialEq
+            },
+        ),
+    };
+    let (impl_generics, ty_generics, where_clauses) = generics.split_for_impl();
+
+    match input {
+        Input::UnitEnum(ref item) => {
+            let variant_idents = item.variants.iter().map(|variant| &variant.ident);
+
+            // Unit enums have no fields, the field mask is always empty.
+            quote! {
+                impl #impl_generics ::fieldmask::SelfMaskable for #ident #ty_generics
+                #where_clauses
+                {
+                    fn project(
+                        &mut self,
+                        _mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        _options: &::fieldmask::ProjectOptions,
+                    ) {}
+
+                    fn project_ref(
+                        &self,
+                        _mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        _options: &::fieldmask::ProjectOptions,
+                    ) -> Self {
+                        match self {
+                            #(Self::#variant_idents => Self::#variant_idents,)*
+                        }
+                    }
+
+                    fn clear(
+                        &mut self,
+                        _mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        _options: &::fieldmask::ProjectOptions,
+                    ) {
+                        *self = ::core::default::Default::default();
+                    }
+
+                    fn update_as_field(
+                        &mut self,
+                        source: Self,
+                        _mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        _options: &::fieldmask::UpdateOptions,
+                    ) {
+                        *self = source;
+                    }
+
+                    fn merge(&mut self, source: Self, _options: &::fieldmask::UpdateOptions) {
+                        if source != ::core::default::Default::default() {
+                            *self = source;
+                        }
+                    }
+
+                    fn update_as_field_reporting(
+                        &mut self,
+                        source: Self,
+                        _mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        _options: &::fieldmask::UpdateOptions,
+                    ) -> ::core::option::Option<<Self as ::fieldmask::Maskable>::Mask> {
+                        let changed = ::fieldmask::SelfMaskable::diff(self, &source);
+                        *self = source;
+                        changed
+                    }
+
+                    fn diff(
+                        &self,
+                        other: &Self,
+                    ) -> ::core::option::Option<<Self as ::fieldmask::Maskable>::Mask> {
+                        if ::core::mem::discriminant(self) != ::core::mem::discriminant(other) {
+                            ::core::option::Option::Some(())
+                        } else {
+                            ::core::option::Option::None
+                        }
+                    }
+
+                    fn eq_masked(
+                        &self,
+                        other: &Self,
+                        _mask: &<Self as ::fieldmask::Maskable>::Mask,
+                    ) -> bool {
+                        ::core::mem::discriminant(self) == ::core::mem::discriminant(other)
+                    }
+
+                    fn visit(
+                        &self,
+                        _mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        path: &mut ::std::vec::Vec<&'static ::core::primitive::str>,
+                        visitor: &mut dyn ::fieldmask::MaskVisitor,
+                    ) {
+                        visitor.visit_field(path, ::fieldmask::FieldRef::new(self));
+                    }
+                }
+            }
+        }
+        Input::OneOfEnum(ref item) => syn::Error::new_spanned(
+            &item.ident,
+            "cannot derive `SelfMaskable` for a oneof enum; derive `OptionMaskable` instead and \
+             use the enum as `Option<_>`",
+        )
+        .to_compile_error(),
+        Input::Struct(ref item) if item.is_transparent => {
+            let member = &fields[0].member;
+            quote! {
+                impl #impl_generics ::fieldmask::SelfMaskable for #ident #ty_generics
+                #where_clauses
+                {
+                    fn project(
+                        &mut self,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        options: &::fieldmask::ProjectOptions,
+                    ) {
+                        ::fieldmask::SelfMaskable::project(&mut self.#member, mask, options);
+                    }
+
+                    fn project_ref(
+                        &self,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        options: &::fieldmask::ProjectOptions,
+                    ) -> Self {
+                        Self {
+                            #member: ::fieldmask::SelfMaskable::project_ref(&self.#member, mask, options),
+                        }
+                    }
+
+                    fn clear(
+                        &mut self,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        options: &::fieldmask::ProjectOptions,
+                    ) {
+                        ::fieldmask::SelfMaskable::clear(&mut self.#member, mask, options);
+                    }
+
+                    fn update_as_field(
+                        &mut self,
+                        source: Self,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        options: &::fieldmask::UpdateOptions,
+                    ) {
+                        ::fieldmask::SelfMaskable::update_as_field(
+                            &mut self.#member,
+                            source.#member,
+                            mask,
+                            options,
+                        );
+                    }
+
+                    fn merge(&mut self, source: Self, options: &::fieldmask::UpdateOptions) {
+                        ::fieldmask::SelfMaskable::merge(&mut self.#member, source.#member, options);
+                    }
+
+                    fn update_as_field_reporting(
+                        &mut self,
+                        source: Self,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        options: &::fieldmask::UpdateOptions,
+                    ) -> ::core::option::Option<<Self as ::fieldmask::Maskable>::Mask> {
+                        ::fieldmask::SelfMaskable::update_as_field_reporting(
+                            &mut self.#member,
+                            source.#member,
+                            mask,
+                            options,
+                        )
+                    }
+
+                    fn merge_reporting(
+                        &mut self,
+                        source: Self,
+                        options: &::fieldmask::UpdateOptions,
+                    ) -> ::core::option::Option<<Self as ::fieldmask::Maskable>::Mask> {
+                        ::fieldmask::SelfMaskable::merge_reporting(
+                            &mut self.#member,
+                            source.#member,
+                            options,
+                        )
+                    }
+
+                    fn diff(
+                        &self,
+                        other: &Self,
+                    ) -> ::core::option::Option<<Self as ::fieldmask::Maskable>::Mask> {
+                        ::fieldmask::SelfMaskable::diff(&self.#member, &other.#member)
+                    }
+
+                    fn eq_masked(
+                        &self,
+                        other: &Self,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                    ) -> bool {
+                        ::fieldmask::SelfMaskable::eq_masked(&self.#member, &other.#member, mask)
+                    }
+
+                    fn visit(
+                        &self,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        path: &mut ::std::vec::Vec<&'static ::core::primitive::str>,
+                        visitor: &mut dyn ::fieldmask::MaskVisitor,
+                    ) {
+                        ::fieldmask::SelfMaskable::visit(&self.#member, mask, path, visitor);
+                    }
+
+                    fn validate(
+                        &self,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        path: &mut ::std::vec::Vec<&'static ::core::primitive::str>,
+                    ) -> ::core::result::Result<(), ::fieldmask::ValidationError> {
+                        ::fieldmask::SelfMaskable::validate(&self.#member, mask, path)
+                    }
+
+                    fn clear_output_only(&mut self) {
+                        ::fieldmask::SelfMaskable::clear_output_only(&mut self.#member);
+                    }
+
+                    fn redact(&mut self, mask: &<Self as ::fieldmask::Maskable>::Mask) {
+                        ::fieldmask::SelfMaskable::redact(&mut self.#member, mask);
+                    }
+
+                    fn check_required(
+                        &self,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        path: &mut ::std::vec::Vec<&'static ::core::primitive::str>,
+                        missing: &mut ::std::vec::Vec<::std::string::String>,
+                    ) {
+                        ::fieldmask::SelfMaskable::check_required(&self.#member, mask, path, missing);
+                    }
+                }
+            }
+        }
+        Input::Struct(_) => {
+            let places = fields
+                .iter()
+                .map(|field| {
+                    let member = &field.member;
+                    quote! { self.#member }
+                })
+                .collect::<Vec<_>>();
+            let sources = fields
+                .iter()
+                .map(|field| {
+                    let member = &field.member;
+                    quote! { source.#member }
+                })
+                .collect::<Vec<_>>();
+
+            let refs = places
+                .iter()
+                .map(|place| quote! { &#place })
+                .collect::<Vec<_>>();
+
+            let normalize_arms = fields_normalize(&fields, &places);
+            let project_arms = fields_project(&fields, &places);
+            let normalize_ref_arms = fields_normalize_ref(&fields, &refs);
+            let project_ref_arms = fields_project_ref(&fields, &refs);
+            let clear_all_arms = fields_clear_all(&fields, &places);
+            let other_refs = fields
+                .iter()
+                .map(|field| {
+                    let member = &field.member;
+                    quote! { &other.#member }
+                })
+                .collect::<Vec<_>>();
+            let eq_all = fields_eq_masked_all(&fields, &refs, &other_refs);
+            let eq = fields_eq_masked(&fields, &refs, &other_refs);
+            let visit_all_arms = fields_visit_all(&fields, &refs);
+            let visit_arms = fields_visit(&fields, &refs);
+            let validate_all_arms = fields_validate_all(&fields, &refs);
+            let validate_arms = fields_validate(&fields, &refs);
+            let check_required_all_arms = fields_check_required_all(&fields, &refs);
+            let check_required_arms = fields_check_required(&fields, &refs);
+            let clear_arms = fields_clear(&fields, &places);
+            let update_arms = fields_update(&fields, &places, &sources, false);
+            let merge_arms = fields_merge(&fields, &places, &sources, false);
+            let update_reporting_arms = fields_update(&fields, &places, &sources, true);
+            let merge_reporting_arms = fields_merge(&fields, &places, &sources, true);
+            let replace_reporting_arms = fields_replace(&fields, &places, &sources, true);
+            // Restricted fields are replaced one by one, so that they can be kept.
+            let replace = if has_restricted_fields(&fields) {
+                fields_replace(&fields, &places, &sources, false)
+            } else {
+                quote! { *self = source; }
+            };
+            let clear_output_only = fields_clear_output_only(&fields, &places);
+            let redact_all_arms = fields_redact_all(&fields, &places);
+            let redact_arms = fields_redact(&fields, &places);
+            let diff = fields_diff(
+                &fields,
+                &refs,
+                &other_refs,
+            );
+
+            quote! {
+                impl #impl_generics ::fieldmask::SelfMaskable for #ident #ty_generics
+                #where_clauses
+                {
+                    fn project(
+                        &mut self,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        options: &::fieldmask::ProjectOptions,
+                    ) {
+                        if mask == &<Self as ::fieldmask::Maskable>::empty_mask() {
+                            if options.normalize {
+                                #normalize_arms
+                            }
+                            return;
+                        }
+
+                        #project_arms
+                    }
+
+                    fn project_ref(
+                        &self,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        options: &::fieldmask::ProjectOptions,
+                    ) -> Self {
+                        if mask == &<Self as ::fieldmask::Maskable>::empty_mask() {
+                            return Self { #normalize_ref_arms };
+                        }
+
+                        Self { #project_ref_arms }
+                    }
+
+                    fn clear(
+                        &mut self,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        options: &::fieldmask::ProjectOptions,
+                    ) {
+                        if mask == &<Self as ::fieldmask::Maskable>::empty_mask() {
+                            #clear_all_arms
+                            return;
+                        }
+
+                        #clear_arms
+                    }
+
+                    fn update_as_field(
+                        &mut self,
+                        source: Self,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        options: &::fieldmask::UpdateOptions,
+                    ) {
+                        if mask == &<Self as ::fieldmask::Maskable>::empty_mask() {
+                            ::fieldmask::SelfMaskable::merge(self, source, options);
+                            return;
+                        }
+
+                        #update_arms
+                    }
+
+                    fn merge(&mut self, source: Self, options: &::fieldmask::UpdateOptions) {
+                        if options.replace_message {
+                            #replace
+                            return;
+                        }
+
+                        #merge_arms
+                    }
+
+                    fn update_as_field_reporting(
+                        &mut self,
+                        source: Self,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        options: &::fieldmask::UpdateOptions,
+                    ) -> ::core::option::Option<<Self as ::fieldmask::Maskable>::Mask> {
+                        if mask == &<Self as ::fieldmask::Maskable>::empty_mask() {
+                            return ::fieldmask::SelfMaskable::merge_reporting(self, source, options);
+                        }
+
+                        let mut changed = <Self as ::fieldmask::Maskable>::empty_mask();
+                        #update_reporting_arms
+                        if changed == <Self as ::fieldmask::Maskable>::empty_mask() {
+                            ::core::option::Option::None
+                        } else {
+                            ::core::option::Option::Some(changed)
+                        }
+                    }
+
+                    fn merge_reporting(
+                        &mut self,
+                        source: Self,
+                        options: &::fieldmask::UpdateOptions,
+                    ) -> ::core::option::Option<<Self as ::fieldmask::Maskable>::Mask> {
+                        let mut changed = <Self as ::fieldmask::Maskable>::empty_mask();
+                        if options.replace_message {
+                            #replace_reporting_arms
+                        } else {
+                            #merge_reporting_arms
+                        }
+                        if changed == <Self as ::fieldmask::Maskable>::empty_mask() {
+                            ::core::option::Option::None
+                        } else {
+                            ::core::option::Option::Some(changed)
+                        }
+                    }
+
+                    fn diff(
+                        &self,
+                        other: &Self,
+                    ) -> ::core::option::Option<<Self as ::fieldmask::Maskable>::Mask> {
+                        let mask = #diff;
+                        if mask == <Self as ::fieldmask::Maskable>::empty_mask() {
+                            ::core::option::Option::None
+                        } else {
+                            ::core::option::Option::Some(mask)
+                        }
+                    }
+
+                    fn eq_masked(
+                        &self,
+                        other: &Self,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                    ) -> bool {
+                        if mask == &<Self as ::fieldmask::Maskable>::empty_mask() {
+                            return #eq_all;
+                        }
+
+                        #eq
+                    }
+
+                    fn visit(
+                        &self,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        path: &mut ::std::vec::Vec<&'static ::core::primitive::str>,
+                        visitor: &mut dyn ::fieldmask::MaskVisitor,
+                    ) {
+                        if mask == &<Self as ::fieldmask::Maskable>::empty_mask() {
+                            #visit_all_arms
+                            return;
+                        }
+
+                        #visit_arms
+                    }
+
+                    fn validate(
+                        &self,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        path: &mut ::std::vec::Vec<&'static ::core::primitive::str>,
+                    ) -> ::core::result::Result<(), ::fieldmask::ValidationError> {
+                        if mask == &<Self as ::fieldmask::Maskable>::empty_mask() {
+                            #validate_all_arms
+                        } else {
+                            #validate_arms
+                        }
+                        ::core::result::Result::Ok(())
+                    }
+
+                    fn clear_output_only(&mut self) {
+                        #clear_output_only
+                    }
+
+                    fn redact(&mut self, mask: &<Self as ::fieldmask::Maskable>::Mask) {
+                        if mask == &<Self as ::fieldmask::Maskable>::empty_mask() {
+                            #redact_all_arms
+                            return;
+                        }
+
+                        #redact_arms
+                    }
+
+                    fn check_required(
+                        &self,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        path: &mut ::std::vec::Vec<&'static ::core::primitive::str>,
+                        missing: &mut ::std::vec::Vec<::std::string::String>,
+                    ) {
+                        if mask == &<Self as ::fieldmask::Maskable>::empty_mask() {
+                            #check_required_all_arms
+                        } else {
+                            #check_required_arms
+                        }
+                    }
+                }
+            }
+        }
+    }.into()
+}
+
+/// The implementation for `derive_masked_hash`.
+pub fn derive_masked_hash_impl(input: TokenStream) -> TokenStream {
+    let input: Input = parse_macro_input!(input);
+    let MessageInfo {
+        ident,
+        generics,
+        bound,
+        fields,
+        ..
+    } = input.get_message_info();
+
+    let generics = match input {
+        Input::UnitEnum(_) | Input::OneOfEnum(_) => generics.clone(),
+        Input::Struct(_) => with_bound(
+            generics,
+            ident,
+            bound,
+            fields.iter().map(|field| field.ty),
+            quote! { ::fieldmask::MaskedHash },
+        ),
+    };
+    let (impl_generics, ty_generics, where_clauses) = generics.split_for_impl();
+
+    match input {
+        Input::UnitEnum(_) => quote! {
+            impl #impl_generics ::fieldmask::MaskedHash for #ident #ty_generics
+            #where_clauses
+            {
+                fn hash_masked<__H: ::core::hash::Hasher>(
+                    &self,
+                    _mask: &<Self as ::fieldmask::Maskable>::Mask,
+                    state: &mut __H,
+                ) {
+                    ::core::hash::Hash::hash(&::core::mem::discriminant(self), state);
+                }
+            }
+        },
+        Input::OneOfEnum(ref item) => syn::Error::new_spanned(
+            &item.ident,
+            "cannot derive `MaskedHash` for a oneof enum; derive `OptionMaskedHash` instead and \
+             use the enum as `Option<_>`",
+        )
+        .to_compile_error(),
+        Input::Struct(ref item) if item.is_transparent => {
+            let member = &fields[0].member;
+            quote! {
+                impl #impl_generics ::fieldmask::MaskedHash for #ident #ty_generics
+                #where_clauses
+                {
+                    fn hash_masked<__H: ::core::hash::Hasher>(
+                        &self,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        state: &mut __H,
+                    ) {
+                        ::fieldmask::MaskedHash::hash_masked(&self.#member, mask, state);
+                    }
+                }
+            }
+        }
+        Input::Struct(_) => {
+            let refs = fields
+                .iter()
+                .map(|field| {
+                    let member = &field.member;
+                    quote! { &self.#member }
+                })
+                .collect::<Vec<_>>();
+            let hash_all_arms = fields_hash_masked_all(&fields, &refs);
+            let hash_arms = fields_hash_masked(&fields, &refs);
+
+            quote! {
+                impl #impl_generics ::fieldmask::MaskedHash for #ident #ty_generics
+                #where_clauses
+                {
+                    fn hash_masked<__H: ::core::hash::Hasher>(
+                        &self,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        state: &mut __H,
+                    ) {
+                        if mask == &<Self as ::fieldmask::Maskable>::empty_mask() {
+                            #hash_all_arms
+                            return;
+                        }
+
+                        #hash_arms
+                    }
+                }
+            }
+        }
+    }
+    .into()
+}
+
+/// The implementation for `derive_option_masked_hash`.
+pub fn derive_option_masked_hash_impl(input: TokenStream) -> TokenStream {
+    let input: Input = parse_macro_input!(input);
+    let MessageInfo {
+        ident,
+        generics,
+        bound,
+        fields,
+        variants,
+    } = input.get_message_info();
+
+    let generics = match input {
+        Input::UnitEnum(_) => generics.clone(),
+        Input::OneOfEnum(_) => with_bound(
+            generics,
+            ident,
+            bound,
+            variants.iter().flat_map(variant_types),
+            quote! { ::fieldmask::MaskedHash },
+        ),
+        Input::Struct(ref item) if item.is_transparent => with_bound(
+            generics,
+            ident,
+            bound,
+            [fields[0].ty],
+            quote! { ::fieldmask::OptionMaskedHash },
+        ),
+        Input::Struct(_) => with_self_bound(
+            generics,
+            ident,
+            bound,
+            quote! { ::fieldmask::MaskedHash + ::core::default::Default },
+        ),
+    };
+    let (impl_generics, ty_generics, where_clauses) = generics.split_for_impl();
+
+    match input {
+        Input::UnitEnum(_) => quote! {
+            impl #impl_generics ::fieldmask::OptionMaskedHash for #ident #ty_generics
+            #where_clauses
+            {
+                fn option_hash_masked<__H: ::core::hash::Hasher>(
+                    this: ::core::option::Option<&Self>,
+                    _mask: &<Self as ::fieldmask::Maskable>::Mask,
+                    state: &mut __H,
+                ) {
+                    ::core::hash::Hash::hash(&this.map(::core::mem::discriminant), state);
+                }
+            }
+        },
+        Input::OneOfEnum(_) => {
+            let hash_all_arms = variants.iter().enumerate().map(|(i, variant)| {
+                let variant_ident = variant.ident;
+                match &variant.kind {
+                    MessageVariantKind::Unit => quote! {
+                        Self::#variant_ident => {
+                            ::core::hash::Hash::hash(&::core::option::Option::Some(#i), state);
+                        }
+                    },
+                    MessageVariantKind::Tuple(ty) => quote! {
+                        Self::#variant_ident(this) => {
+                            ::core::hash::Hash::hash(&::core::option::Option::Some(#i), state);
+                            ::fieldmask::MaskedHash::hash_masked(
+                                this,
+                                &<#ty as ::fieldmask::Maskable>::empty_mask(),
+                                state,
+                            );
+                        }
+                    },
+                    MessageVariantKind::Fields(fields) => {
+                        let this_pat = variant_fields_pattern(variant_ident, fields, "this");
+                        let hash_all =
+                            fields_hash_masked_all(fields, &variant_fields_refs(fields, "this"));
+                        quote! {
+                            #this_pat => {
+                                ::core::hash::Hash::hash(&::core::option::Option::Some(#i), state);
+                                #hash_all
+                            }
+                        }
+                    }
+                }
+            });
+
+            // If the variant is not selected by the mask, hash it the same as `None`.
+            let hash_arms = variants.iter().enumerate().map(|(i, variant)| {
+                let index = Index::from(i);
+                let variant_ident = variant.ident;
+                match &variant.kind {
+                    MessageVariantKind::Unit => quote! {
+                        Self::#variant_ident => {
+                            if mask.#index.is_some() {
+                                ::core::hash::Hash::hash(&::core::option::Option::Some(#i), state);
+                                return;
+                            }
+                        }
+                    },
+                    MessageVariantKind::Tuple(_) => quote! {
+                        Self::#variant_ident(this) => {
+                            if let ::core::option::Option::Some(mask) = &mask.#index {
+                                ::core::hash::Hash::hash(&::core::option::Option::Some(#i), state);
+                                ::fieldmask::MaskedHash::hash_masked(this, mask, state);
+                                return;
+                            }
+                        }
+                    },
+                    MessageVariantKind::Fields(fields) => {
+                        let this_pat = variant_fields_pattern(variant_ident, fields, "this");
+                        let empty_mask = fields_empty_mask(fields);
+                        let refs = variant_fields_refs(fields, "this");
+                        let hash_all = fields_hash_masked_all(fields, &refs);
+                        let hash = fields_hash_masked(fields, &refs);
+                        quote! {
+                            #this_pat => {
+                                if let ::core::option::Option::Some(mask) = &mask.#index {
+                                    ::core::hash::Hash::hash(&::core::option::Option::Some(#i), state);
+                                    if mask == &#empty_mask {
+                                        #hash_all
+                                    } else {
+                                        #hash
+                                    }
+                                    return;
+                                }
+                            }
+                        }
+                    }
+                }
+            });
+
+            quote! {
+                impl #impl_generics ::fieldmask::OptionMaskedHash for #ident #ty_generics
+                #where_clauses
+                {
+                    fn option_hash_masked<__H: ::core::hash::Hasher>(
+                        this: ::core::option::Option<&Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        state: &mut __H,
+                    ) {
+                        if mask == &<Self as ::fieldmask::Maskable>::empty_mask() {
+                            match this {
+                                ::core::option::Option::Some(this) => match this {
+                                    #(#hash_all_arms)*
+                                },
+                                ::core::option::Option::None => {
+                                    ::core::hash::Hash::hash(&::core::option::Option::None::<usize>, state);
+                                }
+                            }
+                            return;
+                        }
+
+                        if let ::core::option::Option::Some(this) = this {
+                            match this {
+                                #(#hash_arms)*
+                            }
+                        }
+                        ::core::hash::Hash::hash(&::core::option::Option::None::<usize>, state);
+                    }
+                }
+            }
+        }
+        Input::Struct(ref item) if item.is_transparent => {
+            let member = &fields[0].member;
+            quote! {
+                impl #impl_generics ::fieldmask::OptionMaskedHash for #ident #ty_generics
+                #where_clauses
+                {
+                    fn option_hash_masked<__H: ::core::hash::Hasher>(
+                        this: ::core::option::Option<&Self>,
+                        mask: &<Self as ::fieldmask::Maskable>::Mask,
+                        state: &mut __H,
+                    ) {
+                        ::fieldmask::OptionMaskedHash::option_hash_masked(
+                            this.map(|this| &this.#member),
+                            mask,
+                            state,
+                        );
+                    }
+                }
+            }
+        }
+        Input::Struct(_) => quote! {
+            impl #impl_generics ::fieldmask::OptionMaskedHash for #ident #ty_generics
+            #where_clauses
+            {
+                fn option_hash_masked<__H: ::core::hash::Hasher>(
+                    this: ::core::option::Option<&Self>,
+                    mask: &<Self as ::fieldmask::Maskable>::Mask,
+                    state: &mut __H,
+                ) {
+                    // `None` is treated the same as the default value.
+                    match this {
+                        ::core::option::Option::Some(this) => {
+                            ::fieldmask::MaskedHash::hash_masked(this, mask, state);
+                        }
+                        ::core::option::Option::None => {
+                            ::fieldmask::MaskedHash::hash_masked(
+                                &<Self as ::core::default::Default>::default(),
+                                mask,
+                                state,
+                            );
+                        }
+                    }
+                }
+            }
+        },
+    }
+    .into()
+}
+
+/// The type whose mask is the sub-mask of a field. A repeated field declared with
+/// `#[fieldmask(key = "...")]` is masked with the mask of its elements, e.g. `items.name`.
+fn field_mask_ty(field: &MessageField) -> TokenStream2 {
+    let ty = field.ty;
+    if field.key.is_some() {
+        quote! { <#ty as ::fieldmask::KeyedRepeated>::Element }
+    } else {
+        quote! { #ty }
+    }
+}
+
+/// The trait that implements the operations on a field with its sub-mask.
+fn field_ops(field: &MessageField) -> TokenStream2 {
+    if field.key.is_some() {
+        quote! { ::fieldmask::KeyedRepeated }
+    } else {
+        quote! { ::fieldmask::SelfMaskable }
+    }
+}
+
+/// The trait that hashes a field with its sub-mask.
+fn field_hash_ops(field: &MessageField) -> TokenStream2 {
+    if field.key.is_some() {
+        quote! { ::fieldmask::KeyedMaskedHash }
+    } else {
+        quote! { ::fieldmask::MaskedHash }
+    }
+}
+
+/// The type of the mask of a list of fields.
+fn fields_mask_type(fields: &[MessageField]) -> TokenStream2 {
+    let mask_type_arms = fields.iter().map(|field| {
+        let field_ty = field_mask_ty(field);
+        if field.is_flatten {
+            quote! {
+                ::fieldmask::Mask<#field_ty>,
+            }
+        } else {
+            quote! {
+                ::core::option::Option<::fieldmask::Mask<#field_ty>>,
+            }
+        }
+    });
+
+    quote! { (#(#mask_type_arms)*) }
+}
+
+/// The empty mask of a list of fields.
+fn fields_empty_mask(fields: &[MessageField]) -> TokenStream2 {
+    let empty_mask_arms = fields.iter().map(|field| {
+        if field.is_flatten {
+            quote! {
+                ::fieldmask::Mask::empty(),
+            }
+        } else {
+            quote! {
+                ::core::option::Option::None,
+            }
+        }
+    });
+
+    quote! { (#(#empty_mask_arms)*) }
+}
+
+/// The full mask of a list of fields.
+fn fields_full_mask(fields: &[MessageField]) -> TokenStream2 {
+    let full_mask_arms = fields.iter().map(|field| {
+        if field.is_flatten {
+            quote! {
+                ::fieldmask::Mask::full(),
+            }
+        } else if field.key.is_some() {
+            // An empty mask of the elements selects the whole repeated field, so that it's merged
+            // by key as a whole.
+            quote! {
+                ::core::option::Option::Some(::fieldmask::Mask::empty()),
+            }
+        } else {
+            quote! {
+                ::core::option::Option::Some(::fieldmask::Mask::full()),
+            }
+        }
+    });
+
+    quote! { (#(#full_mask_arms)*) }
+}
+
+/// The behaviors declared on a field, e.g. `#[fieldmask(output_only)]`.
+fn field_behavior_list(field: &MessageField) -> Vec<TokenStream2> {
+    let mut behaviors = Vec::new();
+    if field.is_output_only {
+        behaviors.push(quote! { ::fieldmask::FieldBehavior::OutputOnly });
+    }
+    if field.is_immutable {
+        behaviors.push(quote! { ::fieldmask::FieldBehavior::Immutable });
+    }
+    if field.is_required {
+        behaviors.push(quote! { ::fieldmask::FieldBehavior::Required });
+    }
+    behaviors
+}
+
+/// An expression that evaluates to the behaviors of the field at `field_path` in a list of fields.
+fn fields_behaviors(fields: &[MessageField]) -> TokenStream2 {
+    let match_arms = fields
+        .iter()
+        .filter(|field| !field.is_flatten)
+        .map(|field| {
+            let name = &field.name;
+            let ty = field_mask_ty(field);
+            let behaviors = field_behavior_list(field);
+            let own_arm = if behaviors.is_empty() {
+                quote! {}
+            } else {
+                quote! {
+                    [#name] => ::std::vec![#(#behaviors),*],
+                }
+            };
+            quote! {
+                #own_arm
+                [#name, tail @ ..] => <#ty as ::fieldmask::Maskable>::field_behaviors(tail),
+            }
+        });
+
+    // The fields of flattened fields are looked up as if they were fields of the parent.
+    let flatten_types = fields
+        .iter()
+        .filter(|field| field.is_flatten)
+        .map(|field| field.ty)
+        .collect::<Vec<_>>();
+    let fallback = if flatten_types.is_empty() {
+        quote! { ::std::vec::Vec::new() }
+    } else {
+        quote! {{
+            let mut behaviors = ::std::vec::Vec::new();
+            #(
+                if behaviors.is_empty() {
+                    behaviors = <#flatten_types as ::fieldmask::Maskable>::field_behaviors(field_path);
+                }
+            )*
+            behaviors
+        }}
+    };
+
+    quote! {
+        match field_path {
+            #(#match_arms)*
+            _ => #fallback,
+        }
+    }
+}
+
+/// An expression that evaluates to whether the tags of a field are all in `allowed`.
+fn field_tags_permitted(field: &MessageField) -> TokenStream2 {
+    let tags = field.tags;
+    if tags.is_empty() {
+        quote! { true }
+    } else {
+        quote! {
+            [#(#tags),*].iter().all(|tag| allowed.contains(tag))
+        }
+    }
+}
+
+/// An expression that evaluates to whether every field in a list of fields, including nested
+/// ones, is permitted by `allowed`.
+fn fields_tags_permit_all(fields: &[MessageField]) -> TokenStream2 {
+    let permit_arms = fields.iter().map(|field| {
+        let ty = field_mask_ty(field);
+        let tags_permitted = field_tags_permitted(field);
+        quote! {
+            (#tags_permitted && <#ty as ::fieldmask::Maskable>::tags_permit_all(allowed, stack))
+        }
+    });
+
+    quote! { true #(&& #permit_arms)* }
+}
+
+/// A statement that sets the optional sub-mask `place` of type `ty` to select the fields permitted
+/// by `allowed`, if `tags_permitted` evaluates to `true`, and sets `permitted` if any is selected.
+fn permitted_sub_mask(
+    place: &TokenStream2,
+    ty: &TokenStream2,
+    tags_permitted: &TokenStream2,
+) -> TokenStream2 {
+    quote! {
+        if #tags_permitted {
+            if <#ty as ::fieldmask::Maskable>::tags_permit_all(allowed, &mut ::std::vec::Vec::new()) {
+                #place = ::core::option::Option::Some(::fieldmask::Mask::empty());
+                permitted = true;
+            } else {
+                let mut sub_mask = ::fieldmask::Mask::<#ty>::empty();
+                if <#ty as ::fieldmask::Maskable>::make_mask_for_tags(&mut sub_mask, allowed, stack) {
+                    #place = ::core::option::Option::Some(sub_mask);
+                    permitted = true;
+                }
+            }
+        }
+    }
+}
+
+/// Statements that make `mask`, the mask of a list of fields, select the fields permitted by
+/// `allowed`, and set `permitted` if any is selected.
+fn fields_make_mask_for_tags(fields: &[MessageField]) -> TokenStream2 {
+    let field_arms = fields.iter().enumerate().map(|(i, field)| {
+        let index = Index::from(i);
+        let ty = field_mask_ty(field);
+        if field.is_flatten {
+            // A flattened field is always narrowed down, since an empty mask of a flattened field
+            // selects nothing.
+            quote! {
+                if <#ty as ::fieldmask::Maskable>::make_mask_for_tags(&mut mask.#index, allowed, stack) {
+                    permitted = true;
+                }
+            }
+        } else {
+            permitted_sub_mask(&quote! { mask.#index }, &ty, &field_tags_permitted(field))
+        }
+    });
+
+    quote! { #(#field_arms)* }
+}
+
+/// A statement that panics if two of the fields share the same name in field masks.
+///
+/// A path is routed to the first field that accepts it, so a field shadowed by another field with
+/// the same name would be unreachable. When flattened fields are involved, their names are only
+/// known at runtime, so they are checked the first time a mask of the type is created.
+///
+/// A non-generic type is checked until it passes once, with a static of its own. A static in a
+/// generic impl is shared by all of its instantiations, whose flattened fields can differ, so each
+/// instantiation of a generic type is recorded by `assert_unique_field_names_once` instead.
+fn fields_assert_unique_names(
+    fields: &[MessageField],
+    type_name: &TokenStream2,
+    is_generic: bool,
+) -> TokenStream2 {
+    if !fields.iter().any(|field| field.is_flatten) {
+        return quote! {};
+    }
+
+    let field_names = fields_names(fields);
+    if is_generic {
+        quote! {
+            ::fieldmask::assert_unique_field_names_once(
+                ::core::any::type_name::<Self>(),
+                #type_name,
+                || #field_names,
+            );
+        }
+    } else {
+        quote! {
+            {
+                // Not a `Once`, which would be poisoned by the panic and then fail differently.
+                static CHECKED: ::std::sync::atomic::AtomicBool =
+                    ::std::sync::atomic::AtomicBool::new(false);
+                if !CHECKED.load(::std::sync::atomic::Ordering::Relaxed) {
+                    ::fieldmask::assert_unique_field_names(#type_name, &#field_names);
+                    CHECKED.store(true, ::std::sync::atomic::Ordering::Relaxed);
+                }
+            }
+        }
+    }
+}
+
+/// An expression that includes `field_path` in `mask`, which is the mask of a list of fields.
+fn fields_include_field(fields: &[MessageField], type_name: &TokenStream2) -> TokenStream2 {
+    // For each field, generate a match arm that processes a matching field path.
+    let make_mask_include_field_match_arms = fields.iter().enumerate().map(|(i, field)| {
+        let field_index = Index::from(i);
+        // For flatten field, try to make the field parse the mask. If the field is not found, go to
+        // the next match arm.
+        if field.is_flatten {
+            quote! {
+                _ if mask
+                    .#field_index
+                    .include_field(field_path)
+                    .map(|_| true)
+                    .or_else(|e| {
+                        if let ::fieldmask::DeserializeMaskError::FieldNotFound { .. } = e {
+                            ::core::result::Result::Ok(false)
+                        } else {
+                            ::core::result::Result::Err(e)
+                        }
+                    })? =>
+                {
+                    ::core::result::Result::Ok(())
+                }
+            }
+        } else {
+            let field_name = &field.name;
+            let field_ty = field_mask_ty(field);
+            quote! {
+                [#field_name, tail @ ..] => {
+                    mask.#field_index
+                        .get_or_insert_with(|| ::fieldmask::Mask::<#field_ty>::empty())
+                        .include_field(tail)
+                        .map_err(|err| {
+                            ::fieldmask::DeserializeMaskError::InvalidField {
+                                field: #field_name,
+                                err: ::std::boxed::Box::new(err),
+                            }
+                        })?;
+                    ::core::result::Result::Ok(())
+                }
+            }
+        }
+    });
+
+    quote! {
+        {
+            match field_path {
+                [] => ::core::result::Result::Ok(()),
+                #(#make_mask_include_field_match_arms)*
+                [field, ..] => ::core::result::Result::Err(
+                    ::fieldmask::DeserializeMaskError::FieldNotFound {
+                        type_name: #type_name,
+                        field,
+                    }
+                ),
+            }
+        }
+    }
+}
+
+/// An expression that lists the names of a list of fields, including the fields of flattened
+/// children.
+fn fields_names(fields: &[MessageField]) -> TokenStream2 {
+    let push_arms = fields.iter().map(|field| {
+        if field.is_flatten {
+            let ty = field_mask_ty(field);
+            quote! {
+                field_names.extend(<#ty as ::fieldmask::Maskable>::field_names());
+            }
+        } else {
+            let name = &field.name;
+            quote! {
+                field_names.push(#name);
+            }
+        }
+    });
+
+    quote! {
+        {
+            #[allow(unused_mut)]
+            let mut field_names = ::std::vec::Vec::new();
+            #(#push_arms)*
+            field_names
+        }
+    }
+}
+
+/// Statements that collect the paths selected by `mask`, the mask of a list of fields, and evaluate
+/// to them.
+fn fields_mask_paths(fields: &[MessageField]) -> TokenStream2 {
+    let mask_field_paths_arms = fields.iter().enumerate().map(|(i, field)| {
+        let index = Index::from(i);
+        let ty = field_mask_ty(field);
+        if field.is_flatten {
+            quote! {
+                paths.extend(<#ty as ::fieldmask::Maskable>::mask_field_paths(&mask.#index));
+            }
+        } else {
+            prefixed_mask_paths(
+                &field.name,
+                &quote! { &mask.#index },
+                &quote! { <#ty as ::fieldmask::Maskable>::mask_field_paths(mask) },
+            )
+        }
+    });
+
+    quote! {
+        let _ = mask;
+        #[allow(unused_mut)]
+        let mut paths = ::std::vec::Vec::new();
+        #(#mask_field_paths_arms)*
+        paths
+    }
+}
+
+/// A statement that pushes the paths selected by the optional sub-mask `mask` to `paths`, prefixed
+/// by `name`. `sub_paths` are statements that evaluate to the paths selected by the sub-mask.
+fn prefixed_mask_paths(name: &str, mask: &TokenStream2, sub_paths: &TokenStream2) -> TokenStream2 {
+    quote! {
+        if let ::core::option::Option::Some(mask) = #mask {
+            let sub_paths: ::std::vec::Vec<::std::vec::Vec<::std::borrow::Cow<'static, ::core::primitive::str>>> = {
+                #sub_paths
+            };
+            if sub_paths.is_empty() {
+                paths.push(::std::vec![::std::borrow::Cow::Borrowed(#name)]);
+            } else {
+                paths.extend(sub_paths.into_iter().map(|mut path| {
+                    path.insert(0, ::std::borrow::Cow::Borrowed(#name));
+                    path
+                }));
+            }
+        }
+    }
+}
+
+/// The types of the fields of a variant.
+fn variant_types<'a>(variant: &MessageVariant<'a>) -> Vec<&'a Type> {
+    match &variant.kind {
+        MessageVariantKind::Unit => vec![],
+        MessageVariantKind::Tuple(ty) => vec![*ty],
+        MessageVariantKind::Fields(fields) => fields.iter().map(|field| field.ty).collect(),
+    }
+}
+
+/// Items that fail to compile if a flattened field is atomic, e.g. `#[fieldmask(flatten)] a: u32`.
+///
+/// Fields whose types mention a type parameter are not checked, since their types are only known
+/// when the parameters are.
+fn assert_flattenable(
+    generics: &Generics,
+    fields: &[MessageField],
+    variants: &[MessageVariant],
+) -> TokenStream2 {
+    let variant_fields = variants.iter().flat_map(|variant| match &variant.kind {
+        MessageVariantKind::Fields(fields) => fields.as_slice(),
+        MessageVariantKind::Unit | MessageVariantKind::Tuple(_) => &[],
+    });
+    let assertions = fields
+        .iter()
+        .chain(variant_fields)
+        .filter(|field| field.is_flatten && !mentions_type_param(generics, field.ty))
+        .map(|field| {
+            let ty = field_mask_ty(field);
+            quote_spanned! {ty.span()=>
+                let _: fn() = assert_flattenable::<#ty>;
+            }
+        })
+        .collect::<Vec<_>>();
+    if assertions.is_empty() {
+        return quote! {};
+    }
+
+    quote! {
+        const _: () = {
+            fn assert_flattenable<T: ::fieldmask::Flattenable>() {}
+            #(#assertions)*
+        };
+    }
+}
+
+/// The type name reported when a field is not found in a variant of a oneof enum.
+fn variant_type_name(ident: &Ident, variant: &MessageVariant) -> TokenStream2 {
+    let variant_ident = variant.ident;
+    quote! {
+        ::core::concat!(::core::stringify!(#ident), "::", ::core::stringify!(#variant_ident))
+    }
+}
+
+/// Statements that normalize each field at `places`.
+fn fields_normalize(fields: &[MessageField], places: &[TokenStream2]) -> TokenStream2 {
+    let normalize_arms = fields.iter().zip(places).map(|(field, place)| {
+        let ty = field_mask_ty(field);
+        let ops = field_ops(field);
+        quote! {
+            #ops::project(
+                &mut #place,
+                &<#ty as ::fieldmask::Maskable>::empty_mask(),
+                options,
+            );
+        }
+    });
+
+    quote! { #(#normalize_arms)* }
+}
+
+/// Statements that project each field at `places` with `mask`.
+fn fields_project(fields: &[MessageField], places: &[TokenStream2]) -> TokenStream2 {
+    let project_arms = fields
+        .iter()
+        .zip(places)
+        .enumerate()
+        .map(|(i, (field, place))| {
+            let index = Index::from(i);
+            let ty = field_mask_ty(field);
+            let ops = field_ops(field);
+
+            let redact = field_redact(
+                field,
+                place,
+                &quote! { &<#ty as ::fieldmask::Maskable>::empty_mask() },
+            );
+
+            if field.is_flatten {
+                quote! {
+                    if mask.#index == ::fieldmask::Mask::<#ty>::empty() {
+                        if options.redact {
+                            #redact
+                        } else {
+                            #place = ::core::default::Default::default();
+                        }
+                    } else {
+                        #ops::project(&mut #place, &mask.#index, options);
+                    }
+                }
+            } else {
+                quote! {
+                    match mask.#index.as_deref() {
+                        ::core::option::Option::Some(mask) => {
+                            #ops::project(&mut #place, mask, options);
+                        }
+                        ::core::option::Option::None => {
+                            if options.redact {
+                                #redact
+                            } else {
+                                #place = ::core::default::Default::default();
+                            }
+                        }
+                    }
+                }
+            }
+        });
+
+    quote! { #(#project_arms)* }
+}
+
+/// Field initializers that copy each field referenced by `refs`, normalized if requested.
+fn fields_normalize_ref(fields: &[MessageField], refs: &[TokenStream2]) -> TokenStream2 {
+    let normalize_arms = fields.iter().zip(refs).map(|(field, r#ref)| {
+        let member = &field.member;
+        let ty = field_mask_ty(field);
+        let ops = field_ops(field);
+        quote! {
+            #member: #ops::project_ref(
+                #r#ref,
+                &<#ty as ::fieldmask::Maskable>::empty_mask(),
+                options,
+            ),
+        }
+    });
+
+    quote! { #(#normalize_arms)* }
+}
+
+/// Field initializers that copy the parts of each field referenced by `refs` selected by `mask`.
+fn fields_project_ref(fields: &[MessageField], refs: &[TokenStream2]) -> TokenStream2 {
+    let project_arms = fields
+        .iter()
+        .zip(refs)
+        .enumerate()
+        .map(|(i, (field, r#ref))| {
+            let index = Index::from(i);
+            let member = &field.member;
+            let ty = field_mask_ty(field);
+            let ops = field_ops(field);
+
+            let redact = field_redact_ref(field, r#ref);
+
+            if field.is_flatten {
+                quote! {
+                    #member: if mask.#index == ::fieldmask::Mask::<#ty>::empty() {
+                        if options.redact {
+                            #redact
+                        } else {
+                            ::core::default::Default::default()
+                        }
+                    } else {
+                        #ops::project_ref(#r#ref, &mask.#index, options)
+                    },
+                }
+            } else {
+                quote! {
+                    #member: match mask.#index.as_deref() {
+                        ::core::option::Option::Some(mask) => {
+                            #ops::project_ref(#r#ref, mask, options)
+                        }
+                        ::core::option::Option::None if options.redact => #redact,
+                        ::core::option::Option::None => ::core::default::Default::default(),
+                    },
+                }
+            }
+        });
+
+    quote! { #(#project_arms)* }
+}
+
+/// Statements that reset each field at `places` to its default value.
+fn fields_clear_all(fields: &[MessageField], places: &[TokenStream2]) -> TokenStream2 {
+    let clear_arms = fields.iter().zip(places).map(|(field, place)| {
+        let ty = field_mask_ty(field);
+        let ops = field_ops(field);
+        quote! {
+            #ops::clear(
+                &mut #place,
+                &<#ty as ::fieldmask::Maskable>::empty_mask(),
+                options,
+            );
+        }
+    });
+
+    quote! { #(#clear_arms)* }
+}
+
+/// Statements that reset each field at `places` selected by `mask` to its default value.
+fn fields_clear(fields: &[MessageField], places: &[TokenStream2]) -> TokenStream2 {
+    let clear_arms = fields
+        .iter()
+        .zip(places)
+        .enumerate()
+        .map(|(i, (field, place))| {
+            let index = Index::from(i);
+            let ty = field_mask_ty(field);
+            let ops = field_ops(field);
+
+            if field.is_flatten {
+                quote! {
+                    if mask.#index == ::fieldmask::Mask::<#ty>::empty() {
+                        if options.normalize {
+                            #ops::project(
+                                &mut #place,
+                                &<#ty as ::fieldmask::Maskable>::empty_mask(),
+                                options,
+                            );
+                        }
+                    } else {
+                        #ops::clear(&mut #place, &mask.#index, options);
+                    }
+                }
+            } else {
+                quote! {
+                    match mask.#index.as_deref() {
+                        ::core::option::Option::Some(mask) => {
+                            #ops::clear(&mut #place, mask, options);
+                        }
+                        ::core::option::Option::None => {
+                            if options.normalize {
+                                #ops::project(
+                                    &mut #place,
+                                    &<#ty as ::fieldmask::Maskable>::empty_mask(),
+                                    options,
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        });
+
+    quote! { #(#clear_arms)* }
+}
+
+/// An expression that evaluates to the placeholder of a redacted field, given a reference to its
+/// value.
+fn redact_placeholder(redact_with: &RedactWith, r#ref: &TokenStream2) -> TokenStream2 {
+    match redact_with {
+        // Convert string literals, so that they can be used for `String` fields.
+        RedactWith::Value(lit @ Lit::Str(_)) => quote! { ::core::convert::Into::into(#lit) },
+        RedactWith::Value(lit) => quote! { #lit },
+        RedactWith::Function(path) => quote! { #path(#r#ref) },
+    }
+}
+
+/// A statement that redacts the field at `place` with `mask`, an expression that evaluates to the
+/// sub-mask of the field. A field with a placeholder is replaced as a whole.
+fn field_redact(field: &MessageField, place: &TokenStream2, mask: &TokenStream2) -> TokenStream2 {
+    match field.redact_with {
+        Some(redact_with) => {
+            let placeholder = redact_placeholder(redact_with, &quote! { &#place });
+            quote! {
+                #place = #placeholder;
+            }
+        }
+        None => {
+            let ops = field_ops(field);
+            quote! {
+                #ops::redact(&mut #place, #mask);
+            }
+        }
+    }
+}
+
+/// An expression that evaluates to a redacted copy of the field referenced by `ref`.
+fn field_redact_ref(field: &MessageField, r#ref: &TokenStream2) -> TokenStream2 {
+    let ty = field_mask_ty(field);
+    let ops = field_ops(field);
+    match field.redact_with {
+        Some(redact_with) => redact_placeholder(redact_with, r#ref),
+        None => quote! {{
+            let mut value = #ops::project_ref(
+                #r#ref,
+                &<#ty as ::fieldmask::Maskable>::empty_mask(),
+                options,
+            );
+            #ops::redact(
+                &mut value,
+                &<#ty as ::fieldmask::Maskable>::empty_mask(),
+            );
+            value
+        }},
+    }
+}
+
+/// Statements that redact each field at `places`.
+fn fields_redact_all(fields: &[MessageField], places: &[TokenStream2]) -> TokenStream2 {
+    let redact_arms = fields.iter().zip(places).map(|(field, place)| {
+        let ty = field_mask_ty(field);
+        field_redact(
+            field,
+            place,
+            &quote! { &<#ty as ::fieldmask::Maskable>::empty_mask() },
+        )
+    });
+
+    quote! { #(#redact_arms)* }
+}
+
+/// Statements that redact each field at `places` selected by `mask`.
+fn fields_redact(fields: &[MessageField], places: &[TokenStream2]) -> TokenStream2 {
+    let redact_arms = fields
+        .iter()
+        .zip(places)
+        .enumerate()
+        .map(|(i, (field, place))| {
+            let index = Index::from(i);
+            let ty = field_mask_ty(field);
+            let ops = field_ops(field);
+
+            if field.is_flatten {
+                quote! {
+                    if mask.#index != ::fieldmask::Mask::<#ty>::empty() {
+                        #ops::redact(&mut #place, &mask.#index);
+                    }
+                }
+            } else {
+                let redact = field_redact(field, place, &quote! { mask });
+                quote! {
+                    if let ::core::option::Option::Some(mask) = mask.#index.as_deref() {
+                        #redact
+                    }
+                }
+            }
+        });
+
+    quote! { #(#redact_arms)* }
+}
+
+/// An expression that compares each field referenced by `this_refs` with the one referenced by
+/// `other_refs` as a whole.
+fn fields_eq_masked_all(
+    fields: &[MessageField],
+    this_refs: &[TokenStream2],
+    other_refs: &[TokenStream2],
+) -> TokenStream2 {
+    let eq_arms =
+        fields
+            .iter()
+            .zip(this_refs.iter().zip(other_refs))
+            .map(|(field, (this, other))| {
+                let ty = field_mask_ty(field);
+                let ops = field_ops(field);
+                quote! {
+                    #ops::eq_masked(
+                        #this,
+                        #other,
+                        &<#ty as ::fieldmask::Maskable>::empty_mask(),
+                    )
+                }
+            });
+
+    quote! { true #(&& #eq_arms)* }
+}
+
+/// An expression that compares the fields referenced by `this_refs` with the ones referenced by
+/// `other_refs` according to `mask`.
+fn fields_eq_masked(
+    fields: &[MessageField],
+    this_refs: &[TokenStream2],
+    other_refs: &[TokenStream2],
+) -> TokenStream2 {
+    let eq_arms = fields
+        .iter()
+        .zip(this_refs.iter().zip(other_refs))
+        .enumerate()
+        .map(|(i, (field, (this, other)))| {
+            let index = Index::from(i);
+            let ty = field_mask_ty(field);
+            let ops = field_ops(field);
+
+            if field.is_flatten {
+                quote! {
+                    (mask.#index == ::fieldmask::Mask::<#ty>::empty()
+                        || #ops::eq_masked(#this, #other, &mask.#index))
+                }
+            } else {
+                quote! {
+                    mask.#index.as_deref().is_none_or(|mask| {
+                        #ops::eq_masked(#this, #other, mask)
+                    })
+                }
+            }
+        });
+
+    quote! { true #(&& #eq_arms)* }
+}
+
+/// Statements that visit each field referenced by `refs` as a whole.
+fn fields_visit_all(fields: &[MessageField], refs: &[TokenStream2]) -> TokenStream2 {
+    let visit_arms = fields.iter().zip(refs).map(|(field, r#ref)| {
+        let ty = field_mask_ty(field);
+        let ops = field_ops(field);
+        let visit = quote! {
+            #ops::visit(
+                #r#ref,
+                &<#ty as ::fieldmask::Maskable>::empty_mask(),
+                path,
+                visitor,
+            );
+        };
+
+        // The fields of a flattened field are visited as if they were fields of the parent.
+        if field.is_flatten {
+            visit
+        } else {
+            let name = &field.name;
+            quote! {
+                path.push(#name);
+                #visit
+                path.pop();
+            }
+        }
+    });
+
+    quote! { #(#visit_arms)* }
+}
+
+/// Statements that visit the fields referenced by `refs` according to `mask`.
+fn fields_visit(fields: &[MessageField], refs: &[TokenStream2]) -> TokenStream2 {
+    let visit_arms = fields
+        .iter()
+        .zip(refs)
+        .enumerate()
+        .map(|(i, (field, r#ref))| {
+            let index = Index::from(i);
+            let ty = field_mask_ty(field);
+            let ops = field_ops(field);
+
+            if field.is_flatten {
+                quote! {
+                    if mask.#index != ::fieldmask::Mask::<#ty>::empty() {
+                        #ops::visit(#r#ref, &mask.#index, path, visitor);
+                    }
+                }
+            } else {
+                let name = &field.name;
+                quote! {
+                    if let ::core::option::Option::Some(mask) = mask.#index.as_deref() {
+                        path.push(#name);
+                        #ops::visit(#r#ref, mask, path, visitor);
+                        path.pop();
+                    }
+                }
+            }
+        });
+
+    quote! { #(#visit_arms)* }
+}
+
+/// Statements that run the validator of the field referenced by `ref`, if it has one. `path` must
+/// already contain the name of the field.
+fn field_run_validator(field: &MessageField, r#ref: &TokenStream2) -> TokenStream2 {
+    match field.validate {
+        Some(validate) => quote! {
+            if let ::core::result::Result::Err(err) = #validate(#r#ref) {
+                return ::core::result::Result::Err(::fieldmask::ValidationError {
+                    path: path.join("."),
+                    message: ::std::string::ToString::to_string(&err),
+                });
+            }
+        },
+        None => quote! {},
+    }
+}
+
+/// Statements that validate each field referenced by `refs` as a whole.
+fn fields_validate_all(fields: &[MessageField], refs: &[TokenStream2]) -> TokenStream2 {
+    let validate_arms = fields.iter().zip(refs).map(|(field, r#ref)| {
+        let ty = field_mask_ty(field);
+        let ops = field_ops(field);
+        let validate = quote! {
+            #ops::validate(
+                #r#ref,
+                &<#ty as ::fieldmask::Maskable>::empty_mask(),
+                path,
+            )?;
+        };
+
+        // The fields of a flattened field are validated as if they were fields of the parent.
+        if field.is_flatten {
+            validate
+        } else {
+            let name = &field.name;
+            let run_validator = field_run_validator(field, r#ref);
+            quote! {
+                path.push(#name);
+                #run_validator
+                #validate
+                path.pop();
+            }
+        }
+    });
+
+    quote! { #(#validate_arms)* }
+}
+
+/// Statements that validate the fields referenced by `refs` according to `mask`. The validator of
+/// a field runs if any part of the field is selected.
+fn fields_validate(fields: &[MessageField], refs: &[TokenStream2]) -> TokenStream2 {
+    let validate_arms = fields
+        .iter()
+        .zip(refs)
+        .enumerate()
+        .map(|(i, (field, r#ref))| {
+            let index = Index::from(i);
+            let ty = field_mask_ty(field);
+            let ops = field_ops(field);
+
+            if field.is_flatten {
+                quote! {
+                    if mask.#index != ::fieldmask::Mask::<#ty>::empty() {
+                        #ops::validate(#r#ref, &mask.#index, path)?;
+                    }
+                }
+            } else {
+                let name = &field.name;
+                let run_validator = field_run_validator(field, r#ref);
+                quote! {
+                    if let ::core::option::Option::Some(mask) = mask.#index.as_deref() {
+                        path.push(#name);
+                        #run_validator
+                        #ops::validate(#r#ref, mask, path)?;
+                        path.pop();
+                    }
+                }
+            }
+        });
+
+    quote! { #(#validate_arms)* }
+}
+
+/// Statements that check the required fields referenced by `refs`, and the ones nested in `refs`,
+/// as a whole. `path` must already contain the name of the field.
+fn field_check_required(
+    field: &MessageField,
+    r#ref: &TokenStream2,
+    mask: TokenStream2,
+) -> TokenStream2 {
+    let ops = field_ops(field);
+    let check = quote! {
+        #ops::check_required(#r#ref, #mask, path, missing);
+    };
+    if field.is_required {
+        let is_default = field_is_default(field, r#ref);
+        quote! {
+            if #is_default {
+                missing.push(path.join("."));
+            } else {
+                #check
+            }
+        }
+    } else {
+        check
+    }
+}
+
+/// Statements that check the required fields referenced by `refs` as a whole.
+fn fields_check_required_all(fields: &[MessageField], refs: &[TokenStream2]) -> TokenStream2 {
+    let check_arms = fields.iter().zip(refs).map(|(field, r#ref)| {
+        let ty = field_mask_ty(field);
+        let check = field_check_required(
+            field,
+            r#ref,
+            quote! { &<#ty as ::fieldmask::Maskable>::empty_mask() },
+        );
+
+        // The fields of a flattened field are checked as if they were fields of the parent.
+        if field.is_flatten {
+            check
+        } else {
+            let name = &field.name;
+            quote! {
+                path.push(#name);
+                #check
+                path.pop();
+            }
+        }
+    });
+
+    quote! { #(#check_arms)* }
+}
+
+/// Statements that check the required fields referenced by `refs` according to `mask`.
+fn fields_check_required(fields: &[MessageField], refs: &[TokenStream2]) -> TokenStream2 {
+    let check_arms = fields
+        .iter()
+        .zip(refs)
+        .enumerate()
+        .map(|(i, (field, r#ref))| {
+            let index = Index::from(i);
+            let ty = field_mask_ty(field);
+            let ops = field_ops(field);
+
+            if field.is_flatten {
+                quote! {
+                    if mask.#index != ::fieldmask::Mask::<#ty>::empty() {
+                        #ops::check_required(#r#ref, &mask.#index, path, missing);
+                    }
+                }
+            } else {
+                let name = &field.name;
+                let check = field_check_required(field, r#ref, quote! { mask });
+                quote! {
+                    if let ::core::option::Option::Some(mask) = mask.#index.as_deref() {
+                        path.push(#name);
+                        #check
+                        path.pop();
+                    }
+                }
+            }
+        });
+
+    quote! { #(#check_arms)* }
+}
+
+/// Statements that hash each field referenced by `refs` as a whole.
+fn fields_hash_masked_all(fields: &[MessageField], refs: &[TokenStream2]) -> TokenStream2 {
+    let hash_arms = fields.iter().zip(refs).map(|(field, r#ref)| {
+        let ty = field_mask_ty(field);
+        let hash_ops = field_hash_ops(field);
+        quote! {
+            #hash_ops::hash_masked(
+                #r#ref,
+                &<#ty as ::fieldmask::Maskable>::empty_mask(),
+                state,
+            );
+        }
+    });
+
+    quote! { #(#hash_arms)* }
+}
+
+/// Statements that hash the fields referenced by `refs` according to `mask`.
+fn fields_hash_masked(fields: &[MessageField], refs: &[TokenStream2]) -> TokenStream2 {
+    let hash_arms = fields
+        .iter()
+        .zip(refs)
+        .enumerate()
+        .map(|(i, (field, r#ref))| {
+            let index = Index::from(i);
+            let ty = field_mask_ty(field);
+            let hash_ops = field_hash_ops(field);
+
+            if field.is_flatten {
+                quote! {
+                    if mask.#index != ::fieldmask::Mask::<#ty>::empty() {
+                        #hash_ops::hash_masked(#r#ref, &mask.#index, state);
+                    }
+                }
+            } else {
+                quote! {
+                    if let ::core::option::Option::Some(mask) = mask.#index.as_deref() {
+                        #hash_ops::hash_masked(#r#ref, mask, state);
+                    }
+                }
+            }
+        });
+
+    quote! { #(#hash_arms)* }
+}
+
+/// Statements that update each field at `places` with `sources` according to `mask`.
+///
+/// If `reporting` is true, the fields are updated with `SelfMaskable::update_as_field_reporting`,
+/// and the masks of the modified fields are stored in `changed`.
+fn fields_update(
+    fields: &[MessageField],
+    places: &[TokenStream2],
+    sources: &[TokenStream2],
+    reporting: bool,
+) -> TokenStream2 {
+    let update_arms = fields
+        .iter()
+        .zip(places.iter().zip(sources))
+        .enumerate()
+        .map(|(i, (field, (place, source)))| {
+            let index = Index::from(i);
+
+            let override_options = field_override_options(field);
+            let update = report_change(
+                field,
+                &index,
+                reporting,
+                quote! { update_as_field },
+                quote! { (&mut #place, #source, mask, options) },
+            );
+
+            // An empty mask of a flattened field selects none of its fields, rather than all of
+            // them.
+            if field.is_flatten {
+                let ty = field_mask_ty(field);
+                quote! {
+                    if mask.#index != ::fieldmask::Mask::<#ty>::empty() {
+                        #override_options
+                        let mask = &mask.#index;
+                        #update
+                    }
+                }
+            } else {
+                let update = guard_restricted_update(
+                    field,
+                    place,
+                    merge_by_key_or(
+                        field,
+                        &index,
+                        place,
+                        source,
+                        quote! { &**mask },
+                        reporting,
+                        update,
+                    ),
+                );
+                quote! {
+                    if let ::core::option::Option::Some(mask) = &mask.#index {
+                        #override_options
+                        #update
+                    }
+                }
+            }
+        });
+
+    quote! { #(#update_arms)* }
+}
+
+/// Statements that merge each field from `sources` into `places`.
+///
+/// If `reporting` is true, the masks of the modified fields are stored in `changed`, the same way
+/// `fields_update` does.
+fn fields_merge(
+    fields: &[MessageField],
+    places: &[TokenStream2],
+    sources: &[TokenStream2],
+    reporting: bool,
+) -> TokenStream2 {
+    let merge_arms = fields
+        .iter()
+        .zip(places.iter().zip(sources))
+        .enumerate()
+        .map(|(i, (field, (place, source)))| {
+            let index = Index::from(i);
+            let mask_ty = field_mask_ty(field);
+            let merge = report_change(
+                field,
+                &index,
+                reporting,
+                quote! { merge },
+                quote! { (&mut #place, #source, options) },
+            );
+            let merge = guard_restricted_update(
+                field,
+                place,
+                merge_by_key_or(
+                    field,
+                    &index,
+                    place,
+                    source,
+                    quote! { &<#mask_ty as ::fieldmask::Maskable>::empty_mask() },
+                    reporting,
+                    merge,
+                ),
+            );
+            if field.replace_repeated.is_none() && field.replace_message.is_none() {
+                return merge;
+            }
+            let override_options = field_override_options(field);
+            quote! {{
+                #override_options
+                #merge
+            }}
+        });
+
+    quote! { #(#merge_arms)* }
+}
+
+/// A statement that calls `SelfMaskable::#method` with `args`, or its reporting variant that stores
+/// the mask of the modified field at `index` in `changed` if `reporting` is true.
+fn report_change(
+    field: &MessageField,
+    index: &Index,
+    reporting: bool,
+    method: TokenStream2,
+    args: TokenStream2,
+) -> TokenStream2 {
+    let ops = field_ops(field);
+    if !reporting {
+        return quote! { #ops::#method #args; };
+    }
+
+    let method = format_ident!("{}_reporting", method.to_string());
+    if field.is_flatten {
+        quote! {
+            changed.#index = #ops::#method #args
+                .map(::fieldmask::Mask::new)
+                .unwrap_or_else(::fieldmask::Mask::empty);
+        }
+    } else {
+        quote! {
+            changed.#index = #ops::#method #args.map(::fieldmask::Mask::new);
+        }
+    }
+}
+
+/// Wraps `update`, statements that update the field at `place` with `source`, so that a repeated
+/// field declared with `#[fieldmask(key = "...")]` is merged by key with
+/// `RepeatedStrategy::MergeByKey`. The matched elements are updated with `mask`, an expression that
+/// evaluates to the mask of the elements.
+///
+/// If `reporting` is true, the mask of the field is stored in `changed` when it's modified by the
+/// merge.
+fn merge_by_key_or(
+    field: &MessageField,
+    index: &Index,
+    place: &TokenStream2,
+    source: &TokenStream2,
+    mask: TokenStream2,
+    reporting: bool,
+    update: TokenStream2,
+) -> TokenStream2 {
+    let Some(key) = field.key else {
+        return update;
+    };
+    let merge = quote! {
+        ::fieldmask::merge_by_key(&mut #place, #source, |element| &element.#key, #mask, options);
+    };
+    let merge = if reporting {
+        // Elements are matched by key, so the repeated field is compared as a whole.
+        let ty = field_mask_ty(field);
+        quote! {
+            let original = ::fieldmask::KeyedRepeated::project_ref(
+                &#place,
+                &<#ty as ::fieldmask::Maskable>::empty_mask(),
+                &::core::default::Default::default(),
+            );
+            #merge
+            changed.#index =
+                ::fieldmask::KeyedRepeated::diff(&original, &#place).map(::fieldmask::Mask::new);
+        }
+    } else {
+        merge
+    };
+    quote! {
+        if !options.replace_repeated
+            && options.repeated_strategy == ::fieldmask::RepeatedStrategy::MergeByKey
+        {
+            #merge
+        } else {
+            #update
+        }
+    }
+}
+
+/// A statement that shadows `options` with the options overridden by the field attributes, e.g.
+/// `#[fieldmask(replace_repeated)]`, if any.
+fn field_override_options(field: &MessageField) -> TokenStream2 {
+    let replace_repeated = field
+        .replace_repeated
+        .map(|value| quote! { .with_replace_repeated(#value) });
+    let replace_message = field
+        .replace_message
+        .map(|value| quote! { .with_replace_message(#value) });
+    if replace_repeated.is_none() && replace_message.is_none() {
+        return quote! {};
+    }
+    quote! {
+        let options = &options #replace_repeated #replace_message;
+    }
+}
+
+/// Statements that replace each field at `places` with the value from `sources`.
+///
+/// If `reporting` is true, the masks of the modified fields are stored in `changed`, the same way
+/// `fields_update` does.
+fn fields_replace(
+    fields: &[MessageField],
+    places: &[TokenStream2],
+    sources: &[TokenStream2],
+    reporting: bool,
+) -> TokenStream2 {
+    let replace_arms = fields
+        .iter()
+        .zip(places.iter().zip(sources))
+        .enumerate()
+        .map(|(i, (field, (place, source)))| {
+            let replace = if reporting {
+                let index = Index::from(i);
+                let ops = field_ops(field);
+                let diff = if field.is_flatten {
+                    quote! {
+                        #ops::diff(&#place, &#source)
+                            .map(::fieldmask::Mask::new)
+                            .unwrap_or_else(::fieldmask::Mask::empty)
+                    }
+                } else {
+                    quote! {
+                        #ops::diff(&#place, &#source).map(::fieldmask::Mask::new)
+                    }
+                };
+                quote! {
+                    changed.#index = #diff;
+                    #place = #source;
+                }
+            } else {
+                quote! { #place = #source; }
+            };
+            guard_restricted_update(field, place, replace)
+        });
+
+    quote! { #(#replace_arms)* }
+}
+
+/// Wraps `update`, statements that update the field at `place`, so that they only run if
+/// `options.field_behavior_policy` allows updating the field. Output-only fields are never updated,
+/// and immutable fields are only updated while they are unset.
+fn guard_restricted_update(
+    field: &MessageField,
+    place: &TokenStream2,
+    update: TokenStream2,
+) -> TokenStream2 {
+    let allow = quote! {
+        options.field_behavior_policy == ::fieldmask::FieldBehaviorPolicy::Allow
+    };
+    if field.is_output_only {
+        quote! {
+            if #allow {
+                #update
+            }
+        }
+    } else if field.is_immutable {
+        let is_default = field_is_default(field, &quote! { &#place });
+        quote! {
+            if #allow || #is_default {
+                #update
+            }
+        }
+    } else {
+        update
+    }
+}
+
+/// An expression that evaluates to whether the field referenced by `ref` has its default value. An
+/// optional message that is `None` is the same as its default value.
+fn field_is_default(field: &MessageField, r#ref: &TokenStream2) -> TokenStream2 {
+    let ty = field.ty;
+    quote! {
+        ::fieldmask::SelfMaskable::eq_masked(
+            #r#ref,
+            &<#ty as ::core::default::Default>::default(),
+            &<#ty as ::fieldmask::Maskable>::empty_mask(),
+        )
+    }
+}
+
+/// Whether any of the fields is output only or immutable.
+fn has_restricted_fields(fields: &[MessageField]) -> bool {
+    fields
+        .iter()
+        .any(|field| field.is_output_only || field.is_immutable)
+}
+
+/// Statements that reset the output-only fields at `places` to their default values, and clear the
+/// output-only fields nested in the other fields.
+fn fields_clear_output_only(fields: &[MessageField], places: &[TokenStream2]) -> TokenStream2 {
+    let clear_arms = fields.iter().zip(places).map(|(field, place)| {
+        if field.is_output_only {
+            quote! {
+                #place = ::core::default::Default::default();
+            }
+        } else {
+            quote! {
+                ::fieldmask::SelfMaskable::clear_output_only(&mut #place);
+            }
+        }
+    });
+
+    quote! { #(#clear_arms)* }
+}
+
+/// The mask that selects the fields that differ between `this` and `other`, which are references
+/// to the fields.
+fn fields_diff(
+    fields: &[MessageField],
+    this: &[TokenStream2],
+    other: &[TokenStream2],
+) -> TokenStream2 {
+    let diff_arms = fields
+        .iter()
+        .zip(this.iter().zip(other))
+        .map(|(field, (this, other))| {
+            let ops = field_ops(field);
+            if field.is_flatten {
+                quote! {
+                    #ops::diff(#this, #other)
+                        .map(::fieldmask::Mask::new)
+                        .unwrap_or_else(::fieldmask::Mask::empty),
+                }
+            } else {
+                quote! {
+                    #ops::diff(#this, #other).map(::fieldmask::Mask::new),
+                }
+            }
+        });
+
+    quote! { (#(#diff_arms)*) }
+}
+
+/// Statements that update the fields of a struct-like variant, mirroring
+/// `SelfMaskable::update_as_field` of a struct.
+fn variant_fields_update(
+    fields: &[MessageField],
+    places: &[TokenStream2],
+    sources: &[TokenStream2],
+) -> TokenStream2 {
+    let empty_mask = fields_empty_mask(fields);
+    let merge = fields_merge(fields, places, sources, false);
+    let update = fields_update(fields, places, sources, false);
+    let replace = fields_replace(fields, places, sources, false);
+
+    quote! {
+        if mask == &#empty_mask {
+            if options.replace_message {
+                #replace
+            } else {
+                #merge
+            }
+        } else {
+            #update
+        }
+    }
+}
+
+/// The names that the fields of a struct-like variant are bound to.
+fn variant_fields_bindings(fields: &[MessageField], prefix: &str) -> Vec<Ident> {
+    fields
+        .iter()
+        .map(|field| match &field.member {
+            Member::Named(ident) => format_ident!("{}_{}", prefix, ident.unraw()),
+            Member::Unnamed(index) => format_ident!("{}_{}", prefix, index.index),
+        })
+        .collect()
+}
+
+/// The places of the fields of a struct-like variant bound by `variant_fields_pattern` with the
+/// `this` prefix.
+fn variant_fields_places(fields: &[MessageField]) -> Vec<TokenStream2> {
+    variant_fields_bindings(fields, "this")
+        .into_iter()
+        .map(|binding| quote! { (*#binding) })
+        .collect()
+}
+
+/// The references to the fields of a struct-like variant bound by `variant_fields_pattern` with
+/// `prefix`.
+fn variant_fields_refs(fields: &[MessageField], prefix: &str) -> Vec<TokenStream2> {
+    variant_fields_bindings(fields, prefix)
+        .into_iter()
+        .map(|binding| quote! { #binding })
+        .collect()
+}
+
+/// A pattern (or expression) of a struct-like variant with each field bound to a name.
+fn variant_fields_pattern(
+    variant_ident: &Ident,
+    fields: &[MessageField],
+    prefix: &str,
+) -> TokenStream2 {
+    let members = fields.iter().map(|field| &field.member);
+    let bindings = variant_fields_bindings(fields, prefix);
+    quote! {
+        Self::#variant_ident { #(#members: #bindings),* }
+    }
 }
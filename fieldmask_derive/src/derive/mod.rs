@@ -1,4 +1,5 @@
 mod ast;
+mod bound;
 mod derive_impls;
 
 pub use derive_impls::*;
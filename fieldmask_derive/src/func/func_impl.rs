@@ -15,6 +15,8 @@ pub fn maskable_atomic_impl(input: TokenStream) -> TokenStream {
     } = parse_macro_input!(input);
 
     let (impl_generics, _ty_generics, where_clauses) = generics.split_for_impl();
+    // A custom update reports its changes with the default implementation, which compares the
+    // value before and after the update.
     let update_as_field_fn = update_as_field_fn
         .map(|item| item.to_token_stream())
         .unwrap_or_else(|| {
@@ -27,6 +29,19 @@ pub fn maskable_atomic_impl(input: TokenStream) -> TokenStream {
                 ) {
                     *self = source;
                 }
+
+                fn update_as_field_reporting(
+                    &mut self,
+                    source: Self,
+                    _mask: &<Self as ::fieldmask::Maskable>::Mask,
+                    _options: &::fieldmask::UpdateOptions,
+                ) -> ::core::option::Option<<Self as ::fieldmask::Maskable>::Mask> {
+                    if *self == source {
+                        return ::core::option::Option::None;
+                    }
+                    *self = source;
+                    ::core::option::Option::Some(())
+                }
             }
         });
     let merge_fn = merge_fn
@@ -38,10 +53,22 @@ pub fn maskable_atomic_impl(input: TokenStream) -> TokenStream {
                     source: Self,
                     _options: &::fieldmask::UpdateOptions,
                 ) {
-                    if source != ::core::default::Default::default() {
+                    if source != <Self as ::core::default::Default>::default() {
                         *self = source;
                     }
                 }
+
+                fn merge_reporting(
+                    &mut self,
+                    source: Self,
+                    _options: &::fieldmask::UpdateOptions,
+                ) -> ::core::option::Option<<Self as ::fieldmask::Maskable>::Mask> {
+                    if *self == source || source == <Self as ::core::default::Default>::default() {
+                        return ::core::option::Option::None;
+                    }
+                    *self = source;
+                    ::core::option::Option::Some(())
+                }
             }
         });
     let option_project_fn = option_project_fn
@@ -89,8 +116,44 @@ pub fn maskable_atomic_impl(input: TokenStream) -> TokenStream {
         {
             fn project(&mut self, _mask: &Self::Mask, _options: &::fieldmask::ProjectOptions) {}
 
+            fn project_ref(
+                &self,
+                _mask: &Self::Mask,
+                _options: &::fieldmask::ProjectOptions,
+            ) -> Self {
+                ::core::clone::Clone::clone(self)
+            }
+
+            fn clear(&mut self, _mask: &Self::Mask, _options: &::fieldmask::ProjectOptions) {
+                *self = ::core::default::Default::default();
+            }
+
             #update_as_field_fn
             #merge_fn
+
+            fn diff(
+                &self,
+                other: &Self,
+            ) -> ::core::option::Option<<Self as ::fieldmask::Maskable>::Mask> {
+                if self != other {
+                    ::core::option::Option::Some(())
+                } else {
+                    ::core::option::Option::None
+                }
+            }
+
+            fn eq_masked(&self, other: &Self, _mask: &Self::Mask) -> bool {
+                self == other
+            }
+
+            fn visit(
+                &self,
+                _mask: &Self::Mask,
+                path: &mut ::std::vec::Vec<&'static ::core::primitive::str>,
+                visitor: &mut dyn ::fieldmask::MaskVisitor,
+            ) {
+                visitor.visit_field(path, ::fieldmask::FieldRef::new(self));
+            }
         }
 
         impl #impl_generics ::fieldmask::OptionMaskable for #ty
@@ -98,6 +161,24 @@ pub fn maskable_atomic_impl(input: TokenStream) -> TokenStream {
         {
             #option_project_fn
 
+            fn option_project_ref(
+                this: ::core::option::Option<&Self>,
+                mask: &<Self as ::fieldmask::Maskable>::Mask,
+                options: &::fieldmask::ProjectOptions,
+            ) -> ::core::option::Option<Self> {
+                let mut this = this.cloned();
+                <Self as ::fieldmask::OptionMaskable>::option_project(&mut this, mask, options);
+                this
+            }
+
+            fn option_clear(
+                this: &mut ::core::option::Option<Self>,
+                _mask: &<Self as ::fieldmask::Maskable>::Mask,
+                _options: &::fieldmask::ProjectOptions,
+            ) {
+                *this = ::core::option::Option::None;
+            }
+
             fn option_update_as_field(
                 this: &mut ::core::option::Option<Self>,
                 source: ::core::option::Option<Self>,
@@ -139,6 +220,82 @@ pub fn maskable_atomic_impl(input: TokenStream) -> TokenStream {
                     }
                 }
             }
+
+            fn option_update_as_field_reporting(
+                this: &mut ::core::option::Option<Self>,
+                source: ::core::option::Option<Self>,
+                mask: &<Self as ::fieldmask::Maskable>::Mask,
+                options: &::fieldmask::UpdateOptions,
+            ) -> ::core::option::Option<<Self as ::fieldmask::Maskable>::Mask> {
+                match (this.as_mut(), source) {
+                    (::core::option::Option::Some(this), ::core::option::Option::Some(source)) => {
+                        ::fieldmask::SelfMaskable::update_as_field_reporting(this, source, mask, options)
+                    }
+                    (::core::option::Option::Some(this), ::core::option::Option::None) => {
+                        ::fieldmask::SelfMaskable::update_as_field_reporting(
+                            this,
+                            ::core::default::Default::default(),
+                            mask,
+                            options,
+                        )
+                    }
+                    (::core::option::Option::None, ::core::option::Option::Some(mut source)) => {
+                        ::fieldmask::SelfMaskable::project(&mut source, mask, &::core::default::Default::default());
+                        *this = ::core::option::Option::Some(source);
+                        ::core::option::Option::Some(())
+                    }
+                    (::core::option::Option::None, ::core::option::Option::None) => {
+                        ::core::option::Option::None
+                    }
+                }
+            }
+
+            fn option_merge_reporting(
+                this: &mut ::core::option::Option<Self>,
+                source: ::core::option::Option<Self>,
+                options: &::fieldmask::UpdateOptions,
+            ) -> ::core::option::Option<<Self as ::fieldmask::Maskable>::Mask> {
+                match (this.as_mut(), source) {
+                    (::core::option::Option::Some(this), ::core::option::Option::Some(source)) => {
+                        ::fieldmask::SelfMaskable::merge_reporting(this, source, options)
+                    }
+                    (_, ::core::option::Option::None) => ::core::option::Option::None,
+                    (::core::option::Option::None, source) => {
+                        *this = source;
+                        ::core::option::Option::Some(())
+                    }
+                }
+            }
+
+            fn option_diff(
+                this: ::core::option::Option<&Self>,
+                other: ::core::option::Option<&Self>,
+            ) -> ::core::option::Option<<Self as ::fieldmask::Maskable>::Mask> {
+                if this != other {
+                    ::core::option::Option::Some(())
+                } else {
+                    ::core::option::Option::None
+                }
+            }
+
+            fn option_eq_masked(
+                this: ::core::option::Option<&Self>,
+                other: ::core::option::Option<&Self>,
+                _mask: &<Self as ::fieldmask::Maskable>::Mask,
+            ) -> bool {
+                this == other
+            }
+
+            fn option_visit(
+                this: ::core::option::Option<&Self>,
+                _mask: &<Self as ::fieldmask::Maskable>::Mask,
+                path: &mut ::std::vec::Vec<&'static ::core::primitive::str>,
+                visitor: &mut dyn ::fieldmask::MaskVisitor,
+            ) {
+                if let ::core::option::Option::Some(this) = this {
+                    visitor.visit_field(path, ::fieldmask::FieldRef::new(this));
+                }
+            }
         }
     }
     .into()
@@ -1,7 +1,10 @@
 mod derive;
 mod func;
 
-use derive::{derive_maskable_impl, derive_option_maskable_impl, derive_self_maskable_impl};
+use derive::{
+    derive_maskable_impl, derive_masked_hash_impl, derive_option_maskable_impl,
+    derive_option_masked_hash_impl, derive_self_maskable_impl,
+};
 use func::maskable_atomic_impl;
 use proc_macro::TokenStream;
 
@@ -9,9 +12,27 @@ use proc_macro::TokenStream;
 ///
 /// The type must be one of the following types:
 /// - A unit-like enum.
-/// - An enum where each variant has exactly one unnamed associated field. The associated field must
-///   implement `Maskable`.
+/// - A oneof enum, where each variant is either a unit variant, a tuple variant with exactly one
+///   field, or a variant with named (or several unnamed) fields. The type of each associated field
+///   must implement `Maskable`. Fields of a struct-like variant are addressed under the variant
+///   name (e.g. `shape.circle.radius`).
 /// - A struct with named fields, where the type of each field must implement `Maskable`.
+/// - A tuple struct, where the type of each field must implement `Maskable`. Fields are referred to
+///   by their index (e.g. `0`, `1`) unless they are renamed with `#[fieldmask(rename = "name")]`.
+///
+/// A struct with exactly one field can be marked with `#[fieldmask(transparent)]`. A transparent
+/// struct uses the mask of its field directly, the same way `Box<T>` does.
+///
+/// # Generics
+/// For generic types, every field type that mentions a type parameter is required to implement the
+/// derived trait (e.g. `Vec<T>: Maskable`). Fields that refer to the type itself are skipped. Use
+/// `#[fieldmask(bound = "T: MyBound")]` on the struct or the enum to replace the inferred bounds of
+/// all derives.
+///
+/// # Tags
+/// A field can be tagged with `#[fieldmask(tags("pii", "admin"))]`. `Mask::for_tags` selects only
+/// the fields whose tags are all allowed, which is useful to build role-based masks. Flattened
+/// fields cannot be tagged.
 #[proc_macro_derive(Maskable, attributes(fieldmask))]
 pub fn derive_maskable(input: TokenStream) -> TokenStream {
     derive_maskable_impl(input)
@@ -21,9 +42,11 @@ pub fn derive_maskable(input: TokenStream) -> TokenStream {
 ///
 /// The type must be one of the following types:
 /// - A unit-like enum.
-/// - An enum where each variant has exactly one unnamed associated field. The associated field must
-///   implement `SelfMaskable` and `Default`.
+/// - A oneof enum, where each variant is either a unit variant, a tuple variant with exactly one
+///   field, or a variant with named (or several unnamed) fields. The type of each associated field
+///   must implement `SelfMaskable` and `Default`.
 /// - A struct that implements `Default`, `PartialEq` and `SelfMaskable`.
+/// - A transparent struct, where the type of its field implements `OptionMaskable`.
 ///
 /// # Caveats:
 /// When deriving `OptionMaskable` for a unit enum that implements `Default`, you should consider
@@ -43,22 +66,93 @@ pub fn derive_option_maskable(input: TokenStream) -> TokenStream {
 /// - A unit-like enum that implements `Default` and `PartialEqual`.
 /// - A struct with named fields, where the type of each field must implement `Default`,
 ///   `PartialEqual`, and `SelfMaskable`.
+/// - A tuple struct, where the type of each field must implement `Default`, `PartialEqual`, and
+///   `SelfMaskable`.
+/// - A transparent struct, where the type of its field implements `SelfMaskable`.
+///
+/// # Validation
+/// A field of a struct or a struct-like variant can be marked with `#[fieldmask(validate = path)]`,
+/// where `path` is a function that takes a reference to the field and returns a `Result<(), E>`
+/// with `E: Display`. The function is called by `SelfMaskable::validate` (e.g. in
+/// `Mask::try_update`) when the field is selected by the mask. Flattened fields cannot be
+/// validated.
+///
+/// # Field behaviors
+/// A field can be marked with `#[fieldmask(output_only)]` or `#[fieldmask(immutable)]`. Updates
+/// leave output-only fields unchanged, and immutable fields unchanged once they are set (i.e. not
+/// equal to their default values), according to `UpdateOptions::field_behavior_policy`. The type
+/// of an immutable field must implement `Default`.
+///
+/// A field can also be marked with `#[fieldmask(required)]`, so that `Mask::check_required`
+/// reports it when it has its default value. The type of a required field must implement
+/// `Default`. Flattened fields cannot be marked with any field behavior.
+///
+/// # Redaction
+/// A field can be given a placeholder with `#[fieldmask(redact_with = "***")]`, or with
+/// `#[fieldmask(redact_with = path)]`, where `path` is a function that takes a reference to the
+/// field and returns its placeholder. String literals are converted into the type of the field
+/// with `Into`. `Mask::redact`, and projections with `ProjectOptions::redact`, replace the field
+/// with its placeholder instead of resetting it. Flattened fields cannot be redacted.
+///
+/// # Update options
+/// A field can override the options of updates with `#[fieldmask(replace_repeated)]` and
+/// `#[fieldmask(replace_message)]`, or `#[fieldmask(replace_repeated = false)]` and
+/// `#[fieldmask(replace_message = false)]`. The overrides apply to the field and the fields nested
+/// in it, unless they are overridden again.
+///
+/// A repeated field of messages can name the field that identifies its elements with
+/// `#[fieldmask(key = "id")]`. The mask of such a field is the mask of its elements, so a mask can
+/// select fields of the elements, e.g. `items.name`. When `UpdateOptions::repeated_strategy` is
+/// `RepeatedStrategy::MergeByKey`, the elements of the target are updated with the elements of the
+/// source with the same key according to that mask, and the others are appended. Without a key,
+/// the field is appended.
 #[proc_macro_derive(SelfMaskable, attributes(fieldmask))]
 pub fn derive_self_maskable(input: TokenStream) -> TokenStream {
     derive_self_maskable_impl(input)
 }
 
+/// Derive `MaskedHash` for the type.
+///
+/// The type must be one of the following types:
+/// - A unit-like enum.
+/// - A struct or a tuple struct, where the type of each field must implement `MaskedHash`.
+/// - A transparent struct, where the type of its field implements `MaskedHash`.
+#[proc_macro_derive(MaskedHash, attributes(fieldmask))]
+pub fn derive_masked_hash(input: TokenStream) -> TokenStream {
+    derive_masked_hash_impl(input)
+}
+
+/// Derive `OptionMaskedHash` for the type.
+///
+/// The type must be one of the following types:
+/// - A unit-like enum.
+/// - A oneof enum, where the type of each associated field must implement `MaskedHash`.
+/// - A struct that implements `Default` and `MaskedHash`.
+/// - A transparent struct, where the type of its field implements `OptionMaskedHash`.
+#[proc_macro_derive(OptionMaskedHash, attributes(fieldmask))]
+pub fn derive_option_masked_hash(input: TokenStream) -> TokenStream {
+    derive_option_masked_hash_impl(input)
+}
+
 /// Treat the type as an atomic value and Implement `Maskable`, `OptionMaskable`, `SelfMaskable`
 /// for the type.
 ///
+/// The type must implement `Clone`, `Default` and `PartialEq`, and must be `'static`. The generic
+/// parameters of the type usually need the same bounds, e.g. `T: Clone + PartialEq + 'static` for
+/// `Vec<T>`. `Clone` and `'static` are new requirements: `project_ref` clones the selected values,
+/// and `visit` passes them as `FieldRef`s, which are downcast with `Any`.
+///
 /// You can override the default implementation of `update_as_field` and `merge` if needed.
 ///
+/// `MaskedHash` and `OptionMaskedHash` are not implemented. Implement them manually if the type is
+/// used in a message that derives them.
+///
 /// ### Example:
 /// ```ignore
 /// maskable_atomic!(impl bool {});
 ///
 /// maskable_atomic!(
-///     impl<T> Vec<T> {
+///     impl<T: Clone + PartialEq + 'static> Vec<T> {
 ///         // Omit this if you don't want to override the default implementation.
 ///         fn update_as_field(&mut self, source: Self, _mask: &Self::Mask, options: &UpdateOptions) {
 ///             self.merge(source, options);